@@ -0,0 +1,38 @@
+fn main() {
+    // Generate bindings to libslurm only when the backend is enabled; the default build doesn't need SLURM installed
+    #[cfg(feature = "libslurm")]
+    {
+        use std::env;
+        use std::path::PathBuf;
+
+        println!("cargo:rerun-if-env-changed=SLURM_INCLUDE_DIR");
+        println!("cargo:rerun-if-env-changed=SLURM_LIB_DIR");
+
+        if let Ok(lib_dir) = env::var("SLURM_LIB_DIR") {
+            println!("cargo:rustc-link-search=native={}", lib_dir);
+        }
+        println!("cargo:rustc-link-lib=slurm");
+
+        let mut builder = bindgen::Builder::default()
+            .header_contents(
+                "slurm_wrapper.h",
+                "#include <slurm/slurm.h>\n#include <slurm/slurm_errno.h>\n",
+            )
+            .allowlist_function("slurm_(init|init_job_desc_msg|submit_batch_job|free_submit_response_response_msg|load_job|free_job_info_msg|job_state_string|kill_job|get_errno|strerror)")
+            .allowlist_var("SHOW_ALL|JOB_STATE_BASE|ESLURM_INVALID_JOB_ID|MEM_PER_CPU|MAIL_JOB_(BEGIN|END|FAIL|REQUEUE)")
+            .prepend_enum_name(false);
+
+        if let Ok(include_dir) = env::var("SLURM_INCLUDE_DIR") {
+            builder = builder.clang_arg(format!("-I{}", include_dir));
+        }
+
+        let bindings = builder.generate().expect(
+            "Could not generate libslurm bindings (is the SLURM development package installed?)",
+        );
+
+        let out_path = PathBuf::from(env::var("OUT_DIR").unwrap()).join("slurm_bindings.rs");
+        bindings
+            .write_to_file(out_path)
+            .expect("Could not write libslurm bindings");
+    }
+}
@@ -0,0 +1,447 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::directives::SbatchDirectives;
+use crate::duration::parse_duration;
+use crate::error::{SlurmtailError, current_dir};
+
+// A project's own config file, in the directory slurmtail is run from; its settings win over the global file's
+pub const PROJECT_CONFIG_FILE: &str = ".slurmtail.toml";
+
+// slurmtail's settings from its config files; anything left out keeps its built-in default, and command-line options
+// win over all of it
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // How to talk to the scheduler, as with --scheduler
+    pub scheduler: Option<String>,
+    // The host (and directory there) to run the scheduler's commands on over SSH, as with --remote
+    pub remote: Option<String>,
+    // How long to wait for the log file to appear, and for new output once it has, as with --timeout: a number of
+    // seconds, or a duration like "10m" (see duration::parse_duration)
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub timeout: Option<Duration>,
+    // How long to wait for the log file to appear, and for new output once it has, as with --file-timeout and
+    // --idle-timeout (each instead of timeout)
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub file_timeout: Option<Duration>,
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub idle_timeout: Option<Duration>,
+    // How long to stay attached before detaching and leaving the job running, as with --max-duration
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub max_duration: Option<Duration>,
+    // How long to keep reading the log after the job has left the queue, as with --grace-period
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub grace_period: Option<Duration>,
+    // How long between checks for new output, as with --poll-interval (default: 1 second)
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub poll_interval: Option<Duration>,
+    // How many of the log's last lines to show when starting to follow it (default: 150)
+    pub history_lines: Option<usize>,
+    // Desktop notifications, as with --notify
+    pub notify: Option<bool>,
+    // Learn when jobs end from an strigger, as with --strigger
+    pub strigger: Option<bool>,
+    // Addresses to email a summary to once the job has ended, as with --mail-to
+    pub mail_to: Vec<String>,
+    // Rules for emphasizing parts of the job's output, applied in order (see output::set_highlights)
+    pub highlight: Vec<HighlightRule>,
+    // URLs to POST the job's lifecycle events to (see webhook)
+    pub webhook: Vec<WebhookConfig>,
+    // An ntfy topic to publish the job's lifecycle events to (see ntfy)
+    pub ntfy: Option<NtfyConfig>,
+    // An OpenTelemetry collector to send the jobs' traces to (see otel)
+    pub otlp: Option<OtlpConfig>,
+    // Commands to run when something happens to the job (see hooks)
+    pub hooks: HooksConfig,
+    // Lua scripts every line of the job's output goes through, as with --plugin (see plugin)
+    pub plugins: Vec<PathBuf>,
+    // Where jobs are submitted, passed to the scheduler as --clusters, --partition, --qos, and --account (see
+    // submit_args)
+    pub cluster: Option<String>,
+    pub partition: Option<String>,
+    pub qos: Option<String>,
+    pub account: Option<String>,
+    // Named sets of these settings, picked with --profile (see with_profile)
+    // e.g.:
+    // [profile.gpu]
+    // partition = "gpu"
+    // qos = "high"
+    // timeout = 900
+    pub profile: BTreeMap<String, Config>,
+}
+
+// e.g.:
+// [[highlight]]
+// pattern = "loss=[0-9.]+"
+// style = "bold green"
+#[derive(Debug, Deserialize)]
+pub struct HighlightRule {
+    pub pattern: String,
+    pub style: String,
+}
+
+// e.g.:
+// [[webhook]]
+// url = "https://alerts.example.com/slurm"
+// events = ["end", "fail"]
+// lines = 50
+#[derive(Debug, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    // What the payload looks like (default: slurmtail's own JSON)
+    #[serde(default)]
+    pub format: WebhookFormat,
+    // Which of the job's milestones to post: "start", "end", and/or "fail" (default: all of them)
+    #[serde(default = "all_events")]
+    pub events: Vec<String>,
+    // How many of the log's last lines to include (default: 20)
+    #[serde(default = "default_webhook_lines")]
+    pub lines: usize,
+}
+
+// The payloads webhooks can be sent: slurmtail's own JSON, or a message for a Slack or Discord incoming webhook
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    #[default]
+    Json,
+    Slack,
+    Discord,
+}
+
+// e.g.:
+// [ntfy]
+// url = "https://ntfy.sh/my-topic"
+// token = "tk_..."
+// events = ["end"]
+#[derive(Debug, Deserialize)]
+pub struct NtfyConfig {
+    // The topic's URL, on ntfy.sh or a server of one's own
+    pub url: String,
+    // An access token, for topics that need one
+    pub token: Option<String>,
+    // Which of the job's milestones to publish: "start", "end", and/or "fail" (default: all of them)
+    #[serde(default = "all_events")]
+    pub events: Vec<String>,
+}
+
+// e.g.:
+// [otlp]
+// endpoint = "http://otel-collector.example.com:4318"
+// headers = { "x-api-key" = "..." }
+#[derive(Debug, Deserialize)]
+pub struct OtlpConfig {
+    // The collector's OTLP/HTTP base URL (the traces go to <endpoint>/v1/traces)
+    pub endpoint: String,
+    // Headers to send with the traces, e.g. for authentication
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    // The service the traces are from (default: "slurmtail")
+    pub service_name: Option<String>,
+}
+
+// Shell commands, each run with the event's details in SLURMTAIL_* environment variables and as JSON on its stdin
+// e.g.:
+// [hooks]
+// on_end = "notify-me.sh"
+// on_fail = "rsync -a results/ backup:failed/"
+// [[hooks.on_line_match]]
+// pattern = "loss=nan"
+// command = "scancel $SLURMTAIL_JOB_ID"
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    pub on_submit: Option<String>,
+    pub on_start: Option<String>,
+    // Run whenever the job finishes, failures included
+    pub on_end: Option<String>,
+    pub on_fail: Option<String>,
+    pub on_line_match: Vec<LineMatchHook>,
+}
+
+// A command to run for each line of the job's output matching `pattern` (a regex)
+#[derive(Debug, Deserialize)]
+pub struct LineMatchHook {
+    pub pattern: String,
+    pub command: String,
+}
+
+fn all_events() -> Vec<String> {
+    vec!["start".to_string(), "end".to_string(), "fail".to_string()]
+}
+
+fn default_webhook_lines() -> usize {
+    crate::webhook::DEFAULT_LINES
+}
+
+// slurmtail's config file under $XDG_CONFIG_HOME (default: ~/.config)
+pub fn config_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_home.join("slurmtail").join("config.toml"))
+}
+
+// Read the global config file, and the project's in the current directory on top of it (a missing one is the same as
+// an empty one)
+pub fn load_config() -> Result<Config, SlurmtailError> {
+    let global = match config_path() {
+        Some(path) => read_config(&path)?,
+        None => Config::default(),
+    };
+    let project = read_config(&current_dir()?.join(PROJECT_CONFIG_FILE))?;
+
+    Ok(global.merge(project))
+}
+
+fn read_config(path: &Path) -> Result<Config, SlurmtailError> {
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+
+    let content = read_to_string(path).map_err(SlurmtailError::io("read", path))?;
+    let config =
+        toml::from_str(&content).map_err(|e| SlurmtailError::parse("config file", path, e))?;
+    tracing::debug!(path = ?path, "Read config file");
+
+    Ok(config)
+}
+
+impl Config {
+    // These settings, with a project's on top: its settings replace these, while its highlight rules (first, so they
+    // take precedence), webhooks, addresses, on_line_match hooks, and plugins are added to them
+    fn merge(self, project: Config) -> Config {
+        Config {
+            scheduler: project.scheduler.or(self.scheduler),
+            remote: project.remote.or(self.remote),
+            timeout: project.timeout.or(self.timeout),
+            // A timeout covers both, over the file and idle timeouts of the settings underneath
+            file_timeout: project
+                .file_timeout
+                .or(project.timeout)
+                .or(self.file_timeout),
+            idle_timeout: project
+                .idle_timeout
+                .or(project.timeout)
+                .or(self.idle_timeout),
+            max_duration: project.max_duration.or(self.max_duration),
+            grace_period: project.grace_period.or(self.grace_period),
+            poll_interval: project.poll_interval.or(self.poll_interval),
+            history_lines: project.history_lines.or(self.history_lines),
+            notify: project.notify.or(self.notify),
+            strigger: project.strigger.or(self.strigger),
+            mail_to: [self.mail_to, project.mail_to]
+                .into_iter()
+                .flatten()
+                .collect(),
+            highlight: [project.highlight, self.highlight]
+                .into_iter()
+                .flatten()
+                .collect(),
+            webhook: [self.webhook, project.webhook]
+                .into_iter()
+                .flatten()
+                .collect(),
+            ntfy: project.ntfy.or(self.ntfy),
+            otlp: project.otlp.or(self.otlp),
+            hooks: HooksConfig {
+                on_submit: project.hooks.on_submit.or(self.hooks.on_submit),
+                on_start: project.hooks.on_start.or(self.hooks.on_start),
+                on_end: project.hooks.on_end.or(self.hooks.on_end),
+                on_fail: project.hooks.on_fail.or(self.hooks.on_fail),
+                on_line_match: [self.hooks.on_line_match, project.hooks.on_line_match]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+            },
+            plugins: [self.plugins, project.plugins]
+                .into_iter()
+                .flatten()
+                .collect(),
+            cluster: project.cluster.or(self.cluster),
+            partition: project.partition.or(self.partition),
+            qos: project.qos.or(self.qos),
+            account: project.account.or(self.account),
+            // A profile defined in both is merged the same way
+            profile: {
+                let mut profiles = self.profile;
+                for (name, profile) in project.profile {
+                    let merged = match profiles.remove(&name) {
+                        Some(global) => global.merge(profile),
+                        None => profile,
+                    };
+                    profiles.insert(name, merged);
+                }
+                profiles
+            },
+        }
+    }
+
+    // These settings with a profile's on top (--profile), the way a project's go on top of the global ones
+    pub fn with_profile(mut self, name: &str) -> Result<Config, SlurmtailError> {
+        let Some(profile) = self.profile.remove(name) else {
+            let known: Vec<&str> = self.profile.keys().map(String::as_str).collect();
+            return Err(SlurmtailError::NotFound(format!(
+                "Unknown profile {:?}: {}",
+                name,
+                if known.is_empty() {
+                    "no profiles are defined in the config files".to_string()
+                } else {
+                    format!("expected one of {}", known.join(", "))
+                }
+            )));
+        };
+
+        Ok(self.merge(profile))
+    }
+
+    // These settings with those from SLURMTAIL_* environment variables on top (e.g. set by a site's environment module,
+    // or for CI), which win over the config files but not over the command line
+    // A list from the environment (SLURMTAIL_MAIL_TO, comma-separated) replaces the config's
+    pub fn with_env(mut self) -> Result<Config, SlurmtailError> {
+        let env = Config {
+            scheduler: env_var("SLURMTAIL_SCHEDULER"),
+            remote: env_var("SLURMTAIL_REMOTE"),
+            timeout: duration_env_var("SLURMTAIL_TIMEOUT")?,
+            file_timeout: duration_env_var("SLURMTAIL_FILE_TIMEOUT")?,
+            idle_timeout: duration_env_var("SLURMTAIL_IDLE_TIMEOUT")?,
+            max_duration: duration_env_var("SLURMTAIL_MAX_DURATION")?,
+            grace_period: duration_env_var("SLURMTAIL_GRACE_PERIOD")?,
+            poll_interval: duration_env_var("SLURMTAIL_POLL_INTERVAL")?,
+            history_lines: parse_env_var("SLURMTAIL_HISTORY_LINES")?,
+            notify: match env_var("SLURMTAIL_NOTIFY") {
+                Some(value) => Some(parse_switch("SLURMTAIL_NOTIFY", &value)?),
+                None => None,
+            },
+            strigger: match env_var("SLURMTAIL_STRIGGER") {
+                Some(value) => Some(parse_switch("SLURMTAIL_STRIGGER", &value)?),
+                None => None,
+            },
+            mail_to: env_var("SLURMTAIL_MAIL_TO")
+                .map(|addresses| {
+                    addresses
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|address| !address.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            cluster: env_var("SLURMTAIL_CLUSTER"),
+            partition: env_var("SLURMTAIL_PARTITION"),
+            qos: env_var("SLURMTAIL_QOS"),
+            account: env_var("SLURMTAIL_ACCOUNT"),
+            ..Config::default()
+        };
+        if !env.mail_to.is_empty() {
+            self.mail_to.clear();
+        }
+
+        Ok(self.merge(env))
+    }
+
+    // Catch settings that parse but make no sense, once all of them are in
+    pub fn validate(&self) -> Result<(), SlurmtailError> {
+        if self
+            .poll_interval
+            .is_some_and(|poll_interval| poll_interval.is_zero())
+        {
+            return Err(SlurmtailError::Invalid(
+                "Invalid poll_interval 0: expected a positive duration".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    // The options that submit a script where these settings say, e.g. ["--partition=gpu", "--qos=high"]
+    // They are defaults: an option the script's own #SBATCH lines set is left to the script
+    pub fn submit_args(&self, script_path: &Path) -> Result<Vec<String>, SlurmtailError> {
+        let directives = SbatchDirectives::from_file(script_path)?;
+
+        Ok([
+            ("clusters", &self.cluster),
+            ("partition", &self.partition),
+            ("qos", &self.qos),
+            ("account", &self.account),
+        ]
+        .into_iter()
+        .filter(|(option, _)| !directives.options.iter().any(|(name, _)| name == option))
+        .filter_map(|(option, value)| Some(format!("--{}={}", option, value.as_ref()?)))
+        .collect())
+    }
+}
+
+// An environment variable's value, unless it is unset or empty
+pub fn env_var(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+}
+
+// A duration from an environment variable, read as in the config files
+fn duration_env_var(name: &str) -> Result<Option<Duration>, SlurmtailError> {
+    let Some(value) = env_var(name) else {
+        return Ok(None);
+    };
+    let duration = parse_duration(&value).map_err(|e| invalid_env_var(name, &value, e))?;
+
+    Ok(Some(duration))
+}
+
+// A duration in a config file: a number of seconds (e.g. `timeout = 600`), or a string like `timeout = "10m"`
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Setting {
+        Seconds(f64),
+        Text(String),
+    }
+
+    let text = match Setting::deserialize(deserializer)? {
+        Setting::Seconds(seconds) => seconds.to_string(),
+        Setting::Text(text) => text,
+    };
+    parse_duration(&text)
+        .map(Some)
+        .map_err(|e| serde::de::Error::custom(format!("invalid duration {:?}: {}", text, e)))
+}
+
+fn parse_env_var<T>(name: &str) -> Result<Option<T>, SlurmtailError>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    let Some(value) = env_var(name) else {
+        return Ok(None);
+    };
+    let parsed = value
+        .trim()
+        .parse()
+        .map_err(|e| invalid_env_var(name, &value, e))?;
+
+    Ok(Some(parsed))
+}
+
+// e.g.: "1", "true", "yes", or "on"
+fn parse_switch(name: &str, value: &str) -> Result<bool, SlurmtailError> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(invalid_env_var(name, value, "expected true or false")),
+    }
+}
+
+// e.g. "Invalid SLURMTAIL_TIMEOUT "soon": ..."
+fn invalid_env_var(name: &str, value: &str, message: impl std::fmt::Display) -> SlurmtailError {
+    SlurmtailError::Invalid(format!("Invalid {} {:?}: {}", name, value, message))
+}
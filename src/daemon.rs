@@ -0,0 +1,842 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs::{DirBuilder, OpenOptions};
+use std::net::SocketAddr;
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tokio::sync::{Notify, broadcast};
+use tokio::time::{self, MissedTickBehavior};
+
+use crate::error::SlurmtailError;
+use crate::history;
+use crate::http;
+use crate::interrupt;
+use crate::lifecycle;
+use crate::output;
+use crate::remote;
+use crate::runtime;
+use crate::scheduler::{is_active_state, scheduler};
+use crate::state::{self, finish_session_if_done, xdg_state_root};
+
+// How often the daemon looks for sessions it isn't following yet, and for new output in the logs it is
+const RESCAN_INTERVAL: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// How often the daemon asks the scheduler whether a job it follows is still around
+const JOB_STATE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// How many of a job's lines the daemon keeps for replaying (the oldest ones make way)
+const MAX_KEPT_LINES: usize = 10_000;
+
+// How long a finished job's lines are kept, for resuming it after it has ended
+const KEEP_FINISHED: Duration = Duration::from_secs(60 * 60);
+
+// How many of the history's latest submissions are looked through for the projects with sessions to follow
+const RESCAN_SUBMISSIONS: usize = 200;
+
+// How long `slurmtail daemon` waits for the daemon it starts to answer
+const START_WAIT: Duration = Duration::from_secs(5);
+
+// Name of the daemon's log (in slurmtail's directory under $XDG_STATE_HOME), when it runs in the background
+const LOG_FILE: &str = "daemon.log";
+
+// What a client asks the daemon, as a line of JSON
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum Request {
+    // A job's output: the lines kept from byte `from` of its log on (or its last `lines` lines, if that's before
+    // the oldest line kept), then new lines as they come, until the job has ended
+    Attach {
+        job_id: u64,
+        from: Option<u64>,
+        lines: usize,
+    },
+    // The jobs being followed
+    Status,
+    Stop,
+}
+
+// What the daemon answers, as lines of JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "reply", rename_all = "snake_case")]
+pub enum Reply {
+    // A line of the job's output (with its line ending), and the byte of the log it ends at
+    Line { line: String, end: u64 },
+    State { state: String },
+    // The job has ended (in this state, if the scheduler said)
+    End { state: Option<String> },
+    // Attaching: the daemon isn't following that job
+    NotFollowing,
+    Job(JobStatus),
+    Stopping,
+}
+
+// A job the daemon follows, as `slurmtail daemon --status` lists it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobStatus {
+    pub job_id: u64,
+    pub project: PathBuf,
+    pub log: PathBuf,
+    pub state: Option<String>,
+    pub lines: usize,
+    pub finished: bool,
+}
+
+// A job the daemon follows: where its log is, what it has written, and who is listening
+struct Followed {
+    project: PathBuf,
+    log: PathBuf,
+    state: Option<String>,
+    // The log's lines, with the byte each ends at
+    lines: VecDeque<(String, u64)>,
+    finished_at: Option<Instant>,
+    updates: broadcast::Sender<Reply>,
+    // How much of the log has been read
+    lines_read: u64,
+    bytes_read: u64,
+    // How long the job waited in the queue before it started, once it has
+    queue_wait: Option<Duration>,
+}
+
+static FOLLOWED: Mutex<BTreeMap<u64, Followed>> = Mutex::new(BTreeMap::new());
+
+// How much of the logs the daemon has read, including from jobs it has since let go of
+static LINES_READ: AtomicU64 = AtomicU64::new(0);
+static BYTES_READ: AtomicU64 = AtomicU64::new(0);
+
+// Where the daemon listens: $SLURMTAIL_DAEMON_SOCKET, or in $XDG_RUNTIME_DIR, or a directory of the user's own in
+// /tmp (not under $HOME, which login nodes tend to share, while each runs a daemon of its own)
+pub fn socket_path() -> PathBuf {
+    if let Some(path) = std::env::var_os("SLURMTAIL_DAEMON_SOCKET")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+    {
+        return path;
+    }
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .map(|runtime_dir| runtime_dir.join("slurmtail"))
+        .unwrap_or_else(|| {
+            std::env::temp_dir().join(format!("slurmtail-{}", unsafe { libc::getuid() }))
+        });
+
+    dir.join("daemon.sock")
+}
+
+// Start the daemon in the background, in a session of its own so closing the terminal doesn't stop it
+// (`slurmtail daemon`); it runs `slurmtail daemon --foreground` with the same options, logging to daemon.log
+pub fn start() -> Result<(), SlurmtailError> {
+    if runtime::block_on(connect()).is_some() {
+        return Err(SlurmtailError::Daemon(format!(
+            "A slurmtail daemon is already running (at {:?})",
+            socket_path()
+        )));
+    }
+
+    let log_path = xdg_state_root().map(|root| root.join(LOG_FILE));
+    let log = match &log_path {
+        Some(log_path) => {
+            if let Some(parent) = log_path.parent() {
+                std::fs::create_dir_all(parent).map_err(SlurmtailError::io("create", parent))?;
+            }
+            Stdio::from(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(log_path)
+                    .map_err(SlurmtailError::io("open", log_path))?,
+            )
+        }
+        None => Stdio::null(),
+    };
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let exe =
+        std::env::current_exe().map_err(SlurmtailError::io("find", Path::new("slurmtail")))?;
+    let mut command = Command::new(exe);
+    command
+        .args(args)
+        .arg("--foreground")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(log);
+    // Leave the terminal's session, so its SIGHUP doesn't reach the daemon
+    unsafe {
+        command.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    let child = command.spawn().map_err(|e| {
+        SlurmtailError::Daemon(format!("Could not start the slurmtail daemon: {}", e))
+    })?;
+
+    let deadline = Instant::now() + START_WAIT;
+    while runtime::block_on(connect()).is_none() {
+        if Instant::now() >= deadline {
+            return Err(SlurmtailError::Daemon(
+                "The slurmtail daemon didn't start (see its log)".to_string(),
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    status!(
+        "[INFO] Started the slurmtail daemon (pid {}), listening at {:?}",
+        child.id(),
+        socket_path()
+    );
+    if let Some(log_path) = log_path {
+        status!("[INFO] Its log is at {:?}", log_path);
+    }
+
+    Ok(())
+}
+
+// Ask the running daemon to stop (`slurmtail daemon --stop`)
+pub fn stop() -> Result<(), SlurmtailError> {
+    let replies = runtime::block_on(ask(Request::Stop))?;
+    if matches!(replies.first(), Some(Reply::Stopping)) {
+        status!("[INFO] Stopped the slurmtail daemon");
+    }
+
+    Ok(())
+}
+
+// List the jobs the running daemon follows (`slurmtail daemon --status`)
+pub fn print_status() -> Result<(), SlurmtailError> {
+    let replies = runtime::block_on(ask(Request::Status))?;
+    let jobs: Vec<JobStatus> = replies
+        .into_iter()
+        .filter_map(|reply| match reply {
+            Reply::Job(job) => Some(job),
+            _ => None,
+        })
+        .collect();
+
+    if output::machine_readable() {
+        output::print_json(&jobs, false)?;
+        return Ok(());
+    }
+    if jobs.is_empty() {
+        println!("The slurmtail daemon isn't following any jobs.");
+        return Ok(());
+    }
+    for job in jobs {
+        println!(
+            "{}\t{}{}\t{} lines\t{}",
+            job.job_id,
+            job.state.as_deref().unwrap_or("UNKNOWN"),
+            if job.finished { " (finished)" } else { "" },
+            job.lines,
+            job.log.display()
+        );
+    }
+
+    Ok(())
+}
+
+// Send a request to the running daemon, and read every reply
+async fn ask(request: Request) -> Result<Vec<Reply>, SlurmtailError> {
+    let mut replies = send(&request)
+        .await
+        .map_err(lost_connection)?
+        .ok_or_else(|| {
+            SlurmtailError::Daemon(format!(
+                "No slurmtail daemon is running (at {:?})",
+                socket_path()
+            ))
+        })?;
+
+    let mut all = Vec::new();
+    while let Some(line) = replies.next_line().await.map_err(lost_connection)? {
+        all.push(serde_json::from_str(&line).map_err(|e| lost_connection(e.into()))?);
+    }
+    Ok(all)
+}
+
+// The daemon stopped answering (or answered with something that isn't a reply)
+fn lost_connection(e: std::io::Error) -> SlurmtailError {
+    SlurmtailError::Daemon(format!(
+        "Lost the connection to the slurmtail daemon: {}",
+        e
+    ))
+}
+
+async fn connect() -> Option<UnixStream> {
+    UnixStream::connect(socket_path()).await.ok()
+}
+
+// Send a request to the running daemon; its replies come as lines of JSON (None if no daemon is running)
+pub async fn send(request: &Request) -> std::io::Result<Option<Lines<BufReader<UnixStream>>>> {
+    let Some(mut stream) = connect().await else {
+        return Ok(None);
+    };
+    let request = serde_json::to_string(request).map_err(std::io::Error::other)?;
+    stream
+        .write_all(format!("{}\n", request).as_bytes())
+        .await?;
+
+    Ok(Some(BufReader::new(stream).lines()))
+}
+
+// Run the daemon here until it is asked to stop, or gets SIGTERM (`slurmtail daemon --foreground`, `slurmtail
+// serve`): follow the sessions of every job slurmtail submitted that is still going, and serve their output on the
+// socket, and over HTTP at `bind`
+pub fn serve(bind: Option<SocketAddr>) -> Result<(), SlurmtailError> {
+    let path = socket_path();
+    if runtime::block_on(connect()).is_some() {
+        return Err(SlurmtailError::Daemon(format!(
+            "A slurmtail daemon is already running (at {:?})",
+            path
+        )));
+    }
+    if let Some(dir) = path.parent() {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .map_err(SlurmtailError::io("create", dir))?;
+    }
+    // Left behind by a daemon that didn't get to clean up
+    if path.exists() {
+        std::fs::remove_file(&path).map_err(SlurmtailError::io("remove", &path))?;
+    }
+    interrupt::install();
+
+    let result = runtime::block_on(async {
+        let listener = UnixListener::bind(&path).map_err(SlurmtailError::io("listen at", &path))?;
+        status!("[INFO] slurmtail daemon listening at {:?}", path);
+        let http_listener = match bind {
+            Some(bind) => {
+                let http_listener = TcpListener::bind(bind).await.map_err(|e| {
+                    SlurmtailError::Daemon(format!("Could not serve HTTP at {}: {}", bind, e))
+                })?;
+                let address = http_listener.local_addr().unwrap_or(bind);
+                status!("[INFO] Serving the jobs' output at http://{}/", address);
+                if !address.ip().is_loopback() {
+                    warning!(
+                        "[WARNING] {} can be reached from other machines, and anyone who reaches it can read the jobs' output",
+                        address
+                    );
+                }
+                Some(http_listener)
+            }
+            None => None,
+        };
+
+        let stopping = Arc::new(Notify::new());
+        let mut rescans = time::interval(RESCAN_INTERVAL);
+        rescans.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut signal_checks = time::interval(POLL_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = rescans.tick() => rescan().await,
+                _ = signal_checks.tick() => {
+                    if interrupt::interrupted() || interrupt::terminated().is_some() {
+                        status!("[INFO] Stopping the slurmtail daemon");
+                        return Ok(());
+                    }
+                }
+                _ = stopping.notified() => {
+                    status!("[INFO] Asked to stop; stopping the slurmtail daemon");
+                    return Ok(());
+                }
+                accepted = listener.accept() => {
+                    let (stream, _) = accepted.map_err(SlurmtailError::io("accept a connection at", &path))?;
+                    runtime::spawn(answer(stream, Arc::clone(&stopping)));
+                }
+                accepted = accept_http(&http_listener) => {
+                    let (stream, _) = accepted.map_err(|e| {
+                        SlurmtailError::Daemon(format!("Could not accept an HTTP connection: {}", e))
+                    })?;
+                    runtime::spawn(http::answer(stream));
+                }
+            }
+        }
+    });
+
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+// The next HTTP connection (never, when not serving HTTP)
+async fn accept_http(
+    http_listener: &Option<TcpListener>,
+) -> std::io::Result<(TcpStream, SocketAddr)> {
+    match http_listener {
+        Some(http_listener) => http_listener.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn write_reply(writer: &mut OwnedWriteHalf, reply: &Reply) -> std::io::Result<()> {
+    let reply = serde_json::to_string(reply).map_err(std::io::Error::other)?;
+    writer.write_all(format!("{}\n", reply).as_bytes()).await
+}
+
+// Answer a client's request
+async fn answer(stream: UnixStream, stopping: Arc<Notify>) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let Some(request) = BufReader::new(reader).lines().next_line().await? else {
+        return Ok(());
+    };
+    let request = match serde_json::from_str::<Request>(&request) {
+        Ok(request) => request,
+        Err(e) => {
+            warning!("[WARNING] Ignoring an invalid request: {}", e);
+            return Ok(());
+        }
+    };
+
+    match request {
+        Request::Attach {
+            job_id,
+            from,
+            lines,
+        } => attach(job_id, from, lines, &mut writer).await,
+        Request::Status => {
+            let jobs = jobs();
+            for job in jobs {
+                write_reply(&mut writer, &Reply::Job(job)).await?;
+            }
+            Ok(())
+        }
+        Request::Stop => {
+            write_reply(&mut writer, &Reply::Stopping).await?;
+            stopping.notify_one();
+            Ok(())
+        }
+    }
+}
+
+// What attaching to a job gives: the replies that catch up on it, then (unless it has ended) the ones to come
+pub(crate) struct Attached {
+    pub replay: Vec<Reply>,
+    pub updates: Option<broadcast::Receiver<Reply>>,
+}
+
+// Attach to a job the daemon follows: its state and the lines kept from byte `from` of its log on (or its last
+// `lines` lines, if that's before the oldest line kept), and what happens next (None if it isn't followed)
+pub(crate) fn subscribe(job_id: u64, from: Option<u64>, lines: usize) -> Option<Attached> {
+    // Taken together, so no line falls between the replay and the updates
+    let followed = FOLLOWED.lock().ok()?;
+    let job = followed.get(&job_id)?;
+
+    let oldest_start = job.lines.front().map(|(line, end)| end - line.len() as u64);
+    let skip = match (from, oldest_start) {
+        (Some(from), Some(oldest_start)) if from >= oldest_start => job
+            .lines
+            .iter()
+            .take_while(|(line, end)| end - (line.len() as u64) < from)
+            .count(),
+        _ => job.lines.len().saturating_sub(lines),
+    };
+    let mut replay: Vec<Reply> = job
+        .state
+        .iter()
+        .map(|state| Reply::State {
+            state: state.clone(),
+        })
+        .collect();
+    replay.extend(job.lines.iter().skip(skip).map(|(line, end)| Reply::Line {
+        line: line.clone(),
+        end: *end,
+    }));
+    if job.finished_at.is_some() {
+        replay.push(Reply::End {
+            state: job.state.clone(),
+        });
+        return Some(Attached {
+            replay,
+            updates: None,
+        });
+    }
+
+    Some(Attached {
+        replay,
+        updates: Some(job.updates.subscribe()),
+    })
+}
+
+// The jobs the daemon follows
+pub(crate) fn jobs() -> Vec<JobStatus> {
+    let Ok(followed) = FOLLOWED.lock() else {
+        return Vec::new();
+    };
+    followed
+        .iter()
+        .map(|(job_id, job)| JobStatus {
+            job_id: *job_id,
+            project: job.project.clone(),
+            log: job.log.clone(),
+            state: job.state.clone(),
+            lines: job.lines.len(),
+            finished: job.finished_at.is_some(),
+        })
+        .collect()
+}
+
+// The daemon's metrics, in Prometheus's text format: the jobs it follows and their states, how much of their logs it
+// has read, and how long they waited in the queue
+pub(crate) fn metrics() -> String {
+    let Ok(followed) = FOLLOWED.lock() else {
+        return String::new();
+    };
+    let mut states: BTreeMap<&str, usize> = BTreeMap::new();
+    for job in followed.values() {
+        *states
+            .entry(job.state.as_deref().unwrap_or("UNKNOWN"))
+            .or_default() += 1;
+    }
+
+    let mut metrics = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+        metrics.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} {}\n",
+            name, help, name, kind
+        ));
+        for (labels, value) in samples {
+            metrics.push_str(&format!("{}{} {}\n", name, labels, value));
+        }
+    };
+    metric(
+        "slurmtail_jobs_followed",
+        "gauge",
+        "Jobs the daemon follows that haven't ended",
+        vec![(
+            String::new(),
+            followed
+                .values()
+                .filter(|job| job.finished_at.is_none())
+                .count()
+                .to_string(),
+        )],
+    );
+    metric(
+        "slurmtail_jobs",
+        "gauge",
+        "Jobs the daemon keeps the output of, by state",
+        states
+            .iter()
+            .map(|(state, count)| (format!("{{state=\"{}\"}}", state), count.to_string()))
+            .collect(),
+    );
+    metric(
+        "slurmtail_lines_read_total",
+        "counter",
+        "Lines read from the jobs' logs",
+        vec![(
+            String::new(),
+            LINES_READ.load(Ordering::Relaxed).to_string(),
+        )],
+    );
+    metric(
+        "slurmtail_bytes_read_total",
+        "counter",
+        "Bytes read from the jobs' logs",
+        vec![(
+            String::new(),
+            BYTES_READ.load(Ordering::Relaxed).to_string(),
+        )],
+    );
+    metric(
+        "slurmtail_job_lines_read_total",
+        "counter",
+        "Lines read from a job's log",
+        followed
+            .iter()
+            .map(|(job_id, job)| {
+                (
+                    format!("{{job_id=\"{}\"}}", job_id),
+                    job.lines_read.to_string(),
+                )
+            })
+            .collect(),
+    );
+    metric(
+        "slurmtail_job_bytes_read_total",
+        "counter",
+        "Bytes read from a job's log",
+        followed
+            .iter()
+            .map(|(job_id, job)| {
+                (
+                    format!("{{job_id=\"{}\"}}", job_id),
+                    job.bytes_read.to_string(),
+                )
+            })
+            .collect(),
+    );
+    metric(
+        "slurmtail_job_queue_wait_seconds",
+        "gauge",
+        "How long a job waited in the queue before it started",
+        followed
+            .iter()
+            .filter_map(|(job_id, job)| {
+                let queue_wait = job.queue_wait?;
+                Some((
+                    format!("{{job_id=\"{}\"}}", job_id),
+                    queue_wait.as_secs_f64().to_string(),
+                ))
+            })
+            .collect(),
+    );
+
+    metrics
+}
+
+// The next thing that happens to an attached job (None once the daemon has let go of it)
+pub(crate) async fn next_update(updates: &mut broadcast::Receiver<Reply>) -> Option<Reply> {
+    loop {
+        match updates.recv().await {
+            Ok(reply) => return Some(reply),
+            // A client too slow to keep up misses some lines, rather than holding up the others
+            Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
+
+// Replay a job's kept lines to a client, then pass on what happens next until the job has ended
+async fn attach(
+    job_id: u64,
+    from: Option<u64>,
+    lines: usize,
+    writer: &mut OwnedWriteHalf,
+) -> std::io::Result<()> {
+    let Some(Attached { replay, updates }) = subscribe(job_id, from, lines) else {
+        return write_reply(writer, &Reply::NotFollowing).await;
+    };
+
+    for reply in &replay {
+        write_reply(writer, reply).await?;
+    }
+    let Some(mut updates) = updates else {
+        return Ok(());
+    };
+    while let Some(reply) = next_update(&mut updates).await {
+        write_reply(writer, &reply).await?;
+        if matches!(reply, Reply::End { .. }) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+// Start following the sessions of jobs that are still going (from the projects in the submission history), and
+// let go of jobs that finished long enough ago
+async fn rescan() {
+    let sessions = runtime::blocking(|| {
+        let submissions = history::recent_submissions(RESCAN_SUBMISSIONS).unwrap_or_default();
+        let projects: BTreeSet<PathBuf> = submissions
+            .into_iter()
+            .filter(|submission| {
+                submission.final_state.is_none() && submission.scheduler == scheduler().name()
+            })
+            .filter_map(|submission| submission.project)
+            .collect();
+
+        projects
+            .into_iter()
+            .flat_map(|project| {
+                let sessions = state::load_sessions(&project).unwrap_or_default();
+                sessions.into_iter().filter_map(move |session| {
+                    let project = session.project.clone().unwrap_or_else(|| project.clone());
+                    let submitted_at = session.submit_time();
+                    Some((
+                        session.job_id?,
+                        project,
+                        session.stdout,
+                        session.cluster,
+                        submitted_at,
+                    ))
+                })
+            })
+            .collect::<Vec<_>>()
+    })
+    .await;
+
+    let Ok(mut followed) = FOLLOWED.lock() else {
+        return;
+    };
+    followed.retain(|_, job| {
+        job.finished_at
+            .is_none_or(|finished_at| finished_at.elapsed() < KEEP_FINISHED)
+    });
+    for (job_id, project, log, cluster, submitted_at) in sessions {
+        if followed.contains_key(&job_id) {
+            continue;
+        }
+        if let Some(cluster) = &cluster {
+            scheduler().set_job_cluster(job_id, cluster);
+        }
+        status!("[INFO] Following job {}: {:?}", job_id, log);
+        followed.insert(
+            job_id,
+            Followed {
+                project: project.clone(),
+                log: log.clone(),
+                state: None,
+                lines: VecDeque::new(),
+                finished_at: None,
+                updates: broadcast::channel(1024).0,
+                lines_read: 0,
+                bytes_read: 0,
+                queue_wait: None,
+            },
+        );
+        runtime::spawn(follow(job_id, project, log, submitted_at));
+    }
+}
+
+// Keep a job's log, from its beginning, until the job has left the queue and the log is drained; then record how it
+// ended, as `slurmtail resume` would
+async fn follow(
+    job_id: u64,
+    project: PathBuf,
+    log: PathBuf,
+    submitted_at: Option<jiff::Timestamp>,
+) {
+    let mut polls = time::interval(POLL_INTERVAL);
+    polls.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut job_checks = time::interval(JOB_STATE_CHECK_INTERVAL);
+    job_checks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    // With --remote, the log is read from a copy kept here
+    let read_path = remote::local_copy(&log);
+    let mut reader = None;
+    let mut end = 0;
+    let mut job_gone = false;
+    let mut seen_pending = false;
+    let mut seen_started = false;
+    loop {
+        if reader.is_none()
+            && let Ok(file) = tokio::fs::File::open(&read_path).await
+        {
+            reader = Some(BufReader::new(file));
+        }
+        if let Some(reader) = &mut reader {
+            let mut line = Vec::new();
+            while reader.read_until(b'\n', &mut line).await.unwrap_or(0) > 0 {
+                end += line.len() as u64;
+                keep_line(job_id, String::from_utf8_lossy(&line).into_owned(), end);
+                line.clear();
+            }
+        }
+        if job_gone {
+            break;
+        }
+
+        tokio::select! {
+            _ = polls.tick() => {}
+            _ = job_checks.tick() => {
+                let state = runtime::blocking(move || scheduler().job_state(job_id).ok()).await;
+                match state {
+                    Some(Some(state)) => {
+                        output::observe_state(job_id, &state);
+                        job_gone = !is_active_state(&state);
+                        if state == "PENDING" {
+                            seen_pending = true;
+                        } else if !seen_started {
+                            seen_started = true;
+                            let queue_wait = queue_wait(job_id, submitted_at, seen_pending).await;
+                            if let Ok(mut followed) = FOLLOWED.lock()
+                                && let Some(job) = followed.get_mut(&job_id)
+                            {
+                                job.queue_wait = queue_wait;
+                            }
+                        }
+                        update_state(job_id, state);
+                    }
+                    Some(None) => job_gone = true,
+                    // If the scheduler can't be reached, don't pretend to know the job is gone
+                    None => {}
+                }
+                if job_gone {
+                    remote::catch_up(&log).await;
+                }
+            }
+        }
+    }
+
+    let final_state = runtime::blocking({
+        let project = project.clone();
+        move || finish_session_if_done(&project, job_id)
+    })
+    .await;
+    status!(
+        "[INFO] Job {} has ended ({})",
+        job_id,
+        final_state.as_deref().unwrap_or("UNKNOWN")
+    );
+    if let Ok(mut followed) = FOLLOWED.lock()
+        && let Some(job) = followed.get_mut(&job_id)
+    {
+        job.finished_at = Some(Instant::now());
+        if let Some(state) = &final_state {
+            job.state = Some(state.clone());
+        }
+        let _ = job.updates.send(Reply::End { state: final_state });
+    }
+}
+
+// How long a job that has just been seen to have started waited in the queue: from its submission to its start time
+// in the scheduler's accounting, or (if the daemon saw it pending) to now
+async fn queue_wait(
+    job_id: u64,
+    submitted_at: Option<jiff::Timestamp>,
+    seen_pending: bool,
+) -> Option<Duration> {
+    let submitted_at = submitted_at?;
+    let started_at = runtime::blocking(move || {
+        let accounting = scheduler().job_accounting(job_id).ok()?;
+        lifecycle::accounting_time(&accounting, "Start")
+    })
+    .await
+    .or_else(|| seen_pending.then(jiff::Timestamp::now))?;
+
+    started_at.duration_since(submitted_at).try_into().ok()
+}
+
+fn keep_line(job_id: u64, line: String, end: u64) {
+    let Ok(mut followed) = FOLLOWED.lock() else {
+        return;
+    };
+    let Some(job) = followed.get_mut(&job_id) else {
+        return;
+    };
+
+    LINES_READ.fetch_add(1, Ordering::Relaxed);
+    BYTES_READ.fetch_add(end - job.bytes_read, Ordering::Relaxed);
+    job.lines_read += 1;
+    job.bytes_read = end;
+    if job.lines.len() == MAX_KEPT_LINES {
+        job.lines.pop_front();
+    }
+    job.lines.push_back((line.clone(), end));
+    let _ = job.updates.send(Reply::Line { line, end });
+}
+
+fn update_state(job_id: u64, state: String) {
+    let Ok(mut followed) = FOLLOWED.lock() else {
+        return;
+    };
+    let Some(job) = followed.get_mut(&job_id) else {
+        return;
+    };
+
+    if job.state.as_deref() != Some(state.as_str()) {
+        job.state = Some(state.clone());
+        let _ = job.updates.send(Reply::State { state });
+    }
+}
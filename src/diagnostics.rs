@@ -0,0 +1,82 @@
+use std::fmt;
+use std::io::{self, Write};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::format::{FmtSpan, Writer};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::output;
+
+// slurmtail's internal diagnostics (what it reads, each scheduler call and how long it took, and so on), which are
+// off unless asked for: with RUST_LOG (e.g. RUST_LOG=slurmtail=debug, or RUST_LOG=slurmtail::scheduler=trace), or
+// else with -v (debug) or -vv (trace)
+pub fn init(verbosity: u8) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::new(match verbosity {
+            0 => "off",
+            1 => "slurmtail=debug",
+            _ => "slurmtail=trace",
+        })
+    });
+
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_ansi(false)
+        .event_format(StatusFormat)
+        .with_writer(|| StatusWriter)
+        .try_init();
+}
+
+// Diagnostics look like slurmtail's other messages: "[DEBUG] Reading log file start=0 size=1024 (follow{...})",
+// with the spans they happened in (innermost last) at the end
+struct StatusFormat;
+
+impl<S, N> FormatEvent<S, N> for StatusFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        write!(writer, "[{}] ", event.metadata().level())?;
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+
+        if let Some(scope) = ctx.event_scope() {
+            let spans: Vec<String> = scope
+                .from_root()
+                .map(|span| {
+                    let extensions = span.extensions();
+                    match extensions.get::<FormattedFields<N>>() {
+                        Some(fields) if !fields.is_empty() => {
+                            format!("{}{{{}}}", span.name(), fields)
+                        }
+                        _ => span.name().to_string(),
+                    }
+                })
+                .collect();
+            write!(writer, " ({})", spans.join(" > "))?;
+        }
+
+        writeln!(writer)
+    }
+}
+
+// Writes diagnostics to stderr like other status messages (dimmed, out of the spinner's way, and copied by --tee)
+struct StatusWriter;
+
+impl Write for StatusWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        output::print_diagnostic(&String::from_utf8_lossy(buf));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,645 @@
+use std::fs::read_to_string;
+use std::path::Path;
+
+use crate::error::SlurmtailError;
+
+// Long names of the sbatch options that take no value (or only an optional one, which has to be given with '=')
+const FLAG_OPTIONS: &[&str] = &[
+    "contiguous",
+    "exclusive",
+    "get-user-env",
+    "help",
+    "hold",
+    "ignore-pbs",
+    "no-kill",
+    "no-requeue",
+    "overcommit",
+    "oversubscribe",
+    "parsable",
+    "quiet",
+    "reboot",
+    "requeue",
+    "spread-job",
+    "test-only",
+    "usage",
+    "use-min-nodes",
+    "verbose",
+    "version",
+    "wait",
+];
+
+// Long names of the sbatch options that take a value
+const VALUE_OPTIONS: &[&str] = &[
+    "account",
+    "acctg-freq",
+    "array",
+    "batch",
+    "bb",
+    "bbf",
+    "begin",
+    "chdir",
+    "cluster-constraint",
+    "clusters",
+    "comment",
+    "constraint",
+    "container",
+    "container-id",
+    "core-spec",
+    "cores-per-socket",
+    "cpu-freq",
+    "cpus-per-gpu",
+    "cpus-per-task",
+    "deadline",
+    "delay-boot",
+    "dependency",
+    "distribution",
+    "error",
+    "exclude",
+    "export",
+    "export-file",
+    "extra",
+    "extra-node-info",
+    "gid",
+    "gpu-bind",
+    "gpu-freq",
+    "gpus",
+    "gpus-per-node",
+    "gpus-per-socket",
+    "gpus-per-task",
+    "gres",
+    "gres-flags",
+    "hint",
+    "input",
+    "job-name",
+    "kill-on-invalid-dep",
+    "licenses",
+    "mail-type",
+    "mail-user",
+    "mcs-label",
+    "mem",
+    "mem-bind",
+    "mem-per-cpu",
+    "mem-per-gpu",
+    "mincpus",
+    "network",
+    "nice",
+    "nodefile",
+    "nodelist",
+    "nodes",
+    "ntasks",
+    "ntasks-per-core",
+    "ntasks-per-gpu",
+    "ntasks-per-node",
+    "ntasks-per-socket",
+    "open-mode",
+    "output",
+    "partition",
+    "power",
+    "prefer",
+    "priority",
+    "profile",
+    "propagate",
+    "qos",
+    "reservation",
+    "resv-ports",
+    "segment",
+    "signal",
+    "sockets-per-node",
+    "switches",
+    "thread-spec",
+    "threads-per-core",
+    "time",
+    "time-min",
+    "tmp",
+    "tres-bind",
+    "tres-per-task",
+    "uid",
+    "wait-all-nodes",
+    "wckey",
+    "wrap",
+];
+
+// Short sbatch options and the long options they stand for
+const SHORT_OPTIONS: &[(char, &str)] = &[
+    ('A', "account"),
+    ('a', "array"),
+    ('b', "begin"),
+    ('C', "constraint"),
+    ('c', "cpus-per-task"),
+    ('D', "chdir"),
+    ('d', "dependency"),
+    ('e', "error"),
+    ('F', "nodefile"),
+    ('G', "gpus"),
+    ('H', "hold"),
+    ('h', "help"),
+    ('i', "input"),
+    ('J', "job-name"),
+    ('k', "no-kill"),
+    ('L', "licenses"),
+    ('M', "clusters"),
+    ('m', "distribution"),
+    ('N', "nodes"),
+    ('n', "ntasks"),
+    ('O', "overcommit"),
+    ('o', "output"),
+    ('p', "partition"),
+    ('Q', "quiet"),
+    ('q', "qos"),
+    ('S', "core-spec"),
+    ('s', "oversubscribe"),
+    ('t', "time"),
+    ('V', "version"),
+    ('v', "verbose"),
+    ('W', "wait"),
+    ('w', "nodelist"),
+    ('x', "exclude"),
+];
+
+// Whether sbatch knows an option (given by its long name)
+pub fn is_known_option(name: &str) -> bool {
+    VALUE_OPTIONS.contains(&name) || FLAG_OPTIONS.contains(&name)
+}
+
+// How an option is written on the command line: "--name" for long options, "-n" for single letters
+pub fn option_display_name(name: &str) -> String {
+    if name.chars().count() == 1 {
+        format!("-{}", name)
+    } else {
+        format!("--{}", name)
+    }
+}
+
+// The lines of a script that batch schedulers read directives from: everything up to the first command
+// Like sbatch, this stops at the first line that is neither blank nor a comment, so "#SBATCH" lines further down
+// (in heredocs, or commented-out examples after the first command) are ignored
+// Lines end in "\n" or, for scripts edited on Windows, "\r\n"; neither ending is part of the line
+pub fn directive_lines(content: &str) -> impl Iterator<Item = &str> {
+    content
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .take_while(|line| {
+            let line = line.trim();
+            line.is_empty() || line.starts_with('#')
+        })
+}
+
+// Whether a script has Windows ("\r\n") line endings, which sbatch refuses ("DOS line breaks") and bash trips over
+pub fn has_crlf_line_endings(content: &str) -> bool {
+    content.contains("\r\n")
+}
+
+// Split the text of a directive line into words the way sbatch does: on whitespace, except inside single or double
+// quotes (which are removed)
+// e.g.: --output="results dir/run %j.log" -J 'my job'
+//       -> ["--output=results dir/run %j.log", "-J", "my job"]
+pub fn directive_words(directive: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+
+    for c in directive.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_word = true;
+            }
+            None if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            None => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+
+    words
+}
+
+// Turn sbatch-style arguments into (long option name, value) pairs, the way sbatch's option parser reads them
+// e.g.: ["--output", "run.log", "-Jname", "--hold", "-t=10"]
+//       -> [("output", "run.log"), ("job-name", "name"), ("hold", ""), ("time", "10")]
+// Spacing around '=' is tolerated ("--output = run.log"), and a "#" word starts a trailing comment
+pub fn parse_sbatch_args(words: &[String]) -> Vec<(String, String)> {
+    let mut options = Vec::new();
+    let mut words = words.iter().map(String::as_str).peekable();
+
+    while let Some(word) = words.next() {
+        if word.starts_with('#') {
+            break;
+        }
+
+        let (name, inline_value) = if let Some(long) = word.strip_prefix("--") {
+            match long.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (long.to_string(), None),
+            }
+        } else if let Some(short) = word.strip_prefix('-') {
+            let mut chars = short.chars();
+            let Some(letter) = chars.next() else {
+                continue;
+            };
+            let name = SHORT_OPTIONS
+                .iter()
+                .find(|(short, _)| *short == letter)
+                .map_or(letter.to_string(), |(_, long)| long.to_string());
+            // "-ovalue" and "-o=value" carry their value in the same word
+            let rest = chars.as_str();
+            let rest = rest.strip_prefix('=').unwrap_or(rest);
+            (name, (!rest.is_empty()).then(|| rest.to_string()))
+        } else {
+            // Stray words (e.g. the value half of an unknown option) have no option to belong to
+            continue;
+        };
+
+        let value = match inline_value {
+            // "--output= run.log"
+            Some(value) if value.is_empty() => words.next().unwrap_or("").to_string(),
+            Some(value) => value,
+            None if FLAG_OPTIONS.contains(&name.as_str()) => String::new(),
+            None => match words.peek().copied() {
+                // "--output = run.log" and "--output =run.log"
+                Some("=") => {
+                    words.next();
+                    words.next().unwrap_or("").to_string()
+                }
+                Some(next) if next.starts_with('=') => {
+                    words.next();
+                    next[1..].to_string()
+                }
+                Some(next) if !next.starts_with('-') && !next.starts_with('#') => {
+                    words.next();
+                    next.to_string()
+                }
+                _ => String::new(),
+            },
+        };
+
+        options.push((name, value));
+    }
+
+    options
+}
+
+// The #SBATCH directives of a batch script (or of one heterogeneous job component)
+// The common options get their own fields; every directive is also kept, in order, in `options`
+// As with sbatch, a later directive overrides an earlier one for the same option
+#[derive(Debug, Default, Clone)]
+pub struct SbatchDirectives {
+    pub output: Option<String>,
+    pub error: Option<String>,
+    pub job_name: Option<String>,
+    pub array: Option<String>,
+    pub chdir: Option<String>,
+    pub time: Option<String>,
+    pub partition: Option<String>,
+    pub account: Option<String>,
+    pub qos: Option<String>,
+    pub dependency: Option<String>,
+    pub clusters: Option<String>,
+    pub nodes: Option<String>,
+    pub ntasks: Option<String>,
+    pub options: Vec<(String, String)>,
+}
+
+impl SbatchDirectives {
+    // Parse every component of a script; scripts without "#SBATCH hetjob" (or the older "#SBATCH packjob")
+    // separators have exactly one
+    pub fn parse_components(content: &str) -> Vec<SbatchDirectives> {
+        let mut components = vec![SbatchDirectives::default()];
+
+        for line in directive_lines(content) {
+            let Some(directive) = line.trim().strip_prefix("#SBATCH") else {
+                continue;
+            };
+            let words = directive_words(directive);
+
+            if matches!(
+                words.first().map(String::as_str),
+                Some("hetjob" | "packjob")
+            ) {
+                components.push(SbatchDirectives::default());
+            } else if let Some(component) = components.last_mut() {
+                for (name, value) in parse_sbatch_args(&words) {
+                    component.set(name, value);
+                }
+            }
+        }
+
+        components
+    }
+
+    // Parse a script's directives (for heterogeneous jobs, those of the first component)
+    pub fn parse(content: &str) -> SbatchDirectives {
+        Self::parse_components(content)
+            .into_iter()
+            .next()
+            .unwrap_or_default()
+    }
+
+    // Read and parse a script's directives
+    pub fn from_file(script_path: &Path) -> Result<SbatchDirectives, SlurmtailError> {
+        let content =
+            read_to_string(script_path).map_err(SlurmtailError::io("read", script_path))?;
+
+        Ok(Self::parse(&content))
+    }
+
+    // Parse sbatch command-line arguments (e.g. a pipeline job's extra sbatch_args) the same way as directives
+    pub fn from_args(args: &[String]) -> SbatchDirectives {
+        let mut directives = SbatchDirectives::default();
+        for (name, value) in parse_sbatch_args(args) {
+            directives.set(name, value);
+        }
+
+        directives
+    }
+
+    // These directives with sbatch command-line arguments on top, which win over them as they do with sbatch
+    pub fn with_args(mut self, args: &[String]) -> SbatchDirectives {
+        for (name, value) in parse_sbatch_args(args) {
+            self.set(name, value);
+        }
+
+        self
+    }
+
+    // Whether the job asks for GPUs: with --gpus (or --gpus-per-node and the like), or a GRES or TRES naming them
+    pub fn requests_gpus(&self) -> bool {
+        self.options
+            .iter()
+            .any(|(name, value)| match name.as_str() {
+                "gpus" | "gpus-per-node" | "gpus-per-socket" | "gpus-per-task" => true,
+                "gres" | "tres-per-task" => value.contains("gpu"),
+                _ => false,
+            })
+    }
+
+    // Record one directive (given by its long option name)
+    fn set(&mut self, name: String, value: String) {
+        let field = match name.as_str() {
+            "output" => Some(&mut self.output),
+            "error" => Some(&mut self.error),
+            "job-name" => Some(&mut self.job_name),
+            "array" => Some(&mut self.array),
+            "chdir" => Some(&mut self.chdir),
+            "time" => Some(&mut self.time),
+            "partition" => Some(&mut self.partition),
+            "account" => Some(&mut self.account),
+            "qos" => Some(&mut self.qos),
+            "dependency" => Some(&mut self.dependency),
+            "clusters" => Some(&mut self.clusters),
+            "nodes" => Some(&mut self.nodes),
+            "ntasks" => Some(&mut self.ntasks),
+            _ => None,
+        };
+        if let Some(field) = field
+            && !value.is_empty()
+        {
+            *field = Some(value.clone());
+        }
+
+        self.options.push((name, value));
+    }
+}
+
+// The time limit sbatch reads "UNLIMITED" (or "INFINITE", or -1) as, in minutes: SLURM's INFINITE
+pub const UNLIMITED_MINUTES: u32 = u32::MAX;
+
+/// A job's directives as the fields of a SLURM job description, for the schedulers that submit one themselves
+/// (libslurm and slurmrestd) instead of handing the script to sbatch, which would read its `#SBATCH` lines
+///
+/// Only the options a job description has a field for can be passed on; any other is refused rather than dropped:
+///
+/// ```
+/// use slurmtail::directives::{JobRequest, SbatchDirectives};
+///
+/// let directives = SbatchDirectives::parse(
+///     "#!/bin/bash\n#SBATCH -p gpu --time=1-02:30\n#SBATCH --nodes=2-4 --mem=4G\n#SBATCH --error=err_%j.log\n",
+/// );
+/// let request = JobRequest::from_directives(&directives, "libslurm").unwrap();
+/// assert_eq!(request.partition.as_deref(), Some("gpu"));
+/// assert_eq!(request.error.as_deref(), Some("err_%j.log"));
+/// assert_eq!(request.time_limit, Some(24 * 60 + 2 * 60 + 30));
+/// assert_eq!((request.min_nodes, request.max_nodes), (Some(2), Some(4)));
+/// assert_eq!(request.mem_per_node, Some(4096));
+///
+/// let directives = SbatchDirectives::parse("#!/bin/bash\n#SBATCH --gpu-bind=closest\n");
+/// let error = JobRequest::from_directives(&directives, "libslurm").unwrap_err();
+/// assert_eq!(error.to_string(), "--gpu-bind is not supported by the libslurm scheduler");
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct JobRequest {
+    pub name: Option<String>,
+    pub output: Option<String>,
+    pub error: Option<String>,
+    pub input: Option<String>,
+    pub partition: Option<String>,
+    pub account: Option<String>,
+    pub qos: Option<String>,
+    pub dependency: Option<String>,
+    pub clusters: Option<String>,
+    pub array: Option<String>,
+    pub chdir: Option<String>,
+    pub comment: Option<String>,
+    pub constraint: Option<String>,
+    pub reservation: Option<String>,
+    pub nodelist: Option<String>,
+    pub exclude: Option<String>,
+    pub licenses: Option<String>,
+    pub wckey: Option<String>,
+    pub mail_user: Option<String>,
+    /// Upper-case, e.g. ["BEGIN", "END"]
+    pub mail_type: Vec<String>,
+    /// In minutes ([`UNLIMITED_MINUTES`] for no limit)
+    pub time_limit: Option<u32>,
+    pub time_min: Option<u32>,
+    pub min_nodes: Option<u32>,
+    pub max_nodes: Option<u32>,
+    pub ntasks: Option<u32>,
+    pub ntasks_per_node: Option<u32>,
+    pub cpus_per_task: Option<u32>,
+    /// In megabytes
+    pub mem_per_node: Option<u64>,
+    pub mem_per_cpu: Option<u64>,
+    pub hold: bool,
+    pub requeue: Option<bool>,
+}
+
+impl JobRequest {
+    /// Translate directives (in order, so a later one overrides an earlier one, as with sbatch) into a job
+    /// description; options it has no field for are [`SlurmtailError::Unsupported`] by `scheduler`
+    pub fn from_directives(
+        directives: &SbatchDirectives,
+        scheduler: &str,
+    ) -> Result<JobRequest, SlurmtailError> {
+        let mut request = JobRequest::default();
+
+        for (name, value) in &directives.options {
+            let invalid = |expected: &str| {
+                SlurmtailError::Invalid(format!(
+                    "Invalid {} {:?}: expected {}",
+                    option_display_name(name),
+                    value,
+                    expected
+                ))
+            };
+
+            if let Some(field) = request.text_field(name) {
+                if !value.is_empty() {
+                    *field = Some(value.clone());
+                }
+                continue;
+            }
+
+            match name.as_str() {
+                "mail-type" => {
+                    request.mail_type = value
+                        .split(',')
+                        .map(|mail_type| mail_type.trim().to_ascii_uppercase())
+                        .filter(|mail_type| !mail_type.is_empty())
+                        .collect();
+                }
+                "time" => {
+                    request.time_limit = Some(time_minutes(value).ok_or_else(|| {
+                        invalid("MINUTES, HOURS:MINUTES:SECONDS, or DAYS-HOURS:MINUTES")
+                    })?);
+                }
+                "time-min" => {
+                    request.time_min = Some(time_minutes(value).ok_or_else(|| {
+                        invalid("MINUTES, HOURS:MINUTES:SECONDS, or DAYS-HOURS:MINUTES")
+                    })?);
+                }
+                "nodes" => {
+                    let (min, max) = match value.split_once('-') {
+                        Some((min, max)) => (min, Some(max)),
+                        None => (value.as_str(), None),
+                    };
+                    request.min_nodes = Some(min.parse().map_err(|_| invalid("MIN or MIN-MAX"))?);
+                    request.max_nodes = max
+                        .map(|max| max.parse().map_err(|_| invalid("MIN or MIN-MAX")))
+                        .transpose()?;
+                }
+                "ntasks" => request.ntasks = Some(value.parse().map_err(|_| invalid("a number"))?),
+                "ntasks-per-node" => {
+                    request.ntasks_per_node = Some(value.parse().map_err(|_| invalid("a number"))?);
+                }
+                "cpus-per-task" => {
+                    request.cpus_per_task = Some(value.parse().map_err(|_| invalid("a number"))?);
+                }
+                // --mem and --mem-per-cpu are mutually exclusive; the later one wins
+                "mem" => {
+                    request.mem_per_node =
+                        Some(megabytes(value).ok_or_else(|| invalid("a size, e.g. 500M or 4G"))?);
+                    request.mem_per_cpu = None;
+                }
+                "mem-per-cpu" => {
+                    request.mem_per_cpu =
+                        Some(megabytes(value).ok_or_else(|| invalid("a size, e.g. 500M or 4G"))?);
+                    request.mem_per_node = None;
+                }
+                "hold" => request.hold = true,
+                "requeue" => request.requeue = Some(true),
+                "no-requeue" => request.requeue = Some(false),
+                // The job is given slurmtail's whole environment either way
+                "export" if value.eq_ignore_ascii_case("ALL") => {}
+                _ => {
+                    return Err(SlurmtailError::unsupported(
+                        option_display_name(name),
+                        scheduler,
+                    ));
+                }
+            }
+        }
+
+        Ok(request)
+    }
+
+    // The field for one of the options whose value is passed on as it is
+    fn text_field(&mut self, name: &str) -> Option<&mut Option<String>> {
+        match name {
+            "job-name" => Some(&mut self.name),
+            "output" => Some(&mut self.output),
+            "error" => Some(&mut self.error),
+            "input" => Some(&mut self.input),
+            "partition" => Some(&mut self.partition),
+            "account" => Some(&mut self.account),
+            "qos" => Some(&mut self.qos),
+            "dependency" => Some(&mut self.dependency),
+            "clusters" => Some(&mut self.clusters),
+            "array" => Some(&mut self.array),
+            "chdir" => Some(&mut self.chdir),
+            "comment" => Some(&mut self.comment),
+            "constraint" => Some(&mut self.constraint),
+            "reservation" => Some(&mut self.reservation),
+            "nodelist" => Some(&mut self.nodelist),
+            "exclude" => Some(&mut self.exclude),
+            "licenses" => Some(&mut self.licenses),
+            "wckey" => Some(&mut self.wckey),
+            "mail-user" => Some(&mut self.mail_user),
+            _ => None,
+        }
+    }
+}
+
+// A time limit the way sbatch reads one, in minutes (seconds rounded up): "MINUTES", "MINUTES:SECONDS",
+// "HOURS:MINUTES:SECONDS", "DAYS-HOURS", "DAYS-HOURS:MINUTES", or "DAYS-HOURS:MINUTES:SECONDS"
+fn time_minutes(value: &str) -> Option<u32> {
+    if matches!(
+        value.to_ascii_uppercase().as_str(),
+        "-1" | "INFINITE" | "UNLIMITED"
+    ) {
+        return Some(UNLIMITED_MINUTES);
+    }
+
+    let (days, rest) = match value.split_once('-') {
+        Some((days, rest)) => (Some(days.parse::<u32>().ok()?), rest),
+        None => (None, value),
+    };
+    let parts: Vec<u32> = rest
+        .split(':')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    let (hours, minutes, seconds) = match (days.is_some(), parts.as_slice()) {
+        (false, [minutes]) => (0, *minutes, 0),
+        (false, [minutes, seconds]) => (0, *minutes, *seconds),
+        (_, [hours, minutes, seconds]) => (*hours, *minutes, *seconds),
+        (true, [hours]) => (*hours, 0, 0),
+        (true, [hours, minutes]) => (*hours, *minutes, 0),
+        _ => return None,
+    };
+
+    days.unwrap_or(0)
+        .checked_mul(24)?
+        .checked_add(hours)?
+        .checked_mul(60)?
+        .checked_add(minutes)?
+        .checked_add(seconds.div_ceil(60))
+}
+
+// A memory size the way sbatch reads one, in megabytes: a number with an optional K, M (the default), G, or T suffix
+fn megabytes(value: &str) -> Option<u64> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, "M"),
+    };
+    let number: u64 = number.parse().ok()?;
+
+    match unit.to_ascii_uppercase().as_str() {
+        "K" | "KB" => Some(number.div_ceil(1024)),
+        "M" | "MB" => Some(number),
+        "G" | "GB" => number.checked_mul(1024),
+        "T" | "TB" => number.checked_mul(1024 * 1024),
+        _ => None,
+    }
+}
@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+// Read a duration as given on the command line, in a config file, or in an environment variable: a number of seconds
+// (fractions allowed, e.g. "90" or "0.5"), or with units, e.g. "10m", "2h 30m", "1h30m", "250ms", "1.5h", or "2d"
+// (days being 24 hours)
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let invalid =
+        || "expected a number of seconds, or a duration like 90s, 10m, 2h30m, or 250ms".to_string();
+
+    let duration = match text.parse::<f64>() {
+        Ok(seconds) => return Duration::try_from_secs_f64(seconds).map_err(|_| invalid()),
+        Err(_) => text
+            .parse::<jiff::SignedDuration>()
+            .or_else(|_| {
+                text.parse::<jiff::Span>()?
+                    .to_duration(jiff::SpanRelativeTo::days_are_24_hours())
+            })
+            .map_err(|_| invalid())?,
+    };
+
+    Duration::try_from(duration).map_err(|_| invalid())
+}
+
+// A duration the way parse_duration reads it, e.g. "2h 30m", "1m 30s", or "250ms"
+pub fn format_duration(duration: Duration) -> String {
+    match jiff::SignedDuration::try_from(duration) {
+        Ok(duration) => format!("{:#}", duration),
+        Err(_) => format!("{}s", duration.as_secs()),
+    }
+}
+
+// A duration as a number of seconds in an event's fields: whole seconds as an integer, as they always were
+pub fn seconds_value(duration: Duration) -> serde_json::Value {
+    if duration.subsec_nanos() == 0 {
+        duration.as_secs().into()
+    } else {
+        duration.as_secs_f64().into()
+    }
+}
@@ -0,0 +1,175 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// With --exit-zero-on-timeout: timing out exits with 0 (see exit_code)
+static EXIT_ZERO_ON_TIMEOUT: AtomicBool = AtomicBool::new(false);
+
+/// The ways slurmtail can fail: the ones wrapper scripts may want to tell apart each have an exit code of their own
+/// (see exit_code), and the rest (scheduler, parse, and file errors, and so on) exit with 1
+#[derive(Debug, thiserror::Error)]
+pub enum SlurmtailError {
+    /// No new output for longer than the timeout
+    #[error(
+        "Timeout while monitoring - no new bytes read for {}",
+        crate::duration::format_duration(*.0)
+    )]
+    IdleTimeout(Duration),
+    /// The log file didn't appear within the timeout
+    #[error(
+        "Timeout waiting for log file (longer than {})",
+        crate::duration::format_duration(*.0)
+    )]
+    FileTimeout(Duration),
+    /// The scheduler didn't take the job
+    #[error("Submitting the job failed: {0}")]
+    SubmitFailed(String),
+    /// The job ended in a state other than COMPLETED, or wrote a line matching --fail-on
+    #[error("{0}")]
+    JobFailed(String),
+    /// The job was cancelled
+    #[error("Job {0} was cancelled")]
+    JobCancelled(u64),
+    /// Following stopped on Ctrl-C, leaving the job running
+    #[error("Interrupted; stopped following the job")]
+    Interrupted,
+    /// slurmtail was stopped by SIGHUP or SIGTERM after reading this far into the log, leaving the job running
+    #[error(
+        "Stopped by {}; `slurmtail resume` continues where this left off",
+        crate::interrupt::signal_name(*signal)
+    )]
+    Terminated { signal: i32, offset: u64 },
+    /// One of the scheduler's commands (or its API), or another command slurmtail runs (ssh, tmux, sendmail), failed,
+    /// e.g. "scancel failed: Invalid job id specified"
+    #[error("{command} failed: {message}")]
+    Scheduler { command: String, message: String },
+    /// A file slurmtail reads (a config file, manifest, or resume file) isn't in the format it should be, e.g.
+    /// "Invalid config file "~/.config/slurmtail/config.toml": ..."
+    #[error("Invalid {what} {path:?}: {message}")]
+    Parse {
+        what: &'static str,
+        path: PathBuf,
+        message: String,
+    },
+    /// Reading or writing a file failed, e.g. "Could not write the resume file "._slurmtail/1234.json": ..."
+    #[error("Could not {action} {path:?}: {source}")]
+    Io {
+        action: &'static str,
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The selected scheduler can't do what was asked, e.g. "Attaching to job steps is not supported by the pbs
+    /// scheduler"
+    #[error("{what} is not supported by the {scheduler} scheduler")]
+    Unsupported { what: String, scheduler: String },
+    /// An option, setting, or environment variable slurmtail can't use as given, e.g. "Invalid --remote "a:b:c":
+    /// expected HOST or HOST:DIR"
+    #[error("{0}")]
+    Invalid(String),
+    /// What was asked for isn't there, e.g. "No session found for job 1234"
+    #[error("{0}")]
+    NotFound(String),
+    /// Another slurmtail (this process ID) is already following the job
+    #[error("Job {job_id} is already being followed by another slurmtail (pid {pid})")]
+    AlreadyFollowed { job_id: u64, pid: String },
+    /// The submission history database couldn't be read or written
+    #[error("Submission history: {0}")]
+    History(#[from] rusqlite::Error),
+    /// Talking to the slurmtail daemon (or starting one) failed, e.g. "Lost the connection to the slurmtail daemon"
+    #[error("{0}")]
+    Daemon(String),
+    /// A --plugin couldn't be loaded
+    #[error("Could not load plugin {}: {message}", path.display())]
+    Plugin { path: PathBuf, message: String },
+    /// Several log files were followed, and following some of them failed (none with an exit code of its own)
+    #[error("Monitoring failed: {}", .0.join("; "))]
+    Followers(Vec<String>),
+}
+
+impl SlurmtailError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            SlurmtailError::IdleTimeout(_) => 10,
+            SlurmtailError::FileTimeout(_) => 11,
+            SlurmtailError::SubmitFailed(_) => 12,
+            SlurmtailError::JobFailed(_) => 13,
+            SlurmtailError::JobCancelled(_) => 14,
+            // As for any program stopped by Ctrl-C (128 + SIGINT)
+            SlurmtailError::Interrupted => 130,
+            // Likewise 128 + the signal: 129 for SIGHUP, 143 for SIGTERM
+            SlurmtailError::Terminated { signal, .. } => 128 + signal,
+            SlurmtailError::Scheduler { .. }
+            | SlurmtailError::Parse { .. }
+            | SlurmtailError::Io { .. }
+            | SlurmtailError::Unsupported { .. }
+            | SlurmtailError::Invalid(_)
+            | SlurmtailError::NotFound(_)
+            | SlurmtailError::AlreadyFollowed { .. }
+            | SlurmtailError::History(_)
+            | SlurmtailError::Daemon(_)
+            | SlurmtailError::Plugin { .. }
+            | SlurmtailError::Followers(_) => 1,
+        }
+    }
+
+    /// e.g.: SlurmtailError::scheduler("sbatch", stderr)
+    pub fn scheduler(command: &str, message: impl ToString) -> SlurmtailError {
+        SlurmtailError::Scheduler {
+            command: command.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    /// e.g.: SlurmtailError::parse("config file", path, toml_error)
+    pub fn parse(what: &'static str, path: &Path, message: impl ToString) -> SlurmtailError {
+        SlurmtailError::Parse {
+            what,
+            path: path.to_path_buf(),
+            message: message.to_string(),
+        }
+    }
+
+    /// e.g.: SlurmtailError::unsupported("Attaching to job steps", self.name())
+    pub fn unsupported(what: impl ToString, scheduler: &str) -> SlurmtailError {
+        SlurmtailError::Unsupported {
+            what: what.to_string(),
+            scheduler: scheduler.to_string(),
+        }
+    }
+
+    /// For wrapping an I/O error with what was being done to which file, e.g.:
+    /// `read_to_string(path).map_err(SlurmtailError::io("read", path))?`
+    pub fn io(action: &'static str, path: &Path) -> impl FnOnce(std::io::Error) -> SlurmtailError {
+        let path = path.to_path_buf();
+        move |source| SlurmtailError::Io {
+            action,
+            path,
+            source,
+        }
+    }
+}
+
+/// The current directory, which the project's state and the job's paths are relative to
+pub fn current_dir() -> Result<PathBuf, SlurmtailError> {
+    std::env::current_dir().map_err(SlurmtailError::io("read", Path::new(".")))
+}
+
+/// Exit with 0 after timing out (waiting for the log file, or for new output), for wrappers that expect it; the
+/// timeout is still reported
+pub fn set_exit_zero_on_timeout() {
+    EXIT_ZERO_ON_TIMEOUT.store(true, Ordering::SeqCst);
+}
+
+/// The exit code for how a command ended: 0 for success (or a timeout, with --exit-zero-on-timeout), or the error's
+/// own code
+pub fn exit_code(result: &Result<(), SlurmtailError>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(SlurmtailError::IdleTimeout(_) | SlurmtailError::FileTimeout(_))
+            if EXIT_ZERO_ON_TIMEOUT.load(Ordering::SeqCst) =>
+        {
+            0
+        }
+        Err(e) => e.exit_code(),
+    }
+}
@@ -0,0 +1,301 @@
+use regex::Regex;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::error::SlurmtailError;
+use crate::timestamps;
+
+// How often to mention lines that were filtered out, so a quiet view doesn't look like a stuck job
+const FILTERED_NOTE_INTERVAL: Duration = Duration::from_secs(30);
+
+// Which lines of the job's output are shown: with --grep, only those matching one of `include`; with --grep-v,
+// none matching one of `exclude`
+struct LineFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+static FILTER: OnceLock<LineFilter> = OnceLock::new();
+
+// A filter typed in while following (see keys), on top of --grep/--grep-v: only lines matching it are shown
+static LIVE_FILTER: Mutex<Option<Regex>> = Mutex::new(None);
+
+// With --context, how many lines before and after each line the filters let through are shown with it
+static CONTEXT: OnceLock<usize> = OnceLock::new();
+
+// With --since, lines of the job's output from before this instant (by the timestamps they start with) aren't shown
+static SINCE: OnceLock<jiff::Timestamp> = OnceLock::new();
+
+// Lines filtered out since the last note about them, and when that note was printed
+static FILTERED: Mutex<Option<(u64, Instant)>> = Mutex::new(None);
+
+// How a line of the job's output settled the outcome, so following stops early
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    // A line matched --until-pattern or --succeed-on
+    Succeeded,
+    // A line matched --fail-on
+    Failed,
+}
+
+// Lines that settle the outcome: (--until-pattern and) --succeed-on patterns, and --fail-on patterns
+struct StopPatterns {
+    succeed: Vec<Regex>,
+    fail: Vec<Regex>,
+    cancel_on_failure: bool,
+}
+
+static STOP_PATTERNS: OnceLock<StopPatterns> = OnceLock::new();
+
+// The first verdict reached (in any of the logs being followed)
+static VERDICT: OnceLock<Verdict> = OnceLock::new();
+
+// With --max-lines, how many lines of the job's output are shown before following stops, and how many have been (in
+// all the logs being followed)
+static MAX_LINES: OnceLock<u64> = OnceLock::new();
+static LINES_SHOWN: AtomicU64 = AtomicU64::new(0);
+
+fn compile(patterns: &[String], flag: &str) -> Result<Vec<Regex>, SlurmtailError> {
+    let regexes = patterns
+        .iter()
+        .map(|pattern| {
+            Regex::new(pattern).map_err(|e| {
+                SlurmtailError::Invalid(format!("Invalid {} pattern {:?}: {}", flag, pattern, e))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(regexes)
+}
+
+// Only show lines of the job's output matching one of `include` (if any are given) and none of `exclude`
+pub fn set_filter(include: &[String], exclude: &[String]) -> Result<(), SlurmtailError> {
+    if include.is_empty() && exclude.is_empty() {
+        return Ok(());
+    }
+
+    let _ = FILTER.set(LineFilter {
+        include: compile(include, "--grep")?,
+        exclude: compile(exclude, "--grep-v")?,
+    });
+
+    Ok(())
+}
+
+// Show this many lines before and after each line the filters let through, like grep -C
+pub fn set_context(lines: usize) {
+    let _ = CONTEXT.set(lines);
+}
+
+// Don't show lines of the job's output from before `since`, by their timestamps (see Context::admit)
+pub fn set_since(since: jiff::Timestamp) {
+    let _ = SINCE.set(since);
+}
+
+// Whether a line of the job's output gets through --grep/--grep-v and the live filter
+fn matches(line: &str) -> bool {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let shown_by_flags = FILTER.get().is_none_or(|filter| {
+        (filter.include.is_empty() || filter.include.iter().any(|pattern| pattern.is_match(line)))
+            && !filter.exclude.iter().any(|pattern| pattern.is_match(line))
+    });
+    let shown_live = LIVE_FILTER.lock().map_or(true, |live_filter| {
+        live_filter
+            .as_ref()
+            .is_none_or(|pattern| pattern.is_match(line))
+    });
+    shown_by_flags && shown_live
+}
+
+// Count a line that wasn't shown, noting how many there have been every so often
+fn note_filtered() {
+    if let Ok(mut filtered) = FILTERED.lock() {
+        let (count, last_note) = filtered.get_or_insert_with(|| (0, Instant::now()));
+        *count += 1;
+        if last_note.elapsed() >= FILTERED_NOTE_INTERVAL {
+            status!(
+                "[INFO] {} line{} filtered out",
+                count,
+                if *count == 1 { "" } else { "s" }
+            );
+            *count = 0;
+            *last_note = Instant::now();
+        }
+    }
+}
+
+// The lines to show once a line of the job's output has come in (see Context::admit)
+pub struct Shown {
+    // Whether lines were left out since the last ones shown, which is marked with "--", like grep does
+    pub gap: bool,
+    // The line, after the lines before it kept for --context, or nothing if it isn't shown (yet)
+    pub lines: Vec<String>,
+}
+
+// The lines around those the filters let through, for --context; one per log being followed
+#[derive(Default)]
+pub struct Context {
+    // The last lines that weren't shown, to go before the next one that is
+    before: VecDeque<String>,
+    // How many more lines are shown after the last one the filters let through
+    after: usize,
+    // Whether any line has been shown yet, and whether lines have been left out since
+    shown_any: bool,
+    skipped: bool,
+    // With --since: whether the log has got past it, and how many lines before it were left out until then
+    past_since: bool,
+    before_since: u64,
+}
+
+impl Context {
+    // Which lines to show now that this one has come in: it, if the filters let it through, along with --context
+    // lines before and after it; lines that aren't shown are counted, and every so often a note says how many
+    // there were
+    pub fn admit(&mut self, line: &str) -> Shown {
+        if self.is_before_since(line) {
+            return Shown {
+                gap: false,
+                lines: Vec::new(),
+            };
+        }
+
+        let context = CONTEXT.get().copied().unwrap_or(0);
+        let matched = matches(line);
+        if matched || self.after > 0 {
+            self.after = if matched { context } else { self.after - 1 };
+            let mut lines: Vec<String> = self.before.drain(..).collect();
+            lines.push(line.to_string());
+            let skipped = std::mem::take(&mut self.skipped);
+            let gap = context > 0 && self.shown_any && skipped;
+            self.shown_any = true;
+            return Shown { gap, lines };
+        }
+
+        self.before.push_back(line.to_string());
+        if self.before.len() > context {
+            self.before.pop_front();
+            self.skipped = true;
+            note_filtered();
+        }
+        Shown {
+            gap: false,
+            lines: Vec::new(),
+        }
+    }
+
+    // Whether a line is from before --since: it has a timestamp before then, or it has none and comes after one that
+    // has (lines before the first timestamp are shown). Logs are taken to be in order, so once a line is from after
+    // --since, so is every line after it
+    fn is_before_since(&mut self, line: &str) -> bool {
+        let Some(since) = SINCE.get().filter(|_| !self.past_since) else {
+            return false;
+        };
+
+        match timestamps::find(line) {
+            Some((_, timestamp)) if timestamp < *since => self.before_since += 1,
+            None if self.before_since > 0 => self.before_since += 1,
+            None => return false,
+            Some(_) => {
+                self.past_since = true;
+                if self.before_since > 0 {
+                    status!(
+                        "[INFO] Skipped {} line{} from before {}",
+                        self.before_since,
+                        if self.before_since == 1 { "" } else { "s" },
+                        since
+                            .to_zoned(jiff::tz::TimeZone::system())
+                            .strftime("%Y-%m-%d %H:%M:%S")
+                    );
+                }
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+// Only show lines matching `pattern` from now on (None shows everything --grep/--grep-v let through again)
+pub fn set_live_filter(pattern: Option<Regex>) {
+    if let Ok(mut live_filter) = LIVE_FILTER.lock() {
+        *live_filter = pattern;
+    }
+}
+
+// Stop following once the job writes a line matching one of these patterns: successfully for `succeed`, and
+// unsuccessfully (after cancelling the job, with `cancel_on_failure`) for `fail`
+pub fn set_stop_patterns(
+    succeed: &[String],
+    fail: &[String],
+    cancel_on_failure: bool,
+) -> Result<(), SlurmtailError> {
+    if succeed.is_empty() && fail.is_empty() {
+        return Ok(());
+    }
+
+    let _ = STOP_PATTERNS.set(StopPatterns {
+        succeed: compile(succeed, "--until-pattern/--succeed-on")?,
+        fail: compile(fail, "--fail-on")?,
+        cancel_on_failure,
+    });
+
+    Ok(())
+}
+
+// Whether a line of the job's output settles its outcome (failure wins if it matches both ways); once it has, every
+// follower stops (see stopped)
+pub fn check_line(line: &str) -> Option<Verdict> {
+    let patterns = STOP_PATTERNS.get()?;
+    let line = line.trim_end_matches(['\r', '\n']);
+
+    let verdict = if patterns.fail.iter().any(|pattern| pattern.is_match(line)) {
+        Verdict::Failed
+    } else if patterns
+        .succeed
+        .iter()
+        .any(|pattern| pattern.is_match(line))
+    {
+        Verdict::Succeeded
+    } else {
+        return None;
+    };
+    let _ = VERDICT.set(verdict);
+
+    Some(verdict)
+}
+
+// Stop following, successfully, once this many lines of the job's output have been shown
+pub fn set_max_lines(lines: u64) {
+    let _ = MAX_LINES.set(lines);
+}
+
+// How many of the `count` lines about to be shown are, with --max-lines, and whether they reach it, which settles the
+// outcome as a success (so every follower stops, see stopped)
+pub fn allow_lines(count: usize) -> (usize, bool) {
+    let Some(max_lines) = MAX_LINES.get().copied() else {
+        return (count, false);
+    };
+
+    let before = LINES_SHOWN.fetch_add(count as u64, Ordering::SeqCst);
+    let allowed = max_lines.saturating_sub(before).min(count as u64) as usize;
+    let reached = before < max_lines && before + count as u64 >= max_lines;
+    if reached {
+        let _ = VERDICT.set(Verdict::Succeeded);
+    }
+
+    (allowed, reached)
+}
+
+// Whether a job that wrote a --fail-on line should be cancelled
+pub fn cancels_on_failure() -> bool {
+    STOP_PATTERNS
+        .get()
+        .is_some_and(|patterns| patterns.cancel_on_failure)
+}
+
+// Whether a line settling the outcome has been seen, so following should stop
+pub fn stopped() -> bool {
+    VERDICT.get().is_some()
+}
@@ -0,0 +1,230 @@
+use std::fs::read_to_string;
+use std::path::Path;
+use std::process::Stdio;
+
+use crate::directives::{directive_lines, directive_words};
+use crate::error::SlurmtailError;
+use crate::remote;
+use crate::scheduler::{Scheduler, split_sbatch_override};
+
+// Flux's own default for `flux batch` output
+const DEFAULT_OUTPUT_TEMPLATE: &str = "flux-{{id}}.out";
+
+// Digits of the base58 "F58" job ID encoding Flux prints by default
+const F58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+// Parse a Flux job ID, either in decimal or in F58 form
+// e.g.: "ƒ2VeHkvZ7" or "f2VeHkvZ7" (what `flux batch` prints when the terminal can't show ƒ)
+fn parse_flux_job_id(job_id: &str) -> Option<u64> {
+    let job_id = job_id.trim();
+    if let Ok(job_id) = job_id.parse::<u64>() {
+        return Some(job_id);
+    }
+
+    let digits = job_id
+        .strip_prefix('ƒ')
+        .or_else(|| job_id.strip_prefix('f'))?;
+    if digits.is_empty() {
+        return None;
+    }
+    digits.chars().try_fold(0u64, |value, digit| {
+        let digit = F58_ALPHABET.find(digit)? as u64;
+        value.checked_mul(58)?.checked_add(digit)
+    })
+}
+
+// Find the value of an option in the "#flux:" directive lines of a script
+// e.g.: "#flux: --output=output.{{id}}.log" or "# flux: --output output.{{id}}.log"
+//       -> "output.{{id}}.log"
+fn find_flux_directive(content: &str, option: &str) -> Option<String> {
+    for line in directive_lines(content) {
+        let Some(comment) = line.trim().strip_prefix('#') else {
+            continue;
+        };
+        let comment = comment.trim_start();
+        let Some(directive) = comment
+            .get(..5)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("flux:"))
+            .map(|_| &comment[5..])
+        else {
+            continue;
+        };
+
+        let mut words = directive_words(directive).into_iter();
+        while let Some(word) = words.next() {
+            if word == option {
+                return words.next();
+            }
+            if let Some(value) = word
+                .strip_prefix(option)
+                .and_then(|rest| rest.strip_prefix('='))
+            {
+                return Some(value.to_string());
+            }
+        }
+    }
+
+    None
+}
+
+// The script's output template, rewritten so the job ID part expands to the decimal form slurmtail works with
+fn decimal_output_template(content: &str) -> String {
+    find_flux_directive(content, "--output")
+        .unwrap_or_else(|| DEFAULT_OUTPUT_TEMPLATE.to_string())
+        .replace("{{id}}", "{{id.dec}}")
+        .replace("{{id.f58}}", "{{id.dec}}")
+        .replace("{{jobid}}", "{{id.dec}}")
+}
+
+// Translate a Flux job status into the SLURM state slurmtail reasons about
+fn flux_state_to_slurm(status: &str) -> String {
+    match status {
+        "DEPEND" | "PRIORITY" | "SCHED" => "PENDING".to_string(),
+        "RUN" => "RUNNING".to_string(),
+        "CLEANUP" => "COMPLETING".to_string(),
+        "CANCELED" => "CANCELLED".to_string(),
+        // COMPLETED, FAILED, and TIMEOUT already match
+        other => other.to_string(),
+    }
+}
+
+// The Flux command-line tools: flux batch, flux jobs, flux cancel, and flux job attach
+pub struct FluxCli;
+
+impl Scheduler for FluxCli {
+    fn name(&self) -> &'static str {
+        "flux"
+    }
+
+    // Flux output templates use mustache placeholders ("{{id}}", "{{name}}") rather than %j/%x
+    fn output_pattern(&self, script_path: &Path) -> Result<String, SlurmtailError> {
+        let content =
+            read_to_string(script_path).map_err(SlurmtailError::io("read", script_path))?;
+
+        Ok(decimal_output_template(&content)
+            .replace("{{id.dec}}", "%j")
+            .replace("{{name}}", "%x"))
+    }
+
+    fn job_name(&self, script_path: &Path) -> Result<Option<String>, SlurmtailError> {
+        let content =
+            read_to_string(script_path).map_err(SlurmtailError::io("read", script_path))?;
+
+        Ok(find_flux_directive(&content, "--job-name"))
+    }
+
+    // Submit with `flux batch`, translating the sbatch-style extra arguments slurmtail uses into its options
+    fn submit(&self, script_path: &Path, extra_args: &[String]) -> Result<u64, SlurmtailError> {
+        let content =
+            read_to_string(script_path).map_err(SlurmtailError::io("read", script_path))?;
+
+        let mut batch_args: Vec<String> = Vec::new();
+        let mut output_given = false;
+        for arg in extra_args {
+            let (option, value) = split_sbatch_override(arg)?;
+            match option {
+                // Flux takes one --dependency per job ("afterok:1234:1235" -> afterok:1234, afterok:1235)
+                "dependency" => {
+                    let mut parts = value.split(':');
+                    let dependency_type = parts.next().unwrap_or("afterok");
+                    for job_id in parts {
+                        batch_args.push(format!("--dependency={}:{}", dependency_type, job_id));
+                    }
+                }
+                "partition" => batch_args.push(format!("--queue={}", value)),
+                "account" => batch_args.push(format!("--bank={}", value)),
+                "job-name" => batch_args.push(format!("--job-name={}", value)),
+                "output" => {
+                    output_given = true;
+                    batch_args.push(format!("--output={}", value));
+                }
+                "error" => batch_args.push(format!("--error={}", value)),
+                _ => {
+                    return Err(SlurmtailError::unsupported(
+                        format!("--{}", option),
+                        self.name(),
+                    ));
+                }
+            }
+        }
+
+        // Have the log named after the decimal job ID (command-line options win over the script's directives)
+        if !output_given {
+            batch_args.push(format!("--output={}", decimal_output_template(&content)));
+        }
+
+        let output = remote::Command::new("flux")
+            .arg("batch")
+            .args(&batch_args)
+            .script(script_path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(SlurmtailError::scheduler("flux batch", stderr));
+        }
+
+        // `flux batch` prints just the job ID, e.g. "ƒ2VeHkvZ7"
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        stdout
+            .split_whitespace()
+            .find_map(parse_flux_job_id)
+            .ok_or_else(|| {
+                SlurmtailError::scheduler("flux batch", "Could not extract job ID from its output")
+            })
+    }
+
+    // `flux jobs` lists any job asked for by ID, including inactive (finished) ones
+    fn job_state(&self, job_id: u64) -> Result<Option<String>, SlurmtailError> {
+        let output = remote::Command::new("flux")
+            .args(["jobs", "-n", "-o", "{status}", &job_id.to_string()])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output()?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let state = stdout
+            .lines()
+            .next()
+            .map(str::trim)
+            .filter(|status| !status.is_empty())
+            .map(flux_state_to_slurm);
+
+        Ok(state)
+    }
+
+    fn cancel(&self, job_id: u64) -> Result<(), SlurmtailError> {
+        let output = remote::Command::new("flux")
+            .args(["cancel", &job_id.to_string()])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(SlurmtailError::scheduler("flux cancel", stderr));
+        }
+
+        Ok(())
+    }
+
+    // `flux job attach` takes over the terminal, showing the job's output (from the start) until it ends; Flux jobs
+    // have no steps, so the step is ignored
+    fn attach(&self, job_id: u64, _step: u32) -> Result<(), SlurmtailError> {
+        let status = remote::Command::new("flux")
+            .args(["job", "attach", &job_id.to_string()])
+            .status()?;
+
+        if !status.success() {
+            return Err(SlurmtailError::scheduler("flux job attach", status));
+        }
+
+        Ok(())
+    }
+
+    fn attach_description(&self, job_id: u64, _step: u32) -> String {
+        format!("job {} with flux job attach", job_id)
+    }
+}
@@ -0,0 +1,312 @@
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::create_dir_all;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::error::SlurmtailError;
+use crate::state::xdg_state_root;
+
+// Name of the submission history database (in slurmtail's directory under $XDG_STATE_HOME)
+const HISTORY_FILE: &str = "history.sqlite";
+
+// How long to wait for another slurmtail that is writing to the database before giving up
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// One row per submitted job; outcome columns are filled in once slurmtail sees the job finish
+// Job IDs are stored as i64 (SQLite integers are signed). `directives` is a JSON array of [option, value] pairs, `accounting` a JSON object of the scheduler's accounting
+// fields (e.g. sacct's ExitCode and Elapsed)
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS submissions (
+    id INTEGER PRIMARY KEY,
+    scheduler TEXT NOT NULL,
+    job_id INTEGER NOT NULL,
+    cluster TEXT,
+    name TEXT,
+    script TEXT,
+    project TEXT,
+    stdout TEXT,
+    directives TEXT NOT NULL,
+    submitted_at TEXT NOT NULL,
+    final_state TEXT,
+    accounting TEXT,
+    finished_at TEXT
+);
+CREATE INDEX IF NOT EXISTS submissions_job_id ON submissions (job_id);
+";
+
+// A submission as recorded in (and read back from) the history database
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Submission {
+    pub scheduler: String,
+    pub job_id: u64,
+    pub cluster: Option<String>,
+    pub name: Option<String>,
+    pub script: Option<PathBuf>,
+    pub project: Option<PathBuf>,
+    pub stdout: Option<PathBuf>,
+    // Every directive and command-line override the job was submitted with, in order
+    pub directives: Vec<(String, String)>,
+    pub submitted_at: String,
+    pub final_state: Option<String>,
+    pub accounting: Vec<(String, String)>,
+    pub finished_at: Option<String>,
+}
+
+// Where the history database lives (None if there's no $XDG_STATE_HOME or $HOME to put it under)
+pub fn history_path() -> Option<PathBuf> {
+    Some(xdg_state_root()?.join(HISTORY_FILE))
+}
+
+fn open() -> Result<Connection, SlurmtailError> {
+    let path = history_path().ok_or_else(|| {
+        SlurmtailError::Invalid(
+            "Can't place the history database: neither $XDG_STATE_HOME nor $HOME is set"
+                .to_string(),
+        )
+    })?;
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).map_err(SlurmtailError::io("create", parent))?;
+    }
+
+    let connection = Connection::open(&path)?;
+    connection.busy_timeout(BUSY_TIMEOUT)?;
+    connection.execute_batch(SCHEMA)?;
+
+    Ok(connection)
+}
+
+// Record a freshly submitted job
+pub fn record_submission(submission: &Submission) -> Result<(), SlurmtailError> {
+    let connection = open()?;
+    connection.execute(
+        "INSERT INTO submissions
+             (scheduler, job_id, cluster, name, script, project, stdout, directives, submitted_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            submission.scheduler,
+            submission.job_id as i64,
+            submission.cluster,
+            submission.name,
+            submission
+                .script
+                .as_ref()
+                .map(|path| path.to_string_lossy().to_string()),
+            submission
+                .project
+                .as_ref()
+                .map(|path| path.to_string_lossy().to_string()),
+            submission
+                .stdout
+                .as_ref()
+                .map(|path| path.to_string_lossy().to_string()),
+            serde_json::to_string(&submission.directives)
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
+            submission.submitted_at,
+        ],
+    )?;
+
+    Ok(())
+}
+
+// Record how a job ended, on its most recent submission (job IDs are only unique per scheduler, and may be reused)
+// Returns whether the job was found in the history
+pub fn record_outcome(
+    scheduler: &str,
+    job_id: u64,
+    final_state: &str,
+    accounting: &[(String, String)],
+) -> Result<bool, SlurmtailError> {
+    let connection = open()?;
+    let accounting: serde_json::Map<String, serde_json::Value> = accounting
+        .iter()
+        .map(|(field, value)| (field.clone(), serde_json::Value::from(value.as_str())))
+        .collect();
+
+    let updated = connection.execute(
+        "UPDATE submissions SET final_state = ?3, accounting = ?4, finished_at = ?5
+         WHERE id = (SELECT max(id) FROM submissions WHERE scheduler = ?1 AND job_id = ?2)",
+        params![
+            scheduler,
+            job_id as i64,
+            final_state,
+            serde_json::Value::Object(accounting).to_string(),
+            jiff::Timestamp::now().to_string(),
+        ],
+    )?;
+
+    Ok(updated > 0)
+}
+
+// Whether the most recent submission of a job already has its outcome recorded
+pub fn has_outcome(scheduler: &str, job_id: u64) -> Result<bool, SlurmtailError> {
+    let connection = open()?;
+    let final_state: Option<Option<String>> = connection
+        .query_row(
+            "SELECT final_state FROM submissions WHERE scheduler = ?1 AND job_id = ?2
+             ORDER BY id DESC LIMIT 1",
+            params![scheduler, job_id as i64],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(matches!(final_state, Some(Some(_))))
+}
+
+// The most recent submissions, newest first
+pub fn recent_submissions(limit: usize) -> Result<Vec<Submission>, SlurmtailError> {
+    query_submissions("ORDER BY id DESC LIMIT ?1", params![limit as i64])
+}
+
+// Every submission, oldest first
+pub fn all_submissions() -> Result<Vec<Submission>, SlurmtailError> {
+    query_submissions("ORDER BY id", params![])
+}
+
+// The most recent submission of a job (job IDs may be reused, and by more than one scheduler), or the most recent
+// submission of all
+pub fn find_submission(job_id: Option<u64>) -> Result<Option<Submission>, SlurmtailError> {
+    let submissions = match job_id {
+        Some(job_id) => query_submissions(
+            "WHERE job_id = ?1 ORDER BY id DESC LIMIT 1",
+            params![job_id as i64],
+        )?,
+        None => recent_submissions(1)?,
+    };
+
+    Ok(submissions.into_iter().next())
+}
+
+// The submissions the rest of a query (its WHERE, ORDER BY, and LIMIT clauses) picks out
+fn query_submissions(
+    clauses: &str,
+    params: impl rusqlite::Params,
+) -> Result<Vec<Submission>, SlurmtailError> {
+    let Some(path) = history_path().filter(|path| path.exists()) else {
+        return Ok(Vec::new());
+    };
+    let connection = Connection::open(path)?;
+    connection.busy_timeout(BUSY_TIMEOUT)?;
+    connection.execute_batch(SCHEMA)?;
+
+    let mut statement = connection.prepare(&format!(
+        "SELECT scheduler, job_id, cluster, name, script, project, stdout, directives, submitted_at,
+                final_state, accounting, finished_at
+         FROM submissions {}",
+        clauses
+    ))?;
+    let rows = statement.query_map(params, |row| {
+        let directives: String = row.get(7)?;
+        let accounting: Option<String> = row.get(10)?;
+        Ok(Submission {
+            scheduler: row.get(0)?,
+            job_id: row.get::<_, i64>(1)? as u64,
+            cluster: row.get(2)?,
+            name: row.get(3)?,
+            script: row.get::<_, Option<String>>(4)?.map(PathBuf::from),
+            project: row.get::<_, Option<String>>(5)?.map(PathBuf::from),
+            stdout: row.get::<_, Option<String>>(6)?.map(PathBuf::from),
+            directives: serde_json::from_str(&directives).unwrap_or_default(),
+            submitted_at: row.get(8)?,
+            final_state: row.get(9)?,
+            accounting: accounting
+                .and_then(|accounting| {
+                    serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&accounting)
+                        .ok()
+                })
+                .map(|fields| {
+                    fields
+                        .into_iter()
+                        .map(|(field, value)| (field, value.as_str().unwrap_or("").to_string()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            finished_at: row.get(11)?,
+        })
+    })?;
+
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
+
+// How the runs of one script went, across the history
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ScriptReport {
+    // The script's path, or "-" for submissions that didn't record one
+    pub script: String,
+    pub runs: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    // Still in the queue, or never seen to finish
+    pub unfinished: usize,
+    // The mean run time of the runs accounting has an elapsed time for, in seconds
+    pub mean_elapsed: Option<u64>,
+    // The energy all its runs used, in joules, where the site collects it
+    pub energy: Option<u64>,
+}
+
+// Sum up the submissions per script, in the order of the scripts' paths
+pub fn report(submissions: &[Submission]) -> Vec<ScriptReport> {
+    let mut reports: BTreeMap<String, (ScriptReport, Vec<u64>)> = BTreeMap::new();
+
+    for submission in submissions {
+        let script = submission
+            .script
+            .as_ref()
+            .map_or("-".to_string(), |script| script.display().to_string());
+        let (report, elapsed) = reports.entry(script.clone()).or_insert_with(|| {
+            let report = ScriptReport {
+                script,
+                ..Default::default()
+            };
+            (report, Vec::new())
+        });
+
+        report.runs += 1;
+        match submission.final_state.as_deref() {
+            Some("COMPLETED") => report.completed += 1,
+            Some(state) if state.starts_with("CANCELLED") => report.cancelled += 1,
+            Some(_) => report.failed += 1,
+            None => report.unfinished += 1,
+        }
+        let field = |name: &str| {
+            submission
+                .accounting
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value.as_str())
+        };
+        elapsed.extend(field("Elapsed").and_then(parse_elapsed));
+        if let Some(joules) = field("ConsumedEnergyRaw").and_then(|value| value.parse::<u64>().ok())
+        {
+            *report.energy.get_or_insert(0) += joules;
+        }
+    }
+
+    reports
+        .into_values()
+        .map(|(mut report, elapsed)| {
+            report.mean_elapsed =
+                (!elapsed.is_empty()).then(|| elapsed.iter().sum::<u64>() / elapsed.len() as u64);
+            report
+        })
+        .collect()
+}
+
+// sacct's elapsed time, in seconds
+// e.g.: "1-02:03:04" -> 93784, "02:03:04" -> 7384, "03:04.512" -> 184
+fn parse_elapsed(elapsed: &str) -> Option<u64> {
+    let (days, time) = match elapsed.split_once('-') {
+        Some((days, time)) => (days.parse::<u64>().ok()?, time),
+        None => (0, elapsed),
+    };
+    let time = time.split('.').next()?;
+
+    let mut seconds = 0;
+    for part in time.split(':') {
+        seconds = seconds * 60 + part.parse::<u64>().ok()?;
+    }
+
+    Some(days * 86400 + seconds)
+}
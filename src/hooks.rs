@@ -0,0 +1,212 @@
+use regex::Regex;
+use serde_json::{Value, json};
+use std::process::Stdio;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::task::JoinHandle;
+
+use crate::config::HooksConfig;
+use crate::error::SlurmtailError;
+use crate::lifecycle::{JobEvent, Milestone};
+use crate::runtime;
+
+// How many of the log's last lines the end hooks get
+const HOOK_LINES: usize = 20;
+
+// How long slurmtail waits for hooks still running when it exits, before leaving them to finish on their own
+const EXIT_WAIT: Duration = Duration::from_secs(30);
+
+// The user's commands to run when something happens to the job (see HooksConfig)
+struct Hooks {
+    on_submit: Option<String>,
+    on_start: Option<String>,
+    on_end: Option<String>,
+    on_fail: Option<String>,
+    on_line_match: Vec<(Regex, String)>,
+}
+
+static HOOKS: OnceLock<Hooks> = OnceLock::new();
+
+// Hooks that were started and may still be running
+static RUNNING: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+
+// Run the hooks in the config file when the job is submitted, starts, writes a matching line, ends, or fails
+pub fn set_hooks(config: &HooksConfig) -> Result<(), SlurmtailError> {
+    let on_line_match = config
+        .on_line_match
+        .iter()
+        .map(|hook| {
+            let regex = Regex::new(&hook.pattern).map_err(|e| {
+                SlurmtailError::Invalid(format!(
+                    "Invalid on_line_match pattern {:?}: {}",
+                    hook.pattern, e
+                ))
+            })?;
+            Ok((regex, hook.command.clone()))
+        })
+        .collect::<Result<Vec<_>, SlurmtailError>>()?;
+
+    let _ = HOOKS.set(Hooks {
+        on_submit: config.on_submit.clone(),
+        on_start: config.on_start.clone(),
+        on_end: config.on_end.clone(),
+        on_fail: config.on_fail.clone(),
+        on_line_match,
+    });
+    Ok(())
+}
+
+// Run the hooks for an event (see output::emit): on_submit when the job was submitted, on_start when it starts
+// running, and on_end when it finishes (however it went), followed by on_fail if it failed
+pub fn run_event(kind: &str, job_id: Option<u64>, fields: &Value) {
+    let (Some(hooks), Some(job_id)) = (HOOKS.get(), job_id) else {
+        return;
+    };
+
+    match kind {
+        "submitted" => {
+            if let Some(command) = &hooks.on_submit {
+                run(
+                    "on_submit",
+                    command,
+                    json!({
+                        "event": "submit",
+                        "job_id": job_id,
+                        "name": fields.get("name"),
+                        "script": fields.get("script"),
+                        "timestamp": jiff::Timestamp::now().to_string(),
+                    }),
+                );
+            }
+        }
+        "state" => {
+            let Some(state) = fields.get("state").and_then(Value::as_str) else {
+                return;
+            };
+            let Some(milestone) = Milestone::from_state(state) else {
+                return;
+            };
+            let hooks = match milestone {
+                Milestone::Start => vec![("on_start", &hooks.on_start)],
+                Milestone::End => vec![("on_end", &hooks.on_end)],
+                Milestone::Fail => vec![("on_end", &hooks.on_end), ("on_fail", &hooks.on_fail)],
+            };
+            let commands: Vec<(&'static str, &String)> = hooks
+                .into_iter()
+                .filter_map(|(hook, command)| Some((hook, command.as_ref()?)))
+                .collect();
+            if commands.is_empty() {
+                return;
+            }
+
+            let payload = JobEvent::gather(milestone, job_id, state).payload(HOOK_LINES);
+            for (hook, command) in commands {
+                run(hook, command, payload.clone());
+            }
+        }
+        _ => {}
+    }
+}
+
+// Run the on_line_match hooks whose pattern a line of the job's output matches
+pub fn check_line(job_id: Option<u64>, source: Option<&str>, line: &str) {
+    let Some(hooks) = HOOKS.get() else {
+        return;
+    };
+    let line = line.trim_end_matches(['\r', '\n']);
+
+    for (regex, command) in &hooks.on_line_match {
+        if regex.is_match(line) {
+            run(
+                "on_line_match",
+                command,
+                json!({
+                    "event": "line_match",
+                    "job_id": job_id,
+                    "line": line,
+                    "pattern": regex.as_str(),
+                    "source": source,
+                    "timestamp": jiff::Timestamp::now().to_string(),
+                }),
+            );
+        }
+    }
+}
+
+// Wait (a while) for the hooks still running, so slurmtail exiting doesn't cut the last ones short
+pub fn wait() {
+    let Ok(mut running) = RUNNING.lock() else {
+        return;
+    };
+    let hooks = std::mem::take(&mut *running);
+    let total = hooks.len();
+    let finished = runtime::block_on(async {
+        let mut finished = 0;
+        let _ = tokio::time::timeout(EXIT_WAIT, async {
+            for hook in hooks {
+                let _ = hook.await;
+                finished += 1;
+            }
+        })
+        .await;
+        finished
+    });
+
+    let unfinished = total - finished;
+    if unfinished > 0 {
+        warning!(
+            "[WARNING] Not waiting any longer for {} hook(s) still running after {} seconds",
+            unfinished,
+            EXIT_WAIT.as_secs()
+        );
+    }
+}
+
+// Run a hook's command with sh in the background: the event goes to its stdin as JSON, and its fields (those that
+// are strings or numbers) into SLURMTAIL_* environment variables, e.g. "job_id" into SLURMTAIL_JOB_ID
+// A hook that can't be run, or that fails, is warned about, and doesn't stop slurmtail
+fn run(hook: &'static str, command: &str, payload: Value) {
+    tracing::debug!(hook, command, "Running hook");
+    let mut child = Command::new("sh");
+    child
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    if let Some(fields) = payload.as_object() {
+        for (field, value) in fields {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                Value::Number(value) => value.to_string(),
+                _ => continue,
+            };
+            child.env(format!("SLURMTAIL_{}", field.to_uppercase()), value);
+        }
+    }
+
+    let handle = runtime::spawn(async move {
+        let mut child = match child.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                warning!("[WARNING] Could not run the {} hook: {}", hook, e);
+                return;
+            }
+        };
+        // A hook is free to ignore its stdin, so a failed write is no reason to stop
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(format!("{}\n", payload).as_bytes()).await;
+        }
+        match child.wait().await {
+            Ok(status) if status.success() => {}
+            Ok(status) => warning!("[WARNING] The {} hook failed ({})", hook, status),
+            Err(e) => warning!("[WARNING] Could not wait for the {} hook: {}", hook, e),
+        }
+    });
+    if let Ok(mut running) = RUNNING.lock() {
+        running.retain(|hook| !hook.is_finished());
+        running.push(handle);
+    }
+}
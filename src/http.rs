@@ -0,0 +1,311 @@
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::{self, MissedTickBehavior};
+
+use crate::daemon::{self, Attached, Reply};
+
+// How long a client gets to send its request, before the connection is dropped
+const REQUEST_WAIT: Duration = Duration::from_secs(10);
+
+// The longest request (line and headers) read; anything longer is turned away
+const MAX_REQUEST_BYTES: usize = 16 * 1024;
+
+// How often a comment goes out on a quiet event stream, so proxies and SSH tunnels don't close it
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+// How many of a job's last lines an event stream starts with, unless it asks for others (?lines=N)
+const DEFAULT_REPLAY_LINES: usize = 1000;
+
+// A page that shows a job's output as it comes, from its event stream
+const JOB_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Job {job_id} - slurmtail</title>
+<style>
+body { font-family: sans-serif; margin: 1em; }
+#output { font-family: monospace; white-space: pre-wrap; }
+</style>
+</head>
+<body>
+<p><a href="/">All jobs</a> &middot; Job {job_id}: <b id="state">...</b></p>
+<div id="output"></div>
+<script>
+const output = document.getElementById("output");
+const state = document.getElementById("state");
+const events = new EventSource("/jobs/{job_id}/events");
+events.addEventListener("line", (event) => {
+  const follow = window.innerHeight + window.scrollY >= document.body.offsetHeight - 10;
+  output.append(event.data + "\n");
+  if (follow) window.scrollTo(0, document.body.scrollHeight);
+});
+events.addEventListener("state", (event) => { state.textContent = event.data; });
+events.addEventListener("end", (event) => {
+  state.textContent = (event.data || "UNKNOWN") + " (ended)";
+  events.close();
+});
+</script>
+</body>
+</html>
+"#;
+
+// A request slurmtail can answer
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    // Where a reconnecting event stream left off (the byte of the log its last line ended at)
+    last_event_id: Option<u64>,
+}
+
+// Answer an HTTP client of the daemon:
+//   GET /                   - a page listing the jobs the daemon follows
+//   GET /jobs               - those jobs, as JSON
+//   GET /jobs/<id>          - a page showing a job's output as it comes
+//   GET /jobs/<id>/events   - a job's output and state, as server-sent events
+//   GET /metrics            - the daemon's metrics, for Prometheus
+pub async fn answer(stream: TcpStream) -> std::io::Result<()> {
+    let mut stream = BufReader::new(stream);
+    let request = match time::timeout(REQUEST_WAIT, read_request(&mut stream)).await {
+        Ok(Ok(Some(request))) => request,
+        Ok(Ok(None)) => {
+            return respond(
+                &mut stream,
+                "400 Bad Request",
+                "text/plain",
+                "Bad request\n",
+            )
+            .await;
+        }
+        Ok(Err(e)) => return Err(e),
+        Err(_) => return Ok(()),
+    };
+    if request.method != "GET" {
+        return respond(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            "Only GET is supported\n",
+        )
+        .await;
+    }
+
+    let segments: Vec<&str> = request
+        .path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    match segments.as_slice() {
+        [] => {
+            respond(
+                &mut stream,
+                "200 OK",
+                "text/html; charset=utf-8",
+                &index_page(),
+            )
+            .await
+        }
+        ["jobs"] => {
+            let jobs = serde_json::to_string(&daemon::jobs()).map_err(std::io::Error::other)?;
+            respond(&mut stream, "200 OK", "application/json", &jobs).await
+        }
+        ["metrics"] => {
+            let metrics = daemon::metrics();
+            respond(&mut stream, "200 OK", "text/plain; version=0.0.4", &metrics).await
+        }
+        ["jobs", job_id] if job_id.parse::<u64>().is_ok() => {
+            let page = JOB_PAGE.replace("{job_id}", job_id);
+            respond(&mut stream, "200 OK", "text/html; charset=utf-8", &page).await
+        }
+        ["jobs", job_id, "events"] if job_id.parse::<u64>().is_ok() => {
+            let lines = query_value(&request.query, "lines")
+                .and_then(|lines| lines.parse().ok())
+                .unwrap_or(DEFAULT_REPLAY_LINES);
+            stream_events(
+                &mut stream,
+                job_id.parse().unwrap_or_default(),
+                request.last_event_id,
+                lines,
+            )
+            .await
+        }
+        _ => respond(&mut stream, "404 Not Found", "text/plain", "Not found\n").await,
+    }
+}
+
+// Read the request line and headers (None if they aren't a request slurmtail understands)
+async fn read_request(stream: &mut BufReader<TcpStream>) -> std::io::Result<Option<Request>> {
+    let mut head = Vec::new();
+    let mut read = 0;
+    loop {
+        let mut line = String::new();
+        let bytes = stream.read_line(&mut line).await?;
+        read += bytes;
+        if bytes == 0 || read > MAX_REQUEST_BYTES {
+            return Ok(None);
+        }
+        let line = line.trim_end_matches(['\r', '\n']).to_string();
+        if line.is_empty() {
+            break;
+        }
+        head.push(line);
+    }
+
+    let Some((request_line, headers)) = head.split_first() else {
+        return Ok(None);
+    };
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target), Some(_version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Ok(None);
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let last_event_id = headers.iter().find_map(|header| {
+        let (name, value) = header.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("last-event-id")
+            .then(|| value.trim().parse().ok())?
+    });
+
+    Ok(Some(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query.to_string(),
+        last_event_id,
+    }))
+}
+
+fn query_value<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+async fn respond<S: AsyncWriteExt + Unpin>(
+    stream: &mut S,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.flush().await
+}
+
+// Send a job's output as server-sent events: "line" events (with the byte of the log each line ends at as their ID,
+// so a reconnecting client picks up where it left off), "state" events when its state changes, and an "end" event
+// once it has ended
+async fn stream_events<S: AsyncWriteExt + Unpin>(
+    stream: &mut S,
+    job_id: u64,
+    from: Option<u64>,
+    lines: usize,
+) -> std::io::Result<()> {
+    let Some(Attached { replay, updates }) = daemon::subscribe(job_id, from, lines) else {
+        return respond(
+            stream,
+            "404 Not Found",
+            "text/plain",
+            &format!("The slurmtail daemon isn't following job {}\n", job_id),
+        )
+        .await;
+    };
+
+    stream
+        .write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        )
+        .await?;
+    for reply in &replay {
+        write_event(stream, reply).await?;
+    }
+    stream.flush().await?;
+    let Some(mut updates) = updates else {
+        return Ok(());
+    };
+
+    let mut keep_alives = time::interval_at(
+        time::Instant::now() + KEEP_ALIVE_INTERVAL,
+        KEEP_ALIVE_INTERVAL,
+    );
+    keep_alives.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = keep_alives.tick() => stream.write_all(b": keep-alive\n\n").await?,
+            reply = daemon::next_update(&mut updates) => {
+                let Some(reply) = reply else {
+                    return Ok(());
+                };
+                write_event(stream, &reply).await?;
+                if matches!(reply, Reply::End { .. }) {
+                    return stream.flush().await;
+                }
+            }
+        }
+        stream.flush().await?;
+    }
+}
+
+async fn write_event<S: AsyncWriteExt + Unpin>(
+    stream: &mut S,
+    reply: &Reply,
+) -> std::io::Result<()> {
+    let event = match reply {
+        // A line can't break an event, so a carriage return (a progress bar redrawing itself) starts a new data line
+        Reply::Line { line, end } => {
+            let data: String = line
+                .trim_end_matches(['\r', '\n'])
+                .split('\r')
+                .map(|part| format!("data: {}\n", part))
+                .collect();
+            format!("event: line\nid: {}\n{}\n", end, data)
+        }
+        Reply::State { state } => format!("event: state\ndata: {}\n\n", state),
+        Reply::End { state } => format!("event: end\ndata: {}\n\n", state.as_deref().unwrap_or("")),
+        _ => return Ok(()),
+    };
+    stream.write_all(event.as_bytes()).await
+}
+
+// The page listing the jobs the daemon follows
+fn index_page() -> String {
+    let rows: String = daemon::jobs()
+        .iter()
+        .rev()
+        .map(|job| {
+            format!(
+                "<tr><td><a href=\"/jobs/{0}\">{0}</a></td><td>{1}</td><td>{2}</td><td>{3}</td></tr>\n",
+                job.job_id,
+                escape_html(&format!(
+                    "{}{}",
+                    job.state.as_deref().unwrap_or("UNKNOWN"),
+                    if job.finished { " (finished)" } else { "" }
+                )),
+                escape_html(&job.project.display().to_string()),
+                escape_html(&job.log.display().to_string()),
+            )
+        })
+        .collect();
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>slurmtail</title>\n</head>\n<body>\n\
+         <table>\n<tr><th>Job</th><th>State</th><th>Project</th><th>Log</th></tr>\n{}</table>\n</body>\n</html>\n",
+        rows
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
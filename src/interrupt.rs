@@ -0,0 +1,160 @@
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use crate::keys;
+use crate::scheduler::scheduler;
+
+// What to do about Ctrl-C while following a job (--on-interrupt)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnInterrupt {
+    // Stop following, leaving the job running (its session stays resumable)
+    Detach,
+    // Cancel the job
+    Cancel,
+    // Ask which of the two (detaching when there's no terminal to ask on)
+    Ask,
+}
+
+// What was made of an interrupt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Detach,
+    Cancel,
+}
+
+static POLICY: OnceLock<OnInterrupt> = OnceLock::new();
+
+// How many times Ctrl-C was pressed (only counted by the signal handler, and acted on by the followers)
+static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+
+// The signal (SIGHUP or SIGTERM) that asked slurmtail to stop, if one did (0 if not)
+static TERMINATED: AtomicI32 = AtomicI32::new(0);
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+// What was decided, so every follower goes the same way and the job is only cancelled once
+static DECISION: Mutex<Option<Decision>> = Mutex::new(None);
+
+pub fn set_policy(policy: OnInterrupt) {
+    let _ = POLICY.set(policy);
+}
+
+// Take over Ctrl-C, SIGHUP (the terminal closing), and SIGTERM (a polite kill), once following starts (or keys are
+// read): the first one is handled by the followers (see decide and terminated), so they can stop cleanly; a second
+// one quits right away
+pub fn install() {
+    if INSTALLED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+        for signal in [libc::SIGHUP, libc::SIGTERM] {
+            libc::signal(
+                signal,
+                on_terminate as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+}
+
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    if INTERRUPTS.fetch_add(1, Ordering::SeqCst) >= 1 {
+        keys::restore_terminal();
+        unsafe {
+            libc::_exit(130);
+        }
+    }
+}
+
+extern "C" fn on_terminate(signal: libc::c_int) {
+    if TERMINATED.swap(signal, Ordering::SeqCst) != 0 {
+        keys::restore_terminal();
+        unsafe {
+            libc::_exit(128 + signal);
+        }
+    }
+}
+
+// Whether Ctrl-C was pressed
+pub fn interrupted() -> bool {
+    INTERRUPTS.load(Ordering::SeqCst) > 0
+}
+
+// The signal slurmtail was asked to stop with (SIGHUP or SIGTERM), if it was
+pub fn terminated() -> Option<i32> {
+    Some(TERMINATED.load(Ordering::SeqCst)).filter(|signal| *signal != 0)
+}
+
+// e.g.: "SIGTERM"
+pub fn signal_name(signal: i32) -> String {
+    match signal {
+        libc::SIGHUP => "SIGHUP".to_string(),
+        libc::SIGINT => "SIGINT".to_string(),
+        libc::SIGTERM => "SIGTERM".to_string(),
+        _ => format!("signal {}", signal),
+    }
+}
+
+// Decide what to do about Ctrl-C (asking, with --on-interrupt ask) and do it, the first time a follower asks; the
+// others get the same decision
+pub fn decide(job_id: Option<u64>) -> Decision {
+    let Ok(mut decision) = DECISION.lock() else {
+        return Decision::Detach;
+    };
+    if let Some(decision) = *decision {
+        return decision;
+    }
+
+    let policy = POLICY.get().copied().unwrap_or(OnInterrupt::Ask);
+    let decided = match (policy, job_id) {
+        (OnInterrupt::Cancel, Some(_)) => Decision::Cancel,
+        (OnInterrupt::Ask, Some(job_id)) => ask(job_id),
+        _ => Decision::Detach,
+    };
+
+    match (decided, job_id) {
+        (Decision::Cancel, Some(job_id)) => match scheduler().cancel(job_id) {
+            Ok(()) => {
+                status!("Cancelled job {}", job_id);
+                crate::output::emit("cancelled", Some(job_id), serde_json::json!({}));
+            }
+            Err(e) => warning!("[WARNING] Could not cancel job {}: {}", job_id, e),
+        },
+        (_, Some(job_id)) => status!(
+            "[INFO] Stopped following; job {} keeps running (pick it up again with `slurmtail resume`)",
+            job_id
+        ),
+        (_, None) => status!("[INFO] Stopped following"),
+    }
+
+    *decision = Some(decided);
+    decided
+}
+
+// Ask whether to detach from the job or cancel it
+fn ask(job_id: u64) -> Decision {
+    if !std::io::stdin().is_terminal() {
+        return Decision::Detach;
+    }
+    // The answer is typed as a line, not read key by key
+    keys::stop();
+
+    eprint!(
+        "\nInterrupted. Detach and leave job {} running, or cancel it? [D/c] (Ctrl-C again quits) ",
+        job_id
+    );
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return Decision::Detach;
+    }
+
+    match answer.trim().to_lowercase().as_str() {
+        "c" | "cancel" => Decision::Cancel,
+        _ => Decision::Detach,
+    }
+}
@@ -0,0 +1,358 @@
+use regex::Regex;
+use std::io::{IsTerminal, Write};
+use std::os::fd::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::unix::AsyncFd;
+use tokio::task::JoinHandle;
+
+use crate::filter;
+use crate::output;
+use crate::runtime;
+use crate::scheduler::scheduler;
+
+// How long the key reader waits for a key before checking whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// How long stop() waits for the key reader to notice it should stop
+const STOP_WAIT: Duration = Duration::from_secs(1);
+
+const ENTER: u8 = b'\n';
+const ESCAPE: u8 = 0x1b;
+const BACKSPACE: u8 = 0x7f;
+const CTRL_H: u8 = 0x08;
+
+// The terminal's settings from before keys were read one at a time, put back once slurmtail is done (or killed)
+static SAVED_TERMIOS: OnceLock<libc::termios> = OnceLock::new();
+
+// Whether keys are being read
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+// Set to make the key reader stop
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+// Set when the user pressed q, so the followers stop (the job keeps running)
+static DETACHED: AtomicBool = AtomicBool::new(false);
+
+// Whether the job's output is held back (space), and the lines held back meanwhile
+static PAUSED: AtomicBool = AtomicBool::new(false);
+static HELD: Mutex<Vec<HeldLine>> = Mutex::new(Vec::new());
+
+// A line of the job's output held back while paused, with what output::job_line was given along with it
+struct HeldLine {
+    job_id: Option<u64>,
+    source: Option<String>,
+    line: String,
+    style: Option<String>,
+}
+
+// Whether the keys have been explained yet (following may start more than once, e.g. with --retries)
+static EXPLAINED: AtomicBool = AtomicBool::new(false);
+
+// The task reading keys (see read_keys)
+static KEY_READER: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+
+// What reading a key found
+enum Input {
+    Key(u8),
+    // No key was pressed within POLL_INTERVAL
+    Nothing,
+    // stdin was closed
+    Closed,
+}
+
+// While following a job on a terminal, react to keys: space pauses and resumes the output, / sets a filter, c cancels
+// the job (after asking), and q detaches; nothing happens without a terminal (or with --tui, which has keys of its own)
+pub fn start(job_id: Option<u64>) {
+    if ACTIVE.load(Ordering::SeqCst)
+        || crate::tui::active()
+        || !std::io::stdin().is_terminal()
+        || !std::io::stderr().is_terminal()
+    {
+        return;
+    }
+    let Ok(mut key_reader) = KEY_READER.lock() else {
+        return;
+    };
+
+    // Read keys as they are pressed, without echoing them, but leave output processing (and Ctrl-C) alone so the
+    // job's output looks the same as ever
+    let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+        return;
+    }
+    let mut cbreak = saved;
+    cbreak.c_lflag &= !(libc::ICANON | libc::ECHO);
+    cbreak.c_cc[libc::VMIN] = 1;
+    cbreak.c_cc[libc::VTIME] = 0;
+    let _ = SAVED_TERMIOS.set(saved);
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &cbreak) } != 0 {
+        return;
+    }
+    // So the terminal gets its settings back however slurmtail is stopped
+    crate::interrupt::install();
+
+    STOPPING.store(false, Ordering::SeqCst);
+    ACTIVE.store(true, Ordering::SeqCst);
+    if !EXPLAINED.swap(true, Ordering::SeqCst) {
+        status!(
+            "[INFO] Keys: space pauses the output, / filters it, c cancels the job, q detaches"
+        );
+    }
+    *key_reader = Some(runtime::spawn(read_keys(job_id)));
+}
+
+// Stop reading keys and give the terminal its settings back (e.g. before asking a question, or when exiting),
+// letting out any output held back
+pub fn stop() {
+    if !ACTIVE.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    STOPPING.store(true, Ordering::SeqCst);
+    let key_reader = KEY_READER
+        .lock()
+        .ok()
+        .and_then(|mut key_reader| key_reader.take());
+    // It notices within POLL_INTERVAL (unless it is asking something, which stopping cuts short); this may be called
+    // from anywhere, async or not, so it is waited for by looking rather than awaiting it
+    if let Some(key_reader) = key_reader {
+        let deadline = Instant::now() + STOP_WAIT;
+        while !key_reader.is_finished() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        key_reader.abort();
+    }
+    restore_terminal();
+    resume_output();
+}
+
+// Whether the user pressed q to detach from the job
+pub fn detached() -> bool {
+    DETACHED.load(Ordering::SeqCst)
+}
+
+// Hold back a line of the job's output while paused (see output::job_line); returns whether it was
+pub fn hold(job_id: Option<u64>, source: Option<&str>, line: &str, style: Option<&str>) -> bool {
+    if !PAUSED.load(Ordering::SeqCst) {
+        return false;
+    }
+    let Ok(mut held) = HELD.lock() else {
+        return false;
+    };
+    // Output was resumed while waiting for the lock
+    if !PAUSED.load(Ordering::SeqCst) {
+        return false;
+    }
+
+    held.push(HeldLine {
+        job_id,
+        source: source.map(str::to_string),
+        line: line.to_string(),
+        style: style.map(str::to_string),
+    });
+    true
+}
+
+// Give the terminal its settings back (also from a signal handler, see interrupt)
+pub fn restore_terminal() {
+    if let Some(saved) = SAVED_TERMIOS.get() {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+        }
+    }
+}
+
+// Wait (briefly) for the next key
+async fn read_key(stdin: &AsyncFd<RawFd>) -> Input {
+    let mut ready = match tokio::time::timeout(POLL_INTERVAL, stdin.readable()).await {
+        Ok(Ok(ready)) => ready,
+        Ok(Err(_)) => return Input::Closed,
+        Err(_) => return Input::Nothing,
+    };
+
+    // stdin itself blocks, so it is only read once poll says there's something there
+    let read = ready.try_io(|stdin| {
+        let mut poll_fd = libc::pollfd {
+            fd: *stdin.get_ref(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut poll_fd, 1, 0) } <= 0 {
+            return Err(std::io::ErrorKind::WouldBlock.into());
+        }
+        let mut key = 0u8;
+        match unsafe { libc::read(*stdin.get_ref(), (&mut key as *mut u8).cast(), 1) } {
+            1 => Ok(Input::Key(key)),
+            _ => Ok(Input::Closed),
+        }
+    });
+    match read {
+        Ok(Ok(input)) => input,
+        Ok(Err(_)) => Input::Closed,
+        Err(_would_block) => Input::Nothing,
+    }
+}
+
+// Wait for the next key, giving up if reading keys stops meanwhile
+async fn wait_for_key(stdin: &AsyncFd<RawFd>) -> Option<u8> {
+    loop {
+        match read_key(stdin).await {
+            Input::Key(key) => return Some(key),
+            Input::Nothing if !STOPPING.load(Ordering::SeqCst) => {}
+            Input::Nothing | Input::Closed => return None,
+        }
+    }
+}
+
+// The key reader: act on keys until told to stop
+async fn read_keys(job_id: Option<u64>) {
+    let Ok(stdin) = AsyncFd::new(libc::STDIN_FILENO) else {
+        return;
+    };
+
+    while !STOPPING.load(Ordering::SeqCst) {
+        match read_key(&stdin).await {
+            Input::Key(b' ') => toggle_pause(),
+            Input::Key(b'/') => ask_filter(&stdin).await,
+            Input::Key(b'c') => match job_id {
+                Some(job_id) => ask_cancel(&stdin, job_id).await,
+                None => warning!(
+                    "[WARNING] The job's ID isn't known, so it can't be cancelled from here"
+                ),
+            },
+            Input::Key(b'q') => {
+                DETACHED.store(true, Ordering::SeqCst);
+                resume_output();
+                status!(
+                    "[INFO] Detached; the job keeps running (pick it up again with `slurmtail resume`)"
+                );
+                return;
+            }
+            Input::Key(_) | Input::Nothing => {}
+            Input::Closed => return,
+        }
+    }
+}
+
+fn toggle_pause() {
+    if PAUSED.swap(true, Ordering::SeqCst) {
+        resume_output();
+    } else {
+        status!("[INFO] Paused; the job's output is held back until you press space again");
+    }
+}
+
+// Let out the output held back while paused, in order, before any new output
+fn resume_output() {
+    let Ok(mut held) = HELD.lock() else {
+        return;
+    };
+    if !PAUSED.load(Ordering::SeqCst) {
+        return;
+    }
+
+    let lines = std::mem::take(&mut *held);
+    if !lines.is_empty() {
+        status!(
+            "[INFO] Resumed ({} line{} held back)",
+            lines.len(),
+            if lines.len() == 1 { "" } else { "s" }
+        );
+    }
+    for held_line in &lines {
+        output::show_styled_job_line(
+            held_line.job_id,
+            held_line.source.as_deref(),
+            &held_line.line,
+            held_line.style.as_deref(),
+        );
+    }
+    // Only now, so followers waiting in hold() print after the held-back lines
+    PAUSED.store(false, Ordering::SeqCst);
+}
+
+// Read a line typed after a prompt (echoing it), with output held back meanwhile; None if Escape was pressed (or
+// reading keys stopped)
+async fn prompt_line(stdin: &AsyncFd<RawFd>, prompt: &str) -> Option<String> {
+    let mut stderr = std::io::stderr();
+    let _ = write!(stderr, "{}", prompt);
+    let _ = stderr.flush();
+
+    let mut typed = Vec::new();
+    loop {
+        match wait_for_key(stdin).await? {
+            ENTER | b'\r' => break,
+            ESCAPE => {
+                let _ = writeln!(stderr);
+                return None;
+            }
+            BACKSPACE | CTRL_H => {
+                if typed.pop().is_some() {
+                    let _ = write!(stderr, "\x08 \x08");
+                }
+            }
+            key => {
+                typed.push(key);
+                let _ = stderr.write_all(&[key]);
+            }
+        }
+        let _ = stderr.flush();
+    }
+    let _ = writeln!(stderr);
+
+    Some(String::from_utf8_lossy(&typed).into_owned())
+}
+
+// Ask for a regex to filter the output with from now on (nothing clears it)
+async fn ask_filter(stdin: &AsyncFd<RawFd>) {
+    let was_paused = PAUSED.swap(true, Ordering::SeqCst);
+    let typed = prompt_line(stdin, "Filter (regex; empty to clear): ").await;
+
+    match typed.as_deref() {
+        None => {}
+        Some("") => {
+            filter::set_live_filter(None);
+            status!("[INFO] Filter cleared");
+        }
+        Some(pattern) => match Regex::new(pattern) {
+            Ok(regex) => {
+                filter::set_live_filter(Some(regex));
+                status!("[INFO] Only showing lines matching {:?}", pattern);
+            }
+            Err(e) => warning!("[WARNING] Invalid filter {:?}: {}", pattern, e),
+        },
+    }
+
+    if !was_paused {
+        resume_output();
+    }
+}
+
+// Ask whether to cancel the job, and do so if the answer is yes
+async fn ask_cancel(stdin: &AsyncFd<RawFd>, job_id: u64) {
+    let was_paused = PAUSED.swap(true, Ordering::SeqCst);
+    let mut stderr = std::io::stderr();
+    let _ = write!(stderr, "Cancel job {}? [y/N] ", job_id);
+    let _ = stderr.flush();
+    let answer = wait_for_key(stdin).await;
+    let _ = writeln!(stderr);
+
+    if matches!(answer, Some(b'y' | b'Y')) {
+        match runtime::blocking(move || scheduler().cancel(job_id).map_err(|e| e.to_string())).await
+        {
+            Ok(()) => {
+                status!("Cancelled job {}", job_id);
+                output::emit("cancelled", Some(job_id), serde_json::json!({}));
+            }
+            Err(e) => warning!("[WARNING] Could not cancel job {}: {}", job_id, e),
+        }
+    } else if answer.is_some() {
+        status!("[INFO] Not cancelling.");
+    }
+
+    if !was_paused {
+        resume_output();
+    }
+}
@@ -0,0 +1,113 @@
+//! Submit batch jobs (to SLURM, or PBS/Torque, LSF, Flux, or slurmrestd) and follow their log files as they are
+//! written: the library behind the `slurmtail` command.
+//!
+//! - [`scheduler`] submits, checks on, and cancels jobs, through whichever [`scheduler::Scheduler`] is selected
+//! - [`script`] reads a batch script's directives, and resolves its output pattern (`%j`, `%x`, `%a`, ...) into the
+//!   log file's path once the job ID is known
+//! - [`watch`] follows log files until the job is done, or times out; [`watch::submit_and_follow`] does everything
+//!   `slurmtail run` does (submit, resolve, record the session, follow)
+//! - [`state`] keeps the sessions `slurmtail resume` picks up from, and the submission history
+//! - [`output`] is where the job's lines and slurmtail's events go: stdout and stderr by default, or JSON
+//!   ([`output::set_output_format`])
+//!
+//! Errors are [`error::SlurmtailError`]s, which tell timeouts, failed jobs, scheduler commands failing, and so on
+//! apart (each with the exit code `slurmtail` gives it), e.g.:
+//!
+//! ```no_run
+//! use slurmtail::error::SlurmtailError;
+//! use slurmtail::scheduler::{self, scheduler};
+//! use slurmtail::script::{format_log_output_string, logfile_string_to_path};
+//! use slurmtail::watch::{FollowOptions, mon_logfile};
+//! use std::path::Path;
+//!
+//! fn main() -> Result<(), SlurmtailError> {
+//!     scheduler::set_scheduler_by_name(scheduler::detect_scheduler(), None, None)?;
+//!
+//!     // Submit the script, and work out where its output goes
+//!     let script_path = Path::new("train.sbatch");
+//!     let job_id = scheduler().submit(script_path, &[])?;
+//!     let pattern = scheduler().output_pattern(script_path)?;
+//!     let job_name = scheduler().job_name(script_path)?;
+//!     let filename = format_log_output_string(pattern, job_id, job_name.as_ref(), None, None);
+//!     let log_path = logfile_string_to_path(script_path, filename, true)?;
+//!
+//!     // Print its output until the job has left the queue
+//!     let options = FollowOptions {
+//!         no_file_timeout: true,
+//!         watch_job: true,
+//!         ..FollowOptions::default()
+//!     };
+//!     mon_logfile(&log_path, &options, None, Some(job_id), None)
+//! }
+//! ```
+
+#[macro_use]
+pub mod output;
+
+pub mod error;
+pub mod scheduler;
+pub mod script;
+pub mod state;
+pub mod watch;
+
+// The rest is public for the slurmtail binary, whose options configure it, rather than as part of the API
+#[doc(hidden)]
+pub mod config;
+#[doc(hidden)]
+pub mod daemon;
+#[doc(hidden)]
+pub mod diagnostics;
+#[doc(hidden)]
+pub mod directives;
+#[doc(hidden)]
+pub mod duration;
+#[doc(hidden)]
+pub mod filter;
+#[doc(hidden)]
+pub mod history;
+#[doc(hidden)]
+pub mod hooks;
+#[doc(hidden)]
+pub mod interrupt;
+#[doc(hidden)]
+pub mod keys;
+#[doc(hidden)]
+pub mod mail;
+#[doc(hidden)]
+pub mod notify;
+#[doc(hidden)]
+pub mod ntfy;
+#[doc(hidden)]
+pub mod otel;
+#[doc(hidden)]
+pub mod pager;
+#[doc(hidden)]
+pub mod pipeline;
+#[doc(hidden)]
+pub mod plugin;
+#[doc(hidden)]
+pub mod prompt;
+#[doc(hidden)]
+pub mod remote;
+#[doc(hidden)]
+pub mod runtime;
+#[doc(hidden)]
+pub mod syslog;
+#[doc(hidden)]
+pub mod timestamps;
+#[doc(hidden)]
+pub mod tmux;
+#[doc(hidden)]
+pub mod tui;
+#[doc(hidden)]
+pub mod webhook;
+
+mod flux;
+mod http;
+#[cfg(feature = "libslurm")]
+mod libslurm;
+mod lifecycle;
+mod lsf;
+mod pbs;
+mod progress;
+mod rest;
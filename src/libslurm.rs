@@ -0,0 +1,229 @@
+use std::env;
+use std::ffi::{CStr, CString, c_char};
+use std::fs::read_to_string;
+use std::path::Path;
+use std::ptr;
+
+use crate::error::{SlurmtailError, current_dir};
+use crate::scheduler::{Scheduler, job_request};
+
+#[allow(
+    non_upper_case_globals,
+    non_camel_case_types,
+    non_snake_case,
+    dead_code,
+    clippy::all
+)]
+mod ffi {
+    include!(concat!(env!("OUT_DIR"), "/slurm_bindings.rs"));
+}
+
+// A string to hand to libslurm, which can't take one with a NUL byte in it
+fn c_string(value: String) -> Result<CString, SlurmtailError> {
+    CString::new(value)
+        .map_err(|e| SlurmtailError::Invalid(format!("Can't pass this to libslurm: {}", e)))
+}
+
+// Backend that calls into libslurm directly instead of running sbatch/squeue/scancel
+pub struct LibSlurm;
+
+impl LibSlurm {
+    // Load the SLURM configuration (from the usual slurm.conf lookup) so later calls can reach slurmctld
+    pub fn init() -> LibSlurm {
+        unsafe { ffi::slurm_init(ptr::null()) };
+        LibSlurm
+    }
+
+    fn last_error() -> String {
+        unsafe {
+            CStr::from_ptr(ffi::slurm_strerror(ffi::slurm_get_errno()))
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+}
+
+impl Scheduler for LibSlurm {
+    fn name(&self) -> &'static str {
+        "libslurm"
+    }
+
+    // Submit a batch script, translating its directives (and the sbatch-style extra arguments) into job description fields
+    fn submit(&self, script_path: &Path, extra_args: &[String]) -> Result<u64, SlurmtailError> {
+        let script = c_string(
+            read_to_string(script_path).map_err(SlurmtailError::io("read", script_path))?,
+        )?;
+        let work_dir = c_string(current_dir()?.to_string_lossy().into_owned())?;
+
+        // The job gets the environment we hand it, like sbatch's default --export=ALL
+        let environment: Vec<CString> = env::vars()
+            .filter_map(|(k, v)| CString::new(format!("{}={}", k, v)).ok())
+            .collect();
+        let mut environment_ptrs: Vec<*mut c_char> = environment
+            .iter()
+            .map(|var| var.as_ptr() as *mut c_char)
+            .collect();
+        environment_ptrs.push(ptr::null_mut());
+
+        // libslurm doesn't read #SBATCH lines, so the script's directives are translated into the job description
+        let request = job_request(script_path, extra_args, self.name())?;
+
+        let mut desc: ffi::job_desc_msg_t = unsafe { std::mem::zeroed() };
+        unsafe { ffi::slurm_init_job_desc_msg(&mut desc) };
+
+        desc.script = script.as_ptr() as *mut c_char;
+        desc.work_dir = work_dir.as_ptr() as *mut c_char;
+        desc.environment = environment_ptrs.as_mut_ptr();
+        desc.env_size = environment.len() as u32;
+        desc.user_id = unsafe { libc::getuid() };
+        desc.group_id = unsafe { libc::getgid() };
+
+        // The strings handed to libslurm have to outlive the submission
+        let mut strings: Vec<CString> = Vec::new();
+        let mut c_field = |value: &Option<String>| -> Result<*mut c_char, SlurmtailError> {
+            let Some(value) = value else {
+                return Ok(ptr::null_mut());
+            };
+            let value = c_string(value.clone())?;
+            let pointer = value.as_ptr() as *mut c_char;
+            strings.push(value);
+            Ok(pointer)
+        };
+        desc.name = c_field(&request.name)?;
+        desc.std_out = c_field(&request.output)?;
+        desc.std_err = c_field(&request.error)?;
+        desc.std_in = c_field(&request.input)?;
+        desc.partition = c_field(&request.partition)?;
+        desc.account = c_field(&request.account)?;
+        desc.qos = c_field(&request.qos)?;
+        desc.dependency = c_field(&request.dependency)?;
+        desc.clusters = c_field(&request.clusters)?;
+        desc.array_inx = c_field(&request.array)?;
+        desc.comment = c_field(&request.comment)?;
+        desc.features = c_field(&request.constraint)?;
+        desc.reservation = c_field(&request.reservation)?;
+        desc.req_nodes = c_field(&request.nodelist)?;
+        desc.exc_nodes = c_field(&request.exclude)?;
+        desc.licenses = c_field(&request.licenses)?;
+        desc.wckey = c_field(&request.wckey)?;
+        desc.mail_user = c_field(&request.mail_user)?;
+        if request.chdir.is_some() {
+            desc.work_dir = c_field(&request.chdir)?;
+        }
+
+        for mail_type in &request.mail_type {
+            desc.mail_type |= match mail_type.as_str() {
+                "NONE" => 0,
+                "BEGIN" => ffi::MAIL_JOB_BEGIN,
+                "END" => ffi::MAIL_JOB_END,
+                "FAIL" => ffi::MAIL_JOB_FAIL,
+                "REQUEUE" => ffi::MAIL_JOB_REQUEUE,
+                "ALL" => {
+                    ffi::MAIL_JOB_BEGIN
+                        | ffi::MAIL_JOB_END
+                        | ffi::MAIL_JOB_FAIL
+                        | ffi::MAIL_JOB_REQUEUE
+                }
+                _ => {
+                    return Err(SlurmtailError::unsupported(
+                        format!("--mail-type={}", mail_type),
+                        self.name(),
+                    ));
+                }
+            } as u16;
+        }
+        if let Some(minutes) = request.time_limit {
+            desc.time_limit = minutes;
+        }
+        if let Some(minutes) = request.time_min {
+            desc.time_min = minutes;
+        }
+        if let Some(nodes) = request.min_nodes {
+            desc.min_nodes = nodes;
+        }
+        if let Some(nodes) = request.max_nodes {
+            desc.max_nodes = nodes;
+        }
+        if let Some(tasks) = request.ntasks {
+            desc.num_tasks = tasks;
+        }
+        if let Some(tasks) = request.ntasks_per_node {
+            desc.ntasks_per_node = tasks as u16;
+        }
+        if let Some(cpus) = request.cpus_per_task {
+            desc.cpus_per_task = cpus as u16;
+        }
+        if let Some(megabytes) = request.mem_per_node {
+            desc.pn_min_memory = megabytes;
+        }
+        if let Some(megabytes) = request.mem_per_cpu {
+            desc.pn_min_memory = megabytes | ffi::MEM_PER_CPU;
+        }
+        // A held job is one with priority 0
+        if request.hold {
+            desc.priority = 0;
+        }
+        if let Some(requeue) = request.requeue {
+            desc.requeue = requeue as u16;
+        }
+
+        let mut response: *mut ffi::submit_response_msg_t = ptr::null_mut();
+        let rc = unsafe { ffi::slurm_submit_batch_job(&mut desc, &mut response) };
+        if rc != 0 || response.is_null() {
+            return Err(SlurmtailError::scheduler(
+                "slurm_submit_batch_job",
+                Self::last_error(),
+            ));
+        }
+
+        let job_id = unsafe { (*response).job_id };
+        unsafe { ffi::slurm_free_submit_response_response_msg(response) };
+
+        Ok(job_id as u64)
+    }
+
+    // Current state of a job (e.g. "RUNNING"), or None if slurmctld no longer knows about it
+    fn job_state(&self, job_id: u64) -> Result<Option<String>, SlurmtailError> {
+        let mut info: *mut ffi::job_info_msg_t = ptr::null_mut();
+        let rc = unsafe { ffi::slurm_load_job(&mut info, job_id as u32, ffi::SHOW_ALL as u16) };
+        if rc != 0 {
+            if unsafe { ffi::slurm_get_errno() } == ffi::ESLURM_INVALID_JOB_ID as i32 {
+                return Ok(None);
+            }
+            return Err(SlurmtailError::scheduler(
+                "slurm_load_job",
+                Self::last_error(),
+            ));
+        }
+
+        let state = unsafe {
+            if (*info).record_count == 0 {
+                None
+            } else {
+                let job = &*(*info).job_array;
+                let base_state = job.job_state & ffi::JOB_STATE_BASE;
+                Some(
+                    CStr::from_ptr(ffi::slurm_job_state_string(base_state))
+                        .to_string_lossy()
+                        .into_owned(),
+                )
+            }
+        };
+        unsafe { ffi::slurm_free_job_info_msg(info) };
+
+        Ok(state)
+    }
+
+    // Cancel a job (like a plain scancel, which sends SIGKILL)
+    fn cancel(&self, job_id: u64) -> Result<(), SlurmtailError> {
+        let rc = unsafe { ffi::slurm_kill_job(job_id as u32, libc::SIGKILL as u16, 0) };
+        if rc != 0 {
+            return Err(SlurmtailError::scheduler(
+                "slurm_kill_job",
+                Self::last_error(),
+            ));
+        }
+
+        Ok(())
+    }
+}
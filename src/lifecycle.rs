@@ -0,0 +1,177 @@
+use serde_json::{Value, json};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+use crate::scheduler::{is_active_state, is_failed_state, scheduler};
+use crate::{remote, state};
+
+// How much of the end of the log is read to find its last lines
+const TAIL_BYTES: u64 = 64 * 1024;
+
+// A point in a job's life worth telling someone about (see notify and webhook)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Milestone {
+    // The job started running
+    Start,
+    // The job finished without failing (completed, or was cancelled)
+    End,
+    // The job finished because something went wrong (see scheduler::is_failed_state)
+    Fail,
+}
+
+impl Milestone {
+    // The milestone a job reached by changing to this state (see output::observe_state), if any
+    pub fn from_state(state: &str) -> Option<Milestone> {
+        match state {
+            "RUNNING" => Some(Milestone::Start),
+            _ if is_active_state(state) => None,
+            _ if is_failed_state(state) => Some(Milestone::Fail),
+            _ => Some(Milestone::End),
+        }
+    }
+
+    // e.g.: "fail" -> Milestone::Fail
+    pub fn from_name(name: &str) -> Option<Milestone> {
+        match name {
+            "start" => Some(Milestone::Start),
+            "end" => Some(Milestone::End),
+            "fail" => Some(Milestone::Fail),
+            _ => None,
+        }
+    }
+
+    // Whether someone who asked for these milestones wants to hear about this one; asking for "end" means hearing
+    // about the job finishing however it went, failures included
+    pub fn wanted_by(self, wanted: &[Milestone]) -> bool {
+        wanted.contains(&self) || (self == Milestone::Fail && wanted.contains(&Milestone::End))
+    }
+
+    // As configured and reported: "start", "end", or "fail"
+    pub fn name(self) -> &'static str {
+        match self {
+            Milestone::Start => "start",
+            Milestone::End => "end",
+            Milestone::Fail => "fail",
+        }
+    }
+}
+
+// What is known about a job when it reaches a milestone
+#[derive(Debug, Clone)]
+pub struct JobEvent {
+    pub milestone: Milestone,
+    pub job_id: u64,
+    // The session's name (from `run --name`), or else the job's name from its script
+    pub name: Option<String>,
+    pub state: String,
+    // e.g. "0:0" (exit code and signal, as sacct reports it), once the job has finished
+    pub exit_code: Option<String>,
+    pub log_path: Option<PathBuf>,
+    // The scheduler's accounting for the job (elapsed time, memory, ...), once it has finished
+    pub accounting: Vec<(String, String)>,
+}
+
+impl JobEvent {
+    // Look up the rest of what there is to say about the job: its name and log from its session, and its exit code
+    // from the scheduler's accounting once it has finished
+    pub fn gather(milestone: Milestone, job_id: u64, state: &str) -> JobEvent {
+        let session = std::env::current_dir()
+            .ok()
+            .and_then(|current_dir| state::load_job_session(&current_dir, job_id).ok());
+        let name = session.as_ref().and_then(|session| {
+            session.name.clone().or_else(|| {
+                let script = session.script.as_ref()?;
+                scheduler().job_name(script).ok().flatten()
+            })
+        });
+        let accounting = match milestone {
+            Milestone::Start => Vec::new(),
+            Milestone::End | Milestone::Fail => {
+                scheduler().job_accounting(job_id).unwrap_or_default()
+            }
+        };
+        let exit_code = accounting
+            .iter()
+            .find_map(|(field, value)| (field == "ExitCode").then(|| value.clone()));
+
+        JobEvent {
+            milestone,
+            job_id,
+            name,
+            state: state.to_string(),
+            exit_code,
+            log_path: session.map(|session| session.stdout),
+            accounting,
+        }
+    }
+
+    // A one-line summary, e.g.: "Job 1234 (train) finished: COMPLETED"
+    pub fn headline(&self) -> String {
+        let job = match &self.name {
+            Some(name) => format!("Job {} ({})", self.job_id, name),
+            None => format!("Job {}", self.job_id),
+        };
+        match self.milestone {
+            Milestone::Start => format!("{} started", job),
+            Milestone::End | Milestone::Fail => format!("{} finished: {}", job, self.state),
+        }
+    }
+
+    // The last lines of the job's log (fewer if it is shorter, none if it can't be read)
+    pub fn last_lines(&self, count: usize) -> Vec<String> {
+        let Some(mut file) = self
+            .log_path
+            .as_ref()
+            .and_then(|path| File::open(remote::local_copy(path)).ok())
+        else {
+            return Vec::new();
+        };
+        let size = file.metadata().map_or(0, |metadata| metadata.len());
+        let start = size.saturating_sub(TAIL_BYTES);
+        let mut tail = Vec::new();
+        if file.seek(SeekFrom::Start(start)).is_err() || file.read_to_end(&mut tail).is_err() {
+            return Vec::new();
+        }
+
+        let tail = String::from_utf8_lossy(&tail);
+        let mut lines: Vec<&str> = tail.lines().collect();
+        // The first line is probably cut off, unless the whole log was read
+        if start > 0 && !lines.is_empty() {
+            lines.remove(0);
+        }
+        lines[lines.len().saturating_sub(count)..]
+            .iter()
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    // The event as JSON, with the log's last `lines` lines
+    // e.g.: {"event":"end","job_id":1234,"name":"train","state":"COMPLETED","exit_code":"0:0",
+    //        "log_path":"/home/me/train.1234.log","lines":["epoch 10 done"],"timestamp":"2024-05-01T12:00:00Z"}
+    pub fn payload(&self, lines: usize) -> Value {
+        json!({
+            "event": self.milestone.name(),
+            "job_id": self.job_id,
+            "name": self.name,
+            "state": self.state,
+            "exit_code": self.exit_code,
+            "log_path": self.log_path,
+            "lines": self.last_lines(lines),
+            "timestamp": jiff::Timestamp::now().to_string(),
+        })
+    }
+}
+
+// When the scheduler's accounting says something happened to the job (its "Start" or "End" field), once it has
+// sacct reports times on the cluster's clock without a time zone, taken to be this machine's
+pub fn accounting_time(accounting: &[(String, String)], field: &str) -> Option<jiff::Timestamp> {
+    let (_, time) = accounting.iter().find(|(name, _)| name == field)?;
+    let time: jiff::civil::DateTime = time.parse().ok()?;
+
+    Some(
+        time.to_zoned(jiff::tz::TimeZone::system())
+            .ok()?
+            .timestamp(),
+    )
+}
@@ -0,0 +1,229 @@
+use std::fs::{File, read_to_string};
+use std::path::Path;
+use std::process::Stdio;
+
+use crate::directives::{directive_lines, directive_words};
+use crate::error::SlurmtailError;
+use crate::remote;
+use crate::scheduler::{Scheduler, split_sbatch_override};
+
+// Output file slurmtail asks for when a script doesn't name one (LSF would otherwise email the output)
+const DEFAULT_OUTPUT_PATTERN: &str = "lsf-%J.out";
+
+// Find the value of a flag (e.g. "-o") in the #BSUB lines of a script
+// e.g.: "#BSUB -o output.%J.log"
+//       -> "output.%J.log"
+fn find_bsub_directive(content: &str, flag: &str) -> Option<String> {
+    for line in directive_lines(content) {
+        let Some(directive) = line.trim().strip_prefix("#BSUB") else {
+            continue;
+        };
+
+        let mut words = directive_words(directive).into_iter();
+        while let Some(word) = words.next() {
+            if word == flag {
+                return words.next();
+            }
+        }
+    }
+
+    None
+}
+
+// The output file named in the script ("-oo" overwrites instead of appending, but names a file all the same)
+fn script_output(content: &str) -> Option<String> {
+    find_bsub_directive(content, "-o").or_else(|| find_bsub_directive(content, "-oo"))
+}
+
+// An LSF output path as the sbatch-style pattern slurmtail resolves log paths from: LSF only fills in %J (the job ID)
+// and %I (the array index), so any other '%' is written as it is
+// e.g.: "out.%J.%x.log" -> "out.%j.%%x.log"
+fn sbatch_pattern(lsf_path: &str) -> String {
+    let mut pattern = String::new();
+    let mut chars = lsf_path.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('%', Some('J')) => {
+                chars.next();
+                pattern.push_str("%j");
+            }
+            ('%', Some('I')) => {
+                chars.next();
+                pattern.push_str("%a");
+            }
+            ('%', _) => pattern.push_str("%%"),
+            (c, _) => pattern.push(c),
+        }
+    }
+
+    pattern
+}
+
+// Translate an LSF dependency into the `bsub -w` expression for a SLURM-style one
+// e.g.: "afterok:1234:1235" -> "done(1234) && done(1235)"
+fn lsf_dependency(dependency: &str) -> Result<String, SlurmtailError> {
+    let mut parts = dependency.split(':');
+    let condition = match parts.next().unwrap_or("") {
+        "afterok" => "done",
+        "afternotok" => "exit",
+        "afterany" => "ended",
+        "after" => "started",
+        other => {
+            return Err(SlurmtailError::unsupported(
+                format!("The dependency type {}", other),
+                "lsf",
+            ));
+        }
+    };
+
+    Ok(parts
+        .map(|job_id| format!("{}({})", condition, job_id))
+        .collect::<Vec<_>>()
+        .join(" && "))
+}
+
+// Translate an LSF job status into the SLURM state slurmtail reasons about
+fn lsf_state_to_slurm(stat: &str) -> String {
+    match stat {
+        "PEND" => "PENDING".to_string(),
+        "RUN" => "RUNNING".to_string(),
+        "PSUSP" | "USUSP" | "SSUSP" => "SUSPENDED".to_string(),
+        "DONE" => "COMPLETED".to_string(),
+        // Killed jobs end up in EXIT too
+        "EXIT" => "FAILED".to_string(),
+        other => other.to_string(),
+    }
+}
+
+// IBM Spectrum LSF command-line tools: bsub, bjobs, bkill, and bpeek
+pub struct LsfCli;
+
+impl Scheduler for LsfCli {
+    fn name(&self) -> &'static str {
+        "lsf"
+    }
+
+    fn output_pattern(&self, script_path: &Path) -> Result<String, SlurmtailError> {
+        let content =
+            read_to_string(script_path).map_err(SlurmtailError::io("read", script_path))?;
+        let output = script_output(&content).unwrap_or_else(|| DEFAULT_OUTPUT_PATTERN.to_string());
+
+        Ok(sbatch_pattern(&output))
+    }
+
+    // Array jobs carry their index range in the name ("name[1-10]"), which isn't part of the name itself
+    fn job_name(&self, script_path: &Path) -> Result<Option<String>, SlurmtailError> {
+        let content =
+            read_to_string(script_path).map_err(SlurmtailError::io("read", script_path))?;
+
+        Ok(find_bsub_directive(&content, "-J")
+            .map(|name| name.split('[').next().unwrap_or("").to_string()))
+    }
+
+    // Submit with bsub, translating the sbatch-style extra arguments slurmtail uses into bsub options
+    fn submit(&self, script_path: &Path, extra_args: &[String]) -> Result<u64, SlurmtailError> {
+        let mut bsub_args: Vec<String> = Vec::new();
+        for arg in extra_args {
+            let (option, value) = split_sbatch_override(arg)?;
+            match option {
+                "dependency" => bsub_args.extend(["-w".to_string(), lsf_dependency(value)?]),
+                "partition" => bsub_args.extend(["-q".to_string(), value.to_string()]),
+                "account" => bsub_args.extend(["-P".to_string(), value.to_string()]),
+                "job-name" => bsub_args.extend(["-J".to_string(), value.to_string()]),
+                "output" => bsub_args.extend(["-o".to_string(), value.to_string()]),
+                "error" => bsub_args.extend(["-e".to_string(), value.to_string()]),
+                _ => {
+                    return Err(SlurmtailError::unsupported(
+                        format!("--{}", option),
+                        self.name(),
+                    ));
+                }
+            }
+        }
+
+        // Make sure the output ends up in a file we can follow
+        let content =
+            read_to_string(script_path).map_err(SlurmtailError::io("read", script_path))?;
+        if script_output(&content).is_none() && !bsub_args.iter().any(|arg| arg == "-o") {
+            bsub_args.extend(["-o".to_string(), DEFAULT_OUTPUT_PATTERN.to_string()]);
+        }
+
+        // bsub only reads the #BSUB directives of scripts fed to it on stdin
+        let output = remote::Command::new("bsub")
+            .args(&bsub_args)
+            .stdin(File::open(script_path).map_err(SlurmtailError::io("read", script_path))?)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(SlurmtailError::scheduler("bsub", stderr));
+        }
+
+        // "Job <1234> is submitted to default queue <normal>."
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        stdout
+            .split_whitespace()
+            .find_map(|word| {
+                word.strip_prefix('<')
+                    .and_then(|word| word.strip_suffix('>'))
+                    .and_then(|job_id| job_id.parse::<u64>().ok())
+            })
+            .ok_or_else(|| {
+                SlurmtailError::scheduler("bsub", "Could not extract job ID from its output")
+            })
+    }
+
+    // `bjobs -a` also lists recently finished jobs; STAT is the third column
+    fn job_state(&self, job_id: u64) -> Result<Option<String>, SlurmtailError> {
+        let output = remote::Command::new("bjobs")
+            .args(["-a", "-noheader", &job_id.to_string()])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output()?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let state = stdout
+            .lines()
+            .next()
+            .and_then(|line| line.split_whitespace().nth(2))
+            .map(lsf_state_to_slurm);
+
+        Ok(state)
+    }
+
+    fn cancel(&self, job_id: u64) -> Result<(), SlurmtailError> {
+        let output = remote::Command::new("bkill")
+            .arg(job_id.to_string())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(SlurmtailError::scheduler("bkill", stderr));
+        }
+
+        Ok(())
+    }
+
+    // LSF spools a running job's output on its execution host until the job ends; `bpeek -f` follows it there (as
+    // `tail -f` would) and returns once the job is done. LSF jobs have no steps, so the step is ignored
+    fn attach(&self, job_id: u64, _step: u32) -> Result<(), SlurmtailError> {
+        let status = remote::Command::new("bpeek")
+            .args(["-f", &job_id.to_string()])
+            .status()?;
+
+        if !status.success() {
+            return Err(SlurmtailError::scheduler("bpeek", status));
+        }
+
+        Ok(())
+    }
+
+    fn attach_description(&self, job_id: u64, _step: u32) -> String {
+        format!("the output of job {} with bpeek", job_id)
+    }
+}
@@ -0,0 +1,126 @@
+use serde_json::Value;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use crate::error::SlurmtailError;
+use crate::lifecycle::{JobEvent, Milestone};
+use crate::{output, runtime};
+
+// How many of the log's last lines the email includes
+const MAIL_LINES: usize = 50;
+
+// Where sendmail lives when it isn't on the PATH (it often is only in sbin)
+const SENDMAIL_PATHS: &[&str] = &["sendmail", "/usr/sbin/sendmail", "/usr/lib/sendmail"];
+
+static MAIL_TO: OnceLock<Vec<String>> = OnceLock::new();
+
+// Email a summary to these addresses once the job has ended (--mail-to)
+pub fn set_mail_to(addresses: Vec<String>) {
+    let _ = MAIL_TO.set(addresses);
+}
+
+// Send the summary for an event (see output::emit), if it is the job ending, in the background (see runtime::deliver)
+pub fn mail_event(kind: &str, job_id: Option<u64>, fields: &Value) {
+    let Some(addresses) = MAIL_TO.get().filter(|addresses| !addresses.is_empty()) else {
+        return;
+    };
+    let (Some(job_id), Some(state)) = (job_id, fields.get("state").and_then(Value::as_str)) else {
+        return;
+    };
+    let Some(milestone) = Milestone::from_state(state)
+        .filter(|milestone| kind == "state" && *milestone != Milestone::Start)
+    else {
+        return;
+    };
+
+    let state = state.to_string();
+    runtime::deliver(move || mail(addresses, milestone, job_id, &state));
+}
+
+fn mail(addresses: &[String], milestone: Milestone, job_id: u64, state: &str) {
+    let event = JobEvent::gather(milestone, job_id, state);
+    if let Err(e) = send(&compose(addresses, &event)) {
+        warning!(
+            "[WARNING] Could not email the summary of job {} to {}: {}",
+            job_id,
+            addresses.join(", "),
+            e
+        );
+    }
+}
+
+// The email, headers and all: what the job was, how it ended, its accounting, and the end of its log
+fn compose(addresses: &[String], event: &JobEvent) -> String {
+    let job = match &event.name {
+        Some(name) => format!("Job {} ({})", event.job_id, name),
+        None => format!("Job {}", event.job_id),
+    };
+
+    let mut message = format!(
+        "To: {}\nSubject: {} finished: {}\nX-Mailer: slurmtail\nContent-Type: text/plain; charset=utf-8\n\n",
+        addresses.join(", "),
+        job,
+        event.state
+    );
+    message.push_str(&format!("{} finished: {}\n\n", job, event.state));
+    message.push_str(&output::accounting_summary(&event.accounting));
+    if let Some(log_path) = &event.log_path {
+        message.push_str(&format!("{:<10} {}\n", "Log:", log_path.display()));
+    }
+
+    let lines = event.last_lines(MAIL_LINES);
+    if !lines.is_empty() {
+        message.push_str(&format!("\nLast {} lines of the log:\n\n", lines.len()));
+        for line in lines {
+            message.push_str(&line);
+            message.push('\n');
+        }
+    }
+    message
+}
+
+// Hand the email to the local mail system, which reads the recipients from its headers
+fn send(message: &str) -> Result<(), SlurmtailError> {
+    let mut last_error = None;
+    for sendmail in SENDMAIL_PATHS {
+        let child = Command::new(sendmail)
+            .args(["-t", "-oi"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                last_error = Some(e);
+                continue;
+            }
+        };
+
+        tracing::debug!(sendmail, "Sending the job's summary by email");
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(message.as_bytes())
+                .map_err(|e| SlurmtailError::scheduler(sendmail, e))?;
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|e| SlurmtailError::scheduler(sendmail, e))?;
+        if !output.status.success() {
+            return Err(SlurmtailError::scheduler(
+                sendmail,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            ));
+        }
+        return Ok(());
+    }
+
+    Err(SlurmtailError::scheduler(
+        "sendmail",
+        match last_error {
+            Some(e) => format!("no sendmail found ({})", e),
+            None => "no sendmail found".to_string(),
+        },
+    ))
+}
@@ -1,317 +1,467 @@
-use clap::{Arg, Command};
-use jiff::{Unit, Zoned};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgMatches, Command};
 use std::env;
-use std::fs::{File, read_to_string};
-use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::io::{IsTerminal, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Command as ProcessCommand, Stdio};
-use std::thread::sleep;
 use std::time::Duration;
 
-// Function responsible for monitoring ('tailing') a log file given to it
-fn mon_logfile(
-    log_path: &Path,
-    file_appear_timeout_s: Option<u32>,
-    timeout_s: Option<u32>,
-    no_file_timeout: bool,
-    no_bytes_timeout: bool,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Handle args
-    let file_appear_timeout = if no_file_timeout {
-        i64::MAX // Effectively infinite timeout
-    } else {
-        file_appear_timeout_s.unwrap_or(120u32) as i64
-    };
-    let timeout = timeout_s.unwrap_or(120u32) as i64;
-
-    // Log start time
-    let start_time = Zoned::now()
-        .round(Unit::Second)
-        .expect("Could not get date/time information!");
-
-    // Retry opening the file until it is created
-    let mut printed_stat = false; // Only print the status once
-    let mut file = loop {
-        match File::open(log_path) {
-            Ok(f) => {
-                println!("[INFO] Found file: {:?}", log_path);
-                break f;
-            }
-            Err(_) => {
-                if !printed_stat {
-                    println!("[INFO] Waiting for log file to be created: {:?}", log_path);
-                    printed_stat = true;
-                }
-                sleep(Duration::from_secs(1));
-            }
-        }
-
-        // Exit if we have been waiting longer than the timeout
-        let time_now = Zoned::now()
-            .round(Unit::Second)
-            .expect("[FATAL] Could not get date/time information!");
-        if start_time
-            .until((Unit::Second, &time_now))
-            .expect("[FATAL] Error while comparing times! Exiting.")
-            .get_seconds()
-            > file_appear_timeout
-        {
-            println!(
-                "[FATAL] File took too long to appear (longer than timeout of {} seconds). Exiting.",
-                file_appear_timeout
-            );
-            return Err("Timeout waiting for log file".into());
-        }
-    };
+use slurmtail::duration::parse_duration;
+use slurmtail::error::SlurmtailError;
+use slurmtail::output::confirm;
+use slurmtail::scheduler::{self, scheduler};
+use slurmtail::state;
+use slurmtail::watch::{
+    self, ExistingLogs, FollowOptions, RetryPolicy, RunOptions, job_outcome, mon_logfile,
+    resume_session, run_with_retries,
+};
+use slurmtail::{
+    config, daemon, diagnostics, error, filter, hooks, interrupt, keys, mail, notify, ntfy, otel,
+    output, pager, pipeline, plugin, prompt, remote, runtime, script, status, syslog, timestamps,
+    tmux, tui, webhook,
+};
 
-    // Find the starting position for the last 150 lines (or beginning if fewer than 150 lines)
-    let start_position = {
-        let file_size = file.metadata()?.len();
-
-        if file_size == 0 {
-            0
-        } else {
-            let mut newline_count = 0;
-            let mut position = file_size;
-            let mut buffer = [0u8; 8192]; // 8KB buffer
-
-            // Seek backwards to find the position where the last 150 lines start
-            // We need to find 149 newlines to get to the start of the 150th line from the end
-            while position > 0 && newline_count < 149 {
-                let chunk_size = std::cmp::min(buffer.len() as u64, position);
-                position -= chunk_size;
-
-                file.seek(SeekFrom::Start(position))?;
-                file.read_exact(&mut buffer[0..chunk_size as usize])?;
-
-                // Count newlines backwards in this chunk
-                for i in (0..chunk_size as usize).rev() {
-                    if buffer[i] == b'\n' {
-                        newline_count += 1;
-                        if newline_count == 149 {
-                            // Found the position where the 150th line from the end starts
-                            position += i as u64 + 1;
-                            break;
-                        }
-                    }
-                }
-            }
+// Options a --tmux pane's `slurmtail tail` needs to show the job's output the same way, when given on the command line
+const TMUX_FORWARDED_ARGS: &[&str] = &[
+    "profile",
+    "scheduler",
+    "rest-url",
+    "rest-api-version",
+    "remote",
+    "poll-interval",
+    "max-duration",
+    "grace-period",
+    "color",
+    "highlight",
+    "grep",
+    "grep-v",
+    "context",
+    "since",
+    "convert-timestamps",
+    "timestamp-format",
+    "plugin",
+    "max-lines",
+    "until-pattern",
+    "succeed-on",
+    "fail-on",
+];
 
-            // If we reached the beginning and haven't found 149 newlines, start from the beginning
-            if position == 0 && newline_count < 149 {
-                0
-            } else {
-                position
-            }
-        }
+// Set up --tmux, if given, passing the options that shape the output on to the panes
+fn enable_tmux(matches: &ArgMatches) -> Result<(), SlurmtailError> {
+    let layout = match matches.get_one::<String>("tmux").map(String::as_str) {
+        None => return Ok(()),
+        Some("windows") => tmux::Layout::Windows,
+        Some(_) => tmux::Layout::Panes,
     };
 
-    // Start reading from the calculated position (this will print last 150 lines + any new content)
-    file.seek(SeekFrom::Start(start_position))?;
-    let mut reader = BufReader::new(file);
-
-    // Set initial timestamp
-    let mut last_updated = Zoned::now().round(Unit::Second).expect(
-        "[FATAL] Could not get date/time information! Won't be able to compare times, so exiting.",
-    );
-
-    // Continuously read new lines
-    // Note: Times out after set time without new bytes read
-    loop {
-        let mut line = String::new();
-        let bytes_read = reader.read_line(&mut line)?;
-
-        let time_now = Zoned::now().round(Unit::Second).expect(
-            "[FATAL] Could not get date/time information! Won't be able to compare times, so exiting.",
-        );
-
-        if bytes_read > 0 {
-            // Print any new lines
-            print!("{}", line);
-            last_updated = time_now.clone();
-        } else if !no_bytes_timeout
-            && last_updated
-                .until((Unit::Second, &time_now))
-                .expect("Error while comparing times! Exiting.")
-                .get_seconds()
-                > timeout
-        {
-            println!(
-                "[WARNING] Timed out after {} seconds with no new bytes read! Exiting.",
-                timeout
-            );
-            return Err("Timeout while monitoring - no new bytes read".into());
-        } else {
-            // No new data, wait a bit
-            sleep(Duration::from_secs(1));
+    let mut args = Vec::new();
+    for id in TMUX_FORWARDED_ARGS {
+        if matches.value_source(id) != Some(ValueSource::CommandLine) {
+            continue;
+        }
+        for value in matches.get_raw(id).unwrap_or_default() {
+            args.push(format!("--{}={}", id, value.to_string_lossy()));
         }
     }
-}
-
-// Function responsible for saving to a tiny file (somewhere) that allows resuming a given tail
-fn save_turd(project_dir: &Path, log_path: &Path) {
-    let turd_path: PathBuf = project_dir.to_path_buf().join("._slurmtail");
-
-    let mut file = File::create(turd_path.as_path()).unwrap_or_else(|_| {
-        panic!(
-            "[FATAL] Could not write resume file to: {:?}",
-            turd_path.clone().to_str()
-        )
-    });
-
-    // KISS: Just store the log file path
-    let turd_message: &str = log_path
-        .to_str()
-        .expect("[FATAL] Could not turn log path into path during resume file creation! Exiting.");
-
-    file.write_all(turd_message.as_bytes())
-        .expect("[FATAL] Could not write resume file! Exiting.");
-}
-
-// Searches a project directory for a resume marker and returns the path of the logfile if it finds it (by reading the resume marker, which contains the path). Also verifies the logfile exists.
-fn read_turd(project_dir: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
-    let turd_path: PathBuf = project_dir.to_path_buf().join("._slurmtail");
-
-    if !turd_path.exists() {
-        return Err("No resume file found".into());
-    }
-
-    let content = read_to_string(&turd_path)?;
-    let log_path = PathBuf::from(content.trim());
-
-    if !log_path.exists() {
-        return Err("Log file from resume file no longer exists".into());
+    if matches.get_flag("cancel-on-fail") {
+        args.push("--cancel-on-fail".to_string());
     }
-
-    Ok(log_path)
-}
-
-// Remove resume file if it exists
-fn clean_turd(project_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    let turd_path: PathBuf = project_dir.to_path_buf().join("._slurmtail");
-
-    if turd_path.exists() {
-        std::fs::remove_file(&turd_path)?;
-        println!("Removed resume file: {:?}", turd_path);
-    } else {
-        println!("No resume file found to clean");
+    if matches.get_flag("exit-zero-on-timeout") {
+        args.push("--exit-zero-on-timeout".to_string());
     }
-
-    Ok(())
-}
-
-// Read the batch file and extract the log output pattern (in SLURM batch file format)
-// e.g.: #SBATCH --output output.%j.log
-//       -> "output.%j.log"
-fn extract_log_output_pattern(script_path: &Path) -> Result<String, Box<dyn std::error::Error>> {
-    let content = read_to_string(script_path)?;
-
-    for line in content.lines() {
-        let line = line.trim();
-        if line.starts_with("#SBATCH --output") || line.starts_with("#SBATCH -o") {
-            // Handle both "--output=value" and "--output value" formats
-            if line.contains('=') {
-                if let Some(output_part) = line.split('=').nth(1) {
-                    return Ok(output_part.to_string());
-                }
-            } else if let Some(output_part) = line.split_whitespace().nth(2) {
-                return Ok(output_part.to_string());
-            }
-        }
+    for _ in 0..matches.get_count("verbose") {
+        args.push("--verbose".to_string());
     }
 
-    Err("No SBATCH output directive found in script".into())
+    tmux::enable(layout, args)
 }
 
-// Extract job name from SLURM script
-fn extract_job_name(script_path: &Path) -> Result<Option<String>, Box<dyn std::error::Error>> {
-    let content = read_to_string(script_path)?;
-
-    for line in content.lines() {
-        let line = line.trim();
-        if line.starts_with("#SBATCH --job-name") || line.starts_with("#SBATCH -J") {
-            // Handle both "--job-name=value" and "--job-name value" formats
-            if line.contains('=') {
-                if let Some(job_name_part) = line.split('=').nth(1) {
-                    return Ok(Some(job_name_part.to_string()));
-                }
-            } else if let Some(job_name_part) = line.split_whitespace().nth(2) {
-                return Ok(Some(job_name_part.to_string()));
-            }
-        }
+// What to do with log files left by an earlier job: --backup-existing, --truncate-existing, or else warn about them
+fn existing_logs(sub_matches: &ArgMatches) -> ExistingLogs {
+    if sub_matches.get_flag("backup-existing") {
+        ExistingLogs::BackUp
+    } else if sub_matches.get_flag("truncate-existing") {
+        ExistingLogs::Truncate
+    } else {
+        ExistingLogs::Warn
     }
-
-    Ok(None)
 }
 
-// Take a SLURM-formatted output path and format it using a known jobid and optional job name
-fn format_log_output_string(
-    logfile_pattern_string: String,
-    jobid: u64,
-    job_name: Option<&String>,
-) -> String {
-    let mut result = logfile_pattern_string.replace("%j", &jobid.to_string());
+// A --highlight rule: REGEX:STYLE, or just REGEX to show its matches in reverse video
+// What follows the last ':' is only taken for the style if it is one (so "Epoch [0-9]+/[0-9]+:" or "time: [0-9]+"
+// are patterns on their own), but a word there that isn't a style is a mistake rather than part of the pattern
+fn highlight_rule(rule: &str) -> Result<(String, String), SlurmtailError> {
+    const DEFAULT_STYLE: &str = "reverse";
 
-    if let Some(name) = job_name {
-        result = result.replace("%x", name);
+    let Some((pattern, style)) = rule.rsplit_once(':') else {
+        return Ok((rule.to_string(), DEFAULT_STYLE.to_string()));
+    };
+    let looks_like_style = !style.trim().is_empty()
+        && style
+            .chars()
+            .all(|c| c.is_ascii_alphabetic() || matches!(c, ' ' | '+' | ','));
+    match output::parse_style(style) {
+        Ok(_) if !pattern.is_empty() => Ok((pattern.to_string(), style.to_string())),
+        Err(e) if looks_like_style => Err(SlurmtailError::Invalid(format!(
+            "Invalid --highlight {:?}: {} (to highlight a ':' followed by that word, write the ':' as [:])",
+            rule, e
+        ))),
+        _ => Ok((rule.to_string(), DEFAULT_STYLE.to_string())),
     }
-
-    result
 }
 
-// Take a now fully formed logfile path and transform it into a full path based on the location of the original script
-fn logfile_string_to_path(
-    script_path: &Path,
-    logfile_string: String,
-    use_cwd: bool,
-) -> Result<PathBuf, Box<dyn std::error::Error>> {
-    let base_dir: PathBuf = match use_cwd {
-        true => env::current_dir().expect("Could not get current working directory! Exiting."),
-        false => script_path.parent().unwrap_or(Path::new(".")).to_path_buf(),
-    };
-
-    // Handle given absolute path
-    let log_path = if Path::new(&logfile_string).is_absolute() {
-        if use_cwd {
-            println!(
-                "[WARNING] Gave instruction to use current directory to find logfile, but the logfile is an absolute path! Will use that instead."
-            );
-        }
-        PathBuf::from(logfile_string)
-    } else {
-        base_dir.join(logfile_string)
+// How long a subcommand waits for the log file to appear, and for new output once it has: --file-timeout and
+// --idle-timeout, or else --timeout for both, or else the config's (see Config::merge)
+fn follow_timeouts(
+    sub_matches: &ArgMatches,
+    config: &config::Config,
+) -> (Option<Duration>, Option<Duration>) {
+    let given = |id| {
+        sub_matches
+            .try_get_one::<Duration>(id)
+            .ok()
+            .flatten()
+            .copied()
     };
-
-    Ok(log_path)
+    let timeout = given("timeout");
+    (
+        given("file-timeout")
+            .or(timeout)
+            .or(config.file_timeout)
+            .or(config.timeout),
+        given("idle-timeout")
+            .or(timeout)
+            .or(config.idle_timeout)
+            .or(config.timeout),
+    )
 }
 
-// Submit a job using sbatch
-fn run_sbatch(script_path: &Path) -> Result<u64, Box<dyn std::error::Error>> {
-    let output = ProcessCommand::new("sbatch")
-        .arg(script_path)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("sbatch failed: {}", stderr).into());
+fn main() {
+    let result = run_cli();
+    keys::stop();
+    // Leave the --tui interface up (with the error, if any) until the user has read it
+    if let Err(e) = &result {
+        tui::push_message(&format!("Error: {}", e));
     }
+    // Not after SIGHUP or SIGTERM, though: nobody is there to read it (or whoever sent it wants slurmtail gone)
+    tui::stop(interrupt::terminated().is_none());
+    output::restore_terminal_title();
+    let code = error::exit_code(&result);
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
+    // The last event tells consumers of --format json how things ended
+    output::emit(
+        "exit",
+        None,
+        serde_json::json!({
+            "code": code,
+            "error": result.as_ref().err().map(|e| e.to_string()),
+        }),
+    );
+    hooks::wait();
+    runtime::wait_for_deliveries();
+    remote::clean_up();
 
-    for word in stdout.split_whitespace() {
-        if let Ok(job_id) = word.parse::<u64>() {
-            return Ok(job_id);
-        }
+    // After SIGHUP the terminal may be gone, so a failed write is no reason to panic
+    if let Err(e) = result {
+        let _ = writeln!(std::io::stderr(), "Error: {}", e);
     }
-
-    Err("Could not extract job ID from sbatch output".into())
+    std::process::exit(code);
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn run_cli() -> Result<(), SlurmtailError> {
     let matches = Command::new("slurmtail")
         .about("Submit SLURM jobs and monitor their log files")
+        .arg(
+            Arg::new("scheduler")
+                .help("How to talk to the scheduler: SLURM's command-line tools, slurmrestd, PBS/Torque, LSF, Flux, a local mock for testing, or libslurm if built with it (default: auto, which picks whichever of sbatch, bsub, qsub, or flux is installed)")
+                .long("scheduler")
+                .global(true)
+                .value_parser(scheduler::scheduler_names()),
+        )
+        .arg(
+            Arg::new("rest-url")
+                .help("Base URL of slurmrestd for --scheduler rest (default: $SLURMRESTD_URL)")
+                .long("rest-url")
+                .global(true),
+        )
+        .arg(
+            Arg::new("rest-api-version")
+                .help("slurmrestd API version for --scheduler rest (default: v0.0.40)")
+                .long("rest-api-version")
+                .global(true),
+        )
+        .arg(
+            Arg::new("remote")
+                .help("Run the scheduler's commands and read the job's logs on HOST over SSH (one shared connection), in DIR there, or in the same directory relative to the home directory (default: `remote` from the config files, or $SLURMTAIL_REMOTE)")
+                .long("remote")
+                .value_name("HOST[:DIR]")
+                .global(true),
+        )
+        .arg(
+            Arg::new("state-location")
+                .help("Where to keep session state: in the project directory (._slurmtail), or under $XDG_STATE_HOME/slurmtail (default: project)")
+                .long("state-location")
+                .global(true)
+                .value_parser(["project", "xdg"])
+                .default_value("project"),
+        )
+        .arg(
+            Arg::new("profile")
+                .help("Use the settings of [profile.NAME] in the config files (where to submit, timeouts, notifications, ...) (default: $SLURMTAIL_PROFILE)")
+                .long("profile")
+                .value_name("NAME")
+                .global(true),
+        )
+        .arg(
+            Arg::new("format")
+                .help("Output format: human-readable text, or json for one JSON event per line (submission, state changes, log lines, timeouts, exit) (default: text)")
+                .long("format")
+                .global(true)
+                .value_parser(["text", "json"])
+                .default_value("text"),
+        )
+        .arg(
+            Arg::new("quiet")
+                .help("Print nothing but the job's output (warnings still go to stderr)")
+                .short('q')
+                .long("quiet")
+                .global(true)
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("verbose")
+                .help("Show debug messages on stderr; give twice (-vv) to trace every step")
+                .short('v')
+                .long("verbose")
+                .global(true)
+                .action(clap::ArgAction::Count)
+                .conflicts_with("quiet"),
+        )
+        .arg(
+            Arg::new("color")
+                .help("When to use color: on terminals unless $NO_COLOR is set (auto), always, or never")
+                .long("color")
+                .value_name("WHEN")
+                .value_parser(["auto", "always", "never"])
+                .default_value("auto")
+                .global(true),
+        )
+        .arg(
+            Arg::new("highlight")
+                .help("Show the parts of the job's output matching REGEX in STYLE (e.g. 'loss=[0-9.]+:bold green' or 'NaN:red'), or in reverse video without a STYLE; may be given more than once")
+                .long("highlight")
+                .value_name("REGEX[:STYLE]")
+                .global(true)
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("grep")
+                .help("Only show lines of the job's output matching REGEX; may be given more than once (a line matching any of them is shown)")
+                .long("grep")
+                .value_name("REGEX")
+                .global(true)
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("grep-v")
+                .help("Hide lines of the job's output matching REGEX; may be given more than once")
+                .long("grep-v")
+                .value_name("REGEX")
+                .global(true)
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("context")
+                .help("Also show N lines before and after each line --grep lets through, like grep -C (default: 0)")
+                .short('C')
+                .long("context")
+                .value_name("N")
+                .global(true)
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("since")
+                .help("Don't show lines of the job's output from before TIME, by the timestamps they start with: a duration back from now (e.g. 10m or 2h) or a date and time (e.g. \"2026-10-16 09:00\")")
+                .long("since")
+                .value_name("TIME")
+                .global(true)
+                .value_parser(timestamps::parse_instant),
+        )
+        .arg(
+            Arg::new("convert-timestamps")
+                .help("Rewrite the timestamps lines of the job's output start with into ZONE: local, utc, or a name like Europe/Berlin")
+                .long("convert-timestamps")
+                .value_name("ZONE")
+                .global(true)
+                .value_parser(timestamps::parse_zone),
+        )
+        .arg(
+            Arg::new("timestamp-format")
+                .help("Write the timestamps --convert-timestamps rewrites in the strftime FORMAT, e.g. \"%H:%M:%S\" (converting to local time if --convert-timestamps isn't given)")
+                .long("timestamp-format")
+                .value_name("FORMAT")
+                .global(true),
+        )
+        .arg(
+            Arg::new("plugin")
+                .help("Pass each line of the job's output through the on_line function of the Lua script FILE, which can rewrite, drop, or color it, or raise events about it; may be given more than once (needs slurmtail built with the lua feature)")
+                .long("plugin")
+                .value_name("FILE")
+                .global(true)
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("max-lines")
+                .help("Stop following (and exit successfully) once N lines of the job's output have been shown, even if it is still running")
+                .long("max-lines")
+                .value_name("N")
+                .global(true)
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("until-pattern")
+                .help("Stop following (and exit successfully) as soon as the job writes a line matching REGEX, even if it is still running")
+                .long("until-pattern")
+                .value_name("REGEX")
+                .global(true),
+        )
+        .arg(
+            Arg::new("succeed-on")
+                .help("Stop following and exit successfully as soon as the job writes a line matching REGEX; may be given more than once")
+                .long("succeed-on")
+                .value_name("REGEX")
+                .global(true)
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("fail-on")
+                .help("Stop following and exit with an error as soon as the job writes a line matching REGEX (e.g. 'CUDA out of memory'); may be given more than once")
+                .long("fail-on")
+                .value_name("REGEX")
+                .global(true)
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("cancel-on-fail")
+                .help("Cancel the job when it writes a line matching --fail-on")
+                .long("cancel-on-fail")
+                .global(true)
+                .requires("fail-on")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("exit-zero-on-timeout")
+                .help("Exit with 0 when waiting for the log file or for new output times out (the timeout is still reported)")
+                .long("exit-zero-on-timeout")
+                .global(true)
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("tee")
+                .help("Also write what slurmtail prints to this file (appending to it), e.g. to keep a copy of the job's output after the cluster's scratch space is purged")
+                .long("tee")
+                .global(true)
+                .value_name("FILE"),
+        )
+        .arg(
+            Arg::new("tee-mode")
+                .help("What --tee copies: everything slurmtail prints, or only the job's output (default: all)")
+                .long("tee-mode")
+                .global(true)
+                .value_parser(["all", "output"])
+                .default_value("all"),
+        )
+        .arg(
+            Arg::new("transcript")
+                .help("Frame each job's output in the --tee file with a header (job ID, script, submit time, overrides, cluster) and a footer (final state and accounting summary)")
+                .long("transcript")
+                .global(true)
+                .action(clap::ArgAction::SetTrue)
+                .requires("tee"),
+        )
+        .arg(
+            Arg::new("on-interrupt")
+                .help("What Ctrl-C does while following a job: stop following and leave the job running (detach), cancel the job, or ask which (a second Ctrl-C always quits)")
+                .long("on-interrupt")
+                .global(true)
+                .value_parser(["ask", "detach", "cancel"])
+                .default_value("ask"),
+        )
+        .arg(
+            Arg::new("notify")
+                .help("Show a desktop notification when the job starts running and when it finishes (in the terminal, over SSH)")
+                .long("notify")
+                .global(true)
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("strigger")
+                .help("Learn when the job ends from an strigger that touches a file in the state directory, instead of asking SLURM about it every 5 seconds (where the site allows triggers and the controller can see that directory)")
+                .long("strigger")
+                .global(true)
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("poll-interval")
+                .help("How often to check for new output, e.g. 0.5 or 250ms (default: 1s, or `poll_interval` from the config files)")
+                .long("poll-interval")
+                .value_name("DURATION")
+                .global(true)
+                .value_parser(parse_duration),
+        )
+        .arg(
+            Arg::new("grace-period")
+                .help("Keep reading the log for this long after the job has left the queue, e.g. 30s, for output that lands late on shared filesystems (default: 0, or `grace_period` from the config files)")
+                .long("grace-period")
+                .value_name("DURATION")
+                .global(true)
+                .value_parser(parse_duration),
+        )
+        .arg(
+            Arg::new("max-duration")
+                .help("Stay attached for at most this long, e.g. 8h, then detach, leaving the job running (`slurmtail resume` picks up where it left off) (default: no limit, or `max_duration` from the config files)")
+                .long("max-duration")
+                .value_name("DURATION")
+                .global(true)
+                .value_parser(parse_duration),
+        )
+        .arg(
+            Arg::new("webhook")
+                .help("POST the job's lifecycle events (started, ended, failed) as JSON to URL; can be given more than once (webhooks in the config file can pick their events)")
+                .long("webhook")
+                .value_name("URL")
+                .global(true)
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("otlp-endpoint")
+                .help("Send each job's life (submission, wait in the queue, run, and how it ended) as an OpenTelemetry trace to the OTLP/HTTP collector at URL (default: $OTEL_EXPORTER_OTLP_ENDPOINT, or `[otlp]` in the config files)")
+                .long("otlp-endpoint")
+                .value_name("URL")
+                .global(true),
+        )
+        .arg(
+            Arg::new("mail-to")
+                .help("Once the job has ended, email a summary (its state, accounting, and the end of its log) to ADDRESS through the local sendmail; can be given more than once")
+                .long("mail-to")
+                .value_name("ADDRESS")
+                .global(true)
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("syslog")
+                .help("Also log lifecycle events (job submitted, started, finished, timed out) to syslog/journald")
+                .long("syslog")
+                .global(true)
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("porcelain")
+                .help("Print stable, tab-separated records (job ID, log path, state changes, log lines) instead of text meant for people")
+                .long("porcelain")
+                .global(true)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("format"),
+        )
         .subcommand(
             Command::new("run")
                 .alias("r")
@@ -324,10 +474,25 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 )
                 .arg(
                     Arg::new("timeout")
-                        .help("Timeout in seconds (default: 120)")
+                        .help("How long to wait for the log file to appear, and for new output once it has (both --file-timeout and --idle-timeout), e.g. 90, 10m, or 1h30m, or 0 to wait forever (default: 2m)")
                         .short('t')
                         .long("timeout")
-                        .value_parser(clap::value_parser!(u32)),
+                        .value_name("DURATION")
+                        .value_parser(parse_duration),
+                )
+                .arg(
+                    Arg::new("file-timeout")
+                        .help("How long to wait for the log file to appear, e.g. 30m, or 0 to wait forever (default: --timeout)")
+                        .long("file-timeout")
+                        .value_name("DURATION")
+                        .value_parser(parse_duration),
+                )
+                .arg(
+                    Arg::new("idle-timeout")
+                        .help("How long to wait for new output once the log file has appeared, e.g. 5m, or 0 to wait forever (default: --timeout)")
+                        .long("idle-timeout")
+                        .value_name("DURATION")
+                        .value_parser(parse_duration),
                 )
                 .arg(
                     Arg::new("no-file-timeout")
@@ -341,6 +506,104 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         .help("Disable timeout for new bytes being written to monitored file")
                         .short('b')
                         .long("no-bytes-timeout")
+                        .visible_alias("no-idle-timeout")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("create-log-dirs")
+                        .help("Create the directories the job's log files go in, if they don't exist (without it, a missing one stops the job from being submitted)")
+                        .long("create-log-dirs")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("backup-existing")
+                        .help("If the job's log file is already there (left by an earlier job under the same name), move it aside to <name>.1 (or the next free number) rather than have the job overwrite it")
+                        .long("backup-existing")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("truncate-existing")
+                        .help("If the job's log file is already there (left by an earlier job under the same name), empty it, so following it shows only the new job's output")
+                        .long("truncate-existing")
+                        .conflicts_with("backup-existing")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("gpu-usage")
+                        .help("For a job that asks for GPUs, show how busy they are and how much of their memory is in use beneath its output, looking every 30 seconds with nvidia-smi in a step sharing the job's resources")
+                        .long("gpu-usage")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("all-components")
+                        .help("For heterogeneous jobs, follow the output of every component")
+                        .long("all-components")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("tmux")
+                        .help("Follow each log file (e.g. with --all-components) in a tmux pane of its own, or a window with --tmux=windows, instead of interleaving them; needs to run inside tmux")
+                        .long("tmux")
+                        .value_parser(["panes", "windows"])
+                        .num_args(0..=1)
+                        .require_equals(true)
+                        .default_missing_value("panes")
+                        .conflicts_with("tui"),
+                )
+                .arg(
+                    Arg::new("name")
+                        .help("Name for this session, so `resume` and `cancel` can find it by name")
+                        .long("name"),
+                )
+                .arg(
+                    Arg::new("step")
+                        .help("Job step to attach to with sattach when the log file can't be followed (default: 0)")
+                        .long("step")
+                        .value_parser(clap::value_parser!(u32)),
+                )
+                .arg(
+                    Arg::new("test-only")
+                        .help("Validate the script with `sbatch --test-only` and report the estimated start time before submitting")
+                        .long("test-only")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("confirm")
+                        .help("Ask for confirmation before submitting (after the pre-flight, if --test-only is given)")
+                        .long("confirm")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("retries")
+                        .help("Resubmit the script up to this many times if the job ends in a retryable state")
+                        .long("retries")
+                        .value_parser(clap::value_parser!(u32)),
+                )
+                .arg(
+                    Arg::new("retry-on")
+                        .help("Comma-separated job states that trigger a resubmission (default: NODE_FAIL)")
+                        .long("retry-on")
+                        .value_delimiter(',')
+                        .requires("retries"),
+                )
+                .arg(
+                    Arg::new("retry-delay")
+                        .help("How long to wait before resubmitting, e.g. 30 or 5m (default: 0)")
+                        .long("retry-delay")
+                        .value_name("DURATION")
+                        .value_parser(parse_duration)
+                        .requires("retries"),
+                )
+                .arg(
+                    Arg::new("tui")
+                        .help("Follow the job in a full-screen interface (scrollable log with search, job status bar, stderr side pane) instead of streaming its output")
+                        .long("tui")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("pager-on-exit")
+                        .help("Once the job has ended, open its complete log in $PAGER (default: less, starting at the end)")
+                        .long("pager-on-exit")
                         .action(clap::ArgAction::SetTrue),
                 ),
         )
@@ -350,10 +613,25 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .alias("m")
                 .arg(
                     Arg::new("timeout")
-                        .help("Timeout in seconds (default: 120)")
+                        .help("How long to wait for the log file to appear, and for new output once it has (both --file-timeout and --idle-timeout), e.g. 90, 10m, or 1h30m, or 0 to wait forever (default: 2m)")
                         .short('t')
                         .long("timeout")
-                        .value_parser(clap::value_parser!(u32)),
+                        .value_name("DURATION")
+                        .value_parser(parse_duration),
+                )
+                .arg(
+                    Arg::new("file-timeout")
+                        .help("How long to wait for the log file to appear, e.g. 30m, or 0 to wait forever (default: --timeout)")
+                        .long("file-timeout")
+                        .value_name("DURATION")
+                        .value_parser(parse_duration),
+                )
+                .arg(
+                    Arg::new("idle-timeout")
+                        .help("How long to wait for new output once the log file has appeared, e.g. 5m, or 0 to wait forever (default: --timeout)")
+                        .long("idle-timeout")
+                        .value_name("DURATION")
+                        .value_parser(parse_duration),
                 )
                 .arg(
                     Arg::new("no-file-timeout")
@@ -367,91 +645,830 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         .help("Disable timeout for new bytes being written to monitored file")
                         .short('b')
                         .long("no-bytes-timeout")
+                        .visible_alias("no-idle-timeout")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("session")
+                        .help("Name or job ID of the session to resume (default: the most recently submitted job)")
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("job")
+                        .help("Job ID of the session to resume (never taken for a session name)")
+                        .long("job")
+                        .short('j')
+                        .value_parser(clap::value_parser!(u64))
+                        .conflicts_with("session"),
+                )
+                .arg(
+                    Arg::new("tui")
+                        .help("Follow the job in a full-screen interface (scrollable log with search, job status bar, stderr side pane) instead of streaming its output")
+                        .long("tui")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("pager-on-exit")
+                        .help("Once the job has ended, open its complete log in $PAGER (default: less, starting at the end)")
+                        .long("pager-on-exit")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("tail")
+                .about("Follow a log file, e.g. one of a job's outputs (this is what each --tmux pane runs)")
+                .arg(
+                    Arg::new("path")
+                        .help("Path to the log file")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("job")
+                        .help("The job writing the file: stop once it has left the queue")
+                        .long("job")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("timeout")
+                        .help("How long to wait for the file to appear, and for new bytes being written once it has (both --file-timeout and --idle-timeout), e.g. 90, 10m, or 1h30m, or 0 to wait forever (default: 2m)")
+                        .short('t')
+                        .long("timeout")
+                        .value_name("DURATION")
+                        .value_parser(parse_duration),
+                )
+                .arg(
+                    Arg::new("file-timeout")
+                        .help("How long to wait for the file to appear, e.g. 30m, or 0 to wait forever (default: --timeout)")
+                        .long("file-timeout")
+                        .value_name("DURATION")
+                        .value_parser(parse_duration),
+                )
+                .arg(
+                    Arg::new("idle-timeout")
+                        .help("How long to wait for new output once the file has appeared, e.g. 5m, or 0 to wait forever (default: --timeout)")
+                        .long("idle-timeout")
+                        .value_name("DURATION")
+                        .value_parser(parse_duration),
+                )
+                .arg(
+                    Arg::new("no-file-timeout")
+                        .help("Disable timeout for waiting for the file to appear")
+                        .short('n')
+                        .long("no-file-timeout")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("no-bytes-timeout")
+                        .help("Disable timeout for new bytes being written to the file")
+                        .short('b')
+                        .long("no-bytes-timeout")
+                        .visible_alias("no-idle-timeout")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("attach")
+                .about("Follow the output of a job submitted without slurmtail (e.g. with sbatch), by its ID or with --newest")
+                .arg(
+                    Arg::new("job")
+                        .help("Job ID of the job to follow")
+                        .index(1)
+                        .required_unless_present("newest")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("newest")
+                        .help("Follow your most recently submitted job that is still in the queue")
+                        .long("newest")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("job"),
+                )
+                .arg(
+                    Arg::new("timeout")
+                        .help("How long to wait for the log file to appear, and for new bytes being written once it has (both --file-timeout and --idle-timeout), e.g. 90, 10m, or 1h30m, or 0 to wait forever (default: 2m)")
+                        .short('t')
+                        .long("timeout")
+                        .value_name("DURATION")
+                        .value_parser(parse_duration),
+                )
+                .arg(
+                    Arg::new("file-timeout")
+                        .help("How long to wait for the log file to appear, e.g. 30m, or 0 to wait forever (default: --timeout)")
+                        .long("file-timeout")
+                        .value_name("DURATION")
+                        .value_parser(parse_duration),
+                )
+                .arg(
+                    Arg::new("idle-timeout")
+                        .help("How long to wait for new output once the log file has appeared, e.g. 5m, or 0 to wait forever (default: --timeout)")
+                        .long("idle-timeout")
+                        .value_name("DURATION")
+                        .value_parser(parse_duration),
+                )
+                .arg(
+                    Arg::new("no-file-timeout")
+                        .help("Disable timeout for waiting for the log file to appear")
+                        .short('n')
+                        .long("no-file-timeout")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("no-bytes-timeout")
+                        .help("Disable timeout for new bytes being written to the log file")
+                        .short('b')
+                        .long("no-bytes-timeout")
+                        .visible_alias("no-idle-timeout")
                         .action(clap::ArgAction::SetTrue),
                 ),
         )
+        .subcommand(
+            Command::new("pipeline")
+                .about("Submit and monitor a DAG of jobs described by a manifest")
+                .alias("p")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("run")
+                        .about("Submit every job in a pipeline manifest (YAML or TOML) and follow their logs")
+                        .alias("r")
+                        .arg(
+                            Arg::new("manifest")
+                                .help("Path to the pipeline manifest")
+                                .required(true)
+                                .index(1),
+                        )
+                        .arg(
+                            Arg::new("create-log-dirs")
+                                .help("Create the directories the jobs' log files go in, if they don't exist (without it, a missing one stops the pipeline from being submitted)")
+                                .long("create-log-dirs")
+                                .action(clap::ArgAction::SetTrue),
+                        )
+                        .arg(
+                            Arg::new("backup-existing")
+                                .help("If a job's log file is already there (left by an earlier job under the same name), move it aside to <name>.1 (or the next free number) rather than have the job overwrite it")
+                                .long("backup-existing")
+                                .action(clap::ArgAction::SetTrue),
+                        )
+                        .arg(
+                            Arg::new("truncate-existing")
+                                .help("If a job's log file is already there (left by an earlier job under the same name), empty it, so following it shows only the new job's output")
+                                .long("truncate-existing")
+                                .conflicts_with("backup-existing")
+                                .action(clap::ArgAction::SetTrue),
+                        )
+                        .arg(
+                            Arg::new("sequential")
+                                .help("Follow the jobs' logs one after another instead of all at once")
+                                .short('s')
+                                .long("sequential")
+                                .action(clap::ArgAction::SetTrue),
+                        )
+                        .arg(
+                            Arg::new("tmux")
+                                .help("Follow each job's log in a tmux pane of its own, or a window with --tmux=windows, instead of interleaving them; needs to run inside tmux")
+                                .long("tmux")
+                                .value_parser(["panes", "windows"])
+                                .num_args(0..=1)
+                                .require_equals(true)
+                                .default_missing_value("panes")
+                                .conflicts_with("sequential"),
+                        )
+                        .arg(
+                            Arg::new("timeout")
+                                .help("How long to wait for new bytes being written, e.g. 90, 10m, or 1h30m, or 0 to wait forever (default: 2m)")
+                                .short('t')
+                                .long("timeout")
+                                .visible_alias("idle-timeout")
+                                .value_name("DURATION")
+                                .value_parser(parse_duration),
+                        )
+                        .arg(
+                            Arg::new("no-bytes-timeout")
+                                .help("Disable timeout for new bytes being written to monitored files")
+                                .short('b')
+                                .long("no-bytes-timeout")
+                                .visible_alias("no-idle-timeout")
+                                .action(clap::ArgAction::SetTrue),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("inspect")
+                .about("Show the directives slurmtail (and sbatch) will read from a batch script")
+                .alias("i")
+                .arg(
+                    Arg::new("script")
+                        .help("Path to the SLURM batch script")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("json")
+                        .help("Print the parsed directives as JSON")
+                        .long("json")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("list")
+                .about("List the jobs submitted from this directory")
+                .alias("l")
+                .arg(
+                    Arg::new("all")
+                        .help("List the jobs of every project with state under $XDG_STATE_HOME (with --state-location xdg)")
+                        .long("all")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("prompt-status")
+                .about("Print a short line about this directory's job for a shell prompt, e.g. \"⏳12345 RUNNING 0:14\"")
+                .arg(
+                    Arg::new("refresh")
+                        .help("Look up the job's state and update the cached one, without printing it")
+                        .long("refresh")
+                        .hide(true)
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("daemon")
+                .about("Keep following the jobs submitted with slurmtail in the background, even after the terminal is closed, so `slurmtail resume` can replay their output right away")
+                .arg(
+                    Arg::new("foreground")
+                        .help("Run the daemon in this terminal instead of in the background")
+                        .long("foreground")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("status")
+                        .help("List the jobs the running daemon follows")
+                        .long("status")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("foreground"),
+                )
+                .arg(
+                    Arg::new("stop")
+                        .help("Stop the running daemon")
+                        .long("stop")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with_all(["foreground", "status"]),
+                )
+                .arg(
+                    Arg::new("bind")
+                        .help("Also serve the jobs' output over HTTP at this address (e.g. 127.0.0.1:8080)")
+                        .long("bind")
+                        .value_name("ADDRESS")
+                        .value_parser(clap::value_parser!(std::net::SocketAddr))
+                        .conflicts_with_all(["status", "stop"]),
+                ),
+        )
+        .subcommand(
+            Command::new("serve")
+                .about("Follow the jobs submitted with slurmtail, like `slurmtail daemon --foreground`, and serve their output and state over HTTP, as web pages and server-sent events")
+                .arg(
+                    Arg::new("bind")
+                        .help("The address to listen at")
+                        .long("bind")
+                        .value_name("ADDRESS")
+                        .default_value("127.0.0.1:8080")
+                        .value_parser(clap::value_parser!(std::net::SocketAddr)),
+                ),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("Show recently submitted jobs and how they ended, from the submission history")
+                .arg(
+                    Arg::new("limit")
+                        .help("Number of submissions to show")
+                        .long("limit")
+                        .short('n')
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("20"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .help("Print the submissions as JSON")
+                        .long("json")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("report")
+                .about("Sum up how each script's runs went (states, mean run time, energy), from the submission history")
+                .arg(
+                    Arg::new("json")
+                        .help("Print the report as JSON")
+                        .long("json")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("rerun")
+                .about("Submit a job from the submission history again, with the directives it was submitted with, and follow it")
+                .arg(
+                    Arg::new("job")
+                        .help("Job ID of the submission to run again (default: the most recent submission)")
+                        .index(1)
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("timeout")
+                        .help("How long to wait for the log file to appear, and for new bytes being written once it has (both --file-timeout and --idle-timeout), e.g. 90, 10m, or 1h30m, or 0 to wait forever (default: 2m)")
+                        .short('t')
+                        .long("timeout")
+                        .value_name("DURATION")
+                        .value_parser(parse_duration),
+                ),
+        )
+        .subcommand(
+            Command::new("cancel")
+                .about("Cancel the job recorded in the resume file")
+                .alias("x")
+                .arg(
+                    Arg::new("session")
+                        .help("Name or job ID of the session to cancel (default: the most recently submitted job)")
+                        .index(1),
+                ),
+        )
         .subcommand(
             Command::new("clean")
                 .about("Remove any existing resume files")
-                .alias("c"),
+                .alias("c")
+                .arg(
+                    Arg::new("stale")
+                        .help("Only remove the sessions of jobs that have finished or that the scheduler no longer knows about")
+                        .long("stale")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("all")
+                        .help("Remove all stored state: this directory's resume files, every session under $XDG_STATE_HOME, and the submission history")
+                        .long("all")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("stale"),
+                )
+                .arg(
+                    Arg::new("yes")
+                        .help("Don't ask for confirmation before removing everything with --all")
+                        .long("yes")
+                        .short('y')
+                        .action(clap::ArgAction::SetTrue)
+                        .requires("all"),
+                ),
         )
         .get_matches();
 
+    // Command-line options win over SLURMTAIL_* environment variables, which win over the config files (and a
+    // --profile from them), which win over the built-in defaults
+    let mut config = config::load_config()?;
+    if let Some(profile) = matches
+        .get_one::<String>("profile")
+        .cloned()
+        .or_else(|| config::env_var("SLURMTAIL_PROFILE"))
+    {
+        config = config.with_profile(&profile)?;
+    }
+    let config = config.with_env()?;
+    config.validate()?;
+
+    // Before picking the scheduler, which (with auto) is the one installed there
+    if let Some(remote) = matches
+        .get_one::<String>("remote")
+        .or(config.remote.as_ref())
+    {
+        remote::set_remote(remote)?;
+    }
+
+    let scheduler_name = match matches
+        .get_one::<String>("scheduler")
+        .or(config.scheduler.as_ref())
+        .map(String::as_str)
+    {
+        Some("auto") | None => scheduler::detect_scheduler(),
+        Some(name) if scheduler::scheduler_names().contains(&name) => name,
+        Some(name) => {
+            return Err(SlurmtailError::Invalid(format!(
+                "Invalid scheduler {:?} (from the config or $SLURMTAIL_SCHEDULER): expected one of {}",
+                name,
+                scheduler::scheduler_names().join(", ")
+            )));
+        }
+    };
+    scheduler::set_scheduler_by_name(
+        scheduler_name,
+        matches.get_one::<String>("rest-url"),
+        matches.get_one::<String>("rest-api-version"),
+    )?;
+
+    if matches.get_flag("porcelain") {
+        output::set_output_format(output::OutputFormat::Porcelain);
+    } else if matches.get_one::<String>("format").map(String::as_str) == Some("json") {
+        output::set_output_format(output::OutputFormat::Json);
+    }
+
+    output::set_quiet(matches.get_flag("quiet"));
+    output::set_color(
+        match matches.get_one::<String>("color").map(String::as_str) {
+            Some("always") => output::ColorChoice::Always,
+            Some("never") => output::ColorChoice::Never,
+            _ => output::ColorChoice::Auto,
+        },
+    );
+
+    // Highlight rules given on the command line take precedence over those in the config file
+    let mut highlights = Vec::new();
+    for rule in matches.get_many::<String>("highlight").unwrap_or_default() {
+        highlights.push(highlight_rule(rule)?);
+    }
+    highlights.extend(
+        config
+            .highlight
+            .iter()
+            .map(|rule| (rule.pattern.clone(), rule.style.clone())),
+    );
+    output::set_highlights(&highlights)?;
+    let webhook_urls: Vec<String> = matches
+        .get_many::<String>("webhook")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    webhook::set_webhooks(&config.webhook, &webhook_urls)?;
+    ntfy::set_ntfy(config.ntfy.as_ref())?;
+    otel::set_otlp(
+        matches
+            .get_one::<String>("otlp-endpoint")
+            .map(String::as_str),
+        config.otlp.as_ref(),
+    )?;
+    hooks::set_hooks(&config.hooks)?;
+
+    let grep: Vec<String> = matches
+        .get_many::<String>("grep")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let grep_v: Vec<String> = matches
+        .get_many::<String>("grep-v")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    filter::set_filter(&grep, &grep_v)?;
+    if let Some(context) = matches.get_one::<usize>("context") {
+        filter::set_context(*context);
+    }
+    if let Some(since) = matches.get_one::<jiff::Timestamp>("since") {
+        filter::set_since(*since);
+    }
+    let timestamp_format = matches.get_one::<String>("timestamp-format");
+    match matches.get_one::<jiff::tz::TimeZone>("convert-timestamps") {
+        Some(zone) => {
+            timestamps::set_conversion(zone.clone(), timestamp_format.map(String::as_str))?
+        }
+        None if timestamp_format.is_some() => timestamps::set_conversion(
+            jiff::tz::TimeZone::system(),
+            timestamp_format.map(String::as_str),
+        )?,
+        None => {}
+    }
+    // Plugins from the command line go after those from the config files
+    let mut plugins = config.plugins.clone();
+    plugins.extend(
+        matches
+            .get_many::<String>("plugin")
+            .unwrap_or_default()
+            .map(PathBuf::from),
+    );
+    plugin::set_plugins(&plugins)?;
+    let mut succeed_on: Vec<String> = matches
+        .get_many::<String>("succeed-on")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    succeed_on.extend(matches.get_one::<String>("until-pattern").cloned());
+    let fail_on: Vec<String> = matches
+        .get_many::<String>("fail-on")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    filter::set_stop_patterns(&succeed_on, &fail_on, matches.get_flag("cancel-on-fail"))?;
+    if let Some(max_lines) = matches.get_one::<u64>("max-lines") {
+        filter::set_max_lines(*max_lines);
+    }
+    if matches.get_flag("exit-zero-on-timeout") {
+        error::set_exit_zero_on_timeout();
+    }
+    output::set_verbosity(matches.get_count("verbose"));
+    diagnostics::init(matches.get_count("verbose"));
+    interrupt::set_policy(
+        match matches
+            .get_one::<String>("on-interrupt")
+            .map(String::as_str)
+        {
+            Some("detach") => interrupt::OnInterrupt::Detach,
+            Some("cancel") => interrupt::OnInterrupt::Cancel,
+            _ => interrupt::OnInterrupt::Ask,
+        },
+    );
+    if matches.get_flag("syslog") {
+        syslog::set_syslog();
+    }
+    if matches.get_flag("notify") || config.notify == Some(true) {
+        notify::set_notify();
+    }
+    if matches.get_flag("strigger") || config.strigger == Some(true) {
+        watch::set_end_triggers();
+    }
+    // Addresses given on the command line replace those in the config file
+    mail::set_mail_to(match matches.get_many::<String>("mail-to") {
+        Some(addresses) => addresses.cloned().collect(),
+        None => config.mail_to.clone(),
+    });
+    output::set_terminal_title(
+        std::io::stderr().is_terminal() && env::var("TERM").map_or(true, |term| term != "dumb"),
+    );
+
+    if let Some(tee_path) = matches.get_one::<String>("tee") {
+        let mode = match matches.get_one::<String>("tee-mode").map(String::as_str) {
+            Some("output") => output::TeeMode::Output,
+            _ => output::TeeMode::All,
+        };
+        output::set_tee(Path::new(tee_path), mode)?;
+        output::set_transcript(matches.get_flag("transcript"));
+    }
+
+    if matches
+        .get_one::<String>("state-location")
+        .map(String::as_str)
+        == Some("xdg")
+    {
+        state::set_state_location(state::StateLocation::Xdg);
+    }
+
+    if let Some(max_duration) = matches
+        .get_one::<Duration>("max-duration")
+        .copied()
+        .or(config.max_duration)
+    {
+        watch::set_max_duration(max_duration);
+    }
+
+    // How logs are followed when the command line doesn't say otherwise
+    let follow_defaults = FollowOptions {
+        poll_interval: matches
+            .get_one::<Duration>("poll-interval")
+            .copied()
+            .or(config.poll_interval),
+        history_lines: config.history_lines,
+        grace_period: matches
+            .get_one::<Duration>("grace-period")
+            .copied()
+            .or(config.grace_period),
+        ..FollowOptions::default()
+    };
+
     match matches.subcommand() {
         Some(("run", sub_matches)) => {
+            if sub_matches.get_flag("tui") {
+                tui::enable()?;
+            }
+            enable_tmux(sub_matches)?;
             let script_path = Path::new(sub_matches.get_one::<String>("script").unwrap());
-            let timeout = sub_matches.get_one::<u32>("timeout").copied();
-            let no_file_timeout = sub_matches.get_flag("no-file-timeout");
-            let no_bytes_timeout = sub_matches.get_flag("no-bytes-timeout");
-
             if !script_path.exists() {
-                eprintln!("Error: Script file does not exist: {:?}", script_path);
-                std::process::exit(1);
+                return Err(SlurmtailError::NotFound(format!(
+                    "Script file does not exist: {:?}",
+                    script_path
+                )));
             }
+            let retries = sub_matches.get_one::<u32>("retries").copied().unwrap_or(0);
+            let (file_timeout, idle_timeout) = follow_timeouts(sub_matches, &config);
+            let run_options = RunOptions {
+                follow: FollowOptions {
+                    file_appear_timeout: file_timeout,
+                    timeout: idle_timeout,
+                    // With --retries, a job that has gone quiet (e.g. on a node that hung) is followed until it
+                    // ends, since how it ends decides whether it is resubmitted
+                    no_file_timeout: sub_matches.get_flag("no-file-timeout") || retries > 0,
+                    no_bytes_timeout: sub_matches.get_flag("no-bytes-timeout") || retries > 0,
+                    // Stop once the job is done, so that the exit code can say how it ended (and a retry can
+                    // follow)
+                    watch_job: true,
+                    ..follow_defaults
+                },
+                all_components: sub_matches.get_flag("all-components"),
+                attach_step: sub_matches.get_one::<u32>("step").copied().unwrap_or(0),
+                session_name: sub_matches.get_one::<String>("name").cloned(),
+                submit_args: config.submit_args(script_path)?,
+                create_log_dirs: sub_matches.get_flag("create-log-dirs"),
+                existing_logs: existing_logs(sub_matches),
+                gpu_usage: sub_matches.get_flag("gpu-usage"),
+            };
+            let policy = RetryPolicy {
+                retries,
+                retry_on: sub_matches
+                    .get_many::<String>("retry-on")
+                    .map(|states| states.cloned().collect())
+                    .unwrap_or_else(|| vec!["NODE_FAIL".to_string()]),
+                retry_delay: sub_matches
+                    .get_one::<Duration>("retry-delay")
+                    .copied()
+                    .unwrap_or_default(),
+            };
 
-            // Extract log output pattern from the script
-            let log_pattern = extract_log_output_pattern(script_path)?;
+            script::warn_about_crlf(script_path)?;
+            if sub_matches.get_flag("test-only") {
+                watch::preflight(script_path, &run_options.submit_args)?;
+            }
+            if sub_matches.get_flag("confirm") && !confirm("Submit the job?")? {
+                status!("Not submitting.");
+                return Ok(());
+            }
 
-            // Extract job name if present
-            let job_name = extract_job_name(script_path)?;
+            let (job_id, final_state) = run_with_retries(script_path, &run_options, &policy)?;
+            if sub_matches.get_flag("pager-on-exit")
+                && final_state.is_some()
+                && let Ok(state) = state::load_job_session(&error::current_dir()?, job_id)
+            {
+                pager::show_log(&state.stdout);
+            }
 
-            // Submit the job
-            println!("Submitting job...");
-            let job_id = run_sbatch(script_path)?;
-            println!("Job submitted with ID: {}", job_id);
+            job_outcome(job_id, final_state.as_deref())?;
+        }
+        Some(("resume", sub_matches)) => {
+            if sub_matches.get_flag("tui") {
+                tui::enable()?;
+            }
+            let (file_timeout, idle_timeout) = follow_timeouts(sub_matches, &config);
+            let no_file_timeout = sub_matches.get_flag("no-file-timeout");
+            let no_bytes_timeout = sub_matches.get_flag("no-bytes-timeout");
+            let current_dir = error::current_dir()?;
 
-            // Format the log file path
-            let log_filename = format_log_output_string(log_pattern, job_id, job_name.as_ref());
-            let log_path = logfile_string_to_path(script_path, log_filename, true)?;
-            println!(
-                "[DEBUG] Will try to use {} as logfile path.",
-                log_path.to_path_buf().to_str().unwrap()
-            );
+            let session = state::choose_session(
+                &current_dir,
+                sub_matches.get_one::<u64>("job").copied(),
+                sub_matches.get_one::<String>("session").map(String::as_str),
+            )?;
+            resume_session(
+                &session,
+                &current_dir,
+                &FollowOptions {
+                    file_appear_timeout: file_timeout,
+                    timeout: idle_timeout,
+                    no_file_timeout,
+                    no_bytes_timeout,
+                    watch_job: false,
+                    ..follow_defaults
+                },
+                sub_matches.contains_id("job"),
+                sub_matches.get_flag("pager-on-exit"),
+            )?;
+        }
+        Some(("tail", sub_matches)) => {
+            let path = Path::new(sub_matches.get_one::<String>("path").unwrap());
+            let job_id = sub_matches.get_one::<u64>("job").copied();
+            let (file_timeout, idle_timeout) = follow_timeouts(sub_matches, &config);
 
-            // Save resume file
-            let current_dir = env::current_dir()?;
-            save_turd(&current_dir, &log_path);
+            keys::start(job_id);
+            mon_logfile(
+                path,
+                &FollowOptions {
+                    file_appear_timeout: file_timeout,
+                    timeout: idle_timeout,
+                    no_file_timeout: sub_matches.get_flag("no-file-timeout"),
+                    no_bytes_timeout: sub_matches.get_flag("no-bytes-timeout"),
+                    watch_job: job_id.is_some(),
+                    ..follow_defaults
+                },
+                None,
+                job_id,
+                None,
+            )?;
+        }
+        Some(("attach", sub_matches)) => {
+            let job_id = match sub_matches.get_one::<u64>("job") {
+                Some(job_id) => *job_id,
+                None => scheduler().newest_job()?.ok_or_else(|| {
+                    SlurmtailError::NotFound(
+                        "You have no jobs in the queue to attach to".to_string(),
+                    )
+                })?,
+            };
+            let log_path = scheduler().output_path(job_id)?.ok_or_else(|| {
+                SlurmtailError::NotFound(format!(
+                    "The scheduler doesn't say where job {} writes its output",
+                    job_id
+                ))
+            })?;
+            let (file_timeout, idle_timeout) = follow_timeouts(sub_matches, &config);
 
-            // Start monitoring
-            println!("Monitoring log file: {:?}", log_path);
+            status!("Attaching to job {}: {:?}", job_id, log_path);
+            keys::start(Some(job_id));
             mon_logfile(
                 &log_path,
-                timeout,
-                timeout,
-                no_file_timeout,
-                no_bytes_timeout,
+                &FollowOptions {
+                    file_appear_timeout: file_timeout,
+                    timeout: idle_timeout,
+                    no_file_timeout: sub_matches.get_flag("no-file-timeout"),
+                    no_bytes_timeout: sub_matches.get_flag("no-bytes-timeout"),
+                    watch_job: true,
+                    ..follow_defaults
+                },
+                None,
+                Some(job_id),
+                None,
             )?;
         }
-        Some(("resume", sub_matches)) => {
-            let timeout = sub_matches.get_one::<u32>("timeout").copied();
-            let no_file_timeout = sub_matches.get_flag("no-file-timeout");
-            let no_bytes_timeout = sub_matches.get_flag("no-bytes-timeout");
-            let current_dir = env::current_dir()?;
-
-            match read_turd(&current_dir) {
-                Ok(log_path) => {
-                    println!("Resuming monitoring of: {:?}", log_path);
-                    mon_logfile(
-                        &log_path,
-                        timeout,
-                        timeout,
-                        no_file_timeout,
+        Some(("pipeline", pipeline_matches)) => {
+            if let Some(("run", sub_matches)) = pipeline_matches.subcommand() {
+                let manifest_path = Path::new(sub_matches.get_one::<String>("manifest").unwrap());
+                let sequential = sub_matches.get_flag("sequential");
+                let (_, idle_timeout) = follow_timeouts(sub_matches, &config);
+                let no_bytes_timeout = sub_matches.get_flag("no-bytes-timeout");
+                enable_tmux(sub_matches)?;
+
+                pipeline::run_pipeline(
+                    manifest_path,
+                    &config,
+                    sub_matches.get_flag("create-log-dirs"),
+                    existing_logs(sub_matches),
+                    sequential,
+                    &FollowOptions {
+                        timeout: idle_timeout,
                         no_bytes_timeout,
-                    )?;
-                }
-                Err(e) => {
-                    eprintln!("Error: {}", e);
-                    std::process::exit(1);
-                }
+                        ..follow_defaults
+                    },
+                )?;
+            }
+        }
+        Some(("inspect", sub_matches)) => {
+            let script_path = Path::new(sub_matches.get_one::<String>("script").unwrap());
+            script::inspect_script(script_path, sub_matches.get_flag("json"))?;
+        }
+        Some(("list", sub_matches)) => {
+            let current_dir = error::current_dir()?;
+            state::list_sessions(&current_dir, sub_matches.get_flag("all"))?;
+        }
+        Some(("prompt-status", sub_matches)) => {
+            if let Ok(current_dir) = env::current_dir() {
+                prompt::print_status(&current_dir, sub_matches.get_flag("refresh"));
+            }
+        }
+        Some(("daemon", sub_matches)) => {
+            if sub_matches.get_flag("stop") {
+                daemon::stop()?;
+            } else if sub_matches.get_flag("status") {
+                daemon::print_status()?;
+            } else if sub_matches.get_flag("foreground") {
+                daemon::serve(sub_matches.get_one("bind").copied())?;
+            } else {
+                daemon::start()?;
             }
         }
-        Some(("clean", _)) => {
-            let current_dir = env::current_dir()?;
-            clean_turd(&current_dir)?;
+        Some(("serve", sub_matches)) => {
+            daemon::serve(sub_matches.get_one("bind").copied())?;
+        }
+        Some(("history", sub_matches)) => {
+            output::print_history(
+                *sub_matches.get_one::<usize>("limit").unwrap(),
+                sub_matches.get_flag("json"),
+            )?;
+        }
+        Some(("report", sub_matches)) => {
+            output::print_report(sub_matches.get_flag("json"))?;
+        }
+        Some(("rerun", sub_matches)) => {
+            let (file_timeout, idle_timeout) = follow_timeouts(sub_matches, &config);
+            watch::rerun(
+                sub_matches.get_one::<u64>("job").copied(),
+                &config,
+                FollowOptions {
+                    file_appear_timeout: file_timeout,
+                    timeout: idle_timeout,
+                    ..follow_defaults
+                },
+            )?;
+        }
+        Some(("cancel", sub_matches)) => {
+            let current_dir = error::current_dir()?;
+            state::cancel_session(
+                &current_dir,
+                sub_matches.get_one::<String>("session").map(String::as_str),
+            )?;
+        }
+        Some(("clean", sub_matches)) => {
+            let current_dir = error::current_dir()?;
+            if sub_matches.get_flag("all") {
+                state::clean_all_state(&current_dir, sub_matches.get_flag("yes"))?;
+            } else if sub_matches.get_flag("stale") {
+                state::clean_stale_sessions(&current_dir)?;
+            } else {
+                state::clean_turd(&current_dir)?;
+            }
         }
         _ => {
-            eprintln!("Use 'sl run <script>', 'sl resume', or 'sl clean'");
-            std::process::exit(1);
+            return Err(SlurmtailError::Invalid(
+                "Use 'sl run <script>', 'sl pipeline run <manifest>', 'sl inspect <script>', 'sl resume', 'sl list', 'sl history', 'sl daemon', 'sl serve', 'sl cancel', or 'sl clean'".to_string(),
+            ));
         }
     }
 
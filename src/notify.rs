@@ -0,0 +1,103 @@
+use serde_json::Value;
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use crate::lifecycle::Milestone;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+// Pop up a desktop notification when the job starts running and when it finishes (--notify)
+pub fn set_notify() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+// Notify about an event (see output::emit), if it is the job starting or finishing
+pub fn notify_event(kind: &str, job_id: Option<u64>, fields: &Value) {
+    if !enabled() || kind != "state" {
+        return;
+    }
+    let Some(state) = fields.get("state").and_then(Value::as_str) else {
+        return;
+    };
+    let Some(milestone) = Milestone::from_state(state) else {
+        return;
+    };
+    let job = job_id.map_or("?".to_string(), |job_id| job_id.to_string());
+
+    let message = match milestone {
+        Milestone::Start => format!("Job {} started", job),
+        Milestone::End | Milestone::Fail => format!("Job {} finished: {}", job, state),
+    };
+    notify("slurmtail", &message, milestone == Milestone::Fail);
+}
+
+// Show a notification on the desktop slurmtail runs on (with notify-send, or osascript on macOS); over SSH, without a
+// desktop, or if neither is there, ask the terminal to show it instead (OSC 777, which not every terminal knows, so
+// ring the bell too)
+fn notify(title: &str, message: &str, urgent: bool) {
+    tracing::debug!(title, message, urgent, "Sending a desktop notification");
+    let over_ssh =
+        std::env::var_os("SSH_CONNECTION").is_some() || std::env::var_os("SSH_TTY").is_some();
+    if !over_ssh
+        && has_desktop()
+        && let Ok(mut child) = desktop_command(title, message, urgent)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+    {
+        // Reap it in the background, so following isn't held up
+        thread::spawn(move || child.wait());
+        return;
+    }
+
+    let mut stderr = std::io::stderr();
+    if stderr.is_terminal() {
+        let clean = |text: &str| text.replace(['\x07', '\x1b', ';'], " ");
+        let _ = write!(
+            stderr,
+            "\x1b]777;notify;{};{}\x1b\\\x07",
+            clean(title),
+            clean(message)
+        );
+        let _ = stderr.flush();
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn has_desktop() -> bool {
+    true
+}
+
+#[cfg(not(target_os = "macos"))]
+fn has_desktop() -> bool {
+    std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+#[cfg(target_os = "macos")]
+fn desktop_command(title: &str, message: &str, _urgent: bool) -> Command {
+    let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut command = Command::new("osascript");
+    command.arg("-e").arg(format!(
+        "display notification {} with title {}",
+        quote(message),
+        quote(title)
+    ));
+    command
+}
+
+#[cfg(not(target_os = "macos"))]
+fn desktop_command(title: &str, message: &str, urgent: bool) -> Command {
+    let mut command = Command::new("notify-send");
+    command
+        .args(["--app-name", "slurmtail", "--urgency"])
+        .arg(if urgent { "critical" } else { "normal" })
+        .args([title, message]);
+    command
+}
@@ -0,0 +1,142 @@
+use serde_json::{Value, json};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::config::NtfyConfig;
+use crate::error::SlurmtailError;
+use crate::lifecycle::{JobEvent, Milestone};
+use crate::runtime;
+
+// How many of the log's last lines a push notification includes (phones only show a few)
+const NTFY_LINES: usize = 5;
+
+// How long to wait for the ntfy server to answer before giving up on it
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Where to publish the job's lifecycle events, and which ones
+struct Topic {
+    // The server's URL, e.g. "https://ntfy.sh", and the topic's name on it
+    server: String,
+    topic: String,
+    token: Option<String>,
+    milestones: Vec<Milestone>,
+}
+
+static TOPIC: OnceLock<Topic> = OnceLock::new();
+
+// Publish the job's lifecycle events (it started, ended, or failed) to the ntfy topic in the config file, so they
+// show up as push notifications on a phone subscribed to it
+pub fn set_ntfy(config: Option<&NtfyConfig>) -> Result<(), SlurmtailError> {
+    let Some(config) = config else {
+        return Ok(());
+    };
+    // ntfy takes JSON messages at the server's root, naming the topic in them
+    let (server, topic) = config
+        .url
+        .trim_end_matches('/')
+        .rsplit_once('/')
+        .filter(|(server, topic)| {
+            server.contains("://") && !server.ends_with('/') && !topic.is_empty()
+        })
+        .ok_or_else(|| {
+            SlurmtailError::Invalid(format!(
+                "Invalid ntfy URL {:?}: expected the topic's URL, e.g. https://ntfy.sh/my-topic",
+                config.url
+            ))
+        })?;
+    let milestones = config
+        .events
+        .iter()
+        .map(|event| {
+            Milestone::from_name(event).ok_or_else(|| {
+                SlurmtailError::Invalid(format!(
+                    "Invalid ntfy event {:?}: expected start, end, or fail",
+                    event
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let _ = TOPIC.set(Topic {
+        server: server.to_string(),
+        topic: topic.to_string(),
+        token: config.token.clone(),
+        milestones,
+    });
+    Ok(())
+}
+
+// Publish an event (see output::emit) to the topic, if it is the job starting or finishing and the topic wants it, in
+// the background (see runtime::deliver)
+// A server that can't be reached is warned about, and doesn't stop slurmtail
+pub fn publish_event(kind: &str, job_id: Option<u64>, fields: &Value) {
+    let Some(topic) = TOPIC.get() else {
+        return;
+    };
+    let (Some(job_id), Some(state)) = (job_id, fields.get("state").and_then(Value::as_str)) else {
+        return;
+    };
+    let Some(milestone) = Milestone::from_state(state).filter(|_| kind == "state") else {
+        return;
+    };
+    if !milestone.wanted_by(&topic.milestones) {
+        return;
+    }
+
+    let state = state.to_string();
+    runtime::deliver(move || publish(topic, milestone, job_id, &state));
+}
+
+fn publish(topic: &Topic, milestone: Milestone, job_id: u64, state: &str) {
+    let event = JobEvent::gather(milestone, job_id, state);
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(TIMEOUT))
+        .build()
+        .into();
+    tracing::debug!(
+        server = %topic.server,
+        topic = %topic.topic,
+        event = milestone.name(),
+        "Publishing to ntfy"
+    );
+    let mut request = agent.post(&topic.server);
+    if let Some(token) = &topic.token {
+        request = request.header("Authorization", &format!("Bearer {}", token));
+    }
+    if let Err(e) = request.send_json(message(&topic.topic, &event)) {
+        warning!(
+            "[WARNING] Could not publish job {}'s {} event to ntfy topic {}: {}",
+            job_id,
+            milestone.name(),
+            topic.topic,
+            e
+        );
+    }
+}
+
+// The notification: the summary as its title, the exit code and the end of the log as its message, and a priority
+// and emoji tag by how the job is doing
+fn message(topic: &str, event: &JobEvent) -> Value {
+    let mut message = Vec::new();
+    if let Some(exit_code) = &event.exit_code {
+        message.push(format!("Exit code: {}", exit_code));
+    }
+    message.extend(event.last_lines(NTFY_LINES));
+    if message.is_empty() {
+        message.push(event.state.clone());
+    }
+
+    let (priority, tag) = match event.milestone {
+        Milestone::Start => (3, "arrow_forward"),
+        Milestone::Fail => (4, "x"),
+        Milestone::End if event.state.starts_with("CANCELLED") => (3, "warning"),
+        Milestone::End => (3, "white_check_mark"),
+    };
+    json!({
+        "topic": topic,
+        "title": event.headline(),
+        "message": message.join("\n"),
+        "priority": priority,
+        "tags": [tag],
+    })
+}
@@ -0,0 +1,492 @@
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::config::{OtlpConfig, env_var};
+use crate::error::SlurmtailError;
+use crate::lifecycle::{self, JobEvent, Milestone};
+use crate::{runtime, state};
+
+// How long to wait for the collector to answer before giving up on it
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Where OTLP/HTTP collectors take traces, under their base URL
+const TRACES_PATH: &str = "/v1/traces";
+
+// OTLP's span kind and status codes
+const SPAN_KIND_INTERNAL: u32 = 1;
+const STATUS_CODE_OK: u32 = 1;
+const STATUS_CODE_ERROR: u32 = 2;
+
+// The collector to send the jobs' traces to
+struct Exporter {
+    // e.g. "http://localhost:4318/v1/traces"
+    url: String,
+    headers: Vec<(String, String)>,
+    service_name: String,
+}
+
+// What has been seen of a job so far, to be sent as its trace once it has ended
+#[derive(Default)]
+struct Trace {
+    submitted_at: Option<jiff::Timestamp>,
+    started_at: Option<jiff::Timestamp>,
+    // Whether slurmtail saw the job waiting in the queue, so the time it started at is when it was seen running
+    seen_waiting: bool,
+    // Whether slurmtail saw the job before it ended, so the time it ended at is when it was seen ending
+    seen_active: bool,
+    queue_span_sent: bool,
+    ended: bool,
+    // From the job's "submitted" event: its script, name, and cluster
+    attributes: Vec<(String, Value)>,
+    events: Vec<SpanEvent>,
+}
+
+#[derive(Clone)]
+struct SpanEvent {
+    time: jiff::Timestamp,
+    name: &'static str,
+    attributes: Vec<(String, Value)>,
+}
+
+static EXPORTER: OnceLock<Exporter> = OnceLock::new();
+static TRACES: Mutex<BTreeMap<u64, Trace>> = Mutex::new(BTreeMap::new());
+
+// Send each job's life as an OpenTelemetry trace (over OTLP/HTTP, as JSON) to the collector at `endpoint` (from
+// --otlp-endpoint), or else the one in $OTEL_EXPORTER_OTLP_TRACES_ENDPOINT / $OTEL_EXPORTER_OTLP_ENDPOINT, or the
+// config file's [otlp] section; without any of them, nothing is sent
+// $OTEL_EXPORTER_OTLP_HEADERS and $OTEL_SERVICE_NAME are honored too, over the config file's headers and service name
+pub fn set_otlp(endpoint: Option<&str>, config: Option<&OtlpConfig>) -> Result<(), SlurmtailError> {
+    // A traces endpoint is used as it is, while a base endpoint gets the traces path added
+    let url = match endpoint {
+        Some(endpoint) => traces_url(endpoint),
+        None => match env_var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT") {
+            Some(url) => url,
+            None => match env_var("OTEL_EXPORTER_OTLP_ENDPOINT")
+                .or_else(|| config.map(|config| config.endpoint.clone()))
+            {
+                Some(endpoint) => traces_url(&endpoint),
+                None => return Ok(()),
+            },
+        },
+    };
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(SlurmtailError::Invalid(format!(
+            "Invalid OTLP endpoint {:?}: expected an http:// or https:// URL, e.g. http://localhost:4318",
+            url
+        )));
+    }
+
+    // e.g. "api-key=secret,x-team=hpc"
+    let headers = match env_var("OTEL_EXPORTER_OTLP_HEADERS") {
+        Some(headers) => headers
+            .split(',')
+            .filter(|header| !header.trim().is_empty())
+            .map(|header| {
+                let (name, value) = header.split_once('=').ok_or_else(|| {
+                    SlurmtailError::Invalid(format!(
+                        "Invalid OTEL_EXPORTER_OTLP_HEADERS entry {:?}: expected NAME=VALUE",
+                        header
+                    ))
+                })?;
+                Ok((name.trim().to_string(), value.trim().to_string()))
+            })
+            .collect::<Result<Vec<_>, SlurmtailError>>()?,
+        None => config
+            .map(|config| config.headers.clone().into_iter().collect())
+            .unwrap_or_default(),
+    };
+    let service_name = env_var("OTEL_SERVICE_NAME")
+        .or_else(|| config.and_then(|config| config.service_name.clone()))
+        .unwrap_or_else(|| "slurmtail".to_string());
+
+    tracing::debug!(url, service_name, "Exporting jobs' traces over OTLP");
+    let _ = EXPORTER.set(Exporter {
+        url,
+        headers,
+        service_name,
+    });
+    Ok(())
+}
+
+fn traces_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with(TRACES_PATH) {
+        endpoint.to_string()
+    } else {
+        format!("{}{}", endpoint, TRACES_PATH)
+    }
+}
+
+// Note an event (see output::emit) in the job's trace: the span for its wait in the queue goes out once it starts,
+// and the rest (the job as a whole, and its run) once it has ended, in the background (see runtime::deliver)
+// A collector that can't be reached is warned about, and doesn't stop slurmtail
+pub fn record_event(kind: &str, job_id: Option<u64>, fields: &Value) {
+    if EXPORTER.get().is_none() {
+        return;
+    }
+    let Some(job_id) = job_id else {
+        return;
+    };
+    let Ok(mut traces) = TRACES.lock() else {
+        return;
+    };
+    let trace = traces.entry(job_id).or_default();
+    let now = jiff::Timestamp::now();
+    let field = |name: &str| fields.get(name).filter(|value| !value.is_null()).cloned();
+
+    match kind {
+        "submitted" => {
+            trace.submitted_at = Some(now);
+            trace.attributes = [
+                ("slurm.job.script", field("script")),
+                ("slurm.job.name", field("name")),
+                ("slurm.cluster", field("cluster")),
+            ]
+            .into_iter()
+            .filter_map(|(key, value)| Some((key.to_string(), value?)))
+            .collect();
+            trace.events.push(SpanEvent {
+                time: now,
+                name: "submitted",
+                attributes: Vec::new(),
+            });
+        }
+        "state" => {
+            let Some(state) = fields.get("state").and_then(Value::as_str) else {
+                return;
+            };
+            trace.events.push(SpanEvent {
+                time: now,
+                name: "state change",
+                attributes: [
+                    ("slurm.job.state", field("state")),
+                    ("slurm.job.previous_state", field("previous")),
+                ]
+                .into_iter()
+                .filter_map(|(key, value)| Some((key.to_string(), value?)))
+                .collect(),
+            });
+
+            match Milestone::from_state(state) {
+                None => {
+                    trace.seen_waiting = true;
+                    trace.seen_active = true;
+                }
+                Some(Milestone::Start) => {
+                    trace.seen_active = true;
+                    // Seen running without having been seen waiting, it may have started a while ago (the
+                    // accounting says when, once it has ended)
+                    if trace.seen_waiting && trace.started_at.is_none() {
+                        trace.started_at = Some(now);
+                        trace.queue_span_sent = true;
+                        let snapshot = snapshot(trace);
+                        runtime::deliver(move || export_queue_wait(job_id, &snapshot));
+                    }
+                }
+                Some(milestone) => {
+                    if trace.ended {
+                        return;
+                    }
+                    trace.ended = true;
+                    let snapshot = snapshot(trace);
+                    let state = state.to_string();
+                    runtime::deliver(move || export_job(job_id, milestone, &state, now, &snapshot));
+                }
+            }
+        }
+        "timeout" => trace.events.push(SpanEvent {
+            time: now,
+            name: "timeout",
+            attributes: [
+                ("slurmtail.waiting_for", field("waiting_for")),
+                ("slurmtail.timeout_seconds", field("seconds")),
+            ]
+            .into_iter()
+            .filter_map(|(key, value)| Some((key.to_string(), value?)))
+            .collect(),
+        }),
+        "cancelled" => trace.events.push(SpanEvent {
+            time: now,
+            name: "cancelled",
+            attributes: Vec::new(),
+        }),
+        _ => {}
+    }
+}
+
+// A copy of what is known of the job, to be sent in the background
+fn snapshot(trace: &Trace) -> Trace {
+    Trace {
+        submitted_at: trace.submitted_at,
+        started_at: trace.started_at,
+        seen_waiting: trace.seen_waiting,
+        seen_active: trace.seen_active,
+        queue_span_sent: trace.queue_span_sent,
+        ended: trace.ended,
+        attributes: trace.attributes.clone(),
+        events: trace.events.clone(),
+    }
+}
+
+// When the job was submitted: as seen by this slurmtail, or else as recorded in its session by the one that
+// submitted it
+fn submit_time(job_id: u64, trace: &Trace) -> Option<jiff::Timestamp> {
+    trace.submitted_at.or_else(|| {
+        let current_dir = std::env::current_dir().ok()?;
+        state::load_job_session(&current_dir, job_id)
+            .ok()?
+            .submit_time()
+    })
+}
+
+fn export_queue_wait(job_id: u64, trace: &Trace) {
+    let (Some(submitted_at), Some(started_at)) = (submit_time(job_id, trace), trace.started_at)
+    else {
+        return;
+    };
+    let ids = TraceIds::new(job_id, submitted_at);
+    let span = span(
+        &ids,
+        &ids.queue,
+        "queue wait",
+        (submitted_at, started_at),
+        vec![("slurm.job.id".to_string(), json!(job_id))],
+        &[],
+        None,
+    );
+    send(job_id, vec![span]);
+}
+
+// Send the job's span (from its submission to its end, with what happened along the way), and its run's; its wait in
+// the queue too, if that wasn't sent when it started
+fn export_job(
+    job_id: u64,
+    milestone: Milestone,
+    state: &str,
+    seen_at: jiff::Timestamp,
+    trace: &Trace,
+) {
+    let event = JobEvent::gather(milestone, job_id, state);
+    let started_at = trace
+        .started_at
+        .or_else(|| lifecycle::accounting_time(&event.accounting, "Start"));
+    // Seen ending while slurmtail was following it, it ended then; otherwise the accounting says when
+    let ended_at = if trace.seen_active {
+        seen_at
+    } else {
+        lifecycle::accounting_time(&event.accounting, "End").unwrap_or(seen_at)
+    };
+    let Some(submitted_at) = submit_time(job_id, trace).or(started_at) else {
+        tracing::debug!(
+            job_id,
+            "Not sending the job's trace: when it was submitted is unknown"
+        );
+        return;
+    };
+    let ids = TraceIds::new(job_id, submitted_at);
+
+    let mut attributes = vec![
+        ("slurm.job.id".to_string(), json!(job_id)),
+        ("slurm.job.state".to_string(), json!(state)),
+    ];
+    attributes.extend(trace.attributes.iter().cloned());
+    if let Some(name) = &event.name
+        && !trace
+            .attributes
+            .iter()
+            .any(|(key, _)| key == "slurm.job.name")
+    {
+        attributes.push(("slurm.job.name".to_string(), json!(name)));
+    }
+    if let Some(exit_code) = &event.exit_code {
+        attributes.push(("slurm.job.exit_code".to_string(), json!(exit_code)));
+    }
+    if let Some(log_path) = &event.log_path {
+        attributes.push((
+            "slurm.job.log_path".to_string(),
+            json!(log_path.display().to_string()),
+        ));
+    }
+    attributes.extend(
+        event
+            .accounting
+            .iter()
+            .filter(|(field, value)| field != "State" && field != "ExitCode" && !value.is_empty())
+            .map(|(field, value)| {
+                (
+                    format!("slurm.accounting.{}", field.to_lowercase()),
+                    json!(value),
+                )
+            }),
+    );
+    let status = (milestone == Milestone::Fail).then(|| state.to_string());
+
+    let mut spans = vec![span(
+        &ids,
+        &ids.job,
+        "slurm job",
+        (submitted_at, ended_at),
+        attributes,
+        &trace.events,
+        Some(status.as_deref()),
+    )];
+    if let Some(started_at) = started_at {
+        if !trace.queue_span_sent && started_at >= submitted_at {
+            spans.push(span(
+                &ids,
+                &ids.queue,
+                "queue wait",
+                (submitted_at, started_at),
+                vec![("slurm.job.id".to_string(), json!(job_id))],
+                &[],
+                None,
+            ));
+        }
+        spans.push(span(
+            &ids,
+            &ids.run,
+            "run",
+            (started_at, ended_at.max(started_at)),
+            vec![("slurm.job.id".to_string(), json!(job_id))],
+            &[],
+            Some(status.as_deref()),
+        ));
+    }
+    send(job_id, spans);
+}
+
+// The IDs of a job's trace and its spans, worked out from the job's ID and when it was submitted, so a slurmtail that
+// resumes following the job (and sends the rest of the trace) comes up with the same ones
+struct TraceIds {
+    trace: String,
+    job: String,
+    queue: String,
+    run: String,
+}
+
+impl TraceIds {
+    fn new(job_id: u64, submitted_at: jiff::Timestamp) -> TraceIds {
+        // Submission times are kept to the second in sessions, so that's all that goes into the IDs
+        let seed = format!("{}@{}", job_id, submitted_at.as_second());
+        let id = |part: &str, offset: u64| {
+            format!("{:016x}", fnv1a(&format!("{}/{}", seed, part), offset))
+        };
+
+        TraceIds {
+            trace: format!("{}{}", id("trace", 0), id("trace", 1)),
+            job: id("job", 0),
+            queue: id("queue", 0),
+            run: id("run", 0),
+        }
+    }
+}
+
+// The 64-bit FNV-1a hash of `text` (started from a different basis for each `offset`), never 0, which OTLP takes to
+// mean no ID
+fn fnv1a(text: &str, offset: u64) -> u64 {
+    let hash = text
+        .bytes()
+        .fold(0xcbf29ce484222325_u64.wrapping_add(offset), |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        });
+    hash.max(1)
+}
+
+// A span in OTLP's JSON encoding; `status` is None for a span without one, and Some(Some(message)) for one that failed
+fn span(
+    ids: &TraceIds,
+    span_id: &str,
+    name: &str,
+    (start, end): (jiff::Timestamp, jiff::Timestamp),
+    attributes: Vec<(String, Value)>,
+    events: &[SpanEvent],
+    status: Option<Option<&str>>,
+) -> Value {
+    let mut span = json!({
+        "traceId": ids.trace,
+        "spanId": span_id,
+        "name": name,
+        "kind": SPAN_KIND_INTERNAL,
+        "startTimeUnixNano": start.as_nanosecond().to_string(),
+        "endTimeUnixNano": end.as_nanosecond().to_string(),
+        "attributes": key_values(attributes),
+        "events": events
+            .iter()
+            .map(|event| json!({
+                "timeUnixNano": event.time.as_nanosecond().to_string(),
+                "name": event.name,
+                "attributes": key_values(event.attributes.clone()),
+            }))
+            .collect::<Vec<_>>(),
+    });
+    if span_id != ids.job {
+        span["parentSpanId"] = json!(ids.job);
+    }
+    match status {
+        None => {}
+        Some(None) => span["status"] = json!({ "code": STATUS_CODE_OK }),
+        Some(Some(message)) => {
+            span["status"] = json!({ "code": STATUS_CODE_ERROR, "message": message });
+        }
+    }
+    span
+}
+
+// Attributes as OTLP's key/value list, e.g. {"key":"slurm.job.id","value":{"intValue":"1234"}}
+fn key_values(attributes: Vec<(String, Value)>) -> Vec<Value> {
+    attributes
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::Number(number) if number.is_i64() || number.is_u64() => {
+                    json!({ "intValue": number.to_string() })
+                }
+                Value::Number(number) => json!({ "doubleValue": number }),
+                Value::Bool(flag) => json!({ "boolValue": flag }),
+                Value::String(text) => json!({ "stringValue": text }),
+                other => json!({ "stringValue": other.to_string() }),
+            };
+            json!({ "key": key, "value": value })
+        })
+        .collect()
+}
+
+fn send(job_id: u64, spans: Vec<Value>) {
+    let Some(exporter) = EXPORTER.get() else {
+        return;
+    };
+    let payload = json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": key_values(vec![
+                    ("service.name".to_string(), json!(exporter.service_name)),
+                    ("service.version".to_string(), json!(env!("CARGO_PKG_VERSION"))),
+                ]),
+            },
+            "scopeSpans": [{
+                "scope": { "name": "slurmtail", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    });
+
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(TIMEOUT))
+        .build()
+        .into();
+    let mut request = agent.post(&exporter.url);
+    for (name, value) in &exporter.headers {
+        request = request.header(name, value);
+    }
+    tracing::debug!(url = %exporter.url, job_id, "Sending the job's spans over OTLP");
+    if let Err(e) = request.send_json(payload) {
+        warning!(
+            "[WARNING] Could not send job {}'s trace to the OTLP collector at {}: {}",
+            job_id,
+            exporter.url,
+            e
+        );
+    }
+}
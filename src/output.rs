@@ -0,0 +1,853 @@
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::duration::format_duration;
+use crate::error::SlurmtailError;
+use crate::history;
+use crate::scheduler::{is_active_state, scheduler};
+
+/// How slurmtail reports what it does: human-readable text, or (for other tools) one JSON event per line, or one
+/// tab-separated record per line (--porcelain)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Porcelain,
+}
+
+// The fields of each event type in porcelain records, in order (after the type and job ID)
+// This is a stable interface: fields may be added at the end of a record, but never removed or reordered
+const PORCELAIN_FIELDS: &[(&str, &[&str])] = &[
+    ("submitted", &["script", "name", "cluster"]),
+    ("log_file", &["path", "source"]),
+    ("line", &["source", "line"]),
+    ("state", &["state", "previous"]),
+    ("timeout", &["waiting_for", "seconds", "path"]),
+    ("cancelled", &[]),
+    ("exit", &["code"]),
+    ("plugin", &["name", "message", "source"]),
+    ("array_progress", &["done", "running", "pending", "failed"]),
+    (
+        "gpu_usage",
+        &["gpus", "utilization", "memory_used", "memory_total"],
+    ),
+];
+
+static OUTPUT_FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// What --tee copies into its file: everything slurmtail prints, or only the job's own output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TeeMode {
+    All,
+    Output,
+}
+
+static TEE: OnceLock<(Mutex<File>, TeeMode)> = OnceLock::new();
+
+// --quiet: leave out everything but the job's output (and warnings)
+static QUIET: OnceLock<bool> = OnceLock::new();
+
+// How many times -v was given (see diagnostics::init); any at all also shows status messages in JSON and porcelain
+// mode
+static VERBOSITY: OnceLock<u8> = OnceLock::new();
+
+/// How much a status message matters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Info,
+    Warning,
+}
+
+/// When to use color (--color)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// On terminals, unless $NO_COLOR is set (or anyway, if $CLICOLOR_FORCE is)
+    Auto,
+    Always,
+    Never,
+}
+
+// Whether stdout (the job's output and its "[source]" prefixes) and stderr (slurmtail's own messages) are colored
+static COLOR: OnceLock<(bool, bool)> = OnceLock::new();
+
+// How "[source]" prefixes, warnings, "[FATAL]" messages, and debug and trace messages are shown
+const PREFIX_STYLE: &str = "36";
+const WARNING_STYLE: &str = "33";
+const FATAL_STYLE: &str = "1;31";
+const DEBUG_STYLE: &str = "2";
+
+// Lines of job output that stand out, and the color (an ANSI SGR code) they are shown in: errors in red, warnings
+// in yellow. The first rule a line matches wins
+const SEVERITY_RULES: &[(&str, &str)] = &[
+    (
+        r"\b(ERROR|FATAL|CRITICAL)\b|^Traceback \(most recent call last\)|slurmstepd: error",
+        "31",
+    ),
+    (r"\bWARN(ING)?\b", "33"),
+];
+
+static SEVERITY_PATTERNS: OnceLock<Vec<(Regex, &str)>> = OnceLock::new();
+
+// The colors and styles highlight rules can use, and their ANSI SGR codes
+const STYLES: &[(&str, &str)] = &[
+    ("bold", "1"),
+    ("dim", "2"),
+    ("italic", "3"),
+    ("underline", "4"),
+    ("reverse", "7"),
+    ("black", "30"),
+    ("red", "31"),
+    ("green", "32"),
+    ("yellow", "33"),
+    ("blue", "34"),
+    ("magenta", "35"),
+    ("cyan", "36"),
+    ("white", "37"),
+];
+
+// The user's highlight rules (from --highlight and the config file): a pattern, and the SGR codes for the parts of
+// the job's output that match it
+static HIGHLIGHTS: OnceLock<Vec<(Regex, String)>> = OnceLock::new();
+
+// Whether the --tee file gets a header and footer describing each job (--transcript)
+static TRANSCRIPT: OnceLock<bool> = OnceLock::new();
+
+// The last state seen for each job, so that only actual transitions are reported
+static JOB_STATES: Mutex<BTreeMap<u64, String>> = Mutex::new(BTreeMap::new());
+
+// Whether the terminal's title shows the state of the job being followed, and whether it has been changed yet (so
+// there is an old title to put back)
+static TITLE: OnceLock<bool> = OnceLock::new();
+static TITLE_CHANGED: AtomicBool = AtomicBool::new(false);
+
+// When each job was first seen running, for the run time in the terminal title
+static RUNNING_SINCE: Mutex<BTreeMap<u64, Instant>> = Mutex::new(BTreeMap::new());
+
+/// Choose the output format; must happen before anything is printed
+pub fn set_output_format(format: OutputFormat) {
+    let _ = OUTPUT_FORMAT.set(format);
+}
+
+fn output_format() -> OutputFormat {
+    *OUTPUT_FORMAT.get_or_init(|| OutputFormat::Text)
+}
+
+/// Start copying output to a file (appending, so a resumed session adds to the same copy)
+pub fn set_tee(path: &Path, mode: TeeMode) -> Result<(), SlurmtailError> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(SlurmtailError::io("open the tee file", path))?;
+    let _ = TEE.set((Mutex::new(file), mode));
+
+    Ok(())
+}
+
+/// Frame each job's output in the --tee file with a header and footer describing it
+pub fn set_transcript(enabled: bool) {
+    let _ = TRANSCRIPT.set(enabled);
+}
+
+pub fn transcript_enabled() -> bool {
+    TEE.get().is_some() && TRANSCRIPT.get().copied().unwrap_or(false)
+}
+
+/// Write a transcript header or footer to the --tee file only (whatever its mode)
+pub fn tee_transcript(text: &str) {
+    if transcript_enabled() {
+        tee(text, true);
+    }
+}
+
+// Copy text to the --tee file, if there is one and it takes this kind of text
+fn tee(text: &str, job_output: bool) {
+    if let Some((file, mode)) = TEE.get()
+        && (job_output || *mode == TeeMode::All)
+        && let Ok(mut file) = file.lock()
+    {
+        let _ = file.write_all(text.as_bytes());
+    }
+}
+
+// Print text to stdout (and the --tee file), flushing right away so it shows up while the job runs
+// With --tui, it goes to the interface's message line instead
+fn write_stdout(text: &str, job_output: bool) {
+    if !crate::tui::push_message(text) {
+        crate::progress::suspend(|| {
+            // Lines from several follower threads must not interleave
+            let mut stdout = std::io::stdout().lock();
+            let _ = stdout.write_all(text.as_bytes());
+            let _ = stdout.flush();
+        });
+    }
+    tee(text, job_output);
+}
+
+// Print one of slurmtail's own messages to stderr (and the --tee file), in the given style if stderr is colored
+// With --tui, it goes to the interface's message line instead
+fn write_stderr(text: &str, style: Option<&str>) {
+    if crate::tui::push_message(text) {
+        tee(text, false);
+        return;
+    }
+
+    crate::progress::suspend(|| {
+        let mut stderr = std::io::stderr().lock();
+        let _ = match style.filter(|_| color_stderr()) {
+            Some(style) => {
+                let content = text.trim_end_matches('\n');
+                write!(
+                    stderr,
+                    "{}{}",
+                    paint(content, style),
+                    &text[content.len()..]
+                )
+            }
+            None => stderr.write_all(text.as_bytes()),
+        };
+    });
+    tee(text, false);
+}
+
+pub fn set_quiet(quiet: bool) {
+    let _ = QUIET.set(quiet);
+}
+
+fn quiet() -> bool {
+    QUIET.get().copied().unwrap_or(false)
+}
+
+/// Decide whether to use color on stdout and stderr; everything colored goes through this, so output piped to a
+/// file stays clean
+pub fn set_color(choice: ColorChoice) {
+    let env_set = |name: &str| std::env::var_os(name).is_some_and(|value| !value.is_empty());
+    let enabled = |is_terminal: bool| match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto if env_set("NO_COLOR") => false,
+        ColorChoice::Auto if env_set("CLICOLOR_FORCE") => {
+            std::env::var_os("CLICOLOR_FORCE").is_some_and(|value| value != "0")
+        }
+        ColorChoice::Auto => is_terminal,
+    };
+    let _ = COLOR.set((
+        enabled(std::io::stdout().is_terminal()),
+        enabled(std::io::stderr().is_terminal()),
+    ));
+}
+
+fn color_stdout() -> bool {
+    COLOR.get().is_some_and(|(stdout, _)| *stdout)
+}
+
+fn color_stderr() -> bool {
+    COLOR.get().is_some_and(|(_, stderr)| *stderr)
+}
+
+// Text in a style (ANSI SGR codes)
+fn paint(text: &str, style: &str) -> String {
+    format!("\x1b[{}m{}\x1b[0m", style, text)
+}
+
+/// Turn a style like "bold green" (or "bold+green") into ANSI SGR codes
+pub fn parse_style(style: &str) -> Result<String, SlurmtailError> {
+    let codes = style
+        .split(['+', ',', ' '])
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            STYLES
+                .iter()
+                .find(|(style, _)| style.eq_ignore_ascii_case(name))
+                .map(|(_, sgr)| *sgr)
+                .ok_or_else(|| {
+                    let known: Vec<&str> = STYLES.iter().map(|(style, _)| *style).collect();
+                    SlurmtailError::Invalid(format!(
+                        "Unknown style {:?} (expected one of: {})",
+                        name,
+                        known.join(", ")
+                    ))
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if codes.is_empty() {
+        return Err(SlurmtailError::Invalid(format!(
+            "No style given in {:?}",
+            style
+        )));
+    }
+
+    Ok(codes.join(";"))
+}
+
+/// Emphasize the parts of the job's output matching these rules (pattern and style, in order of precedence)
+pub fn set_highlights(rules: &[(String, String)]) -> Result<(), SlurmtailError> {
+    let highlights = rules
+        .iter()
+        .map(|(pattern, style)| {
+            let pattern = Regex::new(pattern).map_err(|e| {
+                SlurmtailError::Invalid(format!("Invalid highlight pattern {:?}: {}", pattern, e))
+            })?;
+            Ok((pattern, parse_style(style)?))
+        })
+        .collect::<Result<Vec<_>, SlurmtailError>>()?;
+    let _ = HIGHLIGHTS.set(highlights);
+
+    Ok(())
+}
+
+// A line of job output (without its line ending) with color added, if any applies: the whole line in the color of
+// the first severity rule it matches, and the parts of it matching highlight rules in their style
+fn colorize(line: &str) -> Option<String> {
+    let patterns = SEVERITY_PATTERNS.get_or_init(|| {
+        SEVERITY_RULES
+            .iter()
+            .map(|(pattern, sgr)| (Regex::new(pattern).expect("Invalid severity pattern"), *sgr))
+            .collect()
+    });
+    let severity = patterns
+        .iter()
+        .find(|(pattern, _)| pattern.is_match(line))
+        .map(|(_, sgr)| *sgr);
+
+    // Where matches of several rules overlap, the first rule wins
+    let mut spans: Vec<(usize, usize, &str)> = Vec::new();
+    for (pattern, sgr) in HIGHLIGHTS.get().map_or(&[][..], Vec::as_slice) {
+        for found in pattern.find_iter(line).filter(|found| !found.is_empty()) {
+            if spans
+                .iter()
+                .all(|(start, end, _)| found.end() <= *start || found.start() >= *end)
+            {
+                spans.push((found.start(), found.end(), sgr));
+            }
+        }
+    }
+    if severity.is_none() && spans.is_empty() {
+        return None;
+    }
+    spans.sort_unstable();
+
+    // After each highlighted part, go back to the line's own color
+    let restore = severity.map_or(String::new(), |sgr| format!("\x1b[{}m", sgr));
+    let mut colored = restore.clone();
+    let mut position = 0;
+    for (start, end, sgr) in spans {
+        colored.push_str(&line[position..start]);
+        colored.push_str(&paint(&line[start..end], sgr));
+        colored.push_str(&restore);
+        position = end;
+    }
+    colored.push_str(&line[position..]);
+    colored.push_str("\x1b[0m");
+
+    Some(colored)
+}
+
+pub fn set_verbosity(verbosity: u8) {
+    let _ = VERBOSITY.set(verbosity);
+}
+
+// The least important status messages that are shown
+fn threshold() -> Level {
+    match VERBOSITY.get().copied().unwrap_or(0) {
+        0 if quiet() || machine_readable() => Level::Warning,
+        _ => Level::Info,
+    }
+}
+
+/// Whether ordinary status messages (`"[INFO] ..."`) are shown
+pub fn shows_status() -> bool {
+    Level::Info >= threshold()
+}
+
+/// A duration as H:MM:SS, e.g. "0:42:10"
+pub fn format_elapsed(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Print a status message (see status! and warning!)
+/// Status messages go to stderr, so stdout carries nothing but the job's output (or events in JSON and porcelain
+/// mode, where only warnings are shown unless -v is given, as with --quiet)
+pub fn print_status(level: Level, message: &str) {
+    if level < threshold() {
+        return;
+    }
+
+    let style = match level {
+        Level::Warning if message.starts_with("[FATAL]") => Some(FATAL_STYLE),
+        Level::Warning => Some(WARNING_STYLE),
+        Level::Info => None,
+    };
+    write_stderr(&format!("{}\n", message), style);
+}
+
+/// Print one of slurmtail's internal diagnostics (already formatted, see diagnostics::init), dimmed
+pub fn print_diagnostic(text: &str) {
+    write_stderr(text, Some(DEBUG_STYLE));
+}
+
+/// Whether stdout is reserved for events (JSON or porcelain) rather than text meant for people
+pub fn machine_readable() -> bool {
+    output_format() != OutputFormat::Text
+}
+
+/// Print a value (e.g. the submissions for `slurmtail history --json`) to stdout as JSON, pretty-printed or on one line
+pub fn print_json(value: &impl Serialize, pretty: bool) -> Result<(), SlurmtailError> {
+    let json = if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    };
+    let json = json.map_err(|e| SlurmtailError::io("write", Path::new("/dev/stdout"))(e.into()))?;
+    println!("{}", json);
+
+    Ok(())
+}
+
+// Print one of slurmtail's own status messages ("[INFO] ...", "Submitting job...")
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {
+        $crate::output::print_status($crate::output::Level::Info, &format!($($arg)*))
+    };
+}
+
+// Print a "[WARNING] ..." (or "[FATAL] ...") message, which is shown even when status messages are not
+#[macro_export]
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::output::print_status($crate::output::Level::Warning, &format!($($arg)*))
+    };
+}
+
+// One line of --format json output: the fields every event has, followed by the event's own
+#[derive(Serialize)]
+struct Event<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    timestamp: String,
+    job_id: Option<u64>,
+    #[serde(flatten)]
+    fields: Value,
+}
+
+/// Emit one event as a line of JSON: its type, the current time, the job it concerns (if any), and its own fields
+/// e.g.: {"type":"line","timestamp":"2024-05-01T12:00:00Z","job_id":1234,"line":"epoch 1 done"}
+/// With --porcelain, the event is printed as a tab-separated record instead (see porcelain_record)
+/// Does nothing in text mode, besides logging the event to syslog with --syslog (see syslog::log_event) and notifying
+/// the desktop with --notify (see notify::notify_event), posting to webhooks (see webhook::post_event), publishing to
+/// ntfy (see ntfy::publish_event), emailing with --mail-to (see mail::mail_event), and running the user's hooks (see
+/// hooks::run_event)
+pub fn emit(kind: &str, job_id: Option<u64>, fields: Value) {
+    crate::syslog::log_event(kind, job_id, &fields);
+    crate::notify::notify_event(kind, job_id, &fields);
+    crate::webhook::post_event(kind, job_id, &fields);
+    crate::ntfy::publish_event(kind, job_id, &fields);
+    crate::mail::mail_event(kind, job_id, &fields);
+    crate::otel::record_event(kind, job_id, &fields);
+    crate::hooks::run_event(kind, job_id, &fields);
+
+    let event = match output_format() {
+        OutputFormat::Text => return,
+        OutputFormat::Porcelain => porcelain_record(kind, job_id, &fields),
+        OutputFormat::Json => {
+            let event = Event {
+                kind,
+                timestamp: jiff::Timestamp::now().to_string(),
+                job_id,
+                fields,
+            };
+            let Ok(event) = serde_json::to_string(&event) else {
+                return;
+            };
+            event
+        }
+    };
+
+    write_stdout(&format!("{}\n", event), false);
+}
+
+/// Print a line the job wrote to its log (including its line ending), prefixed with `"[source] "` when several logs
+/// are followed at once; in JSON and porcelain mode it becomes a "line" event instead
+/// While the output is paused (see keys), the line is held back until it is resumed
+pub fn job_line(job_id: Option<u64>, source: Option<&str>, line: &str) {
+    styled_job_line(job_id, source, line, None);
+}
+
+/// Print a line the job wrote, as job_line does, in a style (ANSI SGR codes, see parse_style) of its own on a
+/// colored terminal, in place of the severity and highlight rules' colors
+pub fn styled_job_line(job_id: Option<u64>, source: Option<&str>, line: &str, style: Option<&str>) {
+    if !crate::keys::hold(job_id, source, line, style) {
+        show_styled_job_line(job_id, source, line, style);
+    }
+}
+
+/// Print a line the job wrote, as job_line does, even while the output is paused
+pub fn show_job_line(job_id: Option<u64>, source: Option<&str>, line: &str) {
+    show_styled_job_line(job_id, source, line, None);
+}
+
+pub(crate) fn show_styled_job_line(
+    job_id: Option<u64>,
+    source: Option<&str>,
+    line: &str,
+    style: Option<&str>,
+) {
+    let text = match source {
+        Some(source) => format!("[{}] {}", source, line),
+        None => line.to_string(),
+    };
+
+    if crate::tui::push_line(source, line) {
+        // The interface shows the line, but the --tee file still gets it
+        tee(&text, true);
+    } else if machine_readable() {
+        emit(
+            "line",
+            job_id,
+            serde_json::json!({
+                "line": line.strip_suffix('\n').unwrap_or(line),
+                "source": source,
+            }),
+        );
+        // The events already went to an --tee file taking everything
+        if TEE.get().is_some_and(|(_, mode)| *mode == TeeMode::Output) {
+            tee(&text, true);
+        }
+    } else if color_stdout() {
+        // Only the terminal gets the color; the --tee file gets the line as the job wrote it
+        let content = line.trim_end_matches(['\r', '\n']);
+        let ending = &line[content.len()..];
+        let prefix = source.map_or(String::new(), |source| {
+            format!("{} ", paint(&format!("[{}]", source), PREFIX_STYLE))
+        });
+        let body = match style {
+            Some(style) => paint(content, style),
+            None => colorize(content).unwrap_or_else(|| content.to_string()),
+        };
+
+        crate::progress::suspend(|| {
+            let mut stdout = std::io::stdout().lock();
+            let _ = write!(stdout, "{}{}{}", prefix, body, ending);
+            let _ = stdout.flush();
+        });
+        tee(&text, true);
+    } else {
+        write_stdout(&text, true);
+    }
+}
+
+// An event as a porcelain record: its type, job ID, and fields, separated by tabs
+// e.g.: "state\t1234\tRUNNING\tPENDING"
+// Missing values are written as "-"; tabs and newlines inside values become spaces, so every record is one line
+fn porcelain_record(kind: &str, job_id: Option<u64>, fields: &Value) -> String {
+    let names = PORCELAIN_FIELDS
+        .iter()
+        .find(|(event, _)| *event == kind)
+        .map_or(&[][..], |(_, names)| *names);
+
+    let mut record = vec![
+        kind.to_string(),
+        job_id.map_or("-".to_string(), |id| id.to_string()),
+    ];
+    for name in names {
+        let value = match fields.get(*name) {
+            None | Some(Value::Null) => "-".to_string(),
+            Some(Value::String(value)) => value.clone(),
+            Some(value) => value.to_string(),
+        };
+        record.push(value.replace(['\t', '\n', '\r'], " "));
+    }
+
+    record.join("\t")
+}
+
+/// Show the state of the job being followed in the terminal's title (on stderr, which must be a terminal)
+pub fn set_terminal_title(enabled: bool) {
+    let _ = TITLE.set(enabled);
+}
+
+pub fn shows_terminal_title() -> bool {
+    TITLE.get().copied().unwrap_or(false)
+}
+
+// Set the terminal's title to e.g. "slurmtail 12345 RUNNING 0:42:10"
+fn update_terminal_title(job_id: u64, state: &str) {
+    // The full-screen interface shows the job's state itself, and owns the terminal
+    if !shows_terminal_title() || crate::tui::active() {
+        return;
+    }
+
+    let run_time = RUNNING_SINCE.lock().ok().and_then(|mut running_since| {
+        if state != "RUNNING" {
+            return None;
+        }
+        Some(
+            running_since
+                .entry(job_id)
+                .or_insert_with(Instant::now)
+                .elapsed(),
+        )
+    });
+    let title = match run_time {
+        Some(run_time) => format!(
+            "slurmtail {} {} {}",
+            job_id,
+            state,
+            format_elapsed(run_time)
+        ),
+        None => format!("slurmtail {} {}", job_id, state),
+    };
+
+    let mut stderr = std::io::stderr().lock();
+    // Save the old title first (on terminals that keep a stack of them), so it can be put back on exit
+    if !TITLE_CHANGED.swap(true, Ordering::SeqCst) {
+        let _ = stderr.write_all(b"\x1b[22;0t");
+    }
+    let _ = write!(stderr, "\x1b]0;{}\x07", title);
+}
+
+/// Put back the terminal's title from before slurmtail changed it
+pub fn restore_terminal_title() {
+    if TITLE_CHANGED.load(Ordering::SeqCst) {
+        let _ = std::io::stderr().write_all(b"\x1b[23;0t");
+    }
+}
+
+/// Note the state a job was just seen in, emitting a "state" event if it changed since the last time
+pub fn observe_state(job_id: u64, state: &str) {
+    update_terminal_title(job_id, state);
+    crate::tui::observe_state(job_id, state);
+
+    let Ok(mut states) = JOB_STATES.lock() else {
+        return;
+    };
+    if states.get(&job_id).map(String::as_str) == Some(state) {
+        return;
+    }
+    let previous = states.insert(job_id, state.to_string());
+    drop(states);
+
+    tracing::debug!(job_id, state, previous = ?previous, "Job state changed");
+
+    emit(
+        "state",
+        Some(job_id),
+        serde_json::json!({ "state": state, "previous": previous }),
+    );
+}
+
+/// Ask the user a yes/no question on the terminal (anything other than y/yes counts as no)
+/// The question goes to stderr, as stdout is reserved for the job's output
+pub fn confirm(question: &str) -> Result<bool, SlurmtailError> {
+    // The question needs the terminal back from the --tui interface (or from reading keys)
+    crate::tui::stop(false);
+    crate::keys::stop();
+    eprint!("{} [y/N] ", question);
+
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .map_err(SlurmtailError::io("read", Path::new("/dev/stdin")))?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Start a job's section of the --transcript file: what was submitted, where, when, and with which overrides
+pub fn write_transcript_header(
+    script_path: &Path,
+    extra_args: &[String],
+    job_id: u64,
+    submitted_at: jiff::Timestamp,
+) {
+    if !transcript_enabled() {
+        return;
+    }
+
+    // sbatch's input environment variables override the script's directives too
+    let mut overrides: Vec<String> = env::vars()
+        .filter(|(name, value)| name.starts_with("SBATCH_") && !value.is_empty())
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    overrides.sort();
+    overrides.extend(extra_args.iter().cloned());
+
+    let script = script_path
+        .canonicalize()
+        .unwrap_or_else(|_| script_path.to_path_buf());
+    let mut header = format!(
+        "===== slurmtail transcript: job {} =====\n\
+         Job ID:    {}\n\
+         Cluster:   {}\n\
+         Script:    {}\n\
+         Submitted: {}\n\
+         Overrides: {}\n",
+        job_id,
+        job_id,
+        scheduler()
+            .job_cluster(job_id)
+            .unwrap_or_else(|| "-".to_string()),
+        script.display(),
+        submitted_at,
+        if overrides.is_empty() {
+            "-".to_string()
+        } else {
+            overrides.join(" ")
+        }
+    );
+    header.push_str(&"=".repeat(40));
+    header.push('\n');
+    tee_transcript(&header);
+}
+
+/// A finished job's accounting fields (but its state), one "Field:  value" line each, for the end-of-job summaries
+/// The energy it used (sacct's ConsumedEnergyRaw, in joules) is shown in kWh as well
+pub fn accounting_summary(accounting: &[(String, String)]) -> String {
+    let mut summary = String::new();
+    for (field, value) in accounting {
+        if field == "State" || value.is_empty() {
+            continue;
+        }
+        let line = match (field.as_str(), value.parse::<f64>()) {
+            ("ConsumedEnergyRaw", Ok(joules)) => {
+                format!("{:<10} {} J ({:.2} kWh)", "Energy:", value, joules / 3.6e6)
+            }
+            _ => format!("{:<10} {}", format!("{}:", field), value),
+        };
+        summary.push_str(&line);
+        summary.push('\n');
+    }
+
+    summary
+}
+
+/// End a job's section of the --transcript file with the state it ended in and its accounting summary
+/// (or the state it was still in when slurmtail stopped following it)
+pub fn write_transcript_footer(job_id: u64) {
+    if !transcript_enabled() {
+        return;
+    }
+
+    let state = scheduler()
+        .job_state(job_id)
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "UNKNOWN".to_string());
+    let mut footer = if is_active_state(&state) {
+        format!(
+            "===== job {}: still {} when slurmtail stopped following it =====\n",
+            job_id, state
+        )
+    } else {
+        let mut footer = format!("===== job {} finished: {} =====\n", job_id, state);
+        footer.push_str(&accounting_summary(
+            &scheduler().job_accounting(job_id).unwrap_or_default(),
+        ));
+        footer
+    };
+    footer.push_str(&"=".repeat(40));
+    footer.push('\n');
+    tee_transcript(&footer);
+}
+
+/// Print the most recent submissions from the history, newest first
+pub fn print_history(limit: usize, as_json: bool) -> Result<(), SlurmtailError> {
+    let submissions = history::recent_submissions(limit)?;
+
+    if as_json {
+        print_json(&submissions, true)?;
+        return Ok(());
+    }
+    if submissions.is_empty() {
+        println!("No submissions recorded yet");
+        return Ok(());
+    }
+
+    for submission in &submissions {
+        let submitted_at = submission
+            .submitted_at
+            .parse::<jiff::Timestamp>()
+            .map(|time| {
+                time.to_zoned(jiff::tz::TimeZone::system())
+                    .strftime("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_else(|_| submission.submitted_at.clone());
+        let field = |name: &str| {
+            submission
+                .accounting
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value.as_str())
+                .filter(|value| !value.is_empty())
+        };
+        println!(
+            "  job {:<10} {:<16} {:<12} {:<6} {:<10} {}  {}",
+            submission.job_id,
+            submission.name.as_deref().unwrap_or("-"),
+            submission.final_state.as_deref().unwrap_or("-"),
+            field("ExitCode").unwrap_or("-"),
+            field("Elapsed").unwrap_or("-"),
+            submitted_at,
+            submission
+                .script
+                .as_ref()
+                .map_or("-".to_string(), |script| script.display().to_string())
+        );
+    }
+
+    Ok(())
+}
+
+/// Print how each script's runs went, from the submission history
+pub fn print_report(as_json: bool) -> Result<(), SlurmtailError> {
+    let reports = history::report(&history::all_submissions()?);
+
+    if as_json {
+        print_json(&reports, true)?;
+        return Ok(());
+    }
+    if reports.is_empty() {
+        println!("No submissions recorded yet");
+        return Ok(());
+    }
+
+    println!(
+        "  {:>5} {:>9} {:>6} {:>9} {:>10} {:>12} {:>10}  script",
+        "runs", "completed", "failed", "cancelled", "unfinished", "mean time", "energy"
+    );
+    for report in &reports {
+        println!(
+            "  {:>5} {:>9} {:>6} {:>9} {:>10} {:>12} {:>10}  {}",
+            report.runs,
+            report.completed,
+            report.failed,
+            report.cancelled,
+            report.unfinished,
+            report.mean_elapsed.map_or("-".to_string(), |seconds| {
+                format_duration(Duration::from_secs(seconds))
+            }),
+            report.energy.map_or("-".to_string(), |joules| {
+                format!("{:.2} kWh", joules as f64 / 3_600_000.0)
+            }),
+            report.script
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use std::io::IsTerminal;
+use std::path::Path;
+use std::process::Command;
+
+use crate::{interrupt, keys, output, remote, tui};
+
+// The pager used when $PAGER isn't set
+const DEFAULT_PAGER: &str = "less";
+
+// Once the job has ended, open its complete log in $PAGER (less, by default, starting at the end), to scroll back
+// through everything rather than just what streamed past (--pager-on-exit)
+// Skipped without a terminal, with --tui (which already keeps all of it), and when following was stopped early
+pub fn show_log(log_path: &Path) {
+    // With --remote, that's the copy of the log kept here
+    let log_path = &remote::local_copy(log_path);
+    if !std::io::stdin().is_terminal()
+        || !std::io::stdout().is_terminal()
+        || output::machine_readable()
+        || tui::active()
+        || interrupt::interrupted()
+        || interrupt::terminated().is_some()
+        || !log_path.exists()
+    {
+        return;
+    }
+    // The pager reads the keys now
+    keys::stop();
+
+    // $PAGER may carry options of its own, e.g. "less -R"
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_PAGER.to_string());
+    let mut words = pager.split_whitespace();
+    let Some(program) = words.next() else {
+        return;
+    };
+    let mut command = Command::new(program);
+    command.args(words);
+    if Path::new(program)
+        .file_name()
+        .is_some_and(|name| name == "less")
+    {
+        command.arg("+G");
+    }
+    command.arg(log_path);
+
+    tracing::debug!(pager = %pager, path = ?log_path, "Opening the log in a pager");
+    if let Err(e) = command.status() {
+        warning!("[WARNING] Could not run pager {:?}: {}", program, e);
+    }
+}
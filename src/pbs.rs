@@ -0,0 +1,201 @@
+use std::fs::read_to_string;
+use std::path::Path;
+use std::process::Stdio;
+
+use crate::directives::{directive_lines, directive_words};
+use crate::error::SlurmtailError;
+use crate::remote;
+use crate::scheduler::{Scheduler, split_sbatch_override};
+
+// Find the value of a flag (e.g. "-o") in the #PBS lines of a script
+// Several flags may share a line, e.g.: "#PBS -l nodes=1:ppn=4 -o output.log"
+//       -> "output.log"
+fn find_pbs_directive(content: &str, flag: &str) -> Option<String> {
+    for line in directive_lines(content) {
+        let Some(directive) = line.trim().strip_prefix("#PBS") else {
+            continue;
+        };
+
+        let mut words = directive_words(directive).into_iter();
+        while let Some(word) = words.next() {
+            if word == flag {
+                return words.next();
+            }
+        }
+    }
+
+    None
+}
+
+// Parse the numeric part of a PBS job ID
+// e.g.: "1234.pbs-server.example.org" -> 1234, "1234[].pbs-server" -> 1234
+fn parse_pbs_job_id(job_id: &str) -> Option<u64> {
+    let digits: String = job_id
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+
+    digits.parse::<u64>().ok()
+}
+
+// Read "key = value" attributes out of `qstat -f` output (keys are matched case-insensitively, since Torque and
+// PBS Pro disagree on e.g. "exit_status" vs "Exit_status")
+fn qstat_attribute(qstat_output: &str, key: &str) -> Option<String> {
+    qstat_output.lines().find_map(|line| {
+        line.split_once('=')
+            .filter(|(k, _)| k.trim().eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.trim().to_string())
+    })
+}
+
+// Translate a PBS job state letter into the SLURM state slurmtail reasons about
+fn pbs_state_to_slurm(state: &str, exit_status: Option<&str>) -> String {
+    match state {
+        "Q" | "H" | "W" | "T" => "PENDING".to_string(),
+        "R" | "B" => "RUNNING".to_string(),
+        "E" => "COMPLETING".to_string(),
+        "S" | "U" => "SUSPENDED".to_string(),
+        // Finished jobs (Torque keeps them around as C, PBS Pro's history shows them as F)
+        "C" | "F" => match exit_status {
+            None | Some("0") => "COMPLETED".to_string(),
+            // Exit statuses above 256 mean the job was killed by a signal (e.g. qdel)
+            Some(status) if status.parse::<i64>().is_ok_and(|s| s > 256) => "CANCELLED".to_string(),
+            Some(_) => "FAILED".to_string(),
+        },
+        other => other.to_string(),
+    }
+}
+
+// PBS Pro / Torque command-line tools: qsub, qstat, and qdel
+pub struct PbsCli;
+
+impl Scheduler for PbsCli {
+    fn name(&self) -> &'static str {
+        "pbs"
+    }
+
+    // PBS has no filename placeholders; without "#PBS -o" the output goes to "<job name>.o<job number>"
+    fn output_pattern(&self, script_path: &Path) -> Result<String, SlurmtailError> {
+        let content =
+            read_to_string(script_path).map_err(SlurmtailError::io("read", script_path))?;
+        let job_name = match find_pbs_directive(&content, "-N") {
+            Some(name) => name,
+            None => script_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .ok_or_else(|| {
+                    SlurmtailError::Invalid(format!(
+                        "Could not determine the default PBS job name for {:?}",
+                        script_path
+                    ))
+                })?,
+        };
+        let default_filename = format!("{}.o%j", job_name);
+
+        let Some(output) = find_pbs_directive(&content, "-o") else {
+            return Ok(default_filename);
+        };
+
+        // "-o host:/path/to/file" names the host the file is copied to; we only care about the path
+        let output = match output.split_once(':') {
+            Some((host, path)) if !host.contains('/') => path.to_string(),
+            _ => output,
+        };
+
+        // An output directory gets the default file name inside it
+        if output.ends_with('/') {
+            Ok(format!("{}{}", output, default_filename))
+        } else {
+            Ok(output)
+        }
+    }
+
+    fn job_name(&self, script_path: &Path) -> Result<Option<String>, SlurmtailError> {
+        let content =
+            read_to_string(script_path).map_err(SlurmtailError::io("read", script_path))?;
+
+        Ok(find_pbs_directive(&content, "-N"))
+    }
+
+    // Submit with qsub, translating the sbatch-style extra arguments slurmtail uses into qsub options
+    fn submit(&self, script_path: &Path, extra_args: &[String]) -> Result<u64, SlurmtailError> {
+        let mut qsub_args: Vec<String> = Vec::new();
+        for arg in extra_args {
+            let (option, value) = split_sbatch_override(arg)?;
+            match option {
+                // PBS dependency syntax matches SLURM's ("afterok:1234:1235")
+                "dependency" => qsub_args.extend(["-W".to_string(), format!("depend={}", value)]),
+                "partition" => qsub_args.extend(["-q".to_string(), value.to_string()]),
+                "account" => qsub_args.extend(["-A".to_string(), value.to_string()]),
+                "job-name" => qsub_args.extend(["-N".to_string(), value.to_string()]),
+                "output" => qsub_args.extend(["-o".to_string(), value.to_string()]),
+                "error" => qsub_args.extend(["-e".to_string(), value.to_string()]),
+                _ => {
+                    return Err(SlurmtailError::unsupported(
+                        format!("--{}", option),
+                        self.name(),
+                    ));
+                }
+            }
+        }
+
+        let output = remote::Command::new("qsub")
+            .args(&qsub_args)
+            .script(script_path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(SlurmtailError::scheduler("qsub", stderr));
+        }
+
+        // qsub prints just the full job ID, e.g. "1234.pbs-server"
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        stdout
+            .split_whitespace()
+            .find_map(parse_pbs_job_id)
+            .ok_or_else(|| {
+                SlurmtailError::scheduler("qsub", "Could not extract job ID from its output")
+            })
+    }
+
+    // qstat only lists finished jobs for a while (Torque) or with -x (PBS Pro), so try both
+    fn job_state(&self, job_id: u64) -> Result<Option<String>, SlurmtailError> {
+        for args in [vec!["-f"], vec!["-x", "-f"]] {
+            let output = remote::Command::new("qstat")
+                .args(&args)
+                .arg(job_id.to_string())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .output()?;
+
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            if output.status.success()
+                && let Some(state) = qstat_attribute(&stdout, "job_state")
+            {
+                let exit_status = qstat_attribute(&stdout, "exit_status");
+                return Ok(Some(pbs_state_to_slurm(&state, exit_status.as_deref())));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn cancel(&self, job_id: u64) -> Result<(), SlurmtailError> {
+        let output = remote::Command::new("qdel")
+            .arg(job_id.to_string())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(SlurmtailError::scheduler("qdel", stderr));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,396 @@
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::directives::SbatchDirectives;
+use crate::error::{SlurmtailError, current_dir};
+use crate::filter;
+use crate::output::{self, write_transcript_footer, write_transcript_header};
+use crate::scheduler::{is_active_state, scheduler};
+use crate::script::{
+    extract_array_spec, extract_error_pattern, first_array_task, format_log_output_string,
+    logfile_string_to_path,
+};
+use crate::state::{ResumeState, record_outcome, record_submission, save_turd, write_atomically};
+use crate::watch::{
+    ExistingLogs, FollowOptions, FollowTarget, check_log_collisions, check_log_dirs,
+    check_placement, job_outcome, mon_logfile, mon_logfiles,
+};
+
+// Name of the file (in the project dir) that records the job IDs of the last submitted pipeline
+pub const PIPELINE_STATE_FILE: &str = "._slurmtail_pipeline";
+
+// A pipeline manifest: a list of named jobs and the dependencies between them
+#[derive(Debug, Deserialize)]
+pub struct Manifest {
+    pub jobs: Vec<PipelineJob>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PipelineJob {
+    pub name: String,
+    pub script: PathBuf,
+    // Extra arguments handed to sbatch for this job only (e.g. "--partition=debug")
+    #[serde(default)]
+    pub sbatch_args: Vec<String>,
+    // Names of jobs that must finish before this one may start
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    // SLURM dependency type used for `depends_on` (afterok, afterany, afternotok, ...)
+    #[serde(default = "default_dependency_type")]
+    pub dependency_type: String,
+}
+
+fn default_dependency_type() -> String {
+    "afterok".to_string()
+}
+
+// A job from the manifest after it has been handed to sbatch
+#[derive(Debug)]
+pub struct SubmittedJob {
+    pub name: String,
+    pub job_id: u64,
+    pub log_path: PathBuf,
+    pub array_spec: Option<String>,
+    pub script: PathBuf,
+    // Everything handed to sbatch besides the script (the profile's options, dependencies, and the manifest's
+    // sbatch_args)
+    pub sbatch_args: Vec<String>,
+}
+
+// Read a manifest from disk, choosing the format based on the file extension (YAML unless it ends in .toml)
+pub fn load_manifest(manifest_path: &Path) -> Result<Manifest, SlurmtailError> {
+    let content =
+        read_to_string(manifest_path).map_err(SlurmtailError::io("read", manifest_path))?;
+
+    let mut manifest: Manifest = match manifest_path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml::from_str(&content)
+            .map_err(|e| SlurmtailError::parse("manifest", manifest_path, e))?,
+        _ => serde_yaml::from_str(&content)
+            .map_err(|e| SlurmtailError::parse("manifest", manifest_path, e))?,
+    };
+
+    // Scripts are given relative to the manifest, not to wherever slurmtail is invoked from
+    let base_dir = manifest_path.parent().unwrap_or(Path::new("."));
+    for job in manifest.jobs.iter_mut() {
+        if job.script.is_relative() {
+            job.script = base_dir.join(&job.script);
+        }
+    }
+
+    Ok(manifest)
+}
+
+// Order the jobs so that every job comes after all of its dependencies (keeping manifest order where possible)
+pub fn submission_order(manifest: &Manifest) -> Result<Vec<&PipelineJob>, SlurmtailError> {
+    let mut seen_names = HashSet::new();
+    for job in &manifest.jobs {
+        if !seen_names.insert(job.name.as_str()) {
+            return Err(SlurmtailError::Invalid(format!(
+                "Duplicate job name in pipeline: {}",
+                job.name
+            )));
+        }
+    }
+
+    for job in &manifest.jobs {
+        for dep in &job.depends_on {
+            if !seen_names.contains(dep.as_str()) {
+                return Err(SlurmtailError::Invalid(format!(
+                    "Job '{}' depends on unknown job '{}'",
+                    job.name, dep
+                )));
+            }
+        }
+    }
+
+    let mut ordered: Vec<&PipelineJob> = Vec::with_capacity(manifest.jobs.len());
+    let mut placed: HashSet<&str> = HashSet::new();
+
+    while ordered.len() < manifest.jobs.len() {
+        let ready = manifest.jobs.iter().find(|job| {
+            !placed.contains(job.name.as_str())
+                && job
+                    .depends_on
+                    .iter()
+                    .all(|dep| placed.contains(dep.as_str()))
+        });
+
+        match ready {
+            Some(job) => {
+                placed.insert(job.name.as_str());
+                ordered.push(job);
+            }
+            None => {
+                let stuck: Vec<&str> = manifest
+                    .jobs
+                    .iter()
+                    .filter(|job| !placed.contains(job.name.as_str()))
+                    .map(|job| job.name.as_str())
+                    .collect();
+                return Err(SlurmtailError::Invalid(format!(
+                    "Pipeline has a dependency cycle between jobs: {}",
+                    stuck.join(", ")
+                )));
+            }
+        }
+    }
+
+    Ok(ordered)
+}
+
+// Submit every job in the pipeline, wiring up SLURM dependencies between them; the config's defaults (e.g. from
+// --profile) go to every job whose script doesn't set them, before its own sbatch_args, which win over them
+// Every job's script, log directories, and placement are checked before any job is submitted (see check_log_dirs and
+// check_placement), so a mistake doesn't leave half a pipeline in the queue; log files left by earlier jobs are
+// warned about (or backed up, or emptied) then too
+pub fn submit_pipeline(
+    manifest: &Manifest,
+    config: &Config,
+    create_log_dirs: bool,
+    existing_logs: ExistingLogs,
+) -> Result<Vec<SubmittedJob>, SlurmtailError> {
+    let ordered = submission_order(manifest)?;
+
+    for job in &ordered {
+        if !job.script.exists() {
+            return Err(SlurmtailError::NotFound(format!(
+                "Script for pipeline job '{}' does not exist: {:?}",
+                job.name, job.script
+            )));
+        }
+        let overrides = SbatchDirectives::from_args(&job.sbatch_args);
+        let log_patterns = [
+            Some(match overrides.output {
+                Some(pattern) => pattern,
+                None => scheduler().output_pattern(&job.script)?,
+            }),
+            match overrides.error {
+                Some(pattern) => Some(pattern),
+                None => extract_error_pattern(&job.script)?,
+            },
+        ];
+        let log_patterns: Vec<String> = log_patterns.into_iter().flatten().collect();
+        check_log_dirs(&job.script, &log_patterns, create_log_dirs)?;
+        let mut args = config.submit_args(&job.script)?;
+        args.extend(job.sbatch_args.iter().cloned());
+        let directives = SbatchDirectives::from_file(&job.script)?.with_args(&args);
+        check_placement(&directives)?;
+        check_log_collisions(&job.script, &log_patterns, &directives, existing_logs)?;
+    }
+
+    let mut job_ids: HashMap<&str, u64> = HashMap::new();
+    let mut submitted = Vec::with_capacity(ordered.len());
+
+    for job in ordered {
+        // Overrides on the command line win over the script's own directives, just like sbatch
+        let overrides = SbatchDirectives::from_args(&job.sbatch_args);
+        let log_pattern = match overrides.output {
+            Some(pattern) => pattern,
+            None => scheduler().output_pattern(&job.script)?,
+        };
+        let job_name = match overrides.job_name {
+            Some(name) => Some(name),
+            None => scheduler().job_name(&job.script)?,
+        };
+        let array_spec = match overrides.array {
+            Some(spec) => Some(spec),
+            None => extract_array_spec(&job.script)?,
+        };
+        let array_task = array_spec.as_deref().and_then(first_array_task);
+
+        let mut sbatch_args = config.submit_args(&job.script)?;
+        if !job.depends_on.is_empty() {
+            let dep_ids: Vec<String> = job
+                .depends_on
+                .iter()
+                .map(|dep| job_ids[dep.as_str()].to_string())
+                .collect();
+            sbatch_args.push(format!(
+                "--dependency={}:{}",
+                job.dependency_type,
+                dep_ids.join(":")
+            ));
+        }
+        sbatch_args.extend(job.sbatch_args.iter().cloned());
+
+        status!("Submitting pipeline job '{}'...", job.name);
+        let job_id = scheduler()
+            .submit(&job.script, &sbatch_args)
+            .map_err(|e| SlurmtailError::SubmitFailed(e.to_string()))?;
+        status!("Pipeline job '{}' submitted with ID: {}", job.name, job_id);
+        output::emit(
+            "submitted",
+            Some(job_id),
+            serde_json::json!({
+                "script": job.script,
+                "name": job.name,
+                "cluster": scheduler().job_cluster(job_id),
+            }),
+        );
+
+        if let Some(spec) = &array_spec {
+            warning!(
+                "[WARNING] Pipeline job '{}' is a job array (--array={}), but only the output of task {} will be followed.",
+                job.name,
+                spec,
+                array_task.map_or("?".to_string(), |t| t.to_string())
+            );
+        }
+
+        let log_filename =
+            format_log_output_string(log_pattern, job_id, job_name.as_ref(), array_task, None);
+        let log_path = logfile_string_to_path(&job.script, log_filename, true)?;
+
+        job_ids.insert(job.name.as_str(), job_id);
+        submitted.push(SubmittedJob {
+            name: job.name.clone(),
+            job_id,
+            log_path,
+            array_spec,
+            script: job.script.clone(),
+            sbatch_args,
+        });
+    }
+
+    Ok(submitted)
+}
+
+// Record the submitted pipeline so its jobs can be found again later (one "name<TAB>jobid<TAB>logpath" line per job)
+pub fn save_pipeline_state(
+    project_dir: &Path,
+    jobs: &[SubmittedJob],
+) -> Result<(), SlurmtailError> {
+    let state_path: PathBuf = project_dir.to_path_buf().join(PIPELINE_STATE_FILE);
+
+    let mut content = String::new();
+    for job in jobs {
+        content.push_str(&format!(
+            "{}\t{}\t{}\n",
+            job.name,
+            job.job_id,
+            job.log_path.to_string_lossy()
+        ));
+    }
+
+    write_atomically(&state_path, content.as_bytes()).map_err(SlurmtailError::io(
+        "write the pipeline state file",
+        &state_path,
+    ))
+}
+
+// Follow the logs of all submitted jobs, either one after another (in submission order) or all at once, with the given
+// options for the output (the timeout, how often to check for more, ...)
+pub fn follow_pipeline(
+    jobs: &[SubmittedJob],
+    sequential: bool,
+    options: &FollowOptions,
+) -> Result<(), SlurmtailError> {
+    // Downstream jobs can sit in the queue for as long as their dependencies run, so there is no file timeout here;
+    // each tail instead ends once its job has left the queue, whether or not its log file ever appeared
+    let options = FollowOptions {
+        file_appear_timeout: None,
+        no_file_timeout: true,
+        watch_job: true,
+        ..*options
+    };
+    if sequential {
+        for job in jobs {
+            status!(
+                "[INFO] Following pipeline job '{}' ({})",
+                job.name,
+                job.job_id
+            );
+            mon_logfile(
+                &job.log_path,
+                &options,
+                Some(&job.name),
+                Some(job.job_id),
+                None,
+            )?;
+        }
+        return Ok(());
+    }
+
+    let targets: Vec<FollowTarget> = jobs
+        .iter()
+        .map(|job| FollowTarget {
+            prefix: job.name.clone(),
+            log_path: job.log_path.clone(),
+            job_id: Some(job.job_id),
+        })
+        .collect();
+
+    mon_logfiles(&targets, &options)
+}
+
+// `slurmtail pipeline run`: submit a manifest's jobs, record them (in the pipeline state file, the submission history,
+// and a session for the last one, which `resume` then picks up), follow them, and fail the way the first job that
+// didn't complete did
+pub fn run_pipeline(
+    manifest_path: &Path,
+    config: &Config,
+    create_log_dirs: bool,
+    existing_logs: ExistingLogs,
+    sequential: bool,
+    options: &FollowOptions,
+) -> Result<(), SlurmtailError> {
+    if !manifest_path.exists() {
+        return Err(SlurmtailError::NotFound(format!(
+            "Manifest file does not exist: {:?}",
+            manifest_path
+        )));
+    }
+
+    let manifest = load_manifest(manifest_path)?;
+    let jobs = submit_pipeline(&manifest, config, create_log_dirs, existing_logs)?;
+
+    // Save pipeline state, and point the resume file at the final job's log
+    let current_dir = current_dir()?;
+    save_pipeline_state(&current_dir, &jobs)?;
+    for job in &jobs {
+        let submitted_at = jiff::Timestamp::now();
+        record_submission(
+            &job.script,
+            &job.sbatch_args,
+            job.job_id,
+            &job.log_path,
+            Some(&job.name),
+            submitted_at,
+        );
+        write_transcript_header(&job.script, &job.sbatch_args, job.job_id, submitted_at);
+    }
+    if let Some(last_job) = jobs.last() {
+        save_turd(
+            &current_dir,
+            &ResumeState {
+                job_id: Some(last_job.job_id),
+                cluster: scheduler().job_cluster(last_job.job_id),
+                script: Some(last_job.script.clone()),
+                array: last_job.array_spec.clone(),
+                submitted_at: Some(jiff::Timestamp::now().to_string()),
+                project: Some(current_dir.clone()),
+                ..ResumeState::new(&last_job.log_path)
+            },
+        )?;
+    }
+
+    let result = follow_pipeline(&jobs, sequential, options);
+    // The pipeline failed the way its first unsuccessful job did
+    let mut outcome = Ok(());
+    for job in &jobs {
+        write_transcript_footer(job.job_id);
+        if let Ok(Some(state)) = scheduler().job_state(job.job_id)
+            && !is_active_state(&state)
+        {
+            record_outcome(job.job_id, &state);
+            if outcome.is_ok() && !filter::stopped() {
+                outcome = job_outcome(job.job_id, Some(&state));
+            }
+        }
+    }
+    result?;
+    outcome
+}
@@ -0,0 +1,231 @@
+use std::path::PathBuf;
+
+use crate::error::SlurmtailError;
+
+// What the plugins made of a line of the job's output
+pub enum Outcome {
+    Unchanged,
+    // Dropped, as if the job had never written it
+    Dropped,
+    // Rewritten (with the line's ending kept), and/or given a style (ANSI SGR codes, see output::parse_style)
+    Changed { line: String, style: Option<String> },
+}
+
+#[cfg(feature = "lua")]
+mod lua {
+    use mlua::{Function, Lua, Table, Value};
+    use serde_json::json;
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+
+    use super::Outcome;
+    use crate::error::SlurmtailError;
+    use crate::output;
+
+    // A loaded plugin: its on_line function, in a Lua state of its own
+    struct Plugin {
+        path: PathBuf,
+        lua: Lua,
+        // Turned off after failing on a line, so one bad plugin doesn't drown the output in warnings
+        failed: bool,
+    }
+
+    // Events a plugin raised (with slurmtail.event) while looking at the current line: their names and messages
+    type RaisedEvents = Vec<(String, Option<String>)>;
+
+    static PLUGINS: Mutex<Vec<Plugin>> = Mutex::new(Vec::new());
+
+    pub fn load(paths: &[PathBuf]) -> Result<(), SlurmtailError> {
+        let plugins = paths
+            .iter()
+            .map(|path| {
+                load_plugin(path).map_err(|e| SlurmtailError::Plugin {
+                    path: path.clone(),
+                    message: e.to_string(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Ok(mut loaded) = PLUGINS.lock() {
+            *loaded = plugins;
+        }
+
+        Ok(())
+    }
+
+    fn load_plugin(path: &Path) -> mlua::Result<Plugin> {
+        let code = std::fs::read_to_string(path).map_err(mlua::Error::external)?;
+        let lua = Lua::new();
+        lua.set_app_data(RaisedEvents::new());
+
+        // slurmtail.event(name[, message]): raise an event about the line (see output::emit)
+        let slurmtail = lua.create_table()?;
+        slurmtail.set(
+            "event",
+            lua.create_function(|lua, (name, message): (String, Option<String>)| {
+                if let Some(mut raised) = lua.app_data_mut::<RaisedEvents>() {
+                    raised.push((name, message));
+                }
+                Ok(())
+            })?,
+        )?;
+        lua.globals().set("slurmtail", slurmtail)?;
+
+        lua.load(&code)
+            .set_name(path.display().to_string())
+            .exec()?;
+        if lua
+            .globals()
+            .get::<_, Option<Function>>("on_line")?
+            .is_none()
+        {
+            return Err(mlua::Error::RuntimeError(
+                "it doesn't define an on_line function".to_string(),
+            ));
+        }
+        tracing::debug!(path = ?path, "Loaded plugin");
+
+        Ok(Plugin {
+            path: path.to_path_buf(),
+            lua,
+            failed: false,
+        })
+    }
+
+    pub fn active() -> bool {
+        PLUGINS.lock().is_ok_and(|plugins| !plugins.is_empty())
+    }
+
+    // Pass the line (without its ending) through each plugin's on_line in turn, each getting what the last one made
+    // of it, until one drops it
+    pub fn transform(job_id: Option<u64>, source: Option<&str>, line: &str) -> Outcome {
+        let Ok(mut plugins) = PLUGINS.lock() else {
+            return Outcome::Unchanged;
+        };
+        let content = line.trim_end_matches(['\r', '\n']);
+        let ending = &line[content.len()..];
+        let mut current = content.to_string();
+        let mut style = None;
+        let mut changed = false;
+
+        for plugin in plugins.iter_mut().filter(|plugin| !plugin.failed) {
+            let result = call(&plugin.lua, job_id, source, &current);
+            let raised = plugin
+                .lua
+                .app_data_mut::<RaisedEvents>()
+                .map(|mut raised| std::mem::take(&mut *raised))
+                .unwrap_or_default();
+            for (name, message) in raised {
+                raise(job_id, source, &name, message.as_deref());
+            }
+
+            match result {
+                Ok(None) => return Outcome::Dropped,
+                Ok(Some((line, line_style))) => {
+                    if let Some(line) = line {
+                        changed |= line != current;
+                        current = line;
+                    }
+                    if line_style.is_some() {
+                        changed = true;
+                        style = line_style;
+                    }
+                }
+                Err(e) => {
+                    warning!(
+                        "[WARNING] Plugin {} failed, and is turned off: {}",
+                        plugin.path.display(),
+                        e
+                    );
+                    plugin.failed = true;
+                }
+            }
+        }
+
+        if !changed {
+            return Outcome::Unchanged;
+        }
+        Outcome::Changed {
+            line: format!("{}{}", current, ending),
+            style,
+        }
+    }
+
+    // What on_line(line, job) made of the line: None if it dropped it (returned false), or else the line it was
+    // rewritten to (a string, or a table's `line`) and the style it was given (a table's `style`), if any
+    #[allow(clippy::type_complexity)]
+    fn call(
+        lua: &Lua,
+        job_id: Option<u64>,
+        source: Option<&str>,
+        line: &str,
+    ) -> mlua::Result<Option<(Option<String>, Option<String>)>> {
+        let on_line: Function = lua.globals().get("on_line")?;
+        let job = lua.create_table()?;
+        job.set("id", job_id)?;
+        job.set("source", source)?;
+
+        match on_line.call::<_, Value>((line, job))? {
+            Value::Nil | Value::Boolean(true) => Ok(Some((None, None))),
+            Value::Boolean(false) => Ok(None),
+            Value::String(line) => Ok(Some((Some(line.to_string_lossy().into_owned()), None))),
+            Value::Table(table) => {
+                let table: Table = table;
+                let style = table
+                    .get::<_, Option<String>>("style")?
+                    .map(|style| output::parse_style(&style))
+                    .transpose()
+                    .map_err(mlua::Error::external)?;
+                Ok(Some((table.get::<_, Option<String>>("line")?, style)))
+            }
+            other => Err(mlua::Error::RuntimeError(format!(
+                "on_line returned a {}: expected a string, a table, false, or nothing",
+                other.type_name()
+            ))),
+        }
+    }
+
+    // An event a plugin raised: shown like slurmtail's own messages, and a "plugin" event in JSON and porcelain mode
+    fn raise(job_id: Option<u64>, source: Option<&str>, name: &str, message: Option<&str>) {
+        match message {
+            Some(message) => status!("[{}] {}", name, message),
+            None => status!("[{}]", name),
+        }
+        output::emit(
+            "plugin",
+            job_id,
+            json!({ "name": name, "message": message, "source": source }),
+        );
+    }
+}
+
+// Load the plugins, Lua scripts each defining an on_line(line, job) function that every line of the job's output is
+// passed through (see transform)
+// A plugin that doesn't load is an error; without the "lua" feature, so is asking for plugins at all
+pub fn set_plugins(paths: &[PathBuf]) -> Result<(), SlurmtailError> {
+    if paths.is_empty() {
+        return Ok(());
+    }
+
+    #[cfg(feature = "lua")]
+    return lua::load(paths);
+
+    #[cfg(not(feature = "lua"))]
+    Err(SlurmtailError::Plugin {
+        path: paths[0].clone(),
+        message: "slurmtail was built without Lua plugins (build it with `--features lua`)"
+            .to_string(),
+    })
+}
+
+// What the plugins make of a line of the job's output (including its line ending), before it is filtered, shown, or
+// matched by hooks and stop patterns: they can leave it as it is, drop it, rewrite it, give it a style, and raise
+// events about it
+pub fn transform(job_id: Option<u64>, source: Option<&str>, line: &str) -> Outcome {
+    #[cfg(feature = "lua")]
+    if lua::active() {
+        return lua::transform(job_id, source, line);
+    }
+
+    let _ = (job_id, source, line);
+    Outcome::Unchanged
+}
@@ -0,0 +1,196 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::IsTerminal;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::duration::format_duration;
+use crate::output;
+
+// How often to say that slurmtail is still waiting, when there's no terminal to show a spinner on
+const PLAIN_UPDATE_INTERVAL: Duration = Duration::from_secs(30);
+
+// The spinner currently shown, if any; there is only ever one, and other output has to make way for it
+static SPINNER: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+// The status line shown beneath the job's output (see show_status_line), when it has the spinner's place
+static STATUS_LINE: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+// What the status line says, from each of the things keeping it up to date (by key), in the order they first showed up
+static STATUS_PARTS: Mutex<Vec<(&'static str, String)>> = Mutex::new(Vec::new());
+
+// Something slurmtail is waiting for (e.g. "log file \"out.log\""), shown as a spinner with the time spent waiting and
+// the job's state on a terminal, or as an occasional "[INFO] Still waiting..." line otherwise
+pub struct Waiting {
+    what: String,
+    started: Instant,
+    // When waiting gives up, if it does
+    deadline: Option<Instant>,
+    spinner: Option<ProgressBar>,
+    last_line: Instant,
+}
+
+impl Waiting {
+    // With a deadline, how long there is left until it is shown too
+    pub fn start(what: String, deadline: Option<Instant>) -> Waiting {
+        let mut spinner = None;
+        if std::io::stderr().is_terminal()
+            && output::shows_status()
+            && !crate::tui::active()
+            && let Ok(mut current) = SPINNER.lock()
+            && current.is_none()
+        {
+            let bar = ProgressBar::new_spinner();
+            bar.set_style(
+                ProgressStyle::with_template("{spinner} {msg}")
+                    .unwrap_or_else(|_| ProgressStyle::default_spinner()),
+            );
+            bar.enable_steady_tick(Duration::from_millis(100));
+            *current = Some(bar.clone());
+            spinner = Some(bar);
+        }
+
+        if spinner.is_none() {
+            status!("[INFO] Waiting for {}", what);
+        }
+
+        let waiting = Waiting {
+            what,
+            started: Instant::now(),
+            deadline,
+            spinner,
+            last_line: Instant::now(),
+        };
+        waiting.update(None);
+        waiting
+    }
+
+    // Wait until a later deadline instead
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    // Refresh the time spent waiting, and what is known about the job (e.g. "PENDING (Priority)")
+    pub fn update(&self, job: Option<&str>) {
+        let elapsed = self.elapsed();
+        let message = match job {
+            Some(job) => format!("Waiting for {} ({}; job {})", self.what, elapsed, job),
+            None => format!("Waiting for {} ({})", self.what, elapsed),
+        };
+
+        if let Some(spinner) = &self.spinner {
+            spinner.set_message(message);
+        }
+    }
+
+    // The same, but also printing a plain line now and then when there's no spinner
+    pub fn tick(&mut self, job: Option<&str>) {
+        self.update(job);
+        if self.spinner.is_none() && self.last_line.elapsed() >= PLAIN_UPDATE_INTERVAL {
+            self.last_line = Instant::now();
+            let elapsed = self.elapsed();
+            match job {
+                Some(job) => status!(
+                    "[INFO] Still waiting for {} ({}; job {})",
+                    self.what,
+                    elapsed,
+                    job
+                ),
+                None => status!("[INFO] Still waiting for {} ({})", self.what, elapsed),
+            }
+        }
+    }
+
+    // e.g. "0:01:12" or "0:01:12, giving up in 48s"
+    fn elapsed(&self) -> String {
+        let elapsed = output::format_elapsed(self.started.elapsed());
+        match self.deadline {
+            Some(deadline) => {
+                // Rounded up, so it only says 0s once the time is up
+                let left = deadline.saturating_duration_since(Instant::now());
+                let left = Duration::from_secs(left.as_secs() + u64::from(left.subsec_nanos() > 0));
+                format!("{}, giving up in {}", elapsed, format_duration(left))
+            }
+            None => elapsed,
+        }
+    }
+}
+
+impl Drop for Waiting {
+    // Clear the spinner once the wait is over, so the job's output starts on a clean line
+    fn drop(&mut self) {
+        if let Some(spinner) = self.spinner.take() {
+            spinner.finish_and_clear();
+            if let Ok(mut current) = SPINNER.lock() {
+                *current = None;
+            }
+        }
+    }
+}
+
+// Show a line of status (e.g. an array's task counts) beneath the job's output, kept there as the output scrolls by, on a
+// terminal; otherwise (or with --tui) it is printed as an "[INFO]" line. It waits for its place while a spinner is up
+// Each `key` has a part of the line of its own, so several things can keep it up to date side by side
+// Callers only call this when the status has changed
+pub fn show_status_line(key: &'static str, message: &str) {
+    if !std::io::stderr().is_terminal() || !output::shows_status() || crate::tui::active() {
+        status!("[INFO] {}", message);
+        return;
+    }
+
+    let (Ok(mut current), Ok(mut status_line), Ok(mut parts)) =
+        (SPINNER.lock(), STATUS_LINE.lock(), STATUS_PARTS.lock())
+    else {
+        return;
+    };
+    match parts.iter_mut().find(|(part, _)| *part == key) {
+        Some((_, shown)) => *shown = message.to_string(),
+        None => parts.push((key, message.to_string())),
+    }
+    if status_line.is_none() && current.is_none() {
+        let bar = ProgressBar::new_spinner();
+        bar.set_style(
+            ProgressStyle::with_template("{msg}")
+                .unwrap_or_else(|_| ProgressStyle::default_spinner()),
+        );
+        *current = Some(bar.clone());
+        *status_line = Some(bar);
+    }
+    if let Some(bar) = status_line.as_ref() {
+        bar.set_message(status_message(&parts));
+    }
+}
+
+// Take `key`'s part of the status line away, and the line itself with the last of them (see show_status_line)
+pub fn clear_status_line(key: &'static str) {
+    let (Ok(mut current), Ok(mut status_line), Ok(mut parts)) =
+        (SPINNER.lock(), STATUS_LINE.lock(), STATUS_PARTS.lock())
+    else {
+        return;
+    };
+    parts.retain(|(part, _)| *part != key);
+    if !parts.is_empty() {
+        if let Some(bar) = status_line.as_ref() {
+            bar.set_message(status_message(&parts));
+        }
+    } else if let Some(bar) = status_line.take() {
+        bar.finish_and_clear();
+        *current = None;
+    }
+}
+
+fn status_message(parts: &[(&'static str, String)]) -> String {
+    parts
+        .iter()
+        .map(|(_, message)| message.as_str())
+        .collect::<Vec<_>>()
+        .join("  |  ")
+}
+
+// Run `write` (which prints something) with the spinner, if one is shown, out of the way
+pub fn suspend<T>(write: impl FnOnce() -> T) -> T {
+    let spinner = SPINNER.lock().ok().and_then(|current| current.clone());
+    match spinner {
+        Some(spinner) => spinner.suspend(write),
+        None => write(),
+    }
+}
@@ -0,0 +1,135 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::scheduler::{is_active_state, scheduler};
+use crate::state::{self, ResumeState, write_atomically};
+
+// How long a job's state is trusted before it is looked up again (prompts are drawn far more often than that)
+const CACHE_SECONDS: i64 = 30;
+
+// The cached state, in the project's session directory (it isn't a session, so it doesn't end in .json)
+// e.g.: "12345\tRUNNING\t1714564800" (job ID, state, and when the state was looked up, in seconds since the epoch)
+const CACHE_FILE: &str = "prompt-status";
+
+// What the cache holds about a job
+struct Cached {
+    job_id: u64,
+    state: String,
+    checked_at: i64,
+}
+
+// Print a short line about the current project's job (the latest one still tracked), for a shell prompt, e.g.
+// "⏳12345 RUNNING 0:14": whether it is still going (⏳), completed (✔), or didn't (✘), its ID, its state, and how long
+// ago it was submitted (hours:minutes) while it is still going; nothing without a job (`slurmtail prompt-status`)
+// The state comes from a cache, so that drawing the prompt doesn't wait for the scheduler (or keep asking it); once
+// the cache is older than CACHE_SECONDS, the state is looked up in the background for the next prompt
+// Anything going wrong just leaves the prompt without it, so nothing is ever printed but the line
+// With `refresh`, only look up the state and update the cache (what the background lookup runs)
+pub fn print_status(project_dir: &Path, refresh: bool) {
+    let sessions = state::load_sessions(project_dir).unwrap_or_default();
+    let Some((job_id, session)) = sessions
+        .iter()
+        .rev()
+        .find_map(|session| Some((session.job_id?, session)))
+    else {
+        return;
+    };
+    if let Some(cluster) = &session.cluster {
+        scheduler().set_job_cluster(job_id, cluster);
+    }
+    let cache_path = state::resume_dir(project_dir).join(CACHE_FILE);
+    let now = jiff::Timestamp::now().as_second();
+
+    if refresh {
+        look_up(&cache_path, job_id);
+        return;
+    }
+    let state = match read_cache(&cache_path).filter(|cached| cached.job_id == job_id) {
+        Some(cached) if now - cached.checked_at < CACHE_SECONDS => cached.state,
+        Some(cached) => {
+            // Claim the lookup, so the prompts drawn until it is done don't start more of them
+            write_cache(&cache_path, job_id, &cached.state, now);
+            refresh_in_background();
+            cached.state
+        }
+        // Nothing to show until the state has been looked up once
+        None => look_up(&cache_path, job_id),
+    };
+
+    if let Some(line) = status_line(job_id, &state, session) {
+        println!("{}", line);
+    }
+}
+
+// Ask the scheduler for the job's state, and cache it ("UNKNOWN" if the scheduler doesn't know the job)
+fn look_up(cache_path: &Path, job_id: u64) -> String {
+    let state = scheduler()
+        .job_state(job_id)
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "UNKNOWN".to_string());
+    write_cache(
+        cache_path,
+        job_id,
+        &state,
+        jiff::Timestamp::now().as_second(),
+    );
+
+    state
+}
+
+// e.g.: "⏳12345 RUNNING 0:14", or "✔12345 COMPLETED"; None if the job's state isn't known
+fn status_line(job_id: u64, state: &str, session: &ResumeState) -> Option<String> {
+    if state == "UNKNOWN" {
+        return None;
+    }
+    if !is_active_state(state) {
+        let icon = if state == "COMPLETED" { "✔" } else { "✘" };
+        return Some(format!("{}{} {}", icon, job_id, state));
+    }
+
+    let elapsed = session.submit_time().map(|submitted_at| {
+        let minutes = (jiff::Timestamp::now().as_second() - submitted_at.as_second()).max(0) / 60;
+        format!(" {}:{:02}", minutes / 60, minutes % 60)
+    });
+    Some(format!(
+        "⏳{} {}{}",
+        job_id,
+        state,
+        elapsed.unwrap_or_default()
+    ))
+}
+
+fn read_cache(cache_path: &Path) -> Option<Cached> {
+    let content = std::fs::read_to_string(cache_path).ok()?;
+    let mut fields = content.trim_end().split('\t');
+    Some(Cached {
+        job_id: fields.next()?.parse().ok()?,
+        state: fields.next()?.to_string(),
+        checked_at: fields.next()?.parse().ok()?,
+    })
+}
+
+// The cache is a convenience, so failing to write it (e.g. in a read-only project) only means looking up more often
+fn write_cache(cache_path: &Path, job_id: u64, state: &str, checked_at: i64) {
+    if cache_path.parent().is_some_and(Path::is_dir) {
+        let content = format!("{}\t{}\t{}\n", job_id, state, checked_at);
+        let _ = write_atomically(cache_path, content.as_bytes());
+    }
+}
+
+// Run `slurmtail prompt-status --refresh` (with the same options) without waiting for it, or letting it write to
+// the prompt
+fn refresh_in_background() {
+    let Ok(exe) = std::env::current_exe() else {
+        return;
+    };
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let _ = Command::new(exe)
+        .args(args)
+        .arg("--refresh")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+}
@@ -0,0 +1,415 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::{DirBuilder, File};
+use std::io::IsTerminal;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Output, Stdio};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::error::{SlurmtailError, current_dir};
+use crate::runtime;
+use crate::scheduler::shell_quote;
+
+// How long the SSH connection stays open once nothing uses it, so the commands of a run (and of the next one, soon
+// after) share it
+const CONTROL_PERSIST: &str = "300";
+
+// How long to wait for the local copy of a log to catch up with it, once the job has ended
+const CATCH_UP_WAIT: Duration = Duration::from_secs(10);
+
+// The host the scheduler's commands run on (--remote), and where the current directory is there
+struct Remote {
+    // As ssh takes it, e.g. "user@login-node"
+    host: String,
+    // The directory there, as a shell word: the one given (host:DIR), or the current one's path relative to the
+    // home directory there
+    dir: String,
+    // ssh's ControlPath, for the one connection everything goes over
+    control_path: PathBuf,
+    // Where local copies of the logs being followed go
+    copies_dir: PathBuf,
+}
+
+static REMOTE: OnceLock<Remote> = OnceLock::new();
+
+// The local copies of remote logs, by their path (see local_copy)
+static COPIES: Mutex<BTreeMap<PathBuf, PathBuf>> = Mutex::new(BTreeMap::new());
+
+// Run the scheduler's commands, and read the job's logs, on another host over SSH (--remote HOST[:DIR]): opens the
+// connection that they all go over (asking for a password or passphrase here, if ssh needs one)
+pub fn set_remote(spec: &str) -> Result<(), SlurmtailError> {
+    let (host, dir) = match spec.split_once(':') {
+        Some((host, dir)) if !dir.is_empty() => (host, Some(dir)),
+        Some((host, _)) => (host, None),
+        None => (spec, None),
+    };
+    if host.is_empty() || host.starts_with('-') {
+        return Err(SlurmtailError::Invalid(format!(
+            "Invalid --remote {:?}: expected HOST or HOST:DIR",
+            spec
+        )));
+    }
+    let dir = match dir {
+        Some(dir) => home_relative(dir),
+        None => mapped_path(&current_dir()?, None),
+    };
+
+    // Not $TMPDIR, which can be long enough on macOS for the socket's path to go over the limit
+    let runtime_dir = PathBuf::from(format!("/tmp/slurmtail-{}", unsafe { libc::getuid() }));
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&runtime_dir)
+        .map_err(SlurmtailError::io("create", &runtime_dir))?;
+    let remote = Remote {
+        host: host.to_string(),
+        dir,
+        control_path: runtime_dir.join("ssh-%C"),
+        copies_dir: runtime_dir.join(format!("remote-{}", std::process::id())),
+    };
+
+    // The connection may already be open, from an earlier run
+    let open = remote
+        .ssh()
+        .args(["-O", "check"])
+        .arg(&remote.host)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+    if !open {
+        tracing::debug!(host = %remote.host, "Opening the SSH connection");
+        let status = remote
+            .ssh()
+            .args(["-o", "ControlMaster=yes", "-f", "-N"])
+            .arg(&remote.host)
+            .status()
+            .map_err(|e| SlurmtailError::scheduler("ssh", e))?;
+        if !status.success() {
+            return Err(SlurmtailError::scheduler(
+                "ssh",
+                format!("Could not connect to {} ({})", remote.host, status),
+            ));
+        }
+    }
+
+    let _ = REMOTE.set(remote);
+    Ok(())
+}
+
+pub fn active() -> bool {
+    REMOTE.get().is_some()
+}
+
+impl Remote {
+    // ssh, going over the shared connection (and opening it, if it has gone away)
+    fn ssh(&self) -> std::process::Command {
+        let mut ssh = std::process::Command::new("ssh");
+        ssh.arg("-o")
+            .arg(format!("ControlPath={}", self.control_path.display()))
+            .args(["-o", "ControlMaster=auto", "-o"])
+            .arg(format!("ControlPersist={}", CONTROL_PERSIST));
+        ssh
+    }
+
+    // ssh running a shell command line in the directory there
+    fn run(&self, command_line: &str, terminal: bool) -> std::process::Command {
+        let mut ssh = self.ssh();
+        if terminal {
+            ssh.arg("-t");
+        }
+        ssh.arg(&self.host)
+            .arg("--")
+            .arg(format!("cd {} && {}", self.dir, command_line));
+        ssh
+    }
+}
+
+// A path as a shell word there: paths in the current directory are in the directory there, and other paths in the
+// home directory are in the home directory there
+fn mapped_path(path: &Path, dir: Option<&str>) -> String {
+    if let Some(dir) = dir
+        && let Ok(current_dir) = std::env::current_dir()
+        && let Ok(relative) = path.strip_prefix(current_dir)
+    {
+        if relative.as_os_str().is_empty() {
+            return dir.to_string();
+        }
+        return format!("{}/{}", dir, shell_quote(&relative.to_string_lossy()));
+    }
+    if let Some(home) = std::env::var_os("HOME")
+        && let Ok(relative) = path.strip_prefix(home)
+    {
+        if relative.as_os_str().is_empty() {
+            return "\"$HOME\"".to_string();
+        }
+        return format!("\"$HOME\"/{}", shell_quote(&relative.to_string_lossy()));
+    }
+
+    shell_quote(&path.to_string_lossy())
+}
+
+// A directory given with --remote HOST:DIR as a shell word, with "~" standing for the home directory there
+fn home_relative(dir: &str) -> String {
+    match dir.strip_prefix('~') {
+        Some("") => "\"$HOME\"".to_string(),
+        Some(relative) if relative.starts_with('/') => {
+            format!(
+                "\"$HOME\"/{}",
+                shell_quote(relative.trim_start_matches('/'))
+            )
+        }
+        _ => shell_quote(dir),
+    }
+}
+
+// Whether a command can be found there (for --scheduler auto)
+pub fn has_command(command: &str) -> bool {
+    let Some(remote) = REMOTE.get() else {
+        return false;
+    };
+    remote
+        .run(&format!("command -v {}", shell_quote(command)), false)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// A scheduler command (sbatch, squeue, ...), run here, or with --remote on the remote host over SSH, in the
+/// directory there that the current one maps to
+pub struct Command {
+    program: String,
+    args: Vec<OsString>,
+    script: Option<PathBuf>,
+    stdin: Option<Stdio>,
+    stdout: Option<Stdio>,
+    stderr: Option<Stdio>,
+}
+
+impl Command {
+    pub fn new(program: &str) -> Command {
+        Command {
+            program: program.to_string(),
+            args: Vec::new(),
+            script: None,
+            stdin: None,
+            stdout: None,
+            stderr: None,
+        }
+    }
+
+    pub fn arg<S: Into<OsString>>(&mut self, arg: S) -> &mut Command {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut Command
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    // The job script: its path here, while remotely it is passed on stdin (which sbatch, qsub, and flux batch read
+    // a script from when they aren't given one), as it needn't exist there
+    pub fn script(&mut self, script_path: &Path) -> &mut Command {
+        self.script = Some(script_path.to_path_buf());
+        self
+    }
+
+    pub fn stdin<T: Into<Stdio>>(&mut self, stdin: T) -> &mut Command {
+        self.stdin = Some(stdin.into());
+        self
+    }
+
+    pub fn stdout<T: Into<Stdio>>(&mut self, stdout: T) -> &mut Command {
+        self.stdout = Some(stdout.into());
+        self
+    }
+
+    pub fn stderr<T: Into<Stdio>>(&mut self, stderr: T) -> &mut Command {
+        self.stderr = Some(stderr.into());
+        self
+    }
+
+    // Failing to run the command at all (e.g. it isn't installed) counts as the command failing
+    pub fn output(&mut self) -> Result<Output, SlurmtailError> {
+        self.build(false)?
+            .output()
+            .map_err(|e| SlurmtailError::scheduler(&self.program, e))
+    }
+
+    // Run with the terminal (e.g. sattach), which a remote command gets one of its own for
+    pub fn status(&mut self) -> Result<ExitStatus, SlurmtailError> {
+        self.build(std::io::stdin().is_terminal())?
+            .status()
+            .map_err(|e| SlurmtailError::scheduler(&self.program, e))
+    }
+
+    fn build(&mut self, terminal: bool) -> Result<std::process::Command, SlurmtailError> {
+        let mut command = match REMOTE.get() {
+            None => {
+                let mut command = std::process::Command::new(&self.program);
+                command.args(&self.args).args(&self.script);
+                command
+            }
+            Some(remote) => {
+                let mut command_line = format!("exec {}", shell_quote(&self.program));
+                for arg in &self.args {
+                    command_line.push(' ');
+                    command_line.push_str(&shell_quote(&arg.to_string_lossy()));
+                }
+                if let Some(script_path) = &self.script {
+                    let script =
+                        File::open(script_path).map_err(SlurmtailError::io("read", script_path))?;
+                    self.stdin = Some(script.into());
+                }
+                tracing::debug!(host = %remote.host, command = %command_line, "Running over SSH");
+                remote.run(&command_line, terminal && self.stdin.is_none())
+            }
+        };
+        if let Some(stdin) = self.stdin.take() {
+            command.stdin(stdin);
+        }
+        if let Some(stdout) = self.stdout.take() {
+            command.stdout(stdout);
+        }
+        if let Some(stderr) = self.stderr.take() {
+            command.stderr(stderr);
+        }
+
+        Ok(command)
+    }
+}
+
+// Where to read a log from: the log itself, or with --remote a local copy of it that keeps growing as the log on the
+// remote host does (read with `tail -F` there); the copy only appears once the log has something in it
+pub fn local_copy(log_path: &Path) -> PathBuf {
+    let Some(remote) = REMOTE.get() else {
+        return log_path.to_path_buf();
+    };
+    let Ok(mut copies) = COPIES.lock() else {
+        return log_path.to_path_buf();
+    };
+    if let Some(copy) = copies.get(log_path) {
+        return copy.clone();
+    }
+
+    let copy = remote.copies_dir.join(format!(
+        "{}-{}",
+        copies.len(),
+        log_path
+            .file_name()
+            .map_or("log".into(), |name| name.to_string_lossy())
+    ));
+    copies.insert(log_path.to_path_buf(), copy.clone());
+    runtime::spawn(keep_copy(
+        remote,
+        mapped_path(log_path, Some(&remote.dir)),
+        copy.clone(),
+    ));
+    copy
+}
+
+// Copy a remote log as it grows, until slurmtail exits; the remote tail stops when its stdin (held open here) closes
+async fn keep_copy(remote: &'static Remote, remote_path: String, copy: PathBuf) {
+    let mut tail = tokio::process::Command::from(remote.run(
+        &format!(
+            "tail -c +1 -F -- {} 2>/dev/null & cat >/dev/null; kill $! 2>/dev/null",
+            remote_path
+        ),
+        false,
+    ));
+    tail.stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    let mut child = match tail.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            warning!("[WARNING] Could not run ssh to read {}: {}", remote_path, e);
+            return;
+        }
+    };
+    let _stdin = child.stdin.take();
+    let Some(mut stdout) = child.stdout.take() else {
+        return;
+    };
+
+    let mut file = None;
+    let mut buffer = vec![0; 64 * 1024];
+    while let Ok(read) = stdout.read(&mut buffer).await
+        && read > 0
+    {
+        if file.is_none() {
+            if let Some(parent) = copy.parent()
+                && let Err(e) = DirBuilder::new().recursive(true).mode(0o700).create(parent)
+            {
+                warning!("[WARNING] Could not keep a copy of {}: {}", remote_path, e);
+                return;
+            }
+            match tokio::fs::File::create(&copy).await {
+                Ok(created) => file = Some(created),
+                Err(e) => {
+                    warning!("[WARNING] Could not keep a copy of {}: {}", remote_path, e);
+                    return;
+                }
+            }
+        }
+        if let Some(file) = &mut file
+            && (file.write_all(&buffer[..read]).await.is_err() || file.flush().await.is_err())
+        {
+            return;
+        }
+    }
+}
+
+// Wait (a while) for the local copy of a remote log to have everything the log has, e.g. once the job has ended, so
+// its last lines aren't missed
+pub async fn catch_up(log_path: &Path) {
+    let Some(remote) = REMOTE.get() else {
+        return;
+    };
+    let copy = local_copy(log_path);
+    let remote_path = mapped_path(log_path, Some(&remote.dir));
+    let size = runtime::blocking(move || {
+        let output = remote
+            .run(&format!("wc -c < {}", remote_path), false)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse::<u64>()
+            .ok()
+    })
+    .await;
+    let Some(size) = size else {
+        return;
+    };
+
+    let deadline = Instant::now() + CATCH_UP_WAIT;
+    while Instant::now() < deadline
+        && tokio::fs::metadata(&copy)
+            .await
+            .map_or(0, |metadata| metadata.len())
+            < size
+    {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+// Remove the local copies of remote logs, once done with them
+pub fn clean_up() {
+    if let Some(remote) = REMOTE.get() {
+        let _ = std::fs::remove_dir_all(&remote.copies_dir);
+    }
+}
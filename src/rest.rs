@@ -0,0 +1,252 @@
+use serde_json::{Value, json};
+use std::env;
+use std::fs::read_to_string;
+use std::path::Path;
+
+use crate::directives::UNLIMITED_MINUTES;
+use crate::error::{SlurmtailError, current_dir};
+use crate::scheduler::{Scheduler, job_request};
+
+// API version used when none is given (the oldest version still shipped by current slurmrestd releases)
+const DEFAULT_API_VERSION: &str = "v0.0.40";
+
+// Client for slurmrestd, the SLURM REST API daemon, authenticated with a JWT (e.g. from `scontrol token`)
+pub struct RestClient {
+    base_url: String,
+    api_version: String,
+    user: String,
+    token: String,
+}
+
+impl RestClient {
+    // Build a client from the given URL/version, falling back to $SLURMRESTD_URL, and reading $SLURM_JWT and $USER for auth
+    pub fn from_env(
+        base_url: Option<&String>,
+        api_version: Option<&String>,
+    ) -> Result<RestClient, SlurmtailError> {
+        let base_url = match base_url {
+            Some(url) => url.clone(),
+            None => env::var("SLURMRESTD_URL").map_err(|_| {
+                SlurmtailError::Invalid(
+                    "No slurmrestd URL given (use --rest-url or set SLURMRESTD_URL)".to_string(),
+                )
+            })?,
+        };
+        let token = env::var("SLURM_JWT").map_err(|_| {
+            SlurmtailError::Invalid(
+                "SLURM_JWT is not set (get one with `scontrol token`)".to_string(),
+            )
+        })?;
+        let user =
+            env::var("USER").map_err(|_| SlurmtailError::Invalid("USER is not set".to_string()))?;
+
+        Ok(RestClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_version: api_version
+                .cloned()
+                .unwrap_or_else(|| DEFAULT_API_VERSION.to_string()),
+            user,
+            token,
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/slurm/{}/{}", self.base_url, self.api_version, path)
+    }
+
+    // Turn the "errors" array slurmrestd includes in every response into an error, if it is non-empty
+    fn check_errors(response: &Value) -> Result<(), SlurmtailError> {
+        let errors: Vec<String> = response["errors"]
+            .as_array()
+            .map(|errors| {
+                errors
+                    .iter()
+                    .map(|e| {
+                        e["description"]
+                            .as_str()
+                            .or(e["error"].as_str())
+                            .unwrap_or("unknown error")
+                            .to_string()
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(SlurmtailError::scheduler("slurmrestd", errors.join("; ")))
+        }
+    }
+}
+
+// A request to slurmrestd that didn't get an answer (or not one that could be read)
+fn request_failed(e: ureq::Error) -> SlurmtailError {
+    SlurmtailError::scheduler("slurmrestd", e)
+}
+
+// A number in the form the API takes for fields that can also be left unset or be infinite (e.g. memory_per_node)
+fn number_value(number: u64) -> Value {
+    json!({ "set": true, "infinite": false, "number": number })
+}
+
+// A time limit in minutes, which may be unlimited
+fn minutes_value(minutes: u32) -> Value {
+    match minutes {
+        UNLIMITED_MINUTES => json!({ "set": true, "infinite": true, "number": 0 }),
+        minutes => number_value(minutes.into()),
+    }
+}
+
+impl Scheduler for RestClient {
+    fn name(&self) -> &'static str {
+        "rest"
+    }
+
+    // Submit a batch script, translating its directives (and the sbatch-style extra arguments) into job description fields
+    fn submit(&self, script_path: &Path, extra_args: &[String]) -> Result<u64, SlurmtailError> {
+        let script =
+            read_to_string(script_path).map_err(SlurmtailError::io("read", script_path))?;
+        let cwd = current_dir()?;
+
+        // slurmrestd runs the script in the working directory we give it, with the environment we give it
+        let environment: Vec<String> = env::vars().map(|(k, v)| format!("{}={}", k, v)).collect();
+        let mut job = json!({
+            "current_working_directory": cwd.to_string_lossy(),
+            "environment": environment,
+        });
+
+        // Directives in the script are not interpreted by every slurmrestd version, so they are passed as job fields
+        let request = job_request(script_path, extra_args, self.name())?;
+        let text_fields = [
+            ("name", &request.name),
+            ("standard_output", &request.output),
+            ("standard_error", &request.error),
+            ("standard_input", &request.input),
+            ("partition", &request.partition),
+            ("account", &request.account),
+            ("qos", &request.qos),
+            ("dependency", &request.dependency),
+            ("clusters", &request.clusters),
+            ("array", &request.array),
+            ("current_working_directory", &request.chdir),
+            ("comment", &request.comment),
+            ("constraints", &request.constraint),
+            ("reservation", &request.reservation),
+            ("required_nodes", &request.nodelist),
+            ("excluded_nodes", &request.exclude),
+            ("licenses", &request.licenses),
+            ("wckey", &request.wckey),
+            ("mail_user", &request.mail_user),
+        ];
+        for (field, value) in text_fields {
+            if let Some(value) = value {
+                job[field] = json!(value);
+            }
+        }
+
+        if !request.mail_type.is_empty() {
+            let mut mail_types = Vec::new();
+            for mail_type in &request.mail_type {
+                match mail_type.as_str() {
+                    "NONE" => {}
+                    "BEGIN" | "END" | "FAIL" | "REQUEUE" => mail_types.push(mail_type.as_str()),
+                    "ALL" => mail_types.extend(["BEGIN", "END", "FAIL", "REQUEUE"]),
+                    _ => {
+                        return Err(SlurmtailError::unsupported(
+                            format!("--mail-type={}", mail_type),
+                            self.name(),
+                        ));
+                    }
+                }
+            }
+            job["mail_type"] = json!(mail_types);
+        }
+        if let Some(minutes) = request.time_limit {
+            job["time_limit"] = minutes_value(minutes);
+        }
+        if let Some(minutes) = request.time_min {
+            job["time_minimum"] = minutes_value(minutes);
+        }
+        if let Some(nodes) = request.min_nodes {
+            job["minimum_nodes"] = json!(nodes);
+        }
+        if let Some(nodes) = request.max_nodes {
+            job["maximum_nodes"] = json!(nodes);
+        }
+        if let Some(tasks) = request.ntasks {
+            job["tasks"] = json!(tasks);
+        }
+        if let Some(tasks) = request.ntasks_per_node {
+            job["tasks_per_node"] = json!(tasks);
+        }
+        if let Some(cpus) = request.cpus_per_task {
+            job["cpus_per_task"] = json!(cpus);
+        }
+        if let Some(megabytes) = request.mem_per_node {
+            job["memory_per_node"] = number_value(megabytes);
+        }
+        if let Some(megabytes) = request.mem_per_cpu {
+            job["memory_per_cpu"] = number_value(megabytes);
+        }
+        if request.hold {
+            job["hold"] = json!(true);
+        }
+        if let Some(requeue) = request.requeue {
+            job["requeue"] = json!(requeue);
+        }
+
+        let response: Value = ureq::post(self.url("job/submit"))
+            .header("X-SLURM-USER-NAME", &self.user)
+            .header("X-SLURM-USER-TOKEN", &self.token)
+            .send_json(json!({ "script": script, "job": job }))
+            .map_err(request_failed)?
+            .body_mut()
+            .read_json()
+            .map_err(request_failed)?;
+        Self::check_errors(&response)?;
+
+        response["job_id"].as_u64().ok_or_else(|| {
+            SlurmtailError::scheduler("slurmrestd", "Could not extract job ID from its response")
+        })
+    }
+
+    // Current state of a job (e.g. "RUNNING"), or None if slurmrestd no longer knows about it
+    fn job_state(&self, job_id: u64) -> Result<Option<String>, SlurmtailError> {
+        let response = ureq::get(self.url(&format!("job/{}", job_id)))
+            .header("X-SLURM-USER-NAME", &self.user)
+            .header("X-SLURM-USER-TOKEN", &self.token)
+            .call();
+
+        let response: Value = match response {
+            Ok(mut response) => response.body_mut().read_json().map_err(request_failed)?,
+            Err(ureq::Error::StatusCode(404)) => return Ok(None),
+            Err(e) => return Err(request_failed(e)),
+        };
+        Self::check_errors(&response)?;
+
+        // Newer API versions report the state as a list of flags, older ones as a single string
+        let job_state = &response["jobs"][0]["job_state"];
+        let state = match job_state {
+            Value::Array(states) => states.first().and_then(|s| s.as_str()),
+            Value::String(state) => Some(state.as_str()),
+            _ => None,
+        };
+
+        Ok(state.map(|s| s.to_string()))
+    }
+
+    // Cancel (scancel) a job
+    fn cancel(&self, job_id: u64) -> Result<(), SlurmtailError> {
+        let response: Value = ureq::delete(self.url(&format!("job/{}", job_id)))
+            .header("X-SLURM-USER-NAME", &self.user)
+            .header("X-SLURM-USER-TOKEN", &self.token)
+            .call()
+            .map_err(request_failed)?
+            .body_mut()
+            .read_json()
+            .map_err(request_failed)?;
+
+        Self::check_errors(&response)
+    }
+}
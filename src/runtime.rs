@@ -0,0 +1,89 @@
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+
+// How long slurmtail waits for notifications still being delivered when it exits, before giving up on them
+const DELIVERY_WAIT: Duration = Duration::from_secs(30);
+
+// The runtime the followers, the scheduler polling, the key reader, hooks, and notification deliveries run on
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+// The notification delivery queued last (each one waits for the one before it, so they arrive in order)
+static LAST_DELIVERY: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+
+fn runtime() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .thread_name("slurmtail")
+            .enable_all()
+            .build()
+            .expect("Could not start the async runtime")
+    })
+}
+
+// Run a future to completion, from code that isn't async itself (the library's entry points, and main)
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    runtime().block_on(future)
+}
+
+// Run a future in the background
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    runtime().spawn(future)
+}
+
+// Run something that blocks (asking the scheduler, reading the terminal, ...) off the runtime's threads, and wait
+// for it; a panic in it carries on in the caller
+pub async fn blocking<T, F>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => value,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+// Deliver a notification (a webhook post, an ntfy message, an email) in the background, so following isn't held up
+// by a slow server; deliveries happen one at a time, in the order they were queued
+pub fn deliver<F>(f: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    let Ok(mut last_delivery) = LAST_DELIVERY.lock() else {
+        return;
+    };
+    let previous = last_delivery.take();
+    *last_delivery = Some(spawn(async move {
+        if let Some(previous) = previous {
+            let _ = previous.await;
+        }
+        let _ = tokio::task::spawn_blocking(f).await;
+    }));
+}
+
+// Wait (a while) for the notifications still being delivered, so slurmtail exiting doesn't drop the last ones
+pub fn wait_for_deliveries() {
+    let last_delivery = LAST_DELIVERY
+        .lock()
+        .ok()
+        .and_then(|mut last_delivery| last_delivery.take());
+    let Some(last_delivery) = last_delivery else {
+        return;
+    };
+
+    // The timer needs the runtime, so it is only set once running on it
+    if block_on(async { tokio::time::timeout(DELIVERY_WAIT, last_delivery).await }).is_err() {
+        warning!(
+            "[WARNING] Not waiting any longer for notifications still being delivered after {} seconds",
+            DELIVERY_WAIT.as_secs()
+        );
+    }
+}
@@ -0,0 +1,1495 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, read_to_string};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command as ProcessCommand, Stdio};
+use std::sync::{Mutex, OnceLock};
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::directives::{JobRequest, SbatchDirectives};
+use crate::error::SlurmtailError;
+use crate::flux::FluxCli;
+#[cfg(feature = "libslurm")]
+use crate::libslurm::LibSlurm;
+use crate::lsf::LsfCli;
+use crate::output::observe_state;
+use crate::pbs::PbsCli;
+use crate::remote;
+use crate::rest::RestClient;
+use crate::script::{
+    array_task_count, extract_array_spec, extract_job_name, extract_log_output_pattern,
+    first_array_task, format_log_output_string, job_working_dir, logfile_string_to_path,
+};
+
+/// Everything slurmtail needs from a batch scheduler: submitting, checking on, and cancelling jobs
+pub trait Scheduler: Send + Sync {
+    /// Name used in messages (and for --scheduler)
+    fn name(&self) -> &'static str;
+
+    /// Submit a batch script, passing any extra sbatch-style arguments (overrides, dependencies); returns the job ID
+    fn submit(&self, script_path: &Path, extra_args: &[String]) -> Result<u64, SlurmtailError>;
+
+    /// The script's output file pattern (in SLURM's %j/%x notation), read from the scheduler's own directives
+    fn output_pattern(&self, script_path: &Path) -> Result<String, SlurmtailError> {
+        extract_log_output_pattern(script_path)
+    }
+
+    /// The job name set by the script's directives, if any
+    fn job_name(&self, script_path: &Path) -> Result<Option<String>, SlurmtailError> {
+        extract_job_name(script_path)
+    }
+
+    /// Validate a script without submitting it, returning the scheduler's estimate of when it would start
+    fn test_only(
+        &self,
+        _script_path: &Path,
+        _extra_args: &[String],
+    ) -> Result<String, SlurmtailError> {
+        Err(SlurmtailError::unsupported("--test-only", self.name()))
+    }
+
+    /// Current state of a job (e.g. "RUNNING", "COMPLETED"), or None if the scheduler doesn't know about it
+    fn job_state(&self, job_id: u64) -> Result<Option<String>, SlurmtailError>;
+
+    /// Why a pending job hasn't started yet (e.g. "Priority", "Resources"), if the scheduler says
+    fn pending_reason(&self, _job_id: u64) -> Result<Option<String>, SlurmtailError> {
+        Ok(None)
+    }
+
+    /// When the scheduler expects a pending job to start, if it has an estimate
+    fn estimated_start(&self, _job_id: u64) -> Result<Option<jiff::Timestamp>, SlurmtailError> {
+        Ok(None)
+    }
+
+    /// Why the scheduler would turn down a job asking for the partition, account, and QOS in these directives (e.g.
+    /// "Partition \"gpux\" doesn't exist"), checked before submitting it; empty if nothing is wrong, or there's no
+    /// way to tell
+    fn placement_problems(&self, _directives: &SbatchDirectives) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Cancel a job
+    fn cancel(&self, job_id: u64) -> Result<(), SlurmtailError>;
+
+    /// Accounting fields of a finished job (e.g. "ExitCode", "Elapsed"), for the submission history; empty if the
+    /// scheduler keeps none
+    fn job_accounting(&self, _job_id: u64) -> Result<Vec<(String, String)>, SlurmtailError> {
+        Ok(Vec::new())
+    }
+
+    /// The node a job's batch script runs on, once it has started (None while it is still pending)
+    fn batch_host(&self, _job_id: u64) -> Result<Option<String>, SlurmtailError> {
+        Err(SlurmtailError::unsupported(
+            "Looking up a job's node",
+            self.name(),
+        ))
+    }
+
+    /// The nodes a job has been given, in order (the first one runs its batch script), once it has started (empty
+    /// while it is still pending)
+    fn job_nodes(&self, _job_id: u64) -> Result<Vec<String>, SlurmtailError> {
+        Err(SlurmtailError::unsupported(
+            "Looking up a job's nodes",
+            self.name(),
+        ))
+    }
+
+    /// The state of each task of a job array (e.g. "RUNNING", "COMPLETED"), for showing how far along it is
+    fn array_task_states(&self, _job_id: u64) -> Result<Vec<String>, SlurmtailError> {
+        Err(SlurmtailError::unsupported(
+            "Looking up the tasks of a job array",
+            self.name(),
+        ))
+    }
+
+    /// The user's most recently submitted job that is still in the queue, if there is one
+    fn newest_job(&self) -> Result<Option<u64>, SlurmtailError> {
+        Err(SlurmtailError::unsupported(
+            "Listing your jobs",
+            self.name(),
+        ))
+    }
+
+    /// Where a job writes its output, as the scheduler has it (for following a job slurmtail didn't submit)
+    fn output_path(&self, _job_id: u64) -> Result<Option<PathBuf>, SlurmtailError> {
+        Err(SlurmtailError::unsupported(
+            "Looking up a job's output file",
+            self.name(),
+        ))
+    }
+
+    /// How busy each GPU a running job has is, and how much of its memory is in use (empty if it has none, or isn't
+    /// running)
+    fn gpu_usage(&self, _job_id: u64) -> Result<Vec<GpuUsage>, SlurmtailError> {
+        Err(SlurmtailError::unsupported(
+            "Looking up a job's GPU usage",
+            self.name(),
+        ))
+    }
+
+    /// Have the scheduler touch `sentinel` as soon as a job ends (however it ends), so that is noticed without asking
+    /// about the job over and over
+    fn set_end_trigger(&self, _job_id: u64, _sentinel: &Path) -> Result<(), SlurmtailError> {
+        Err(SlurmtailError::unsupported(
+            "Triggers on a job's end",
+            self.name(),
+        ))
+    }
+
+    /// Attach to a running job step's I/O, returning once the step ends (or the user detaches)
+    fn attach(&self, _job_id: u64, _step: u32) -> Result<(), SlurmtailError> {
+        Err(SlurmtailError::unsupported(
+            "Attaching to job steps",
+            self.name(),
+        ))
+    }
+
+    /// What attaching to a job step hooks up to, for offering it, e.g. "the I/O of job step 1234.0 with sattach"
+    fn attach_description(&self, job_id: u64, step: u32) -> String {
+        format!("the I/O of job step {}.{}", job_id, step)
+    }
+
+    /// The cluster a job was submitted to, for schedulers that federate several (SLURM's multi-cluster mode)
+    fn job_cluster(&self, _job_id: u64) -> Option<String> {
+        None
+    }
+
+    /// Tell the scheduler which cluster a job from an earlier run lives on (e.g. as read back from the resume file)
+    fn set_job_cluster(&self, _job_id: u64, _cluster: &str) {}
+}
+
+// The scheduler selected with --scheduler (set once, in main)
+static SCHEDULER: OnceLock<Box<dyn Scheduler>> = OnceLock::new();
+
+/// One of a job's GPUs, as nvidia-smi sees it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GpuUsage {
+    /// How much of the last sample period it was busy, in percent
+    pub utilization: u32,
+    /// Its memory in use, and in all, in MiB
+    pub memory_used: u64,
+    pub memory_total: u64,
+}
+
+// The fields nvidia-smi is asked for, in the order parse_gpu_query reads them
+const NVIDIA_SMI_QUERY: &str = "--query-gpu=utilization.gpu,memory.used,memory.total";
+
+// nvidia-smi's answer to NVIDIA_SMI_QUERY (with --format=csv,noheader,nounits), one GPU per line, e.g. "98, 40211, 81920"
+// GPUs it can't say something about (e.g. "[N/A]") are left out
+fn parse_gpu_query(stdout: &str) -> Vec<GpuUsage> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(',').map(str::trim);
+            Some(GpuUsage {
+                utilization: fields.next()?.parse().ok()?,
+                memory_used: fields.next()?.parse().ok()?,
+                memory_total: fields.next()?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Names accepted by --scheduler (libslurm only when compiled in)
+pub fn scheduler_names() -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut names = vec!["auto", "slurm", "rest", "pbs", "lsf", "flux", "mock"];
+    #[cfg(feature = "libslurm")]
+    names.push("libslurm");
+    names
+}
+
+// Whether an executable with this name can be found on $PATH
+fn command_on_path(command: &str) -> bool {
+    // With --remote, the commands that count are the ones there
+    if remote::active() {
+        return remote::has_command(command);
+    }
+    env::var_os("PATH")
+        .map(|path| env::split_paths(&path).any(|dir| dir.join(command).is_file()))
+        .unwrap_or(false)
+}
+
+/// Pick the scheduler whose submission command is installed (for --scheduler auto), falling back to SLURM
+/// Running inside a Flux instance wins; otherwise sbatch is checked first, since SLURM clusters sometimes ship
+/// qsub/bsub compatibility wrappers
+pub fn detect_scheduler() -> &'static str {
+    if env::var_os("FLUX_URI").is_some() && command_on_path("flux") {
+        return "flux";
+    }
+
+    [
+        ("sbatch", "slurm"),
+        ("bsub", "lsf"),
+        ("qsub", "pbs"),
+        ("flux", "flux"),
+    ]
+    .into_iter()
+    .find(|(command, _)| command_on_path(command))
+    .map_or("slurm", |(_, name)| name)
+}
+
+/// Select the scheduler used for the rest of the run
+pub fn set_scheduler(scheduler: Box<dyn Scheduler>) {
+    let _ = SCHEDULER.set(Box::new(Traced(scheduler)));
+}
+
+/// Select the scheduler used for the rest of the run by its --scheduler name (other than "auto", see
+/// detect_scheduler); "rest" talks to slurmrestd at `rest_url` (default: $SLURMRESTD_URL) with `rest_api_version`
+pub fn set_scheduler_by_name(
+    name: &str,
+    rest_url: Option<&String>,
+    rest_api_version: Option<&String>,
+) -> Result<(), SlurmtailError> {
+    match name {
+        "slurm" => set_scheduler(Box::<SlurmCli>::default()),
+        "rest" => set_scheduler(Box::new(RestClient::from_env(rest_url, rest_api_version)?)),
+        "pbs" => set_scheduler(Box::new(PbsCli)),
+        "lsf" => set_scheduler(Box::new(LsfCli)),
+        "flux" => set_scheduler(Box::new(FluxCli)),
+        "mock" => set_scheduler(Box::new(MockScheduler::from_env())),
+        #[cfg(feature = "libslurm")]
+        "libslurm" => set_scheduler(Box::new(LibSlurm::init())),
+        _ => {
+            let names: Vec<&str> = scheduler_names()
+                .into_iter()
+                .filter(|name| *name != "auto")
+                .collect();
+            return Err(SlurmtailError::Invalid(format!(
+                "Unknown scheduler {:?}: expected one of {}",
+                name,
+                names.join(", ")
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// The selected scheduler, defaulting to the SLURM command-line tools
+pub fn scheduler() -> &'static dyn Scheduler {
+    SCHEDULER
+        .get_or_init(|| Box::new(Traced(Box::<SlurmCli>::default())))
+        .as_ref()
+}
+
+// Wraps the selected scheduler so that each call to it is a span in slurmtail's diagnostics, timed when it closes
+// (see diagnostics::init): at debug level for the occasional submission or cancellation, and at trace level for the
+// calls made every few seconds while following a job
+struct Traced(Box<dyn Scheduler>);
+
+impl Scheduler for Traced {
+    fn name(&self) -> &'static str {
+        self.0.name()
+    }
+
+    fn submit(&self, script_path: &Path, extra_args: &[String]) -> Result<u64, SlurmtailError> {
+        let _span = tracing::debug_span!("submit", scheduler = self.name(), script = ?script_path)
+            .entered();
+        self.0.submit(script_path, extra_args)
+    }
+
+    fn output_pattern(&self, script_path: &Path) -> Result<String, SlurmtailError> {
+        self.0.output_pattern(script_path)
+    }
+
+    fn job_name(&self, script_path: &Path) -> Result<Option<String>, SlurmtailError> {
+        self.0.job_name(script_path)
+    }
+
+    fn test_only(
+        &self,
+        script_path: &Path,
+        extra_args: &[String],
+    ) -> Result<String, SlurmtailError> {
+        let _span =
+            tracing::debug_span!("test_only", scheduler = self.name(), script = ?script_path)
+                .entered();
+        self.0.test_only(script_path, extra_args)
+    }
+
+    fn job_state(&self, job_id: u64) -> Result<Option<String>, SlurmtailError> {
+        let _span = tracing::trace_span!("job_state", scheduler = self.name(), job_id).entered();
+        self.0.job_state(job_id)
+    }
+
+    fn pending_reason(&self, job_id: u64) -> Result<Option<String>, SlurmtailError> {
+        let _span =
+            tracing::trace_span!("pending_reason", scheduler = self.name(), job_id).entered();
+        self.0.pending_reason(job_id)
+    }
+
+    fn estimated_start(&self, job_id: u64) -> Result<Option<jiff::Timestamp>, SlurmtailError> {
+        let _span =
+            tracing::trace_span!("estimated_start", scheduler = self.name(), job_id).entered();
+        self.0.estimated_start(job_id)
+    }
+
+    fn placement_problems(&self, directives: &SbatchDirectives) -> Vec<String> {
+        let _span = tracing::debug_span!("placement_problems", scheduler = self.name()).entered();
+        self.0.placement_problems(directives)
+    }
+
+    fn cancel(&self, job_id: u64) -> Result<(), SlurmtailError> {
+        let _span = tracing::debug_span!("cancel", scheduler = self.name(), job_id).entered();
+        self.0.cancel(job_id)
+    }
+
+    fn job_accounting(&self, job_id: u64) -> Result<Vec<(String, String)>, SlurmtailError> {
+        let _span =
+            tracing::debug_span!("job_accounting", scheduler = self.name(), job_id).entered();
+        self.0.job_accounting(job_id)
+    }
+
+    fn batch_host(&self, job_id: u64) -> Result<Option<String>, SlurmtailError> {
+        let _span = tracing::trace_span!("batch_host", scheduler = self.name(), job_id).entered();
+        self.0.batch_host(job_id)
+    }
+
+    fn job_nodes(&self, job_id: u64) -> Result<Vec<String>, SlurmtailError> {
+        let _span = tracing::debug_span!("job_nodes", scheduler = self.name(), job_id).entered();
+        self.0.job_nodes(job_id)
+    }
+
+    fn array_task_states(&self, job_id: u64) -> Result<Vec<String>, SlurmtailError> {
+        let _span =
+            tracing::trace_span!("array_task_states", scheduler = self.name(), job_id).entered();
+        self.0.array_task_states(job_id)
+    }
+
+    fn newest_job(&self) -> Result<Option<u64>, SlurmtailError> {
+        let _span = tracing::debug_span!("newest_job", scheduler = self.name()).entered();
+        self.0.newest_job()
+    }
+
+    fn output_path(&self, job_id: u64) -> Result<Option<PathBuf>, SlurmtailError> {
+        let _span = tracing::debug_span!("output_path", scheduler = self.name(), job_id).entered();
+        self.0.output_path(job_id)
+    }
+
+    fn gpu_usage(&self, job_id: u64) -> Result<Vec<GpuUsage>, SlurmtailError> {
+        let _span = tracing::trace_span!("gpu_usage", scheduler = self.name(), job_id).entered();
+        self.0.gpu_usage(job_id)
+    }
+
+    fn set_end_trigger(&self, job_id: u64, sentinel: &Path) -> Result<(), SlurmtailError> {
+        let _span =
+            tracing::debug_span!("set_end_trigger", scheduler = self.name(), job_id).entered();
+        self.0.set_end_trigger(job_id, sentinel)
+    }
+
+    fn attach(&self, job_id: u64, step: u32) -> Result<(), SlurmtailError> {
+        let _span = tracing::debug_span!("attach", scheduler = self.name(), job_id, step).entered();
+        self.0.attach(job_id, step)
+    }
+
+    fn attach_description(&self, job_id: u64, step: u32) -> String {
+        self.0.attach_description(job_id, step)
+    }
+
+    fn job_cluster(&self, job_id: u64) -> Option<String> {
+        self.0.job_cluster(job_id)
+    }
+
+    fn set_job_cluster(&self, job_id: u64, cluster: &str) {
+        self.0.set_job_cluster(job_id, cluster)
+    }
+}
+
+/// What is wrong with asking for these (comma-separated) partitions, accounts, or QOS, given the ones there are, e.g.
+/// `Partition "gpux" doesn't exist here (there are: debug, gpu)`
+pub fn unknown_placement(what: &str, requested: &str, known: &[String]) -> Option<String> {
+    let unknown: Vec<&str> = requested
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && !known.iter().any(|known| known == name))
+        .collect();
+    if unknown.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "{} {} doesn't exist here, or isn't open to you (there are: {})",
+        what,
+        unknown
+            .iter()
+            .map(|name| format!("{:?}", name))
+            .collect::<Vec<_>>()
+            .join(", "),
+        known.join(", ")
+    ))
+}
+
+/// Whether a SLURM job state means the job ended because something went wrong (cancelling it isn't counted)
+pub fn is_failed_state(state: &str) -> bool {
+    matches!(
+        state,
+        "FAILED"
+            | "TIMEOUT"
+            | "NODE_FAIL"
+            | "OUT_OF_MEMORY"
+            | "PREEMPTED"
+            | "BOOT_FAIL"
+            | "DEADLINE"
+    )
+}
+
+/// Whether a SLURM job state means the job hasn't finished yet
+pub fn is_active_state(state: &str) -> bool {
+    matches!(
+        state,
+        "PENDING"
+            | "RUNNING"
+            | "COMPLETING"
+            | "CONFIGURING"
+            | "SUSPENDED"
+            | "REQUEUED"
+            | "RESIZING"
+            | "SIGNALING"
+            | "STAGE_OUT"
+    )
+}
+
+/// Whether a job is still pending/running (anything the scheduler no longer knows about is considered done)
+pub fn job_is_active(job_id: u64) -> bool {
+    match scheduler().job_state(job_id) {
+        Ok(Some(state)) => {
+            observe_state(job_id, &state);
+            is_active_state(&state)
+        }
+        Ok(None) => false,
+        // If the scheduler can't be reached, don't pretend to know the job is gone
+        Err(_) => true,
+    }
+}
+
+/// The state a job ended in (e.g. "COMPLETED", "NODE_FAIL", "TIMEOUT")
+/// Accounting can lag behind the queue, so while the job still shows as active this waits a little for it to show up
+/// in a final state. A job the scheduler doesn't know about (or can't be asked about) gives None straight away.
+pub fn job_final_state(job_id: u64) -> Option<String> {
+    for _ in 0..30 {
+        let state = scheduler().job_state(job_id).ok()??;
+        observe_state(job_id, &state);
+        if !is_active_state(&state) {
+            return Some(state);
+        }
+        sleep(Duration::from_secs(1));
+    }
+
+    None
+}
+
+/// Split one of the "--option=value" sbatch arguments slurmtail generates into its option name and value
+/// Backends that don't run sbatch itself only understand this small set of options
+pub fn split_sbatch_override(arg: &str) -> Result<(&str, &str), SlurmtailError> {
+    arg.strip_prefix("--")
+        .and_then(|arg| arg.split_once('='))
+        .filter(|(option, _)| {
+            matches!(
+                *option,
+                "dependency"
+                    | "partition"
+                    | "account"
+                    | "qos"
+                    | "clusters"
+                    | "job-name"
+                    | "output"
+                    | "error"
+            )
+        })
+        .ok_or_else(|| {
+            SlurmtailError::unsupported(format!("The sbatch argument {}", arg), scheduler().name())
+        })
+}
+
+/// The job description the backends that talk to SLURM themselves (libslurm, slurmrestd) submit a script with: its
+/// directives with the extra arguments on top, but with the log file, job name, and array slurmtail will look for
+/// (as with sbatch, $SBATCH_OUTPUT and the like win over the script, and the arguments win over both)
+pub fn job_request(
+    script_path: &Path,
+    extra_args: &[String],
+    scheduler: &str,
+) -> Result<JobRequest, SlurmtailError> {
+    let directives = SbatchDirectives::from_file(script_path)?.with_args(extra_args);
+    let mut request = JobRequest::from_directives(&directives, scheduler)?;
+
+    let overrides = SbatchDirectives::from_args(extra_args);
+    if overrides.output.is_none() {
+        request.output = Some(extract_log_output_pattern(script_path)?);
+    }
+    if overrides.job_name.is_none() {
+        request.name = extract_job_name(script_path)?;
+    }
+    if overrides.array.is_none() {
+        request.array = extract_array_spec(script_path)?;
+    }
+
+    Ok(request)
+}
+
+// Parse the job ID (and the cluster, in multi-cluster setups) out of sbatch's output
+// Accepts the canonical "Submitted batch job 1234" (optionally followed by " on cluster X") and the --parsable
+// "1234" / "1234;X" forms, on any line, so banners added by wrappers don't get in the way
+fn parse_sbatch_output(stdout: &str) -> Option<(u64, Option<String>)> {
+    stdout.lines().map(str::trim).find_map(|line| {
+        if let Some(rest) = line.strip_prefix("Submitted batch job ") {
+            let mut words = rest.split_whitespace();
+            let job_id = words.next()?.parse::<u64>().ok()?;
+            let cluster = match (words.next(), words.next(), words.next()) {
+                (Some("on"), Some("cluster"), Some(cluster)) => Some(cluster.to_string()),
+                _ => None,
+            };
+            return Some((job_id, cluster));
+        }
+
+        let (job_id, cluster) = match line.split_once(';') {
+            Some((job_id, cluster)) => (job_id, Some(cluster.to_string())),
+            None => (line, None),
+        };
+        Some((job_id.parse::<u64>().ok()?, cluster))
+    })
+}
+
+// sacct fields kept in the submission history once a job has finished
+const SACCT_HISTORY_FIELDS: &[&str] = &[
+    "State",
+    "ExitCode",
+    "Elapsed",
+    "Start",
+    "End",
+    "NodeList",
+    "AllocTRES",
+    "ConsumedEnergyRaw",
+];
+
+/// The SLURM command-line tools: sbatch, squeue, sacct, and scancel
+#[derive(Default)]
+pub struct SlurmCli {
+    // Clusters that jobs were submitted to (only jobs sbatch reported a cluster for)
+    clusters: Mutex<HashMap<u64, String>>,
+}
+
+impl SlurmCli {
+    // The "-M <cluster>" arguments that point squeue/sacct/scancel at the cluster a job lives on, if known
+    fn cluster_args(&self, job_id: u64) -> Vec<String> {
+        match self.job_cluster(job_id) {
+            Some(cluster) => vec!["-M".to_string(), cluster],
+            None => Vec::new(),
+        }
+    }
+
+    // The partitions scontrol knows of (on the given clusters), or None if it can't say
+    fn partitions(clusters: Option<&str>) -> Option<Vec<String>> {
+        let mut command = remote::Command::new("scontrol");
+        if let Some(clusters) = clusters {
+            command.args(["-M", clusters]);
+        }
+        let output = command
+            .args(["show", "partition", "-o"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+
+        // e.g. "PartitionName=debug AllowGroups=ALL ..."
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Some(
+            stdout
+                .lines()
+                .filter_map(|line| {
+                    line.split_whitespace()
+                        .find_map(|field| field.strip_prefix("PartitionName="))
+                })
+                .map(str::to_string)
+                .collect(),
+        )
+    }
+
+    // The accounts the user may submit to (on the given clusters), each with the QOS it allows them (empty for no
+    // restriction), as sacctmgr has them; None without an accounting database to ask
+    fn associations(clusters: Option<&str>) -> Option<Vec<(String, Vec<String>)>> {
+        let user = env::var("USER").ok()?;
+        let mut command = remote::Command::new("sacctmgr");
+        command.args(["-n", "-P", "show", "assoc", &format!("user={}", user)]);
+        if let Some(clusters) = clusters {
+            command.arg(format!("cluster={}", clusters));
+        }
+        let output = command
+            .arg("format=account,qos")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+
+        // e.g. "research|normal,high"
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let associations: Vec<(String, Vec<String>)> = stdout
+            .lines()
+            .filter_map(|line| line.split_once('|'))
+            .map(|(account, qos)| {
+                (
+                    account.trim().to_string(),
+                    qos.split(',')
+                        .map(str::trim)
+                        .filter(|qos| !qos.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                )
+            })
+            .collect();
+
+        (!associations.is_empty()).then_some(associations)
+    }
+}
+
+impl Scheduler for SlurmCli {
+    fn name(&self) -> &'static str {
+        "slurm"
+    }
+
+    fn submit(&self, script_path: &Path, extra_args: &[String]) -> Result<u64, SlurmtailError> {
+        let output = remote::Command::new("sbatch")
+            .args(extra_args)
+            .script(script_path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(SlurmtailError::scheduler("sbatch", stderr));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let (job_id, cluster) = parse_sbatch_output(&stdout).ok_or_else(|| {
+            SlurmtailError::scheduler(
+                "sbatch",
+                format!(
+                    "Could not extract job ID from its output: {}",
+                    stdout.trim()
+                ),
+            )
+        })?;
+
+        if let Some(cluster) = cluster {
+            self.set_job_cluster(job_id, &cluster);
+        }
+
+        Ok(job_id)
+    }
+
+    fn test_only(
+        &self,
+        script_path: &Path,
+        extra_args: &[String],
+    ) -> Result<String, SlurmtailError> {
+        let output = remote::Command::new("sbatch")
+            .arg("--test-only")
+            .args(extra_args)
+            .script(script_path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+
+        // sbatch reports the test-only result on stderr, prefixed with "sbatch: "
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = stderr
+            .lines()
+            .map(|line| line.trim().trim_start_matches("sbatch: ").trim())
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        if !output.status.success() {
+            return Err(SlurmtailError::scheduler("sbatch --test-only", message));
+        }
+
+        Ok(message)
+    }
+
+    // Checked against scontrol's partitions and the user's associations in sacctmgr
+    fn placement_problems(&self, directives: &SbatchDirectives) -> Vec<String> {
+        let clusters = directives.clusters.as_deref();
+        let mut problems = Vec::new();
+        if let Some(partitions) = &directives.partition
+            && let Some(known) = Self::partitions(clusters)
+        {
+            problems.extend(unknown_placement("Partition", partitions, &known));
+        }
+
+        if directives.account.is_none() && directives.qos.is_none() {
+            return problems;
+        }
+        let Some(associations) = Self::associations(clusters) else {
+            return problems;
+        };
+        let accounts: Vec<String> = associations
+            .iter()
+            .map(|(account, _)| account.clone())
+            .collect();
+        if let Some(account) = &directives.account {
+            problems.extend(unknown_placement("Account", account, &accounts));
+        }
+        // The QOS the requested account allows (any of the user's, without one), unless they don't limit it
+        let mut allowed: Vec<String> = associations
+            .into_iter()
+            .filter(|(account, _)| {
+                directives
+                    .account
+                    .as_ref()
+                    .is_none_or(|requested| requested == account)
+            })
+            .flat_map(|(_, qos)| qos)
+            .collect();
+        allowed.sort();
+        allowed.dedup();
+        if let Some(qos) = &directives.qos
+            && !allowed.is_empty()
+        {
+            problems.extend(unknown_placement("QOS", qos, &allowed));
+        }
+
+        problems
+    }
+
+    // squeue's reason column ("None" once the job is running)
+    fn pending_reason(&self, job_id: u64) -> Result<Option<String>, SlurmtailError> {
+        let output = remote::Command::new("squeue")
+            .args(self.cluster_args(job_id))
+            .args(["-h", "-o", "%r", "-j", &job_id.to_string()])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output()?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(stdout
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.starts_with("CLUSTER:"))
+            .filter(|reason| !reason.is_empty() && *reason != "None")
+            .map(str::to_string))
+    }
+
+    // squeue --start's estimate ("N/A" until the scheduler has worked one out), on the cluster's clock without a time
+    // zone, taken to be this machine's
+    fn estimated_start(&self, job_id: u64) -> Result<Option<jiff::Timestamp>, SlurmtailError> {
+        let output = remote::Command::new("squeue")
+            .args(self.cluster_args(job_id))
+            .args(["--start", "-h", "-o", "%S", "-j", &job_id.to_string()])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output()?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(stdout
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.starts_with("CLUSTER:"))
+            .and_then(|start| start.parse::<jiff::civil::DateTime>().ok())
+            .and_then(|start| start.to_zoned(jiff::tz::TimeZone::system()).ok())
+            .map(|start| start.timestamp()))
+    }
+
+    // squeue knows about queued and running jobs; once a job drops out of it, sacct knows how it ended
+    fn job_state(&self, job_id: u64) -> Result<Option<String>, SlurmtailError> {
+        let output = remote::Command::new("squeue")
+            .args(self.cluster_args(job_id))
+            .args(["-h", "-o", "%T", "-j", &job_id.to_string()])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output()?;
+
+        // With -M, squeue prints a "CLUSTER: <name>" line even with -h
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if output.status.success()
+            && let Some(state) = stdout
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.starts_with("CLUSTER:"))
+            && !state.is_empty()
+        {
+            return Ok(Some(state.to_string()));
+        }
+
+        let output = remote::Command::new("sacct")
+            .args(self.cluster_args(job_id))
+            .args(["-n", "-X", "-P", "-o", "State", "-j", &job_id.to_string()])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output();
+
+        // No accounting at all means we can only go by squeue, which no longer lists the job
+        let Ok(output) = output else {
+            return Ok(None);
+        };
+
+        // "CANCELLED by 1234" -> "CANCELLED"
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let state = stdout
+            .lines()
+            .next()
+            .and_then(|line| line.split_whitespace().next())
+            .map(|state| state.to_string());
+
+        Ok(state)
+    }
+
+    fn cancel(&self, job_id: u64) -> Result<(), SlurmtailError> {
+        let output = remote::Command::new("scancel")
+            .args(self.cluster_args(job_id))
+            .arg(job_id.to_string())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(SlurmtailError::scheduler("scancel", stderr));
+        }
+
+        Ok(())
+    }
+
+    fn job_accounting(&self, job_id: u64) -> Result<Vec<(String, String)>, SlurmtailError> {
+        let output = remote::Command::new("sacct")
+            .args(self.cluster_args(job_id))
+            .args(["-n", "-X", "-P", "-o", &SACCT_HISTORY_FIELDS.join(",")])
+            .args(["-j", &job_id.to_string()])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(SlurmtailError::scheduler("sacct", stderr.trim()));
+        }
+
+        // One "|"-separated line for the job allocation itself (-X leaves out the steps)
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let Some(line) = stdout.lines().next() else {
+            return Ok(Vec::new());
+        };
+
+        // Sites that don't collect energy report using none at all
+        Ok(SACCT_HISTORY_FIELDS
+            .iter()
+            .zip(line.split('|'))
+            .map(|(field, value)| match (*field, value) {
+                ("ConsumedEnergyRaw", "0") => (field.to_string(), String::new()),
+                _ => (field.to_string(), value.to_string()),
+            })
+            .collect())
+    }
+
+    fn batch_host(&self, job_id: u64) -> Result<Option<String>, SlurmtailError> {
+        let output = remote::Command::new("squeue")
+            .args(self.cluster_args(job_id))
+            .args(["-h", "-o", "%B", "-j", &job_id.to_string()])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output()?;
+
+        // Pending jobs show "n/a" or "(null)" instead of a host
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let host = stdout
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.starts_with("CLUSTER:"))
+            .filter(|host| !host.is_empty() && *host != "n/a" && *host != "(null)")
+            .map(|host| host.to_string());
+
+        Ok(host)
+    }
+
+    // squeue gives the nodes as a hostlist (e.g. "gpu[01-03,07]"), which scontrol expands into one name per line
+    fn job_nodes(&self, job_id: u64) -> Result<Vec<String>, SlurmtailError> {
+        let output = remote::Command::new("squeue")
+            .args(self.cluster_args(job_id))
+            .args(["-h", "-o", "%N", "-j", &job_id.to_string()])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output()?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let Some(hostlist) = stdout
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.starts_with("CLUSTER:"))
+            .filter(|hostlist| !hostlist.is_empty() && *hostlist != "n/a" && *hostlist != "(null)")
+        else {
+            return Ok(Vec::new());
+        };
+
+        let output = remote::Command::new("scontrol")
+            .args(["show", "hostnames", hostlist])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(SlurmtailError::scheduler(
+                "scontrol show hostnames",
+                stderr.trim(),
+            ));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|node| !node.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    // sacct, with every task on a line of its own (--array); tasks still pending may still come as one range, e.g.
+    // "1234_[5-100]", which counts once per task
+    fn array_task_states(&self, job_id: u64) -> Result<Vec<String>, SlurmtailError> {
+        let output = remote::Command::new("sacct")
+            .args(self.cluster_args(job_id))
+            .args(["-n", "-X", "-P", "--array", "-o", "JobID,State"])
+            .args(["-j", &job_id.to_string()])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(SlurmtailError::scheduler("sacct", stderr.trim()));
+        }
+
+        // e.g. "1234_7|RUNNING" or "1234_3|CANCELLED by 1000"
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut states = Vec::new();
+        for (task, state) in stdout.lines().filter_map(|line| line.split_once('|')) {
+            let state = state.split_whitespace().next().unwrap_or_default();
+            let tasks = task
+                .split_once("_[")
+                .and_then(|(_, range)| array_task_count(range.trim_end_matches(']')))
+                .unwrap_or(1);
+            states.extend(std::iter::repeat_n(state.to_string(), tasks));
+        }
+
+        Ok(states)
+    }
+
+    // squeue sorted by submission time, newest first; array tasks and heterogeneous components give their job's ID
+    fn newest_job(&self) -> Result<Option<u64>, SlurmtailError> {
+        let user = env::var("USER").map_err(|_| {
+            SlurmtailError::Invalid(
+                "$USER isn't set, so there is no telling whose jobs to list".to_string(),
+            )
+        })?;
+        let output = remote::Command::new("squeue")
+            .args(["-u", &user, "--sort=-V", "-h", "-o", "%A"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(SlurmtailError::scheduler("squeue", stderr.trim()));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(stdout
+            .lines()
+            .find_map(|line| line.trim().parse::<u64>().ok()))
+    }
+
+    // The StdOut field of scontrol's record of the job
+    fn output_path(&self, job_id: u64) -> Result<Option<PathBuf>, SlurmtailError> {
+        let output = remote::Command::new("scontrol")
+            .args(self.cluster_args(job_id))
+            .args(["show", "job", "-o", &job_id.to_string()])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(SlurmtailError::scheduler("scontrol", stderr.trim()));
+        }
+
+        // e.g. "JobId=1234 JobName=train ... StdOut=/home/me/train.1234.out ..."
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(stdout
+            .split_whitespace()
+            .find_map(|field| field.strip_prefix("StdOut="))
+            .filter(|path| !path.is_empty() && *path != "(null)")
+            .map(PathBuf::from))
+    }
+
+    // nvidia-smi, run on each of the job's nodes in a step of its own that shares the job's resources (--overlap), and
+    // so sees the GPUs allocated to it there
+    fn gpu_usage(&self, job_id: u64) -> Result<Vec<GpuUsage>, SlurmtailError> {
+        let output = remote::Command::new("srun")
+            .arg(format!("--jobid={}", job_id))
+            .args(["--overlap", "--ntasks-per-node=1", "--quiet"])
+            .args([
+                "nvidia-smi",
+                NVIDIA_SMI_QUERY,
+                "--format=csv,noheader,nounits",
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(SlurmtailError::scheduler("srun nvidia-smi", stderr.trim()));
+        }
+
+        Ok(parse_gpu_query(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    // strigger runs the program on the controller, as the user, once the job is done (--fini: however it ends); it
+    // splits the program from its arguments on spaces, so the sentinel's path can't have any
+    fn set_end_trigger(&self, job_id: u64, sentinel: &Path) -> Result<(), SlurmtailError> {
+        let sentinel = sentinel.to_string_lossy();
+        if sentinel.contains(char::is_whitespace) {
+            return Err(SlurmtailError::scheduler(
+                "strigger",
+                format!("Can't touch {:?}, as its path has spaces", sentinel),
+            ));
+        }
+        let output = remote::Command::new("strigger")
+            .args(self.cluster_args(job_id))
+            .args(["--set", "--fini", &format!("--jobid={}", job_id)])
+            .arg(format!("--program=/bin/touch {}", sentinel))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(SlurmtailError::scheduler("strigger", stderr.trim()));
+        }
+
+        Ok(())
+    }
+
+    // sattach takes over the terminal (stdin, stdout, and stderr) until the step ends
+    fn attach(&self, job_id: u64, step: u32) -> Result<(), SlurmtailError> {
+        let status = remote::Command::new("sattach")
+            .arg(format!("{}.{}", job_id, step))
+            .status()?;
+
+        if !status.success() {
+            return Err(SlurmtailError::scheduler("sattach", status));
+        }
+
+        Ok(())
+    }
+
+    fn attach_description(&self, job_id: u64, step: u32) -> String {
+        format!("the I/O of job step {}.{} with sattach", job_id, step)
+    }
+
+    fn job_cluster(&self, job_id: u64) -> Option<String> {
+        self.clusters.lock().ok()?.get(&job_id).cloned()
+    }
+
+    fn set_job_cluster(&self, job_id: u64, cluster: &str) {
+        if let Ok(mut clusters) = self.clusters.lock() {
+            clusters.insert(job_id, cluster.to_string());
+        }
+    }
+}
+
+// First job ID handed out by the mock scheduler
+const MOCK_FIRST_JOB_ID: u64 = 1000;
+
+// The nodes every mock job "runs" on (for %N and %n): $SLURMTAIL_MOCK_NODES, or just this one
+fn mock_nodes() -> Vec<String> {
+    let nodes: Vec<String> = env::var("SLURMTAIL_MOCK_NODES")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|node| !node.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    match nodes.is_empty() {
+        true => vec!["localhost".to_string()],
+        false => nodes,
+    }
+}
+
+/// A stand-in scheduler for machines without SLURM (tests, demos)
+/// "Submitting" runs the script locally in the background, writing its output where SLURM would. Each job gets a
+/// directory under the mock state dir ($SLURMTAIL_MOCK_DIR, or a temp dir) holding marker files for its state. A job
+/// array runs only its first task, and every task is reported in that task's state.
+/// The state transitions can be scripted with environment variables:
+///   SLURMTAIL_MOCK_PENDING_SECONDS - how long jobs stay PENDING before running (default: 1), and their estimated start
+///   SLURMTAIL_MOCK_FINAL_STATE     - state to report once the script finishes, instead of COMPLETED/FAILED
+///   SLURMTAIL_MOCK_PARTITIONS      - comma-separated partitions that exist (default: any)
+///   SLURMTAIL_MOCK_ACCOUNTS        - comma-separated accounts the user may submit to (default: any)
+///   SLURMTAIL_MOCK_QOS             - comma-separated QOS the user may ask for (default: any)
+///   SLURMTAIL_MOCK_GPUS            - the GPUs running jobs have, as nvidia-smi reports them, separated by ';' (e.g.
+///                                    "98, 40211, 81920; 3, 1024, 81920": utilization, memory used and in all)
+///   SLURMTAIL_MOCK_NODES           - comma-separated nodes jobs are given (default: localhost); the script only runs
+///                                    on the first
+///   SLURMTAIL_MOCK_ENERGY          - the energy (in joules) jobs are accounted for having used (default: none)
+///   SLURMTAIL_MOCK_NO_TRIGGERS     - if set, setting a trigger on a job's end is refused, as on sites that don't allow
+///                                    strigger
+pub struct MockScheduler {
+    state_dir: PathBuf,
+}
+
+impl MockScheduler {
+    pub fn from_env() -> MockScheduler {
+        let state_dir = env::var("SLURMTAIL_MOCK_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| env::temp_dir().join("slurmtail-mock"));
+
+        MockScheduler { state_dir }
+    }
+
+    fn job_dir(&self, job_id: u64) -> PathBuf {
+        self.state_dir.join(job_id.to_string())
+    }
+
+    // Claim the next free job ID (creating its directory is what makes the claim atomic)
+    fn allocate_job(&self) -> Result<u64, SlurmtailError> {
+        fs::create_dir_all(&self.state_dir)
+            .map_err(SlurmtailError::io("create", &self.state_dir))?;
+
+        let mut job_id = MOCK_FIRST_JOB_ID;
+        loop {
+            match fs::create_dir(self.job_dir(job_id)) {
+                Ok(()) => return Ok(job_id),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => job_id += 1,
+                Err(e) => return Err(SlurmtailError::io("create", &self.job_dir(job_id))(e)),
+            }
+        }
+    }
+}
+
+// Write one of the files the mock keeps a job's state in
+fn write_job_file(
+    job_dir: &Path,
+    name: &str,
+    contents: impl AsRef<[u8]>,
+) -> Result<(), SlurmtailError> {
+    let path = job_dir.join(name);
+    fs::write(&path, contents).map_err(SlurmtailError::io("write", &path))
+}
+
+// Quote a string for safe use in the generated shell wrapper
+pub(crate) fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+impl Scheduler for MockScheduler {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn submit(&self, script_path: &Path, extra_args: &[String]) -> Result<u64, SlurmtailError> {
+        let mut output_pattern = extract_log_output_pattern(script_path)?;
+        let mut error_pattern = None;
+        let mut job_name = extract_job_name(script_path)?;
+        let mut dependency = None;
+        for arg in extra_args {
+            let (option, value) = split_sbatch_override(arg)?;
+            match option {
+                "output" => output_pattern = value.to_string(),
+                "error" => error_pattern = Some(value.to_string()),
+                "job-name" => job_name = Some(value.to_string()),
+                "dependency" => dependency = Some(value.to_string()),
+                // Placement options mean nothing when running locally
+                _ => {}
+            }
+        }
+
+        let job_id = self.allocate_job()?;
+        let job_dir = self.job_dir(job_id);
+        let array_task = extract_array_spec(script_path)?
+            .as_deref()
+            .and_then(first_array_task);
+
+        // Resolve the output files the same way slurmtail does, so the tailer finds them
+        let resolve = |pattern: String| {
+            let filename = format_log_output_string(
+                pattern,
+                job_id,
+                job_name.as_ref(),
+                array_task,
+                mock_nodes().first().map(String::as_str),
+            );
+            logfile_string_to_path(script_path, filename, true)
+        };
+        let stdout_path = resolve(output_pattern)?;
+        let stderr_path = match error_pattern {
+            Some(pattern) => resolve(pattern)?,
+            None => stdout_path.clone(),
+        };
+
+        let pending_seconds = env::var("SLURMTAIL_MOCK_PENDING_SECONDS")
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(1);
+        let final_state = env::var("SLURMTAIL_MOCK_FINAL_STATE").unwrap_or_default();
+
+        // Dependencies ("afterok:1000:1001") wait for the named mock jobs to finish
+        let mut wait_for_dependencies = String::new();
+        if let Some(dependency) = dependency {
+            let mut parts = dependency.split(':');
+            let dependency_type = parts.next().unwrap_or("afterok");
+            for dep_id in parts {
+                let dep_state =
+                    shell_quote(&self.state_dir.join(dep_id).join("state").to_string_lossy());
+                wait_for_dependencies
+                    .push_str(&format!("while [ ! -f {dep_state} ]; do sleep 1; done\n"));
+                if dependency_type == "afterok" {
+                    wait_for_dependencies.push_str(&format!(
+                        "if [ \"$(cat {dep_state})\" != COMPLETED ]; then echo CANCELLED > state; exit 0; fi\n"
+                    ));
+                }
+            }
+        }
+
+        let wrapper = format!(
+            "cd {job_dir}\n\
+             echo $$ > pid\n\
+             sleep {pending_seconds}\n\
+             {wait_for_dependencies}\
+             touch started\n\
+             (cd {cwd} && SLURM_JOB_ID={job_id} SLURM_JOB_NAME={job_name} bash {script} >> {stdout} 2>> {stderr})\n\
+             rc=$?\n\
+             echo $rc > exit_code\n\
+             if [ -n {final_state} ]; then echo {final_state} > state\n\
+             elif [ $rc -eq 0 ]; then echo COMPLETED > state\n\
+             else echo FAILED > state; fi\n\
+             if [ -f trigger ]; then touch \"$(cat trigger)\"; fi\n",
+            job_dir = shell_quote(&job_dir.to_string_lossy()),
+            cwd = shell_quote(&job_working_dir(script_path)?.to_string_lossy()),
+            job_name = shell_quote(job_name.as_deref().unwrap_or("mock")),
+            script = shell_quote(
+                &fs::canonicalize(script_path)
+                    .map_err(SlurmtailError::io("find", script_path))?
+                    .to_string_lossy()
+            ),
+            stdout = shell_quote(&stdout_path.to_string_lossy()),
+            stderr = shell_quote(&stderr_path.to_string_lossy()),
+            final_state = shell_quote(&final_state),
+        );
+
+        write_job_file(&job_dir, "stdout", stdout_path.to_string_lossy().as_bytes())?;
+        if let Some(spec) = extract_array_spec(script_path)? {
+            write_job_file(&job_dir, "array", spec)?;
+        }
+        let estimated_start =
+            jiff::Timestamp::now() + jiff::SignedDuration::from_secs(pending_seconds.into());
+        write_job_file(&job_dir, "estimated_start", estimated_start.to_string())?;
+
+        // Run detached from our own stdio (so callers capturing our output don't wait on the job) and in its own
+        // process group (so cancel can take down the whole job)
+        ProcessCommand::new("bash")
+            .args(["-c", &wrapper])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .process_group(0)
+            .spawn()
+            .map_err(|e| SlurmtailError::scheduler("sbatch", e))?;
+
+        Ok(job_id)
+    }
+
+    fn test_only(
+        &self,
+        script_path: &Path,
+        _extra_args: &[String],
+    ) -> Result<String, SlurmtailError> {
+        Ok(format!(
+            "Mock job for {} would start immediately",
+            script_path.display()
+        ))
+    }
+
+    // Checked against the partitions, accounts, and QOS the environment says there are
+    fn placement_problems(&self, directives: &SbatchDirectives) -> Vec<String> {
+        [
+            (
+                "Partition",
+                &directives.partition,
+                "SLURMTAIL_MOCK_PARTITIONS",
+            ),
+            ("Account", &directives.account, "SLURMTAIL_MOCK_ACCOUNTS"),
+            ("QOS", &directives.qos, "SLURMTAIL_MOCK_QOS"),
+        ]
+        .into_iter()
+        .filter_map(|(what, requested, variable)| {
+            let known: Vec<String> = env::var(variable)
+                .ok()?
+                .split(',')
+                .map(|name| name.trim().to_string())
+                .collect();
+            unknown_placement(what, requested.as_deref()?, &known)
+        })
+        .collect()
+    }
+
+    fn job_state(&self, job_id: u64) -> Result<Option<String>, SlurmtailError> {
+        let job_dir = self.job_dir(job_id);
+
+        if !job_dir.exists() {
+            Ok(None)
+        } else if let Ok(state) = read_to_string(job_dir.join("state")) {
+            Ok(Some(state.trim().to_string()))
+        } else if job_dir.join("started").exists() {
+            Ok(Some("RUNNING".to_string()))
+        } else {
+            Ok(Some("PENDING".to_string()))
+        }
+    }
+
+    // Only the first task really runs, and the others are taken to do just what it does
+    fn array_task_states(&self, job_id: u64) -> Result<Vec<String>, SlurmtailError> {
+        let tasks = read_to_string(self.job_dir(job_id).join("array"))
+            .ok()
+            .and_then(|spec| array_task_count(spec.trim()))
+            .unwrap_or(1);
+
+        Ok(match self.job_state(job_id)? {
+            Some(state) => vec![state; tasks],
+            None => Vec::new(),
+        })
+    }
+
+    // The highest-numbered job that hasn't ended yet
+    fn newest_job(&self) -> Result<Option<u64>, SlurmtailError> {
+        let Ok(entries) = fs::read_dir(&self.state_dir) else {
+            return Ok(None);
+        };
+
+        let mut active: Vec<u64> = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| entry.file_name().to_str()?.parse::<u64>().ok())
+            .filter(|job_id| !self.job_dir(*job_id).join("state").exists())
+            .collect();
+        active.sort();
+        Ok(active.pop())
+    }
+
+    // As resolved at submission
+    fn output_path(&self, job_id: u64) -> Result<Option<PathBuf>, SlurmtailError> {
+        Ok(read_to_string(self.job_dir(job_id).join("stdout"))
+            .ok()
+            .map(PathBuf::from))
+    }
+
+    // When it was due to stop pending, as of its submission
+    fn estimated_start(&self, job_id: u64) -> Result<Option<jiff::Timestamp>, SlurmtailError> {
+        if self.job_state(job_id)?.as_deref() != Some("PENDING") {
+            return Ok(None);
+        }
+
+        Ok(read_to_string(self.job_dir(job_id).join("estimated_start"))
+            .ok()
+            .and_then(|start| start.trim().parse().ok()))
+    }
+
+    // Reported the way sacct does ("<exit code>:<signal>")
+    fn job_accounting(&self, job_id: u64) -> Result<Vec<(String, String)>, SlurmtailError> {
+        let mut fields = Vec::new();
+        if let Some(state) = self.job_state(job_id)? {
+            fields.push(("State".to_string(), state));
+        }
+        if let Ok(exit_code) = read_to_string(self.job_dir(job_id).join("exit_code")) {
+            fields.push(("ExitCode".to_string(), format!("{}:0", exit_code.trim())));
+        }
+        if let Ok(energy) = env::var("SLURMTAIL_MOCK_ENERGY") {
+            fields.push(("ConsumedEnergyRaw".to_string(), energy));
+        }
+
+        Ok(fields)
+    }
+
+    fn batch_host(&self, job_id: u64) -> Result<Option<String>, SlurmtailError> {
+        let job_dir = self.job_dir(job_id);
+
+        Ok(job_dir
+            .join("started")
+            .exists()
+            .then(|| mock_nodes().swap_remove(0)))
+    }
+
+    fn job_nodes(&self, job_id: u64) -> Result<Vec<String>, SlurmtailError> {
+        let job_dir = self.job_dir(job_id);
+
+        match job_dir.join("started").exists() {
+            true => Ok(mock_nodes()),
+            false => Ok(Vec::new()),
+        }
+    }
+
+    // The GPUs in $SLURMTAIL_MOCK_GPUS, while the job is running
+    fn gpu_usage(&self, job_id: u64) -> Result<Vec<GpuUsage>, SlurmtailError> {
+        if self.job_state(job_id)?.as_deref() != Some("RUNNING") {
+            return Ok(Vec::new());
+        }
+
+        let gpus = env::var("SLURMTAIL_MOCK_GPUS").unwrap_or_default();
+        Ok(parse_gpu_query(&gpus.replace(';', "\n")))
+    }
+
+    // The wrapper (or cancel) touches the file named in the job's "trigger" file once the job is done
+    fn set_end_trigger(&self, job_id: u64, sentinel: &Path) -> Result<(), SlurmtailError> {
+        if env::var_os("SLURMTAIL_MOCK_NO_TRIGGERS").is_some() {
+            return Err(SlurmtailError::scheduler("strigger", "Access denied"));
+        }
+        let job_dir = self.job_dir(job_id);
+        if !job_dir.exists() {
+            return Err(SlurmtailError::scheduler(
+                "strigger",
+                format!("Invalid job id specified: {}", job_id),
+            ));
+        }
+
+        write_job_file(&job_dir, "trigger", sentinel.to_string_lossy().as_bytes())?;
+        // A job that is already done fires it right away
+        if job_dir.join("state").exists() {
+            fs::write(sentinel, "").map_err(SlurmtailError::io("write", sentinel))?;
+        }
+
+        Ok(())
+    }
+
+    fn cancel(&self, job_id: u64) -> Result<(), SlurmtailError> {
+        let job_dir = self.job_dir(job_id);
+        if !job_dir.exists() {
+            return Err(SlurmtailError::scheduler(
+                "scancel",
+                format!("Invalid job id specified: {}", job_id),
+            ));
+        }
+        if job_dir.join("state").exists() {
+            return Err(SlurmtailError::scheduler(
+                "scancel",
+                format!("Job {} has already finished", job_id),
+            ));
+        }
+
+        // Kill the wrapper's whole process group, then record the outcome ourselves
+        if let Ok(pid) = read_to_string(job_dir.join("pid")) {
+            let _ = ProcessCommand::new("kill")
+                .args(["-TERM", "--", &format!("-{}", pid.trim())])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+        write_job_file(&job_dir, "state", "CANCELLED\n")?;
+        if let Ok(sentinel) = read_to_string(job_dir.join("trigger")) {
+            let _ = fs::write(sentinel, "");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,393 @@
+use std::env;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::directives::{self, SbatchDirectives};
+use crate::error::{SlurmtailError, current_dir};
+use crate::output;
+use crate::scheduler::scheduler;
+
+// Value of one of sbatch's input environment variables (e.g. SBATCH_OUTPUT), if set
+// sbatch lets these override the script's directives (command-line options still override them)
+fn sbatch_env(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Read the batch file and extract the log output pattern (in SLURM batch file format)
+/// e.g.: #SBATCH --output output.%j.log
+///       -> "output.%j.log"
+/// Without an output directive, SLURM writes to "slurm-%j.out" ("slurm-%A_%a.out" for job arrays)
+pub fn extract_log_output_pattern(script_path: &Path) -> Result<String, SlurmtailError> {
+    if let Some(pattern) = sbatch_env("SBATCH_OUTPUT") {
+        return Ok(pattern);
+    }
+
+    if let Some(pattern) = SbatchDirectives::from_file(script_path)?.output {
+        return Ok(pattern);
+    }
+
+    match extract_array_spec(script_path)? {
+        Some(_) => Ok("slurm-%A_%a.out".to_string()),
+        None => Ok("slurm-%j.out".to_string()),
+    }
+}
+
+/// Extract the error output pattern (--error) from SLURM script
+/// Without one, the job's stderr goes to its output file
+pub fn extract_error_pattern(script_path: &Path) -> Result<Option<String>, SlurmtailError> {
+    Ok(error_pattern(&SbatchDirectives::from_file(script_path)?))
+}
+
+/// The error output pattern already parsed directives ask for, unless SBATCH_ERROR overrides it
+pub fn error_pattern(directives: &SbatchDirectives) -> Option<String> {
+    sbatch_env("SBATCH_ERROR").or_else(|| directives.error.clone())
+}
+
+/// Extract job name from SLURM script
+pub fn extract_job_name(script_path: &Path) -> Result<Option<String>, SlurmtailError> {
+    if let Some(name) = sbatch_env("SBATCH_JOB_NAME") {
+        return Ok(Some(name));
+    }
+
+    Ok(SbatchDirectives::from_file(script_path)?.job_name)
+}
+
+/// Extract the job array specification (e.g. "0-15%4") from SLURM script
+pub fn extract_array_spec(script_path: &Path) -> Result<Option<String>, SlurmtailError> {
+    Ok(array_spec(&SbatchDirectives::from_file(script_path)?))
+}
+
+/// The job array specification already parsed directives ask for, unless SBATCH_ARRAY_INX overrides it
+pub fn array_spec(directives: &SbatchDirectives) -> Option<String> {
+    sbatch_env("SBATCH_ARRAY_INX").or_else(|| directives.array.clone())
+}
+
+/// The directory a job runs in: the submission directory, or wherever --chdir points (relative to the submission directory)
+pub fn job_working_dir(script_path: &Path) -> Result<PathBuf, SlurmtailError> {
+    let current_dir = current_dir()?;
+
+    Ok(match SbatchDirectives::from_file(script_path)?.chdir {
+        Some(chdir) => current_dir.join(chdir),
+        None => current_dir,
+    })
+}
+
+/// Find the lowest task index in an array specification
+/// e.g.: "3,1-10:2%4" -> 1
+pub fn first_array_task(array_spec: &str) -> Option<u32> {
+    // Anything after '%' is the concurrency limit, not part of the index list
+    let indices = array_spec.split('%').next().unwrap_or("");
+
+    indices
+        .split(',')
+        .filter_map(|range| {
+            // Ranges may carry a step ("1-10:2"), but the first index is all we care about
+            let start = range.split([':', '-']).next()?;
+            start.trim().parse::<u32>().ok()
+        })
+        .min()
+}
+
+/// How many tasks a job array spec (e.g. "0-99%4", "1,3,5", or "1-10:2") makes, if it is one
+pub fn array_task_count(array_spec: &str) -> Option<usize> {
+    let indices = array_spec.split('%').next().unwrap_or("");
+
+    indices
+        .split(',')
+        .map(|range| {
+            let (range, step) = range.split_once(':').unwrap_or((range, "1"));
+            let step = step.trim().parse::<u32>().ok().filter(|step| *step > 0)?;
+            match range.split_once('-') {
+                Some((start, end)) => {
+                    let start = start.trim().parse::<u32>().ok()?;
+                    let end = end.trim().parse::<u32>().ok()?;
+                    Some((start..=end).step_by(step as usize).count())
+                }
+                None => range.trim().parse::<u32>().ok().map(|_| 1),
+            }
+        })
+        .sum()
+}
+
+// One piece of an sbatch filename pattern: literal text, or a placeholder with its zero-padding width
+// e.g.: "out.%5j.log" -> 'o', 'u', 't', '.', (5, 'j'), '.', 'l', 'o', 'g'
+enum PatternPiece {
+    Literal(char),
+    Placeholder(usize, char),
+}
+
+// Split an sbatch filename pattern into its pieces ("%%" is a literal '%')
+fn parse_log_output_pattern(pattern: &str) -> Vec<PatternPiece> {
+    let mut pieces = Vec::new();
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            pieces.push(PatternPiece::Literal(c));
+            continue;
+        }
+
+        let mut width = String::new();
+        while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+            width.push(digit);
+        }
+
+        match chars.next() {
+            Some('%') if width.is_empty() => pieces.push(PatternPiece::Literal('%')),
+            Some(letter) => {
+                pieces.push(PatternPiece::Placeholder(
+                    width.parse().unwrap_or(0),
+                    letter,
+                ));
+            }
+            // A trailing '%' (or "%5") isn't a placeholder
+            None => {
+                pieces.push(PatternPiece::Literal('%'));
+                pieces.extend(width.chars().map(PatternPiece::Literal));
+            }
+        }
+    }
+
+    pieces
+}
+
+/// Whether a filename pattern uses %N, which can only be resolved once the job has been given a node
+pub fn pattern_uses_node(pattern: &str) -> bool {
+    !pattern.contains('\\')
+        && parse_log_output_pattern(pattern)
+            .iter()
+            .any(|piece| matches!(piece, PatternPiece::Placeholder(_, 'N')))
+}
+
+/// Whether a filename pattern uses %N or %n, which name a different file on each of the job's nodes
+pub fn pattern_is_per_node(pattern: &str) -> bool {
+    !pattern.contains('\\')
+        && parse_log_output_pattern(pattern)
+            .iter()
+            .any(|piece| matches!(piece, PatternPiece::Placeholder(_, 'N' | 'n')))
+}
+
+/// A filename pattern with %n (the node's index, zero-padded to its width) filled in for one of the job's nodes, and
+/// everything else left for format_log_output_string
+pub fn with_node_index(pattern: &str, index: usize) -> String {
+    if pattern.contains('\\') {
+        return pattern.to_string();
+    }
+
+    let mut result = String::new();
+    for piece in parse_log_output_pattern(pattern) {
+        match piece {
+            PatternPiece::Literal('%') => result.push_str("%%"),
+            PatternPiece::Literal(c) => result.push(c),
+            PatternPiece::Placeholder(width, 'n') => {
+                result.push_str(&format!("{:0>width$}", index, width = width))
+            }
+            PatternPiece::Placeholder(0, letter) => result.push_str(&format!("%{}", letter)),
+            PatternPiece::Placeholder(width, letter) => {
+                result.push_str(&format!("%{}{}", width, letter))
+            }
+        }
+    }
+
+    result
+}
+
+/// Whether a filename pattern uses the job ID (%j, %J, or %A), which names a different file for every job
+pub fn pattern_uses_job_id(pattern: &str) -> bool {
+    !pattern.contains('\\')
+        && parse_log_output_pattern(pattern)
+            .iter()
+            .any(|piece| matches!(piece, PatternPiece::Placeholder(_, 'A' | 'J' | 'j')))
+}
+
+/// Take a SLURM-formatted output path and format it using a known jobid, optional job name, optional array task,
+/// and (for %N) optional node name
+/// Supports sbatch's filename patterns: %A/%a (array job/task), %b (task modulo 10), %J/%j (job ID), %N (first node),
+/// %n (node index), %s (step), %t (task), %u (user), %x (job name), and %% (a literal '%')
+/// Placeholders that are unknown, or whose value isn't available, are left as they are (with a warning)
+pub fn format_log_output_string(
+    logfile_pattern_string: String,
+    jobid: u64,
+    job_name: Option<&String>,
+    array_task: Option<u32>,
+    node: Option<&str>,
+) -> String {
+    // Like sbatch, a backslash turns off pattern processing (and is itself dropped)
+    if logfile_pattern_string.contains('\\') {
+        return logfile_pattern_string.replace('\\', "");
+    }
+
+    let mut result = String::new();
+    for piece in parse_log_output_pattern(&logfile_pattern_string) {
+        let (width, letter) = match piece {
+            PatternPiece::Literal(c) => {
+                result.push(c);
+                continue;
+            }
+            PatternPiece::Placeholder(width, letter) => (width, letter),
+        };
+
+        // The batch script is step "batch", task 0 on node 0; non-array jobs report SLURM's NO_VAL as their task
+        let value = match letter {
+            // For arrays, sbatch reports the array's master job ID, which is what %A expands to
+            'A' | 'J' | 'j' => Some(jobid.to_string()),
+            'a' => Some(array_task.map_or("4294967294".to_string(), |task| task.to_string())),
+            'b' => Some(array_task.map_or("4".to_string(), |task| (task % 10).to_string())),
+            'N' => node.map(str::to_string),
+            'n' | 't' => Some("0".to_string()),
+            's' => Some("batch".to_string()),
+            'u' => env::var("USER").ok(),
+            'x' => job_name.cloned(),
+            _ => None,
+        };
+
+        match value {
+            // Only numbers get zero-padded
+            Some(value) if value.chars().all(|c| c.is_ascii_digit()) => {
+                result.push_str(&format!("{:0>width$}", value, width = width));
+            }
+            Some(value) => result.push_str(&value),
+            None => {
+                let placeholder = match width {
+                    0 => format!("%{}", letter),
+                    width => format!("%{}{}", width, letter),
+                };
+                warning!(
+                    "[WARNING] Could not resolve {} in output pattern {:?}; leaving it as is.",
+                    placeholder,
+                    logfile_pattern_string
+                );
+                result.push_str(&placeholder);
+            }
+        }
+    }
+
+    result
+}
+
+// Whether the warning about an absolute logfile path has been given, so resolving a job's files doesn't repeat it
+static WARNED_ABSOLUTE: AtomicBool = AtomicBool::new(false);
+
+/// Take a now fully formed logfile path and transform it into a full path based on the location of the original script
+/// With use_cwd, relative paths are resolved the way SLURM does: against the job's working directory (see job_working_dir)
+pub fn logfile_string_to_path(
+    script_path: &Path,
+    logfile_string: String,
+    use_cwd: bool,
+) -> Result<PathBuf, SlurmtailError> {
+    // Handle given absolute path
+    if Path::new(&logfile_string).is_absolute() {
+        if use_cwd && !WARNED_ABSOLUTE.swap(true, Ordering::SeqCst) {
+            warning!(
+                "[WARNING] Gave instruction to use current directory to find logfile, but the logfile is an absolute path! Will use that instead."
+            );
+        }
+        return Ok(PathBuf::from(logfile_string));
+    }
+
+    let base_dir: PathBuf = match use_cwd {
+        true => job_working_dir(script_path)?,
+        false => script_path.parent().unwrap_or(Path::new(".")).to_path_buf(),
+    };
+
+    Ok(base_dir.join(logfile_string))
+}
+
+/// Warn about scripts with Windows line endings: slurmtail reads their directives fine, but sbatch won't take them
+pub fn warn_about_crlf(script_path: &Path) -> Result<(), SlurmtailError> {
+    if directives::has_crlf_line_endings(
+        &read_to_string(script_path).map_err(SlurmtailError::io("read", script_path))?,
+    ) {
+        warning!(
+            "[WARNING] {:?} has Windows (CRLF) line endings, which sbatch rejects; convert it with e.g. `dos2unix`.",
+            script_path
+        );
+    }
+
+    Ok(())
+}
+
+/// Print every #SBATCH directive in a script as parsed, flagging options sbatch doesn't know and options given more
+/// than once (where, as with sbatch, the last one wins), followed by what slurmtail makes of them
+pub fn inspect_script(script_path: &Path, as_json: bool) -> Result<(), SlurmtailError> {
+    if !script_path.exists() {
+        return Err(SlurmtailError::NotFound(format!(
+            "Script file does not exist: {:?}",
+            script_path
+        )));
+    }
+    if !as_json {
+        warn_about_crlf(script_path)?;
+    }
+
+    let components = SbatchDirectives::parse_components(
+        &read_to_string(script_path).map_err(SlurmtailError::io("read", script_path))?,
+    );
+    let output_pattern = scheduler().output_pattern(script_path)?;
+    let job_name = scheduler().job_name(script_path)?;
+    let array_spec = extract_array_spec(script_path)?;
+
+    let mut component_reports = Vec::new();
+    for (index, component) in components.iter().enumerate() {
+        let mut directive_reports = Vec::new();
+        for (name, value) in &component.options {
+            let known = directives::is_known_option(name);
+            let duplicate = component.options.iter().filter(|(n, _)| n == name).count() > 1;
+
+            if as_json {
+                directive_reports.push(serde_json::json!({
+                    "option": name,
+                    "value": value,
+                    "known": known,
+                    "duplicate": duplicate,
+                }));
+                continue;
+            }
+
+            let mut line = directives::option_display_name(name);
+            if !value.is_empty() {
+                line.push_str(&format!("={}", value));
+            }
+            if !known {
+                line.push_str("  [unknown option]");
+            }
+            if duplicate {
+                line.push_str("  [given more than once; the last one wins]");
+            }
+            if components.len() > 1 {
+                println!("+{}  {}", index, line);
+            } else {
+                println!("{}", line);
+            }
+        }
+        component_reports.push(serde_json::json!({ "directives": directive_reports }));
+    }
+
+    if as_json {
+        let report = serde_json::json!({
+            "script": script_path,
+            "components": component_reports,
+            "output": output_pattern,
+            "job_name": job_name,
+            "array": array_spec,
+        });
+        output::print_json(&report, true)?;
+        return Ok(());
+    }
+
+    if components
+        .iter()
+        .all(|component| component.options.is_empty())
+    {
+        println!("(no #SBATCH directives)");
+    }
+    println!();
+    println!("Output file: {}", output_pattern);
+    println!("Job name: {}", job_name.as_deref().unwrap_or("(none)"));
+    if let Some(array_spec) = array_spec {
+        println!("Array: {}", array_spec);
+    }
+
+    Ok(())
+}
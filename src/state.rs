@@ -0,0 +1,976 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions, TryLockError, read_to_string};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::directives::SbatchDirectives;
+use crate::error::SlurmtailError;
+use crate::scheduler::{is_active_state, scheduler};
+use crate::{history, interrupt, output, pipeline, remote, watch};
+
+/// Name of the directory (in the project dir) that lets `slurmtail resume` pick up where `run` left off, with one
+/// file per submitted job
+pub const RESUME_DIR: &str = "._slurmtail";
+
+// Subdirectory (of a resume directory) that sessions move to once their job has finished
+const FINISHED_DIR: &str = "finished";
+
+// Lock file (in a resume directory) that serializes reads and writes of its session files
+const LOCK_FILE: &str = ".lock";
+
+// File (in a project's state dir under $XDG_STATE_HOME) naming the project directory the sessions belong to
+const PROJECT_FILE: &str = "project";
+
+/// Where session state is kept: in the project directory itself, or under $XDG_STATE_HOME/slurmtail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateLocation {
+    Project,
+    Xdg,
+}
+
+static STATE_LOCATION: OnceLock<StateLocation> = OnceLock::new();
+
+/// Choose where session state is kept; must happen before any state is read or written
+pub fn set_state_location(location: StateLocation) {
+    let _ = STATE_LOCATION.set(location);
+}
+
+fn state_location() -> StateLocation {
+    *STATE_LOCATION.get_or_init(|| StateLocation::Project)
+}
+
+/// slurmtail's directory under $XDG_STATE_HOME (default: ~/.local/state), or $SLURMTAIL_STATE_DIR if set
+pub fn xdg_state_root() -> Option<PathBuf> {
+    if let Some(state_dir) = std::env::var_os("SLURMTAIL_STATE_DIR")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+    {
+        return Some(state_dir);
+    }
+    let state_home = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+        })?;
+
+    Some(state_home.join("slurmtail"))
+}
+
+// A stable name for a project's state dir: the FNV-1a hash of its (canonical) path
+// (std's hashers aren't guaranteed to stay the same between Rust releases)
+fn project_key(project_dir: &Path) -> String {
+    let project_dir = project_dir
+        .canonicalize()
+        .unwrap_or_else(|_| project_dir.to_path_buf());
+
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in project_dir.as_os_str().as_encoded_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    format!("{:016x}", hash)
+}
+
+/// The directory holding a project's session files
+/// e.g.: `"<project>/._slurmtail"`, or `"~/.local/state/slurmtail/3f2a...e1"` with --state-location xdg
+pub fn resume_dir(project_dir: &Path) -> PathBuf {
+    if state_location() == StateLocation::Xdg
+        && let Some(root) = xdg_state_root()
+    {
+        return root.join(project_key(project_dir));
+    }
+
+    project_dir.join(RESUME_DIR)
+}
+
+/// Version of the resume file format written by this build
+/// (files without a version are the legacy format: just the log path)
+pub const RESUME_FORMAT_VERSION: u32 = 1;
+
+/// Everything slurmtail remembers about the job it last submitted from a project directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResumeState {
+    pub version: u32,
+    pub job_id: Option<u64>,
+    /// Multi-cluster jobs can only be queried (or cancelled) by naming their cluster
+    pub cluster: Option<String>,
+    pub script: Option<PathBuf>,
+    pub stdout: PathBuf,
+    /// None when the job's stderr goes to the stdout file
+    pub stderr: Option<PathBuf>,
+    /// The array range, so later features can find the other tasks' logs
+    pub array: Option<String>,
+    /// When the job was submitted (RFC 3339)
+    pub submitted_at: Option<String>,
+    /// How far into the stdout file slurmtail has read
+    #[serde(default)]
+    pub offset: u64,
+    /// The name given with `run --name`, if any
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The directory the job was submitted from
+    #[serde(default)]
+    pub project: Option<PathBuf>,
+    /// The state the job ended in, once it has finished
+    #[serde(default)]
+    pub final_state: Option<String>,
+    /// What stopped the last tail partway, a signal (e.g. "SIGHUP") or "--max-duration", so resuming continues from its
+    /// offset
+    #[serde(default)]
+    pub stopped_by: Option<String>,
+}
+
+impl ResumeState {
+    pub fn new(stdout: &Path) -> ResumeState {
+        ResumeState {
+            version: RESUME_FORMAT_VERSION,
+            job_id: None,
+            cluster: None,
+            script: None,
+            stdout: stdout.to_path_buf(),
+            stderr: None,
+            array: None,
+            submitted_at: None,
+            offset: 0,
+            name: None,
+            project: None,
+            final_state: None,
+            stopped_by: None,
+        }
+    }
+
+    pub fn submit_time(&self) -> Option<jiff::Timestamp> {
+        self.submitted_at
+            .as_deref()
+            .and_then(|time| time.parse::<jiff::Timestamp>().ok())
+    }
+
+    // Read a legacy resume file: just the log path, on its own line
+    // e.g.: "/home/me/output.1234.log"
+    fn from_legacy(content: &str) -> ResumeState {
+        ResumeState::new(Path::new(content.lines().next().unwrap_or("").trim()))
+    }
+}
+
+// Where a session's state lives: one file per job inside the resume directory
+// e.g.: "._slurmtail/1234.json"
+fn session_path(project_dir: &Path, job_id: u64) -> PathBuf {
+    resume_dir(project_dir).join(format!("{}.json", job_id))
+}
+
+/// The file a job's end trigger touches once the job is done (see Scheduler::set_end_trigger)
+/// e.g.: `"._slurmtail/1234.ended"`
+pub fn end_sentinel_path(project_dir: &Path, job_id: u64) -> PathBuf {
+    resume_dir(project_dir).join(format!("{}.ended", job_id))
+}
+
+// Read one session file, in either the current or the legacy format
+fn load_session_file(path: &Path) -> Result<ResumeState, SlurmtailError> {
+    let content = read_to_string(path).map_err(SlurmtailError::io("read", path))?;
+    if !content.trim_start().starts_with('{') {
+        return Ok(ResumeState::from_legacy(&content));
+    }
+
+    let state: ResumeState = serde_json::from_str(&content)
+        .map_err(|e| SlurmtailError::parse("resume file", path, e))?;
+    if state.version > RESUME_FORMAT_VERSION {
+        return Err(SlurmtailError::parse(
+            "resume file",
+            path,
+            format!(
+                "written by a newer slurmtail (format version {})",
+                state.version
+            ),
+        ));
+    }
+
+    Ok(state)
+}
+
+// Take the advisory lock on a resume directory: shared for reading, exclusive for writing
+// The lock is held until the returned file is dropped; None if there is no directory (or it can't be locked)
+fn lock_resume_dir(resume_dir: &Path, exclusive: bool) -> Option<File> {
+    if !resume_dir.is_dir() {
+        return None;
+    }
+
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(resume_dir.join(LOCK_FILE))
+        .ok()?;
+    let locked = if exclusive {
+        file.lock()
+    } else {
+        file.lock_shared()
+    };
+
+    locked.ok().map(|_| file)
+}
+
+/// Write a session's resume file (sessions without a job ID can't be told apart, so they aren't saved)
+pub fn save_turd(project_dir: &Path, state: &ResumeState) -> Result<(), SlurmtailError> {
+    if state.job_id.is_none() {
+        return Ok(());
+    }
+
+    prepare_resume_dir(project_dir)?;
+    let _lock = lock_resume_dir(&resume_dir(project_dir), true);
+    write_session(project_dir, state)
+}
+
+// Make sure a project's resume directory exists, replacing a legacy resume file if one is in the way (it names no job,
+// so there is no session to carry it over to)
+fn prepare_resume_dir(project_dir: &Path) -> Result<(), SlurmtailError> {
+    let resume_dir = resume_dir(project_dir);
+
+    // Older versions kept a single resume file where the directory now goes
+    if resume_dir.is_file() {
+        std::fs::remove_file(&resume_dir).map_err(SlurmtailError::io(
+            "replace the legacy resume file",
+            &resume_dir,
+        ))?;
+    }
+
+    std::fs::create_dir_all(&resume_dir).map_err(SlurmtailError::io(
+        "create the resume directory",
+        &resume_dir,
+    ))?;
+    if state_location() == StateLocation::Xdg {
+        let project_file = resume_dir.join(PROJECT_FILE);
+        write_atomically(&project_file, project_dir.to_string_lossy().as_bytes())
+            .map_err(SlurmtailError::io("write", &project_file))?;
+    }
+
+    Ok(())
+}
+
+/// Replace a file's contents so that readers (and a crash or Ctrl-C midway) only ever see the old or the new
+/// version, never a truncated one: write a temp file next to it, flush it to disk, rename it over the original, and
+/// sync the directory so the rename itself survives a crash
+pub fn write_atomically(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = dir.join(format!(".{}.tmp-{}", file_name, std::process::id()));
+
+    let written = File::create(&temp_path).and_then(|mut file| {
+        file.write_all(content)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| std::fs::rename(&temp_path, path)) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(e);
+    }
+
+    // Not every filesystem lets a directory be synced; the rename has happened either way
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+
+    Ok(())
+}
+
+// Write a session's resume file (the caller holds the resume directory's lock)
+fn write_session(project_dir: &Path, state: &ResumeState) -> Result<(), SlurmtailError> {
+    let Some(job_id) = state.job_id else {
+        return Ok(());
+    };
+
+    let turd_path = session_path(project_dir, job_id);
+    session_json(state)
+        .and_then(|content| write_atomically(&turd_path, content.as_bytes()))
+        .map_err(SlurmtailError::io("write the resume file", &turd_path))
+}
+
+// A session's resume file contents
+fn session_json(state: &ResumeState) -> std::io::Result<String> {
+    let content = serde_json::to_string_pretty(state).map_err(std::io::Error::other)?;
+    Ok(content + "\n")
+}
+
+/// Read every session recorded in a project directory whose job hasn't finished, oldest submission first
+pub fn load_sessions(project_dir: &Path) -> Result<Vec<ResumeState>, SlurmtailError> {
+    load_sessions_in(&resume_dir(project_dir))
+}
+
+/// Read the sessions of a project directory whose jobs have finished, oldest submission first
+pub fn load_finished_sessions(project_dir: &Path) -> Result<Vec<ResumeState>, SlurmtailError> {
+    let finished_dir = resume_dir(project_dir).join(FINISHED_DIR);
+    let _lock = lock_resume_dir(&resume_dir(project_dir), false);
+
+    read_sessions_in(&finished_dir)
+}
+
+/// A project directory and the sessions submitted from it
+pub type ProjectSessions = (PathBuf, Vec<ResumeState>);
+
+/// Read every session (finished ones first) recorded under $XDG_STATE_HOME, grouped by the project they were
+/// submitted from
+pub fn load_all_sessions() -> Result<Vec<ProjectSessions>, SlurmtailError> {
+    let Some(root) = xdg_state_root().filter(|root| root.is_dir()) else {
+        return Ok(Vec::new());
+    };
+
+    let mut projects = Vec::new();
+    for entry in std::fs::read_dir(&root).map_err(SlurmtailError::io("read", &root))? {
+        let state_dir = entry.map_err(SlurmtailError::io("read", &root))?.path();
+        let Ok(project) = read_to_string(state_dir.join(PROJECT_FILE)) else {
+            continue;
+        };
+        let mut sessions = load_sessions_in(&state_dir.join(FINISHED_DIR))?;
+        sessions.extend(load_sessions_in(&state_dir)?);
+        if !sessions.is_empty() {
+            projects.push((PathBuf::from(project), sessions));
+        }
+    }
+    projects.sort_by(|a, b| a.0.cmp(&b.0));
+
+    Ok(projects)
+}
+
+// Read every session file in a resume directory, oldest submission first
+fn load_sessions_in(resume_dir: &Path) -> Result<Vec<ResumeState>, SlurmtailError> {
+    let _lock = lock_resume_dir(resume_dir, false);
+
+    read_sessions_in(resume_dir)
+}
+
+// Read every session file in a resume directory (the caller holds its lock)
+fn read_sessions_in(resume_dir: &Path) -> Result<Vec<ResumeState>, SlurmtailError> {
+    if resume_dir.is_file() {
+        return Ok(vec![load_session_file(resume_dir)?]);
+    }
+    if !resume_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut sessions = Vec::new();
+    for entry in std::fs::read_dir(resume_dir).map_err(SlurmtailError::io("read", resume_dir))? {
+        let path = entry
+            .map_err(SlurmtailError::io("read", resume_dir))?
+            .path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            sessions.push(load_session_file(&path)?);
+        }
+    }
+
+    // Job IDs break ties (and order sessions without a submit time)
+    sessions.sort_by_key(|session| (session.submit_time(), session.job_id));
+
+    Ok(sessions)
+}
+
+/// Read a session, given by name or job ID, or the most recently submitted one
+/// Names take precedence over job IDs, and a reused name refers to the latest job submitted under it
+pub fn load_turd(project_dir: &Path, session: Option<&str>) -> Result<ResumeState, SlurmtailError> {
+    let mut sessions = load_sessions(project_dir)?;
+
+    let Some(session) = session else {
+        return sessions.into_iter().next_back().ok_or_else(|| {
+            if load_finished_sessions(project_dir).is_ok_and(|finished| !finished.is_empty()) {
+                SlurmtailError::NotFound(
+                    "No resume file found for a job that is still running (pass a name or job ID to resume a finished one)"
+                        .to_string(),
+                )
+            } else {
+                SlurmtailError::NotFound("No resume file found".to_string())
+            }
+        });
+    };
+
+    // Finished sessions can still be resumed by name or job ID (unfinished ones win)
+    let active_count = sessions.len();
+    sessions.extend(load_finished_sessions(project_dir)?);
+
+    let job_id = session.parse::<u64>().ok();
+    let matches_name = |state: &ResumeState| state.name.as_deref() == Some(session);
+    let by_name = sessions[..active_count]
+        .iter()
+        .rposition(matches_name)
+        .or_else(|| {
+            sessions[active_count..]
+                .iter()
+                .rposition(matches_name)
+                .map(|index| active_count + index)
+        });
+    let by_job_id = sessions
+        .iter()
+        .position(|state| job_id.is_some() && state.job_id == job_id);
+
+    if let Some(index) = by_name.or(by_job_id) {
+        return Ok(sessions.swap_remove(index));
+    }
+
+    // State kept under $XDG_STATE_HOME can be found from any directory
+    if state_location() == StateLocation::Xdg {
+        let found = load_all_sessions()?
+            .into_iter()
+            .flat_map(|(_, sessions)| sessions)
+            .filter(|state| {
+                state.name.as_deref() == Some(session)
+                    || (job_id.is_some() && state.job_id == job_id)
+            })
+            .max_by_key(|state| (state.name.as_deref() == Some(session), state.submit_time()));
+        if let Some(state) = found {
+            return Ok(state);
+        }
+    }
+
+    Err(SlurmtailError::NotFound(format!(
+        "No session named {:?} (or with that job ID) found",
+        session
+    )))
+}
+
+/// Find the session of a particular job among this project's sessions (finished ones included) or, with state kept
+/// under $XDG_STATE_HOME, among those of every project
+pub fn load_job_session(project_dir: &Path, job_id: u64) -> Result<ResumeState, SlurmtailError> {
+    let mut sessions = load_sessions(project_dir)?;
+    sessions.extend(load_finished_sessions(project_dir)?);
+    if let Some(state) = sessions
+        .into_iter()
+        .find(|state| state.job_id == Some(job_id))
+    {
+        return Ok(state);
+    }
+
+    if state_location() == StateLocation::Xdg
+        && let Some(state) = load_all_sessions()?
+            .into_iter()
+            .flat_map(|(_, sessions)| sessions)
+            .filter(|state| state.job_id == Some(job_id))
+            .max_by_key(|state| state.submit_time())
+    {
+        return Ok(state);
+    }
+
+    Err(SlurmtailError::NotFound(format!(
+        "No session found for job {}",
+        job_id
+    )))
+}
+
+/// Remember how far into the log slurmtail has read (a no-op if no session follows that log)
+/// `stopped_by` names what cut the tail short (a signal, or --max-duration), if anything did
+pub fn save_read_offset(
+    project_dir: &Path,
+    log_path: &Path,
+    offset: u64,
+    stopped_by: Option<&str>,
+) -> Result<(), SlurmtailError> {
+    // A legacy resume file names no job, so it has no session to carry the offset over to; leave it be
+    let resume_dir = resume_dir(project_dir);
+    if resume_dir.is_file() {
+        return Ok(());
+    }
+    prepare_resume_dir(project_dir)?;
+
+    // Hold the lock across the read and the write, so a concurrent update can't slip in between
+    let _lock = lock_resume_dir(&resume_dir, true);
+    let Ok(sessions) = read_sessions_in(&resume_dir) else {
+        return Ok(());
+    };
+
+    for mut state in sessions {
+        if state.stdout == log_path {
+            state.version = RESUME_FORMAT_VERSION;
+            state.offset = offset;
+            state.stopped_by = stopped_by.map(str::to_string);
+            write_session(project_dir, &state)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Point the sessions following one log at another, found in its place (see watch::stand_in), so resuming them reads
+// the file that was actually followed
+fn replace_log_path(
+    project_dir: &Path,
+    log_path: &Path,
+    found: &Path,
+) -> Result<(), SlurmtailError> {
+    let resume_dir = resume_dir(project_dir);
+    if !resume_dir.is_dir() {
+        return Ok(());
+    }
+
+    let _lock = lock_resume_dir(&resume_dir, true);
+    let Ok(sessions) = read_sessions_in(&resume_dir) else {
+        return Ok(());
+    };
+
+    for mut state in sessions {
+        if state.stdout == log_path {
+            state.stdout = found.to_path_buf();
+            if state.stderr.as_deref() == Some(log_path) {
+                state.stderr = Some(found.to_path_buf());
+            }
+            write_session(project_dir, &state)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Claim the right to follow a job's log, so two slurmtail processes don't both tail the same session
+/// The claim is held until the returned file is dropped (or the process exits)
+pub fn claim_follow(project_dir: &Path, job_id: u64) -> Result<Option<File>, SlurmtailError> {
+    let resume_dir = resume_dir(project_dir);
+    if !resume_dir.is_dir() {
+        return Ok(None);
+    }
+
+    let claim_path = resume_dir.join(format!("{}.follow", job_id));
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(&claim_path)
+        .map_err(SlurmtailError::io("open", &claim_path))?;
+
+    match file.try_lock() {
+        Ok(()) => {
+            file.set_len(0)
+                .and_then(|()| write!(file, "{}", std::process::id()))
+                .map_err(SlurmtailError::io("write", &claim_path))?;
+            Ok(Some(file))
+        }
+        Err(TryLockError::WouldBlock) => {
+            let pid = read_to_string(&claim_path).unwrap_or_default();
+            Err(SlurmtailError::AlreadyFollowed {
+                job_id,
+                pid: pid.trim().to_string(),
+            })
+        }
+        Err(TryLockError::Error(e)) => Err(SlurmtailError::io("lock", &claim_path)(e)),
+    }
+}
+
+/// Move a session out of the way once its job has finished, recording the state it ended in
+/// It can still be resumed by name or job ID, but no longer comes up by default
+pub fn finish_session(project_dir: &Path, job_id: u64, final_state: &str) {
+    let resume_dir = resume_dir(project_dir);
+    let _lock = lock_resume_dir(&resume_dir, true);
+
+    let session_file = session_path(project_dir, job_id);
+    let Ok(mut state) = load_session_file(&session_file) else {
+        return;
+    };
+    state.final_state = Some(final_state.to_string());
+
+    let finished_dir = resume_dir.join(FINISHED_DIR);
+    if std::fs::create_dir_all(&finished_dir).is_err() {
+        return;
+    }
+    let finished_file = finished_dir.join(format!("{}.json", job_id));
+    if session_json(&state)
+        .and_then(|content| write_atomically(&finished_file, content.as_bytes()))
+        .is_ok()
+    {
+        let _ = std::fs::remove_file(&session_file);
+        let _ = std::fs::remove_file(resume_dir.join(format!("{}.follow", job_id)));
+    }
+}
+
+/// Delete a session's state, whether or not its job has finished
+pub fn remove_session(project_dir: &Path, state: &ResumeState) -> Result<(), SlurmtailError> {
+    let resume_dir = resume_dir(project_dir);
+
+    // A legacy resume file is the whole "directory"
+    if resume_dir.is_file() {
+        std::fs::remove_file(&resume_dir).map_err(SlurmtailError::io("remove", &resume_dir))?;
+        return Ok(());
+    }
+
+    let Some(job_id) = state.job_id else {
+        return Ok(());
+    };
+    let _lock = lock_resume_dir(&resume_dir, true);
+    for path in [
+        session_path(project_dir, job_id),
+        resume_dir
+            .join(FINISHED_DIR)
+            .join(format!("{}.json", job_id)),
+        resume_dir.join(format!("{}.follow", job_id)),
+    ] {
+        if path.exists() {
+            std::fs::remove_file(&path).map_err(SlurmtailError::io("remove", &path))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Once a session's job has finished, move its state out of the way so `resume` doesn't keep pointing at it; returns the
+/// state it ended in
+pub fn finish_session_if_done(project_dir: &Path, job_id: u64) -> Option<String> {
+    let state = scheduler()
+        .job_state(job_id)
+        .ok()
+        .flatten()
+        .filter(|state| !is_active_state(state))?;
+
+    output::observe_state(job_id, &state);
+    finish_session(project_dir, job_id, &state);
+    record_outcome(job_id, &state);
+
+    Some(state)
+}
+
+/// Add a submitted job to the submission history, with a snapshot of the directives (and overrides) it was submitted
+/// with; the history is a convenience, so failing to write it is only worth a warning
+pub fn record_submission(
+    script_path: &Path,
+    extra_args: &[String],
+    job_id: u64,
+    stdout: &Path,
+    name: Option<&str>,
+    submitted_at: jiff::Timestamp,
+) {
+    let mut directives = SbatchDirectives::from_file(script_path)
+        .map(|directives| directives.options)
+        .unwrap_or_default();
+    directives.extend(SbatchDirectives::from_args(extra_args).options);
+
+    let submission = history::Submission {
+        scheduler: scheduler().name().to_string(),
+        job_id,
+        cluster: scheduler().job_cluster(job_id),
+        name: name.map(str::to_string),
+        script: script_path.canonicalize().ok(),
+        project: std::env::current_dir().ok(),
+        stdout: Some(stdout.to_path_buf()),
+        directives,
+        submitted_at: submitted_at.to_string(),
+        ..Default::default()
+    };
+    if let Err(e) = history::record_submission(&submission) {
+        warning!(
+            "[WARNING] Could not record job {} in the submission history: {}",
+            job_id,
+            e
+        );
+    }
+}
+
+/// Record how a finished job ended in the submission history, along with its accounting fields (looked up only once)
+pub fn record_outcome(job_id: u64, final_state: &str) {
+    let scheduler_name = scheduler().name();
+    if history::has_outcome(scheduler_name, job_id).unwrap_or(true) {
+        return;
+    }
+
+    let accounting = scheduler().job_accounting(job_id).unwrap_or_default();
+    if let Err(e) = history::record_outcome(scheduler_name, job_id, final_state, &accounting) {
+        warning!(
+            "[WARNING] Could not record the outcome of job {} in the submission history: {}",
+            job_id,
+            e
+        );
+    }
+}
+
+/// Note in the resume file how far into the log we got (following always reads up to the end of the file before it
+/// stops, unless SIGHUP, SIGTERM, or --max-duration cut it short), and which file that was, if another was found in
+/// its place
+pub fn record_read_offset(
+    project_dir: &Path,
+    log_path: &Path,
+    result: &Result<(), SlurmtailError>,
+) {
+    // Following may have gone on to a file found in place of the log, which is then the one to resume
+    let stand_in = watch::stand_in(log_path);
+    if let Some(found) = &stand_in
+        && let Err(e) = replace_log_path(project_dir, log_path, found)
+    {
+        warning!(
+            "[WARNING] Could not save which file slurmtail followed in place of the log: {}",
+            e
+        );
+    }
+    let log_path = stand_in.as_deref().unwrap_or(log_path);
+
+    let saved = if let Err(SlurmtailError::Terminated { signal, offset }) = result {
+        let stopped_by = interrupt::signal_name(*signal);
+        save_read_offset(project_dir, log_path, *offset, Some(&stopped_by))
+    } else if let Some(offset) = watch::detached_offset(log_path) {
+        save_read_offset(project_dir, log_path, offset, Some("--max-duration"))
+    } else if let Ok(metadata) = std::fs::metadata(remote::local_copy(log_path)) {
+        save_read_offset(project_dir, log_path, metadata.len(), None)
+    } else {
+        Ok(())
+    };
+    // Only resuming needs it, so there is no reason to fail over it
+    if let Err(e) = saved {
+        warning!(
+            "[WARNING] Could not save how far into the log slurmtail got: {}",
+            e
+        );
+    }
+}
+
+/// Remove the sessions whose jobs have finished (or that the scheduler no longer knows about), printing each one
+/// Sessions whose state can't be determined right now (e.g. the scheduler is unreachable) are kept
+pub fn clean_stale_sessions(project_dir: &Path) -> Result<(), SlurmtailError> {
+    let mut sessions = load_finished_sessions(project_dir)?;
+    sessions.extend(load_sessions(project_dir)?);
+
+    let mut pruned = 0;
+    for session in &sessions {
+        let Some(job_id) = session.job_id else {
+            continue;
+        };
+
+        let reason = match &session.final_state {
+            Some(final_state) => final_state.clone(),
+            None => {
+                if let Some(cluster) = &session.cluster {
+                    scheduler().set_job_cluster(job_id, cluster);
+                }
+                match scheduler().job_state(job_id) {
+                    Ok(Some(state)) if !is_active_state(&state) => state,
+                    Ok(Some(_)) | Err(_) => continue,
+                    Ok(None) => "no longer known to the scheduler".to_string(),
+                }
+            }
+        };
+
+        remove_session(project_dir, session)?;
+        pruned += 1;
+        match &session.name {
+            Some(name) => println!("Removed session for job {} ({}): {}", job_id, name, reason),
+            None => println!("Removed session for job {}: {}", job_id, reason),
+        }
+    }
+
+    if pruned == 0 {
+        println!("No stale sessions found");
+    }
+
+    Ok(())
+}
+
+/// Remove resume file if it exists
+pub fn clean_turd(project_dir: &Path) -> Result<(), SlurmtailError> {
+    let turd_path: PathBuf = resume_dir(project_dir);
+
+    if turd_path.is_dir() {
+        std::fs::remove_dir_all(&turd_path).map_err(SlurmtailError::io("remove", &turd_path))?;
+        println!("Removed resume files: {:?}", turd_path);
+    } else if turd_path.exists() {
+        std::fs::remove_file(&turd_path).map_err(SlurmtailError::io("remove", &turd_path))?;
+        println!("Removed resume file: {:?}", turd_path);
+    } else {
+        println!("No resume file found to clean");
+    }
+
+    let pipeline_state_path = project_dir.join(pipeline::PIPELINE_STATE_FILE);
+    if pipeline_state_path.exists() {
+        std::fs::remove_file(&pipeline_state_path)
+            .map_err(SlurmtailError::io("remove", &pipeline_state_path))?;
+        println!("Removed pipeline state file: {:?}", pipeline_state_path);
+    }
+
+    Ok(())
+}
+
+/// Remove every bit of state slurmtail keeps: this project's resume files and pipeline state, and everything under
+/// $XDG_STATE_HOME/slurmtail (the sessions of every project stored there, and the submission history)
+pub fn clean_all_state(project_dir: &Path, assume_yes: bool) -> Result<(), SlurmtailError> {
+    let mut targets = vec![
+        project_dir.join(RESUME_DIR),
+        project_dir.join(pipeline::PIPELINE_STATE_FILE),
+    ];
+    targets.extend(xdg_state_root());
+    targets.retain(|path| path.exists());
+
+    if targets.is_empty() {
+        println!("No slurmtail state found to clean");
+        return Ok(());
+    }
+
+    println!("This removes all stored sessions and the submission history:");
+    for target in &targets {
+        println!("  {}", target.display());
+    }
+    if !assume_yes && !output::confirm("Remove all of it?")? {
+        println!("Nothing removed");
+        return Ok(());
+    }
+
+    for target in &targets {
+        if target.is_dir() {
+            std::fs::remove_dir_all(target)
+        } else {
+            std::fs::remove_file(target)
+        }
+        .map_err(SlurmtailError::io("remove", target))?;
+        println!("Removed {:?}", target);
+    }
+
+    Ok(())
+}
+
+// Write a numbered table of sessions: job ID, name, current state, and submit time
+fn print_sessions(out: &mut dyn Write, sessions: &[ResumeState]) -> std::io::Result<()> {
+    for (index, session) in sessions.iter().enumerate() {
+        let job = session
+            .job_id
+            .map_or("?".to_string(), |job_id| job_id.to_string());
+        let state = session.final_state.clone().or_else(|| {
+            let job_id = session.job_id?;
+            if let Some(cluster) = &session.cluster {
+                scheduler().set_job_cluster(job_id, cluster);
+            }
+            scheduler().job_state(job_id).ok().flatten()
+        });
+        let submitted_at = session.submit_time().map(|time| {
+            time.to_zoned(jiff::tz::TimeZone::system())
+                .strftime("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        });
+        writeln!(
+            out,
+            "  {:>2}) job {:<10} {:<16} {:<12} {}",
+            index + 1,
+            job,
+            session.name.as_deref().unwrap_or("-"),
+            state.as_deref().unwrap_or("-"),
+            submitted_at.as_deref().unwrap_or("-")
+        )?;
+    }
+
+    Ok(())
+}
+
+/// List the sessions (including finished ones) of the current project, or (with `all`) of every project with state under $XDG_STATE_HOME
+pub fn list_sessions(project_dir: &Path, all: bool) -> Result<(), SlurmtailError> {
+    let projects = if all {
+        load_all_sessions()?
+    } else {
+        let mut sessions = load_finished_sessions(project_dir)?;
+        sessions.extend(load_sessions(project_dir)?);
+        vec![(project_dir.to_path_buf(), sessions)]
+    };
+
+    if projects.iter().all(|(_, sessions)| sessions.is_empty()) {
+        println!("No sessions found");
+        return Ok(());
+    }
+
+    for (project, mut sessions) in projects {
+        sessions.reverse();
+        println!("{}:", project.display());
+        print_sessions(&mut std::io::stdout(), &sessions)
+            .map_err(SlurmtailError::io("write", Path::new("/dev/stdout")))?;
+    }
+
+    Ok(())
+}
+
+/// Let the user pick one of several sessions (given oldest first) on the terminal; just pressing Enter (or closing
+/// stdin) picks the most recent one
+/// The menu goes to stderr, as stdout is reserved for the job's output
+pub fn pick_session(mut sessions: Vec<ResumeState>) -> Result<ResumeState, SlurmtailError> {
+    sessions.reverse();
+
+    eprintln!("Jobs submitted from here:");
+    print_sessions(&mut std::io::stderr(), &sessions)
+        .map_err(SlurmtailError::io("write", Path::new("/dev/stderr")))?;
+
+    loop {
+        eprint!("Resume which job? [1] ");
+
+        let mut answer = String::new();
+        let read = std::io::stdin()
+            .read_line(&mut answer)
+            .map_err(SlurmtailError::io("read", Path::new("/dev/stdin")))?;
+        if read == 0 || answer.trim().is_empty() {
+            return Ok(sessions.swap_remove(0));
+        }
+        match answer.trim().parse::<usize>() {
+            Ok(choice) if (1..=sessions.len()).contains(&choice) => {
+                return Ok(sessions.swap_remove(choice - 1));
+            }
+            _ => eprintln!("Please enter a number from 1 to {}.", sessions.len()),
+        }
+    }
+}
+
+/// The directives a submission from the history was made with that its script (as it is now) doesn't give, as sbatch
+/// arguments, so running it again asks for the same things; none at all if the script hasn't changed, so this works with
+/// any scheduler
+pub fn changed_directives(
+    script_path: &Path,
+    recorded: &[(String, String)],
+) -> Result<Vec<String>, SlurmtailError> {
+    let current = SbatchDirectives::from_file(script_path)?.options;
+
+    Ok(recorded
+        .iter()
+        // Dependencies name the jobs of the earlier run
+        .filter(|(option, _)| option != "dependency")
+        .filter(|directive| !current.contains(directive))
+        .map(|(option, value)| match value.is_empty() {
+            true => format!("--{}", option),
+            false => format!("--{}={}", option, value),
+        })
+        .collect())
+}
+
+/// The session `slurmtail resume` follows: the one of the given job, or the one with the given name, or (with several
+/// to choose from and someone at the terminal) the one the user picks, or else the most recent one
+pub fn choose_session(
+    project_dir: &Path,
+    job_id: Option<u64>,
+    name: Option<&str>,
+) -> Result<ResumeState, SlurmtailError> {
+    if let Some(job_id) = job_id {
+        return load_job_session(project_dir, job_id);
+    }
+
+    let sessions = load_sessions(project_dir).unwrap_or_default();
+    if name.is_none() && sessions.len() > 1 {
+        let interactive = std::io::stdin().is_terminal()
+            && std::io::stdout().is_terminal()
+            && !output::machine_readable();
+        if interactive {
+            return pick_session(sessions);
+        }
+        status!(
+            "[INFO] {} jobs were submitted from here; resuming the most recent one. Pass a name or job ID to resume another.",
+            sessions.len()
+        );
+    }
+
+    load_turd(project_dir, name)
+}
+
+/// Cancel the job of a session (the most recent one, without a name)
+pub fn cancel_session(project_dir: &Path, name: Option<&str>) -> Result<(), SlurmtailError> {
+    let session = load_turd(project_dir, name)?;
+    let job_id = session.job_id.ok_or_else(|| {
+        SlurmtailError::Invalid("Resume file does not record a job ID".to_string())
+    })?;
+
+    if let Some(cluster) = &session.cluster {
+        scheduler().set_job_cluster(job_id, cluster);
+    }
+    scheduler().cancel(job_id)?;
+    status!("Cancelled job {}", job_id);
+    output::emit("cancelled", Some(job_id), serde_json::json!({}));
+
+    Ok(())
+}
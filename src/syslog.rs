@@ -0,0 +1,123 @@
+use serde_json::Value;
+use std::os::unix::net::UnixDatagram;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::duration::format_duration;
+use crate::scheduler::is_failed_state;
+
+// Where the local syslog daemon (or journald, which takes over /dev/log on systemd machines) listens; macOS uses
+// /var/run/syslog
+const SOCKET_PATHS: &[&str] = &["/dev/log", "/var/run/syslog"];
+
+// The "user" facility, for messages from ordinary programs
+const FACILITY_USER: u8 = 1;
+
+const SEVERITY_ERROR: u8 = 3;
+const SEVERITY_WARNING: u8 = 4;
+const SEVERITY_NOTICE: u8 = 5;
+const SEVERITY_INFO: u8 = 6;
+
+static SYSLOG: OnceLock<UnixDatagram> = OnceLock::new();
+
+// Also send lifecycle events (job submitted, started, finished, timed out, ...) to syslog/journald, at the socket
+// in $SLURMTAIL_SYSLOG_SOCKET if set; if there is no syslog to send them to, say so and carry on without
+pub fn set_syslog() {
+    let paths = match std::env::var("SLURMTAIL_SYSLOG_SOCKET") {
+        Ok(path) => vec![path],
+        Err(_) => SOCKET_PATHS.iter().map(|path| path.to_string()).collect(),
+    };
+
+    let Ok(socket) = UnixDatagram::unbound() else {
+        warning!("[WARNING] Could not create a socket for syslog; events won't be logged there");
+        return;
+    };
+    if paths.iter().any(|path| socket.connect(path).is_ok()) {
+        let _ = SYSLOG.set(socket);
+    } else {
+        warning!(
+            "[WARNING] Could not connect to syslog (tried {}); events won't be logged there",
+            paths.join(", ")
+        );
+    }
+}
+
+// Log an event (see output::emit) to syslog, if it is one worth keeping there
+pub fn log_event(kind: &str, job_id: Option<u64>, fields: &Value) {
+    let Some(socket) = SYSLOG.get() else {
+        return;
+    };
+    let Some((severity, message)) = describe(kind, job_id, fields) else {
+        return;
+    };
+
+    // "<PRI>TAG[PID]: MESSAGE" (RFC 3164 without the timestamp and hostname, which the daemon fills in)
+    let record = format!(
+        "<{}>slurmtail[{}]: {}",
+        FACILITY_USER * 8 + severity,
+        std::process::id(),
+        message
+    );
+    let _ = socket.send(record.as_bytes());
+}
+
+// The severity and message for an event; lines of the job's output and the like are left out
+fn describe(kind: &str, job_id: Option<u64>, fields: &Value) -> Option<(u8, String)> {
+    let job = job_id.map_or("-".to_string(), |job_id| job_id.to_string());
+    let field = |name: &str| match fields.get(name) {
+        None | Some(Value::Null) => "-".to_string(),
+        Some(Value::String(value)) => value.clone(),
+        Some(value) => value.to_string(),
+    };
+
+    match kind {
+        "submitted" => Some((
+            SEVERITY_NOTICE,
+            format!("Job {} submitted (script {})", job, field("script")),
+        )),
+        "state" => {
+            let state = field("state");
+            Some(match state.as_str() {
+                "RUNNING" => (SEVERITY_NOTICE, format!("Job {} started", job)),
+                "COMPLETED" => (SEVERITY_NOTICE, format!("Job {} finished: {}", job, state)),
+                _ if state.starts_with("CANCELLED") => {
+                    (SEVERITY_WARNING, format!("Job {} finished: {}", job, state))
+                }
+                _ if is_failed_state(&state) => {
+                    (SEVERITY_ERROR, format!("Job {} finished: {}", job, state))
+                }
+                _ => (SEVERITY_INFO, format!("Job {} is {}", job, state)),
+            })
+        }
+        "log_file" => Some((
+            SEVERITY_INFO,
+            format!("Following job {}'s log file {}", job, field("path")),
+        )),
+        "timeout" => Some((
+            SEVERITY_WARNING,
+            format!(
+                "Timed out after {} waiting for job {}'s {} ({})",
+                fields
+                    .get("seconds")
+                    .and_then(Value::as_f64)
+                    .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                    .map_or("-".to_string(), format_duration),
+                job,
+                field("waiting_for").replace('_', " "),
+                field("path")
+            ),
+        )),
+        "cancelled" => Some((SEVERITY_WARNING, format!("Cancelled job {}", job))),
+        "exit" => {
+            let code = field("code");
+            match fields.get("error").and_then(Value::as_str) {
+                Some(error) => Some((
+                    SEVERITY_ERROR,
+                    format!("Exiting with code {}: {}", code, error),
+                )),
+                None => Some((SEVERITY_INFO, format!("Exiting with code {}", code))),
+            }
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,143 @@
+use regex::Regex;
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use jiff::tz::TimeZone;
+
+use crate::duration::parse_duration;
+use crate::error::SlurmtailError;
+
+// A timestamp at the start of a log line, maybe in brackets: an ISO 8601 date and time, with a 'T' or a space between
+// them, seconds with or without a fraction (after a '.' or a ','), and maybe a UTC offset, e.g. "2026-10-16 14:03:27",
+// "[2026-10-16T14:03:27.512Z]", or "2026-10-16 14:03:27,512+0200"
+const TIMESTAMP_PATTERN: &str =
+    r"^\s*\[?(\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:[.,]\d+)?(?:Z|[+-]\d{2}:?\d{2})?)";
+
+static TIMESTAMP: OnceLock<Regex> = OnceLock::new();
+
+// The time zone --convert-timestamps rewrites the timestamps lines start with into, and the strftime format they're
+// written in, if given
+static CONVERSION: OnceLock<(TimeZone, String)> = OnceLock::new();
+
+// Where the timestamp a log line starts with is, and the instant it stands for, if it starts with one
+// Timestamps without a UTC offset are taken to be in the system's time zone
+pub fn find(line: &str) -> Option<(Range<usize>, jiff::Timestamp)> {
+    let pattern = TIMESTAMP.get_or_init(|| Regex::new(TIMESTAMP_PATTERN).expect("valid regex"));
+    let found = pattern.captures(line)?.get(1)?;
+
+    Some((found.range(), parse(found.as_str())?))
+}
+
+// A timestamp as TIMESTAMP_PATTERN matches it
+fn parse(text: &str) -> Option<jiff::Timestamp> {
+    let text = text.replacen(' ', "T", 1).replace(',', ".");
+    // Past the date's dashes
+    let offset_at = text.rfind(['Z', '+', '-']).filter(|at| *at > 10);
+    match offset_at {
+        Some(at) => {
+            let (time, offset) = text.split_at(at);
+            // "+0200" as well as "+02:00"
+            let offset = match offset.len() {
+                5 => format!("{}:{}", &offset[..3], &offset[3..]),
+                _ => offset.to_string(),
+            };
+            format!("{}{}", time, offset).parse().ok()
+        }
+        None => text
+            .parse::<jiff::civil::DateTime>()
+            .ok()?
+            .to_zoned(TimeZone::system())
+            .ok()
+            .map(|zoned| zoned.timestamp()),
+    }
+}
+
+// Read an instant given on the command line: a duration back from now (e.g. "10m" or "2h30m"), or a date and time
+// (e.g. "2026-10-16 09:00", or with a UTC offset) or a date (its midnight) in the system's time zone
+pub fn parse_instant(text: &str) -> Result<jiff::Timestamp, String> {
+    let text = text.trim();
+    if let Ok(ago) = parse_duration(text) {
+        return jiff::SignedDuration::try_from(ago)
+            .ok()
+            .and_then(|ago| jiff::Timestamp::now().checked_sub(ago).ok())
+            .ok_or_else(|| format!("{:?} is too far back", text));
+    }
+
+    let invalid = || {
+        "expected a duration back from now (e.g. 10m or 2h), or a date and time (e.g. 2026-10-16 09:00)".to_string()
+    };
+    if let Ok(timestamp) = text.parse::<jiff::Timestamp>() {
+        return Ok(timestamp);
+    }
+    let civil = text
+        .replacen(' ', "T", 1)
+        .parse::<jiff::civil::DateTime>()
+        .or_else(|_| {
+            text.parse::<jiff::civil::Date>()
+                .map(|date| date.at(0, 0, 0, 0))
+        })
+        .map_err(|_| invalid())?;
+
+    civil
+        .to_zoned(TimeZone::system())
+        .map(|zoned| zoned.timestamp())
+        .map_err(|_| invalid())
+}
+
+// Read a time zone given on the command line: "local" (the system's), "utc", or an IANA name like "Europe/Berlin"
+pub fn parse_zone(text: &str) -> Result<TimeZone, String> {
+    match text.trim() {
+        zone if zone.eq_ignore_ascii_case("local") => Ok(TimeZone::system()),
+        zone if zone.eq_ignore_ascii_case("utc") || zone.eq_ignore_ascii_case("z") => {
+            Ok(TimeZone::UTC)
+        }
+        zone => TimeZone::get(zone).map_err(|_| {
+            format!(
+                "unknown time zone {:?}: expected local, utc, or a name like Europe/Berlin",
+                zone
+            )
+        }),
+    }
+}
+
+// Rewrite the timestamps lines of the job's output start with into the time zone, in the strftime format (by default
+// ISO 8601 with a 'Z' for UTC, e.g. "2026-10-16T12:03:27Z", and the date and time for other zones, e.g.
+// "2026-10-16 14:03:27", fractions of a second kept either way)
+pub fn set_conversion(zone: TimeZone, format: Option<&str>) -> Result<(), SlurmtailError> {
+    let format = match format {
+        Some(format) => format.to_string(),
+        None if zone == TimeZone::UTC => "%Y-%m-%dT%H:%M:%S%.fZ".to_string(),
+        None => "%Y-%m-%d %H:%M:%S%.f".to_string(),
+    };
+    // A format jiff can't write shows up now rather than on the first timestamp
+    jiff::fmt::strtime::format(&format, &jiff::Timestamp::UNIX_EPOCH.to_zoned(zone.clone()))
+        .map_err(|e| {
+            SlurmtailError::Invalid(format!("Invalid --timestamp-format {:?}: {}", format, e))
+        })?;
+    let _ = CONVERSION.set((zone, format));
+
+    Ok(())
+}
+
+// The line with the timestamp it starts with rewritten as set_conversion set up, or as it is if there's no timestamp
+// to rewrite
+pub fn convert(line: &str) -> Cow<'_, str> {
+    let Some((zone, format)) = CONVERSION.get() else {
+        return Cow::Borrowed(line);
+    };
+    let Some((range, timestamp)) = find(line) else {
+        return Cow::Borrowed(line);
+    };
+    let Ok(converted) = jiff::fmt::strtime::format(format, &timestamp.to_zoned(zone.clone()))
+    else {
+        return Cow::Borrowed(line);
+    };
+
+    Cow::Owned(format!(
+        "{}{}{}",
+        &line[..range.start],
+        converted,
+        &line[range.end..]
+    ))
+}
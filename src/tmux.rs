@@ -0,0 +1,208 @@
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use crate::duration::format_duration;
+use crate::error::{SlurmtailError, current_dir};
+use crate::watch::{FollowOptions, FollowTarget};
+
+// Environment variables passed on to the panes (tmux gives new panes the server's environment, not ours)
+const FORWARDED_ENV_PREFIXES: &[&str] = &["SLURMTAIL_", "SLURMRESTD_"];
+const FORWARDED_ENV: &[&str] = &["RUST_LOG", "NO_COLOR", "XDG_STATE_HOME", "PAGER"];
+
+// Where --tmux puts each followed file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    // Panes tiled in the current window, next to slurmtail's own
+    Panes,
+    // A window of its own for each file
+    Windows,
+}
+
+// The layout, and the command-line options (--grep, --highlight, ...) each pane's `slurmtail tail` gets
+struct Settings {
+    layout: Layout,
+    args: Vec<String>,
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+// Follow several log files in tmux panes (or windows) of their own instead of interleaving them (--tmux); only works
+// from inside tmux
+pub fn enable(layout: Layout, args: Vec<String>) -> Result<(), SlurmtailError> {
+    if std::env::var_os("TMUX").is_none() {
+        return Err(SlurmtailError::Invalid(
+            "--tmux needs slurmtail to run inside a tmux session".to_string(),
+        ));
+    }
+
+    let _ = SETTINGS.set(Settings { layout, args });
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    SETTINGS.get().is_some()
+}
+
+// Open a pane (or window) running `slurmtail tail` for each target, laid out automatically, and wait for all of them
+// to finish
+pub fn follow(targets: &[FollowTarget], options: &FollowOptions) -> Result<(), SlurmtailError> {
+    let Some(settings) = SETTINGS.get() else {
+        return Err(SlurmtailError::Invalid("--tmux isn't enabled".to_string()));
+    };
+    let exe =
+        std::env::current_exe().map_err(SlurmtailError::io("find", Path::new("slurmtail")))?;
+    let current_dir = current_dir()?;
+    let status_dir = std::env::temp_dir().join(format!("slurmtail-tmux-{}", std::process::id()));
+    std::fs::create_dir_all(&status_dir).map_err(SlurmtailError::io("create", &status_dir))?;
+
+    status!(
+        "[INFO] Following {} log files in tmux {}",
+        targets.len(),
+        match settings.layout {
+            Layout::Panes => "panes",
+            Layout::Windows => "windows",
+        }
+    );
+
+    let mut channels = Vec::new();
+    for (index, target) in targets.iter().enumerate() {
+        let channel = format!("slurmtail-{}-{}", std::process::id(), index);
+        let status_file = status_dir.join(index.to_string());
+        let command = pane_command(
+            &exe,
+            &settings.args,
+            target,
+            options,
+            &status_file,
+            &channel,
+        );
+
+        let mut tmux = Command::new("tmux");
+        match settings.layout {
+            Layout::Panes => tmux.arg("split-window").args(own_pane()),
+            Layout::Windows => tmux.args(["new-window", "-n", &target.prefix]),
+        };
+        tmux.args(["-d", "-P", "-F", "#{pane_id}", "-c"])
+            .arg(&current_dir);
+        for (name, value) in forwarded_env() {
+            tmux.arg("-e").arg(format!("{}={}", name, value));
+        }
+        tmux.arg(command);
+
+        let output = tmux
+            .output()
+            .map_err(|e| SlurmtailError::scheduler("tmux", e))?;
+        if !output.status.success() {
+            return Err(SlurmtailError::scheduler(
+                "tmux",
+                format!(
+                    "Could not open a {} for {}: {}",
+                    if settings.layout == Layout::Panes {
+                        "pane"
+                    } else {
+                        "window"
+                    },
+                    target.prefix,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            ));
+        }
+        let pane_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        tracing::debug!(pane = %pane_id, path = ?target.log_path, "Opened tmux pane");
+
+        // Title the pane after what it follows, and keep the panes evenly sized as they are added
+        let _ = Command::new("tmux")
+            .args(["select-pane", "-t", &pane_id, "-T", &target.prefix])
+            .output();
+        if settings.layout == Layout::Panes {
+            let _ = Command::new("tmux")
+                .arg("select-layout")
+                .args(own_pane())
+                .arg("tiled")
+                .output();
+        }
+        channels.push((target, channel, status_file));
+    }
+
+    // Each pane signals its channel once its tail is over (tmux remembers signals nobody was waiting for yet)
+    let mut failures = Vec::new();
+    for (target, channel, status_file) in &channels {
+        let _ = Command::new("tmux").args(["wait-for", channel]).status();
+        let code = std::fs::read_to_string(status_file)
+            .ok()
+            .and_then(|code| code.trim().parse::<i32>().ok());
+        match code {
+            Some(0) => {}
+            Some(code) => failures.push(format!("{}: exited with code {}", target.prefix, code)),
+            None => failures.push(format!("{}: didn't finish", target.prefix)),
+        }
+    }
+    let _ = std::fs::remove_dir_all(&status_dir);
+
+    if !failures.is_empty() {
+        return Err(SlurmtailError::Followers(failures));
+    }
+    Ok(())
+}
+
+// The pane slurmtail runs in, for splitting it (tmux would otherwise pick whichever pane is active)
+fn own_pane() -> Vec<String> {
+    match std::env::var("TMUX_PANE") {
+        Ok(pane) => vec!["-t".to_string(), pane],
+        Err(_) => Vec::new(),
+    }
+}
+
+// The shell command a pane runs: `slurmtail tail` on the target, then note its exit code, tell the waiting slurmtail,
+// and keep the pane open until Enter is pressed, so the output can still be read
+fn pane_command(
+    exe: &Path,
+    args: &[String],
+    target: &FollowTarget,
+    options: &FollowOptions,
+    status_file: &Path,
+    channel: &str,
+) -> String {
+    let mut words = vec![exe.to_string_lossy().into_owned()];
+    words.extend(args.iter().cloned());
+    words.push("tail".to_string());
+    if let Some(job_id) = target.job_id {
+        words.extend(["--job".to_string(), job_id.to_string()]);
+    }
+    if let Some(timeout) = options.timeout {
+        words.extend(["--timeout".to_string(), format_duration(timeout)]);
+    }
+    if options.no_file_timeout {
+        words.push("--no-file-timeout".to_string());
+    }
+    if options.no_bytes_timeout {
+        words.push("--no-bytes-timeout".to_string());
+    }
+    words.push(target.log_path.to_string_lossy().into_owned());
+
+    let tail: Vec<String> = words.iter().map(|word| shell_quote(word)).collect();
+    format!(
+        "{}; echo $? > {}; tmux wait-for -S {}; printf '\\n[%s done; press Enter to close] ' {}; read _",
+        tail.join(" "),
+        shell_quote(&status_file.to_string_lossy()),
+        channel,
+        shell_quote(&target.prefix)
+    )
+}
+
+// Quote a word for sh, e.g.: "it's" -> "'it'\''s'"
+fn shell_quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+fn forwarded_env() -> Vec<(String, String)> {
+    std::env::vars()
+        .filter(|(name, _)| {
+            FORWARDED_ENV.contains(&name.as_str())
+                || FORWARDED_ENV_PREFIXES
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix))
+        })
+        .collect()
+}
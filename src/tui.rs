@@ -0,0 +1,506 @@
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::error::SlurmtailError;
+use crate::output;
+use crate::scheduler::scheduler;
+
+// How many lines each pane keeps to scroll back through
+const SCROLLBACK_LINES: usize = 100_000;
+
+// The source of the lines of the job's separate stderr file, which always go to the side pane
+pub const STDERR_SOURCE: &str = "stderr";
+
+// How often the screen is redrawn (and keys are checked for) when nothing happens
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+// Whether --tui was given (and there is a terminal to show it on)
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+// Whether the full-screen interface is up, so output goes to it instead of stdout/stderr
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+// Set once following is done, so the interface stays up until the user has read it and quits
+static FINISHED: AtomicBool = AtomicBool::new(false);
+
+// Set when the user quits while the job is still being followed, so the followers stop (the job keeps running)
+static DETACHED: AtomicBool = AtomicBool::new(false);
+
+// Set to take the interface down without waiting for the user (e.g. before asking a question on the terminal)
+static CLOSING: AtomicBool = AtomicBool::new(false);
+
+static UI_THREAD: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+
+static SCREEN: Mutex<Screen> = Mutex::new(Screen::new());
+
+// Everything shown on screen, filled in by the log followers and drawn by the interface's own thread
+struct Screen {
+    // Lines of the log being followed
+    main: VecDeque<String>,
+    // The source (see output::job_line) whose lines go to the main pane: the first one seen, other than the job's
+    // stderr; every other source goes to the side pane
+    main_source: Option<Option<String>>,
+    // Lines of the other logs (e.g. the job's stderr), with their source
+    side: VecDeque<(String, String)>,
+    show_side: bool,
+    // slurmtail's latest status message
+    message: String,
+    // The job followed, its state, when it started running, and the node its batch script runs on
+    job: Option<(u64, String)>,
+    running_since: Option<Instant>,
+    node: Option<String>,
+    // How many lines the main pane is scrolled up from the bottom (0 follows new output)
+    scroll: usize,
+    // Lines the main pane showed when last drawn, for paging
+    page: usize,
+    // What is being typed after "/", the search it became, and the line of the main pane n/N last moved to
+    typing: Option<String>,
+    search: Option<String>,
+    current_match: Option<usize>,
+}
+
+impl Screen {
+    const fn new() -> Screen {
+        Screen {
+            main: VecDeque::new(),
+            main_source: None,
+            side: VecDeque::new(),
+            show_side: true,
+            message: String::new(),
+            job: None,
+            running_since: None,
+            node: None,
+            scroll: 0,
+            page: 20,
+            typing: None,
+            search: None,
+            current_match: None,
+        }
+    }
+
+    // Scroll so that line `index` of the main pane is the top one shown (or as close as the end allows)
+    fn scroll_to(&mut self, index: usize) {
+        self.scroll = self.main.len().saturating_sub(index + self.page);
+    }
+
+    // The line at the top of the main pane
+    fn top(&self) -> usize {
+        self.main
+            .len()
+            .saturating_sub(self.scroll)
+            .saturating_sub(self.page)
+    }
+
+    // Move to the next (or previous) line matching the search, from the last match moved to (or, at first, from the
+    // end, so a new search finds the most recent match)
+    fn find(&mut self, forward: bool) {
+        let Some(search) = self.search.clone() else {
+            return;
+        };
+        let from = self.current_match.unwrap_or(self.main.len());
+        let found = if forward {
+            (from + 1..self.main.len()).find(|&index| self.main[index].contains(&search))
+        } else {
+            (0..from.min(self.main.len()))
+                .rev()
+                .find(|&index| self.main[index].contains(&search))
+        };
+
+        let Some(index) = found else {
+            self.message = format!("No more lines matching {:?}", search);
+            return;
+        };
+        self.current_match = Some(index);
+        let top = self.top();
+        if index < top || index >= top + self.page {
+            self.scroll_to(index);
+        }
+    }
+}
+
+// Show the job in the full-screen interface instead of streaming its output (--tui); it needs a terminal
+pub fn enable() -> Result<(), SlurmtailError> {
+    if !std::io::stdout().is_terminal() || !std::io::stdin().is_terminal() {
+        return Err(SlurmtailError::Invalid(
+            "--tui needs a terminal; leave it out to stream the job's output to a pipe or file"
+                .to_string(),
+        ));
+    }
+    if output::machine_readable() {
+        return Err(SlurmtailError::Invalid(
+            "--tui can't be combined with --format json or --porcelain".to_string(),
+        ));
+    }
+
+    let _ = ENABLED.set(true);
+    Ok(())
+}
+
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::SeqCst)
+}
+
+// Whether the user quit the interface while the job was still being followed
+pub fn detached() -> bool {
+    DETACHED.load(Ordering::SeqCst)
+}
+
+// Bring up the interface (with --tui), once following is about to start
+pub fn start() {
+    if !ENABLED.get().copied().unwrap_or(false) || ACTIVE.load(Ordering::SeqCst) {
+        return;
+    }
+    let Ok(mut ui_thread) = UI_THREAD.lock() else {
+        return;
+    };
+
+    let terminal = match ratatui::try_init() {
+        Ok(terminal) => terminal,
+        Err(e) => {
+            warning!(
+                "[WARNING] Could not start the full-screen interface ({}); streaming the output instead",
+                e
+            );
+            return;
+        }
+    };
+    ACTIVE.store(true, Ordering::SeqCst);
+    *ui_thread = Some(thread::spawn(move || run(terminal)));
+}
+
+// Take the interface down: once following is done (`wait`), after the user has had a chance to read it and quits;
+// otherwise right away
+pub fn stop(wait: bool) {
+    if wait {
+        FINISHED.store(true, Ordering::SeqCst);
+    } else {
+        CLOSING.store(true, Ordering::SeqCst);
+    }
+
+    let ui_thread = UI_THREAD
+        .lock()
+        .ok()
+        .and_then(|mut ui_thread| ui_thread.take());
+    if let Some(ui_thread) = ui_thread {
+        let _ = ui_thread.join();
+    }
+}
+
+// Add a line of the job's output to the interface, if it's up; returns whether it was
+pub fn push_line(source: Option<&str>, line: &str) -> bool {
+    if !active() {
+        return false;
+    }
+    let Ok(mut screen) = SCREEN.lock() else {
+        return false;
+    };
+
+    let line = line.trim_end_matches(['\r', '\n']).to_string();
+    let source = source.map(str::to_string);
+    if source.as_deref() != Some(STDERR_SOURCE)
+        && *screen.main_source.get_or_insert_with(|| source.clone()) == source
+    {
+        screen.main.push_back(line);
+        if screen.main.len() > SCROLLBACK_LINES {
+            screen.main.pop_front();
+            screen.current_match = screen.current_match.and_then(|index| index.checked_sub(1));
+        }
+        // Stay on the lines being read while scrolled up
+        if screen.scroll > 0 {
+            screen.scroll += 1;
+        }
+    } else {
+        screen.side.push_back((source.unwrap_or_default(), line));
+        if screen.side.len() > SCROLLBACK_LINES {
+            screen.side.pop_front();
+        }
+    }
+
+    true
+}
+
+// Show one of slurmtail's own messages in the interface's message line, if it's up; returns whether it was
+pub fn push_message(text: &str) -> bool {
+    if !active() {
+        return false;
+    }
+    let Ok(mut screen) = SCREEN.lock() else {
+        return false;
+    };
+
+    if let Some(message) = text.lines().rev().find(|line| !line.trim().is_empty()) {
+        screen.message = message.to_string();
+    }
+    true
+}
+
+// Note the state the followed job was just seen in (see output::observe_state), looking up its node once it runs
+pub fn observe_state(job_id: u64, state: &str) {
+    if !active() {
+        return;
+    }
+
+    let needs_node = state == "RUNNING"
+        && SCREEN.lock().is_ok_and(|screen| {
+            screen.node.is_none() || screen.job.as_ref().is_none_or(|(id, _)| *id != job_id)
+        });
+    let node = if needs_node {
+        scheduler().batch_host(job_id).ok().flatten()
+    } else {
+        None
+    };
+
+    let Ok(mut screen) = SCREEN.lock() else {
+        return;
+    };
+    if screen.job.as_ref().is_none_or(|(id, _)| *id != job_id) {
+        screen.running_since = None;
+        screen.node = None;
+    }
+    if state == "RUNNING" {
+        screen.running_since.get_or_insert_with(Instant::now);
+    }
+    if node.is_some() {
+        screen.node = node;
+    }
+    screen.job = Some((job_id, state.to_string()));
+}
+
+// The interface's thread: draw, handle keys, and repeat until the user quits (or it is taken down)
+fn run(mut terminal: DefaultTerminal) {
+    loop {
+        let _ = terminal.draw(|frame| {
+            if let Ok(mut screen) = SCREEN.lock() {
+                draw(frame, &mut screen);
+            }
+        });
+
+        if CLOSING.load(Ordering::SeqCst) {
+            break;
+        }
+        if event::poll(REDRAW_INTERVAL).unwrap_or(false)
+            && let Ok(Event::Key(key)) = event::read()
+            && key.kind == KeyEventKind::Press
+            && handle_key(key)
+        {
+            break;
+        }
+    }
+
+    ratatui::restore();
+    ACTIVE.store(false, Ordering::SeqCst);
+
+    if !FINISHED.load(Ordering::SeqCst) && !CLOSING.load(Ordering::SeqCst) {
+        DETACHED.store(true, Ordering::SeqCst);
+        status!(
+            "[INFO] Detached; the job keeps running (pick it up again with `slurmtail resume`)"
+        );
+    }
+}
+
+// Act on a key press; returns whether to quit
+fn handle_key(key: KeyEvent) -> bool {
+    let Ok(mut screen) = SCREEN.lock() else {
+        return true;
+    };
+
+    // Typing a search
+    if let Some(typing) = screen.typing.as_mut() {
+        match key.code {
+            KeyCode::Enter => {
+                let search = screen.typing.take().filter(|search| !search.is_empty());
+                screen.search = search;
+                screen.current_match = None;
+                screen.find(false);
+            }
+            KeyCode::Esc => screen.typing = None,
+            KeyCode::Backspace => {
+                typing.pop();
+            }
+            KeyCode::Char(c) => typing.push(c),
+            _ => {}
+        }
+        return false;
+    }
+
+    let page = screen.page.max(1);
+    let max_scroll = screen.main.len().saturating_sub(page);
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return true,
+        KeyCode::Char('q') => return true,
+        KeyCode::Up | KeyCode::Char('k') => screen.scroll = (screen.scroll + 1).min(max_scroll),
+        KeyCode::Down | KeyCode::Char('j') => screen.scroll = screen.scroll.saturating_sub(1),
+        KeyCode::PageUp => screen.scroll = (screen.scroll + page).min(max_scroll),
+        KeyCode::PageDown | KeyCode::Char(' ') => {
+            screen.scroll = screen.scroll.saturating_sub(page)
+        }
+        KeyCode::Home | KeyCode::Char('g') => screen.scroll = max_scroll,
+        KeyCode::End | KeyCode::Char('G') => screen.scroll = 0,
+        KeyCode::Char('/') => screen.typing = Some(String::new()),
+        KeyCode::Char('n') => screen.find(true),
+        KeyCode::Char('N') => screen.find(false),
+        KeyCode::Esc => {
+            screen.search = None;
+            screen.current_match = None;
+        }
+        KeyCode::Tab => screen.show_side = !screen.show_side,
+        _ => {}
+    }
+
+    false
+}
+
+fn draw(frame: &mut Frame, screen: &mut Screen) {
+    let [body, status_bar, message_line] = Layout::vertical([
+        Constraint::Min(3),
+        Constraint::Length(1),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let (main_area, side_area) = if screen.show_side && !screen.side.is_empty() {
+        let [main_area, side_area] =
+            Layout::horizontal([Constraint::Percentage(65), Constraint::Percentage(35)])
+                .areas(body);
+        (main_area, Some(side_area))
+    } else {
+        (body, None)
+    };
+
+    draw_main(frame, screen, main_area);
+    if let Some(side_area) = side_area {
+        draw_side(frame, screen, side_area);
+    }
+    draw_status_bar(frame, screen, status_bar);
+
+    let message = match &screen.typing {
+        Some(typing) => Line::from(format!("/{}", typing)),
+        None if FINISHED.load(Ordering::SeqCst) => Line::styled(
+            format!("{}  (finished; press q to quit)", screen.message),
+            Style::default().add_modifier(Modifier::BOLD),
+        ),
+        None => Line::styled(
+            screen.message.as_str(),
+            Style::default().add_modifier(Modifier::DIM),
+        ),
+    };
+    frame.render_widget(Paragraph::new(message), message_line);
+}
+
+fn draw_main(frame: &mut Frame, screen: &mut Screen, area: Rect) {
+    screen.page = area.height.saturating_sub(2) as usize;
+    let max_scroll = screen.main.len().saturating_sub(screen.page);
+    screen.scroll = screen.scroll.min(max_scroll);
+
+    let top = screen.top();
+    let lines: Vec<Line> = screen
+        .main
+        .range(top..(top + screen.page).min(screen.main.len()))
+        .enumerate()
+        .map(|(offset, line)| {
+            let line = highlight(line, screen.search.as_deref());
+            if screen.current_match == Some(top + offset) {
+                line.style(Style::default().add_modifier(Modifier::BOLD))
+            } else {
+                line
+            }
+        })
+        .collect();
+
+    let title = match screen.scroll {
+        0 => " Output ".to_string(),
+        scroll => format!(" Output (scrolled up {} lines; G to follow) ", scroll),
+    };
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+        area,
+    );
+}
+
+fn draw_side(frame: &mut Frame, screen: &Screen, area: Rect) {
+    let height = area.height.saturating_sub(2) as usize;
+    let sources: Vec<&str> = {
+        let mut sources: Vec<&str> = screen
+            .side
+            .iter()
+            .map(|(source, _)| source.as_str())
+            .collect();
+        sources.sort_unstable();
+        sources.dedup();
+        sources
+    };
+
+    // Lines are only marked with their source when there are several
+    let lines: Vec<Line> = screen
+        .side
+        .range(screen.side.len().saturating_sub(height)..)
+        .map(|(source, line)| match sources.len() {
+            1 => Line::from(line.as_str()),
+            _ => Line::from(format!("[{}] {}", source, line)),
+        })
+        .collect();
+
+    let title = match sources.as_slice() {
+        [source] => format!(" {} ", source),
+        _ => " Other logs ".to_string(),
+    };
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+        area,
+    );
+}
+
+fn draw_status_bar(frame: &mut Frame, screen: &Screen, area: Rect) {
+    let mut fields = Vec::new();
+    if let Some((job_id, state)) = &screen.job {
+        fields.push(format!("Job {}", job_id));
+        fields.push(state.clone());
+        if let Some(running_since) = screen.running_since {
+            fields.push(output::format_elapsed(running_since.elapsed()));
+        }
+        if let Some(node) = &screen.node {
+            fields.push(node.clone());
+        }
+    }
+    if let Some(search) = &screen.search {
+        fields.push(format!("search: {} (n/N)", search));
+    }
+    fields.push("q quit  / search  Tab side pane  ↑↓ PgUp PgDn scroll".to_string());
+
+    frame.render_widget(
+        Paragraph::new(format!(" {}", fields.join(" │ ")))
+            .style(Style::default().add_modifier(Modifier::REVERSED)),
+        area,
+    );
+}
+
+// A line with every match of the search marked
+fn highlight<'a>(line: &'a str, search: Option<&str>) -> Line<'a> {
+    let Some(search) = search.filter(|search| line.contains(search)) else {
+        return Line::from(line);
+    };
+
+    let mut spans = Vec::new();
+    let mut rest = line;
+    while let Some(start) = rest.find(search) {
+        spans.push(Span::raw(&rest[..start]));
+        spans.push(Span::styled(
+            &rest[start..start + search.len()],
+            Style::default().fg(Color::Black).bg(Color::Yellow),
+        ));
+        rest = &rest[start + search.len()..];
+    }
+    spans.push(Span::raw(rest));
+
+    Line::from(spans)
+}
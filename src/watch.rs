@@ -0,0 +1,1948 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs::{File, read_to_string};
+use std::io::{IsTerminal, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::{AsyncBufReadExt, AsyncSeekExt, BufReader};
+use tokio::sync::watch;
+use tokio::time::{self, MissedTickBehavior};
+
+use crate::config::Config;
+use crate::directives::SbatchDirectives;
+use crate::error::{SlurmtailError, current_dir};
+use crate::filter::{self, Verdict};
+use crate::output::{
+    self, confirm, observe_state, write_transcript_footer, write_transcript_header,
+};
+use crate::plugin::{self, Outcome};
+use crate::scheduler::{is_active_state, job_final_state, job_is_active, scheduler};
+use crate::script::{
+    array_spec, error_pattern, first_array_task, format_log_output_string, logfile_string_to_path,
+    pattern_is_per_node, pattern_uses_job_id, pattern_uses_node, with_node_index,
+};
+use crate::state::{self, ResumeState, record_read_offset, record_submission};
+use crate::{
+    daemon, duration, history, hooks, interrupt, keys, notify, pager, progress, remote, runtime,
+    timestamps, tmux, tui,
+};
+
+// How often (while idle) to ask the scheduler whether a followed job is still around
+const JOB_STATE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// With --strigger, how often a job whose end trigger is set is still asked about, in case the trigger never fires (its
+// program runs on the controller, which may not see the sentinel's file system), and how often to look for the sentinel
+const TRIGGERED_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const SENTINEL_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// With --gpu-usage, how often to look at how busy the job's GPUs are (each look is a job step of its own)
+const GPU_USAGE_INTERVAL: Duration = Duration::from_secs(30);
+
+// How long to wait for a job's log file under its exact name before looking for one under a similar name (see
+// find_stand_in), and how often to look again after that
+const STAND_IN_SEARCH_AFTER: Duration = Duration::from_secs(10);
+
+/// How long to wait before checking again for the log file or new output, unless configured otherwise
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many of the log's last lines are shown when starting to follow it, unless configured otherwise
+pub const DEFAULT_HISTORY_LINES: usize = 150;
+
+/// How long to wait for the log file to appear, unless configured otherwise
+pub const DEFAULT_FILE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How long to wait for new output once the log file has appeared, unless configured otherwise
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+// With --max-duration: how long following stays attached, and when that runs out (see set_max_duration)
+static MAX_DURATION: OnceLock<(Duration, Instant)> = OnceLock::new();
+
+// Whether following has detached at --max-duration
+static DETACHED: AtomicBool = AtomicBool::new(false);
+
+// How far into each log following had got when it detached at --max-duration, for resuming (see detached_offset)
+static DETACHED_AT: Mutex<Vec<(PathBuf, u64)>> = Mutex::new(Vec::new());
+
+// Whether to learn when jobs end from a trigger the scheduler fires (--strigger), and the sentinel file each job's
+// trigger touches (None once it has fired, or if setting it failed, so the job is asked about as usual)
+static END_TRIGGERS: AtomicBool = AtomicBool::new(false);
+static SENTINELS: Mutex<BTreeMap<u64, Option<PathBuf>>> = Mutex::new(BTreeMap::new());
+
+// Log files followed in place of the ones expected, which never appeared under their exact names (see find_stand_in)
+static STAND_INS: Mutex<Vec<(PathBuf, PathBuf)>> = Mutex::new(Vec::new());
+
+/// How a log file is followed: how long to wait for the file to appear and for new bytes once it has (a timeout of zero
+/// waiting forever, like no_file_timeout and no_bytes_timeout), whether to stop once the job writing it has left the
+/// queue (and how long to keep reading after that), how often to check for more, and how much of what is already there
+/// to show
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FollowOptions {
+    pub file_appear_timeout: Option<Duration>,
+    pub timeout: Option<Duration>,
+    pub no_file_timeout: bool,
+    pub no_bytes_timeout: bool,
+    pub watch_job: bool,
+    pub poll_interval: Option<Duration>,
+    pub history_lines: Option<usize>,
+    pub grace_period: Option<Duration>,
+}
+
+/// Function responsible for monitoring ('tailing') a log file given to it
+/// If a prefix is given, every printed line is prefixed with `"[prefix] "`
+/// If a job ID is given, events are tagged with it, and (with `watch_job`) monitoring stops (successfully) once that
+/// job has left the queue and the file is drained
+/// If a start offset is given (e.g. where an earlier tail stopped), output picks up from there instead of showing the
+/// last lines (150, unless configured otherwise)
+/// Blocks until monitoring stops (the following itself runs on slurmtail's own async runtime, so this can't be called
+/// from async code)
+pub fn mon_logfile(
+    log_path: &Path,
+    options: &FollowOptions,
+    prefix: Option<&str>,
+    job_id: Option<u64>,
+    start_offset: Option<u64>,
+) -> Result<(), SlurmtailError> {
+    runtime::block_on(follow(
+        log_path.to_path_buf(),
+        *options,
+        prefix.map(str::to_string),
+        job_id,
+        start_offset,
+    ))
+}
+
+// What the scheduler last said about a followed job (see poll_job)
+#[derive(Debug, Clone)]
+struct JobCheck {
+    // Whether it is still pending or running (see job_is_active)
+    active: bool,
+    // Its state for showing while waiting, e.g. "PENDING (Priority)" (see job_status_summary)
+    summary: Option<String>,
+    // When the scheduler expects it to start, while it is pending
+    estimated_start: Option<jiff::Timestamp>,
+}
+
+// Ask the scheduler about a job every JOB_STATE_CHECK_INTERVAL (starting at `first_check`) in the background, so
+// following never waits on it; stops once the job has left the queue, or nobody is listening any more
+// With `summarize`, the pending reason and estimated start are looked up as well, for waiting on the log file
+fn poll_job(job_id: u64, first_check: Instant, summarize: bool) -> watch::Receiver<JobCheck> {
+    let (sender, receiver) = watch::channel(JobCheck {
+        active: true,
+        summary: None,
+        estimated_start: None,
+    });
+    runtime::spawn(async move {
+        // With an end trigger set, the job is mostly left alone until it fires
+        let mut sentinel = runtime::blocking(move || end_sentinel(job_id)).await;
+        let period = match sentinel {
+            Some(_) => TRIGGERED_CHECK_INTERVAL,
+            None => JOB_STATE_CHECK_INTERVAL,
+        };
+        let mut checks = time::interval_at(first_check.into(), period);
+        checks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut sentinel_checks = time::interval(SENTINEL_CHECK_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = checks.tick() => {}
+                _ = sentinel_checks.tick(), if sentinel.is_some() => {
+                    if !sentinel.as_deref().is_some_and(Path::exists) {
+                        continue;
+                    }
+                    tracing::debug!(job_id, "The job's end trigger fired");
+                    sentinel = None;
+                    runtime::blocking(move || end_trigger_fired(job_id)).await;
+                    // Accounting may not have the job's final state just yet, so it is asked about as usual from now on
+                    checks = time::interval_at(
+                        (Instant::now() + JOB_STATE_CHECK_INTERVAL).into(),
+                        JOB_STATE_CHECK_INTERVAL,
+                    );
+                    checks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                }
+                _ = sender.closed() => return,
+            }
+            tracing::trace!(job_id, "Checking whether the job is still in the queue");
+            let check = runtime::blocking(move || check_job(job_id, summarize)).await;
+            let active = check.active;
+            if !active && sentinel.is_some() {
+                runtime::blocking(move || end_trigger_fired(job_id)).await;
+            }
+            if sender.send(check).is_err() || !active {
+                return;
+            }
+        }
+    });
+
+    receiver
+}
+
+/// Learn when the jobs followed end from a trigger the scheduler fires when they do (SLURM's strigger), rather than by
+/// asking about them every few seconds; where setting one fails, they are asked about as usual
+pub fn set_end_triggers() {
+    END_TRIGGERS.store(true, Ordering::SeqCst);
+}
+
+// The sentinel file a job's end trigger touches, setting the trigger the first time, or None if there is none to wait
+// for (without --strigger, with --remote, whose scheduler can't touch files here, or once it has fired)
+fn end_sentinel(job_id: u64) -> Option<PathBuf> {
+    if !END_TRIGGERS.load(Ordering::SeqCst) || remote::active() {
+        return None;
+    }
+    let mut sentinels = SENTINELS.lock().ok()?;
+    if let Some(sentinel) = sentinels.get(&job_id) {
+        return sentinel.clone();
+    }
+
+    let sentinel = match set_end_trigger(job_id) {
+        Ok(sentinel) => {
+            tracing::debug!(job_id, sentinel = ?sentinel, "Set a trigger on the job's end");
+            Some(sentinel)
+        }
+        Err(e) => {
+            warning!(
+                "[WARNING] Could not set a trigger on job {}'s end, so it is checked on every {} instead: {}",
+                job_id,
+                duration::format_duration(JOB_STATE_CHECK_INTERVAL),
+                e
+            );
+            None
+        }
+    };
+    sentinels.insert(job_id, sentinel.clone());
+
+    sentinel
+}
+
+fn set_end_trigger(job_id: u64) -> Result<PathBuf, SlurmtailError> {
+    let sentinel = state::end_sentinel_path(&current_dir()?, job_id);
+    if let Some(dir) = sentinel.parent() {
+        std::fs::create_dir_all(dir).map_err(SlurmtailError::io("create", dir))?;
+    }
+    // Left over from an earlier trigger on the same job, which fired after its slurmtail was gone
+    let _ = std::fs::remove_file(&sentinel);
+    scheduler().set_end_trigger(job_id, &sentinel)?;
+
+    Ok(sentinel)
+}
+
+// A job's end trigger has fired: its sentinel has done its job
+fn end_trigger_fired(job_id: u64) {
+    if let Ok(mut sentinels) = SENTINELS.lock()
+        && let Some(Some(sentinel)) = sentinels.insert(job_id, None)
+    {
+        let _ = std::fs::remove_file(sentinel);
+    }
+}
+
+fn check_job(job_id: u64, summarize: bool) -> JobCheck {
+    match scheduler().job_state(job_id) {
+        Ok(Some(state)) => {
+            observe_state(job_id, &state);
+            let active = is_active_state(&state);
+            let estimated_start = (summarize && state == "PENDING")
+                .then(|| scheduler().estimated_start(job_id).ok().flatten())
+                .flatten();
+            JobCheck {
+                active,
+                summary: summarize.then(|| job_status_summary(job_id, state)),
+                estimated_start,
+            }
+        }
+        Ok(None) => JobCheck {
+            active: false,
+            summary: None,
+            estimated_start: None,
+        },
+        // If the scheduler can't be reached, don't pretend to know the job is gone
+        Err(_) => JobCheck {
+            active: true,
+            summary: None,
+            estimated_start: None,
+        },
+    }
+}
+
+// Wait for the scheduler's next word on a job being polled (forever, if it isn't, or won't be any more)
+async fn next_check(job: &mut Option<watch::Receiver<JobCheck>>) -> JobCheck {
+    if let Some(receiver) = job {
+        if receiver.changed().await.is_ok() {
+            return receiver.borrow_and_update().clone();
+        }
+        *job = None;
+    }
+    std::future::pending().await
+}
+
+// When to stop waiting for a pending job's log file, given when the scheduler expects it to start
+fn deadline_after(estimated_start: jiff::Timestamp, margin: Duration) -> Option<Instant> {
+    let until_start = estimated_start.duration_since(jiff::Timestamp::now());
+    let until_start = Duration::try_from(until_start).unwrap_or_default();
+    Instant::now().checked_add(until_start + margin)
+}
+
+// How following ends for a job that left the queue without writing its log file: as it ended, if that was a failure
+// (e.g. it was cancelled while pending, or its dependency failed), or with a warning. The scheduler is asked once: a
+// job that never started has nothing for accounting to catch up on
+async fn no_log_file(job_id: u64, log_path: &Path) -> Result<(), SlurmtailError> {
+    let final_state = runtime::blocking(move || scheduler().job_state(job_id).ok().flatten())
+        .await
+        .inspect(|state| observe_state(job_id, state))
+        .filter(|state| !is_active_state(state));
+    job_outcome(job_id, final_state.as_deref())?;
+    warning!(
+        "[WARNING] Job {} has left the queue, but its log file {:?} never appeared.",
+        job_id,
+        log_path
+    );
+
+    Ok(())
+}
+
+// Wait until a deadline (forever, without one)
+async fn until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+// Why following should stop before the job is done: another follower already saw the line --until-pattern waits for,
+// the user detached (None, as that's no failure), Ctrl-C, or SIGHUP/SIGTERM (resuming picks up at `offset`)
+async fn stop_requested(job_id: Option<u64>, offset: u64) -> Option<Result<(), SlurmtailError>> {
+    if filter::stopped() || user_detached() {
+        return Some(Ok(()));
+    }
+    if interrupt::interrupted() {
+        // Deciding may ask the user, and cancel the job
+        let failure = runtime::blocking(move || interrupted_failure(job_id)).await;
+        return Some(Err(failure));
+    }
+    if let Some(signal) = interrupt::terminated() {
+        return Some(Err(SlurmtailError::Terminated { signal, offset }));
+    }
+
+    None
+}
+
+// mon_logfile, as a task on the async runtime: waits for the file to appear and then reads it as it grows, while the
+// job is polled in the background (see poll_job), and timeouts are timers rather than counted sleeps
+#[tracing::instrument(level = "debug", name = "follow", skip_all, fields(path = ?log_path, job_id = ?job_id))]
+async fn follow(
+    log_path: PathBuf,
+    options: FollowOptions,
+    prefix: Option<String>,
+    job_id: Option<u64>,
+    start_offset: Option<u64>,
+) -> Result<(), SlurmtailError> {
+    let prefix = prefix.as_deref();
+    // Handle args
+    let mut file_appear_timeout = options.file_appear_timeout.unwrap_or(DEFAULT_FILE_TIMEOUT);
+    let timeout = options.timeout.unwrap_or(DEFAULT_IDLE_TIMEOUT);
+    let poll_interval = options.poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL);
+    // The job's state is also checked to keep the terminal title (or the --tui status bar) up to date, and for --notify,
+    // without stopping when it's done
+    let polled_job = job_id.filter(|_| {
+        options.watch_job || output::shows_terminal_title() || tui::active() || notify::enabled()
+    });
+    interrupt::install();
+
+    let mut polls = time::interval(poll_interval);
+    polls.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    // Retry opening the file until it is created (or the timeout, unless that is zero, runs out), showing how long it
+    // has been and how the job is doing in the meantime
+    // While the job is pending, the timeout runs from when the scheduler expects it to start, if that is later
+    let wait_started = Instant::now();
+    let mut file_deadline = (!options.no_file_timeout && !file_appear_timeout.is_zero())
+        .then(|| wait_started + file_appear_timeout);
+    let margin = file_appear_timeout;
+    let mut expecting_start = false;
+    let mut waiting: Option<progress::Waiting> = None;
+    let mut job_status = None;
+    // Following to the job's end, a job that leaves the queue without its log file appearing ends the wait as well
+    let mut job_checks = job_id
+        .filter(|_| options.watch_job || output::shows_status() || file_deadline.is_some())
+        .map(|job_id| poll_job(job_id, Instant::now(), true));
+    // With --remote, the log is read from a copy kept here
+    let mut log_path = log_path;
+    let mut read_path = remote::local_copy(&log_path);
+    // A job's log that doesn't show up under its exact name is looked for under similar ones (see find_stand_in)
+    let wait_started_at = SystemTime::now();
+    let mut next_search = job_id
+        .filter(|_| !remote::active())
+        .map(|_| wait_started + STAND_IN_SEARCH_AFTER);
+    let mut declined: Vec<PathBuf> = Vec::new();
+    let mut job_left = false;
+    let mut file = loop {
+        if let Some(stop) = stop_requested(job_id, start_offset.unwrap_or(0)).await {
+            return stop;
+        }
+
+        if let Ok(f) = File::open(&read_path) {
+            drop(waiting.take());
+            status!("[INFO] Found file: {:?}", log_path);
+            output::emit(
+                "log_file",
+                job_id,
+                serde_json::json!({ "path": log_path, "source": prefix }),
+            );
+            // The job has most likely just started, which is worth noticing right away
+            if let Some(job_id) = polled_job {
+                runtime::blocking(move || job_is_active(job_id)).await;
+            }
+            break f;
+        }
+        if let Some(job_id) = job_id
+            && let Some(search) = next_search
+            && Instant::now() >= search
+        {
+            next_search = Some(Instant::now() + JOB_STATE_CHECK_INTERVAL);
+            let expected = log_path.clone();
+            let found =
+                runtime::blocking(move || find_stand_in(&expected, job_id, wait_started_at))
+                    .await
+                    .filter(|found| !declined.contains(found));
+            if let Some(found) = found {
+                drop(waiting.take());
+                if use_stand_in(&log_path, &found).await {
+                    if let Ok(mut stand_ins) = STAND_INS.lock() {
+                        stand_ins.push((log_path.clone(), found.clone()));
+                    }
+                    read_path = found.clone();
+                    log_path = found;
+                    continue;
+                }
+                declined.push(found);
+            }
+        }
+        if job_left && let Some(job_id) = job_id {
+            drop(waiting.take());
+            return no_log_file(job_id, &log_path).await;
+        }
+        waiting
+            .get_or_insert_with(|| {
+                progress::Waiting::start(
+                    format!("log file to be created: {:?}", log_path),
+                    file_deadline,
+                )
+            })
+            .tick(job_status.as_deref());
+
+        tokio::select! {
+            _ = polls.tick() => {}
+            check = next_check(&mut job_checks) => {
+                if options.watch_job && !check.active {
+                    // One last look, in case it was written just before the job ended, or under a similar name
+                    job_left = true;
+                    next_search = next_search.map(|_| Instant::now());
+                    continue;
+                }
+                job_status = check.summary;
+                if let Some(current) = file_deadline
+                    && let Some(start) = check.estimated_start
+                    && let Some(deadline) = deadline_after(start, margin)
+                    && deadline > current
+                {
+                    if !std::mem::replace(&mut expecting_start, true) {
+                        status!(
+                            "[INFO] The scheduler expects job {} to start at {}; waiting for its log file until {} after that",
+                            job_id.unwrap_or_default(),
+                            start
+                                .to_zoned(jiff::tz::TimeZone::system())
+                                .strftime("%Y-%m-%d %H:%M:%S"),
+                            duration::format_duration(margin)
+                        );
+                    }
+                    tracing::debug!(estimated_start = %start, "Waiting for the log file until after the job's estimated start");
+                    file_deadline = Some(deadline);
+                    file_appear_timeout = Duration::from_secs((deadline - wait_started).as_secs());
+                    if let Some(waiting) = &mut waiting {
+                        waiting.set_deadline(deadline);
+                    }
+                }
+            }
+            _ = until(max_duration_deadline()) => {
+                drop(waiting.take());
+                detach_at_max_duration(Some((&log_path, start_offset.unwrap_or(0))));
+                return Ok(());
+            }
+            // Exit if we have been waiting longer than the timeout
+            _ = until(file_deadline) => {
+                warning!(
+                    "[FATAL] File took too long to appear (longer than timeout of {}). Exiting.",
+                    duration::format_duration(file_appear_timeout)
+                );
+                output::emit(
+                    "timeout",
+                    job_id,
+                    serde_json::json!({
+                        "waiting_for": "log_file",
+                        "seconds": duration::seconds_value(file_appear_timeout),
+                        "path": log_path,
+                        "source": prefix,
+                    }),
+                );
+                return Err(SlurmtailError::FileTimeout(file_appear_timeout));
+            }
+        }
+    };
+    drop(job_checks);
+
+    // Start at the last lines of the file (or the beginning, if it has fewer)
+    // A start offset past the end of the file means it has been truncated or replaced since, so it is ignored
+    let file_size = file
+        .metadata()
+        .map_err(SlurmtailError::io("read", &log_path))?
+        .len();
+    let start_position = match start_offset.filter(|offset| *offset <= file_size) {
+        Some(offset) => offset,
+        None => last_lines_start(
+            &mut file,
+            file_size,
+            options.history_lines.unwrap_or(DEFAULT_HISTORY_LINES),
+        )
+        .map_err(SlurmtailError::io("read", &log_path))?,
+    };
+
+    // Start reading from the calculated position (this will print the last lines + any new content)
+    tracing::debug!(start = start_position, size = file_size, "Reading log file");
+    file.seek(SeekFrom::Start(start_position))
+        .map_err(SlurmtailError::io("read", &log_path))?;
+    let mut reader = BufReader::new(tokio::fs::File::from_std(file));
+
+    // Times out after set time without new bytes read (never, with a timeout of zero)
+    let idle = time::sleep(timeout);
+    tokio::pin!(idle);
+    let mut job_checks =
+        polled_job.map(|job_id| poll_job(job_id, Instant::now() + JOB_STATE_CHECK_INTERVAL, false));
+    let mut job_gone = false;
+    // Once the job has left the queue, the log is read for the grace period more before following stops
+    let grace_period = options.grace_period.unwrap_or_default();
+    let mut grace_deadline = None;
+
+    let mut context = filter::Context::default();
+
+    // Continuously read new lines
+    loop {
+        // Everything up to here has been shown, so that's where resuming should pick up
+        let offset = reader
+            .stream_position()
+            .await
+            .map_err(SlurmtailError::io("read", &log_path))?;
+        if let Some(stop) = stop_requested(job_id, offset).await {
+            return stop;
+        }
+
+        // Print any new lines
+        let mut line = String::new();
+        while reader
+            .read_line(&mut line)
+            .await
+            .map_err(SlurmtailError::io("read", &log_path))?
+            > 0
+        {
+            tracing::trace!(bytes = line.len(), "Read from log file");
+            if let Some(stop) = show_line(job_id, prefix, &line, &mut context).await {
+                return stop;
+            }
+            idle.as_mut().reset(time::Instant::now() + timeout);
+            line.clear();
+        }
+
+        // The file has been drained since the job was last seen gone
+        if job_gone {
+            status!(
+                "[INFO] Job {} is no longer in the queue. Stopping.",
+                job_id.unwrap_or_default()
+            );
+            return Ok(());
+        }
+
+        tokio::select! {
+            // No new data, wait a bit
+            _ = polls.tick() => {}
+            check = next_check(&mut job_checks) => {
+                if !check.active && options.watch_job && grace_deadline.is_none() {
+                    if !grace_period.is_zero() {
+                        status!(
+                            "[INFO] Job {} has left the queue; reading its output for {} more.",
+                            job_id.unwrap_or_default(),
+                            duration::format_duration(grace_period)
+                        );
+                    }
+                    grace_deadline = Some(Instant::now() + grace_period);
+                }
+            }
+            _ = until(grace_deadline), if grace_deadline.is_some() && !job_gone => {
+                job_gone = true;
+                remote::catch_up(&log_path).await;
+            }
+            // Everything there was has just been shown, so this is where resuming picks up
+            _ = until(max_duration_deadline()) => {
+                let offset = reader
+                    .stream_position()
+                    .await
+                    .map_err(SlurmtailError::io("read", &log_path))?;
+                detach_at_max_duration(Some((&log_path, offset)));
+                return Ok(());
+            }
+            _ = &mut idle, if !options.no_bytes_timeout && !timeout.is_zero() => {
+                warning!(
+                    "[WARNING] Timed out after {} with no new bytes read! Exiting.",
+                    duration::format_duration(timeout)
+                );
+                output::emit(
+                    "timeout",
+                    job_id,
+                    serde_json::json!({
+                        "waiting_for": "output",
+                        "seconds": duration::seconds_value(timeout),
+                        "path": log_path,
+                        "source": prefix,
+                    }),
+                );
+                return Err(SlurmtailError::IdleTimeout(timeout));
+            }
+        }
+    }
+}
+
+// Show a line of the job's output (unless filtered out, with the --context lines around it kept in `context`), and run
+// the hooks on it; whether following should stop
+// because of it: a line matching --until-pattern/--succeed-on ends it successfully, one matching --fail-on as a
+// failure (cancelling the job with --cancel-on-fail)
+async fn show_line(
+    job_id: Option<u64>,
+    prefix: Option<&str>,
+    line: &str,
+    context: &mut filter::Context,
+) -> Option<Result<(), SlurmtailError>> {
+    // Plugins see the line first, and what they make of it is what the rest sees
+    let (line, style) = match plugin::transform(job_id, prefix, line) {
+        Outcome::Unchanged => (Cow::Borrowed(line), None),
+        Outcome::Dropped => return None,
+        Outcome::Changed { line, style } => (Cow::Owned(line), style),
+    };
+    let line = line.as_ref();
+    let shown = context.admit(line);
+    if shown.gap && !output::machine_readable() {
+        output::styled_job_line(job_id, prefix, "--\n", None);
+    }
+    // With --convert-timestamps, only what's shown has its timestamps rewritten; filters, hooks, and stop patterns
+    // see them as the job wrote them
+    let (allowed, reached_max_lines) = filter::allow_lines(shown.lines.len());
+    if let Some((line, before)) = shown.lines[..allowed].split_last() {
+        for context_line in before {
+            output::styled_job_line(job_id, prefix, &timestamps::convert(context_line), None);
+        }
+        output::styled_job_line(job_id, prefix, &timestamps::convert(line), style.as_deref());
+    }
+    hooks::check_line(job_id, prefix, line);
+    if reached_max_lines {
+        status!("[INFO] Showed as many lines as --max-lines allows. Stopping.");
+        return Some(Ok(()));
+    }
+    match filter::check_line(line)? {
+        Verdict::Succeeded => {
+            status!("[INFO] Found a line matching --until-pattern/--succeed-on. Stopping.");
+            Some(Ok(()))
+        }
+        Verdict::Failed => {
+            warning!("[WARNING] Found a line matching --fail-on. Stopping.");
+            if let Some(job_id) = job_id.filter(|_| filter::cancels_on_failure()) {
+                let cancelled = runtime::blocking(move || {
+                    scheduler().cancel(job_id).map_err(|e| e.to_string())
+                })
+                .await;
+                match cancelled {
+                    Ok(()) => {
+                        status!("Cancelled job {}", job_id);
+                        output::emit("cancelled", Some(job_id), serde_json::json!({}));
+                    }
+                    Err(e) => warning!("[WARNING] Could not cancel job {}: {}", job_id, e),
+                }
+            }
+            Some(Err(SlurmtailError::JobFailed(
+                "The job wrote a line matching --fail-on".to_string(),
+            )))
+        }
+    }
+}
+
+/// Follow a job's output through a running `slurmtail daemon` that follows it: the lines it kept from byte `from` of
+/// the log on (or the last lines, 150 unless configured otherwise), then new lines as they come, until the job has
+/// ended; without the timeouts, as the daemon knows when the job is done
+/// None if no daemon is running, or it doesn't follow the job, so the log is to be followed as usual (see mon_logfile)
+pub fn mon_daemon_job(
+    job_id: u64,
+    options: &FollowOptions,
+    from: Option<u64>,
+) -> Option<Result<(), SlurmtailError>> {
+    runtime::block_on(follow_daemon_job(job_id, *options, from))
+}
+
+async fn follow_daemon_job(
+    job_id: u64,
+    options: FollowOptions,
+    from: Option<u64>,
+) -> Option<Result<(), SlurmtailError>> {
+    let request = daemon::Request::Attach {
+        job_id,
+        from,
+        lines: options.history_lines.unwrap_or(DEFAULT_HISTORY_LINES),
+    };
+    let mut replies = daemon::send(&request).await.ok()??;
+    interrupt::install();
+
+    let mut polls = time::interval(options.poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL));
+    polls.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut attached = false;
+    let mut offset = from.unwrap_or(0);
+    let mut context = filter::Context::default();
+    loop {
+        let reply = tokio::select! {
+            _ = polls.tick() => {
+                if attached && let Some(stop) = stop_requested(Some(job_id), offset).await {
+                    return Some(stop);
+                }
+                continue;
+            }
+            reply = replies.next_line() => reply,
+            _ = until(max_duration_deadline()), if attached => {
+                detach_at_max_duration(None);
+                return Some(Ok(()));
+            }
+        };
+        let reply = match reply {
+            Ok(Some(reply)) => serde_json::from_str::<daemon::Reply>(&reply).ok()?,
+            // The daemon went away before the job ended (or never answered)
+            _ if !attached => return None,
+            _ => {
+                return Some(Err(SlurmtailError::Daemon(
+                    "Lost the connection to the slurmtail daemon".to_string(),
+                )));
+            }
+        };
+        if !attached {
+            if matches!(reply, daemon::Reply::NotFollowing) {
+                return None;
+            }
+            status!(
+                "[INFO] Attached to the slurmtail daemon, which follows job {}",
+                job_id
+            );
+            attached = true;
+        }
+
+        match reply {
+            daemon::Reply::Line { line, end } => {
+                offset = end;
+                if let Some(stop) = show_line(Some(job_id), None, &line, &mut context).await {
+                    return Some(stop);
+                }
+            }
+            daemon::Reply::State { state } => observe_state(job_id, &state),
+            daemon::Reply::End { state } => {
+                status!(
+                    "[INFO] Job {} has ended ({}). Stopping.",
+                    job_id,
+                    state.as_deref().unwrap_or("UNKNOWN")
+                );
+                return Some(Ok(()));
+            }
+            _ => {}
+        }
+    }
+}
+
+// Where the last `lines` lines of a file start (0 if it has fewer; its end for none)
+fn last_lines_start(file: &mut File, file_size: u64, lines: usize) -> std::io::Result<u64> {
+    if lines == 0 || file_size == 0 {
+        return Ok(file_size);
+    }
+
+    // The newline ending the file's last line doesn't start another line
+    let mut last_byte = [0u8; 1];
+    file.seek(SeekFrom::Start(file_size - 1))?;
+    file.read_exact(&mut last_byte)?;
+    let mut position = if last_byte[0] == b'\n' {
+        file_size - 1
+    } else {
+        file_size
+    };
+
+    // Seek backwards, a chunk at a time, to the newline before the first of the lines
+    let mut newline_count = 0;
+    let mut buffer = [0u8; 8192]; // 8KB buffer
+    while position > 0 {
+        let chunk_size = std::cmp::min(buffer.len() as u64, position);
+        position -= chunk_size;
+
+        file.seek(SeekFrom::Start(position))?;
+        file.read_exact(&mut buffer[0..chunk_size as usize])?;
+
+        // Count newlines backwards in this chunk
+        for i in (0..chunk_size as usize).rev() {
+            if buffer[i] == b'\n' {
+                newline_count += 1;
+                if newline_count == lines {
+                    return Ok(position + i as u64 + 1);
+                }
+            }
+        }
+    }
+
+    Ok(0)
+}
+
+// A job's state for showing while waiting, with the reason it is pending if there is one, e.g. "PENDING (Priority)"
+fn job_status_summary(job_id: u64, state: String) -> String {
+    if state != "PENDING" {
+        return state;
+    }
+
+    match scheduler().pending_reason(job_id).ok().flatten() {
+        Some(reason) => format!("{} ({})", state, reason),
+        None => state,
+    }
+}
+
+/// A log file to follow alongside others: the prefix for its lines, its path, and the job writing it (if known)
+pub struct FollowTarget {
+    pub prefix: String,
+    pub log_path: PathBuf,
+    pub job_id: Option<u64>,
+}
+
+// How following ends after Ctrl-C: with the job cancelled, or left running, as --on-interrupt says (see
+// interrupt::decide)
+fn interrupted_failure(job_id: Option<u64>) -> SlurmtailError {
+    match (interrupt::decide(job_id), job_id) {
+        (interrupt::Decision::Cancel, Some(job_id)) => SlurmtailError::JobCancelled(job_id),
+        _ => SlurmtailError::Interrupted,
+    }
+}
+
+/// Whether the user detached from the job (with q, in the --tui interface or while following), or following did at
+/// --max-duration, leaving it running
+pub fn user_detached() -> bool {
+    tui::detached() || keys::detached() || DETACHED.load(Ordering::SeqCst)
+}
+
+/// Stay attached for at most this long (from now) over all the jobs followed, whatever they are doing: then following
+/// detaches as if the user had pressed q, leaving them running, and noting how far into each log it got so that
+/// `slurmtail resume` picks up from there
+pub fn set_max_duration(max_duration: Duration) {
+    let _ = MAX_DURATION.set((max_duration, Instant::now() + max_duration));
+}
+
+fn max_duration_deadline() -> Option<Instant> {
+    MAX_DURATION.get().map(|(_, deadline)| *deadline)
+}
+
+// Detach at --max-duration, having got this far into the log, if following read it itself (saying so only once,
+// however many logs are followed)
+fn detach_at_max_duration(log: Option<(&Path, u64)>) {
+    if let Some((log_path, offset)) = log
+        && let Ok(mut detached_at) = DETACHED_AT.lock()
+    {
+        detached_at.push((log_path.to_path_buf(), offset));
+    }
+    if !DETACHED.swap(true, Ordering::SeqCst)
+        && let Some((max_duration, _)) = MAX_DURATION.get()
+    {
+        status!(
+            "[INFO] Followed for --max-duration ({}); detached, and the job keeps running (pick it up again with `slurmtail resume`)",
+            duration::format_duration(*max_duration)
+        );
+    }
+}
+
+// A file that looks like the job's log under another name, for when it doesn't show up under the one expected: some
+// clusters add a suffix to log files, or stage them under another name. It is a file next to the expected one, written
+// since `since`, whose name starts the same way up to the job ID (e.g. "test_output.12345*" for
+// "test_output.12345.log"), or, without the job ID in it, with the whole expected name; the newest, if there are several
+fn find_stand_in(expected: &Path, job_id: u64, since: SystemTime) -> Option<PathBuf> {
+    let name = expected.file_name()?.to_str()?;
+    let id = job_id.to_string();
+    let prefix = match name.find(&id) {
+        Some(start) => &name[..start + id.len()],
+        None => name,
+    };
+    let dir = match expected.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|candidate| candidate != name && candidate.starts_with(prefix))
+        })
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let modified = metadata.modified().ok()?;
+            (metadata.is_file() && modified >= since).then(|| (modified, entry.path()))
+        })
+        .max()
+        .map(|(_, path)| path)
+}
+
+// Whether to follow a file found in place of the expected log (see find_stand_in): asked, with someone at the
+// terminal; otherwise it is followed, saying so
+async fn use_stand_in(expected: &Path, found: &Path) -> bool {
+    let interactive = std::io::stdin().is_terminal()
+        && std::io::stderr().is_terminal()
+        && !output::machine_readable();
+    if !interactive {
+        status!(
+            "[INFO] The log file {:?} hasn't appeared, but {:?} has; following that instead",
+            expected,
+            found
+        );
+        return true;
+    }
+
+    let question = format!(
+        "The log file {:?} hasn't appeared, but {:?} has. Follow that instead?",
+        expected, found
+    );
+    runtime::blocking(move || confirm(&question).unwrap_or(false)).await
+}
+
+/// The file followed in place of a log that never appeared under its expected name, if there was one (see
+/// find_stand_in)
+pub fn stand_in(log_path: &Path) -> Option<PathBuf> {
+    STAND_INS
+        .lock()
+        .ok()?
+        .iter()
+        .find_map(|(expected, found)| (expected == log_path).then(|| found.clone()))
+}
+
+/// How far into the log following had got when it detached at --max-duration, if it did
+pub fn detached_offset(log_path: &Path) -> Option<u64> {
+    DETACHED_AT
+        .lock()
+        .ok()?
+        .iter()
+        .find_map(|(path, offset)| (path == log_path).then_some(*offset))
+}
+
+/// With --tui, also follow the job's separate stderr file, in the interface's side pane, for as long as the interface
+/// is up (without timing out)
+pub fn follow_stderr_in_tui(stderr_path: PathBuf, job_id: u64) {
+    if !tui::active() {
+        return;
+    }
+
+    let options = FollowOptions {
+        no_file_timeout: true,
+        no_bytes_timeout: true,
+        ..FollowOptions::default()
+    };
+    runtime::spawn(follow(
+        stderr_path,
+        options,
+        Some(tui::STDERR_SOURCE.to_string()),
+        Some(job_id),
+        None,
+    ));
+}
+
+/// Follow several log files at once (each in a task of its own), returning once all of them have finished
+pub fn mon_logfiles(
+    targets: &[FollowTarget],
+    options: &FollowOptions,
+) -> Result<(), SlurmtailError> {
+    if tmux::enabled() {
+        return tmux::follow(targets, options);
+    }
+
+    let failures = runtime::block_on(async {
+        let followers: Vec<_> = targets
+            .iter()
+            .map(|target| {
+                runtime::spawn(follow(
+                    target.log_path.clone(),
+                    *options,
+                    Some(target.prefix.clone()),
+                    target.job_id,
+                    None,
+                ))
+            })
+            .collect();
+
+        let mut failures = Vec::new();
+        for (target, follower) in targets.iter().zip(followers) {
+            failures.extend(follower_failure(&target.prefix, follower.await));
+        }
+        failures
+    });
+
+    combine_failures(failures)
+}
+
+// Follow the log files a job's nodes write (for an output pattern with %N or %n, see node_log_targets), each line
+// prefixed with its node: the first (the batch script's node) like any log, and the others once they appear, since
+// nodes that run no step writing to the pattern never write one
+fn mon_node_logfiles(
+    targets: &[FollowTarget],
+    options: &FollowOptions,
+) -> Result<(), SlurmtailError> {
+    let Some((first, others)) = targets.split_first() else {
+        return Ok(());
+    };
+    if tmux::enabled() {
+        return tmux::follow(targets, options);
+    }
+
+    let options = *options;
+    let failures = runtime::block_on(async {
+        let mut followers = vec![(
+            first.prefix.clone(),
+            runtime::spawn(follow(
+                first.log_path.clone(),
+                options,
+                Some(first.prefix.clone()),
+                first.job_id,
+                None,
+            )),
+        )];
+
+        // Look for the other nodes' files until the first one's has been followed to the end
+        let mut waiting: Vec<&FollowTarget> = others.iter().collect();
+        let mut polls = time::interval(options.poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL));
+        while !followers[0].1.is_finished() {
+            polls.tick().await;
+            let (appeared, still_waiting): (Vec<_>, Vec<_>) = waiting
+                .into_iter()
+                .partition(|target| File::open(remote::local_copy(&target.log_path)).is_ok());
+            waiting = still_waiting;
+            for target in appeared {
+                tracing::debug!(path = ?target.log_path, node = target.prefix, "Node log file appeared");
+                followers.push((
+                    target.prefix.clone(),
+                    runtime::spawn(follow(
+                        target.log_path.clone(),
+                        options,
+                        Some(target.prefix.clone()),
+                        target.job_id,
+                        // From the start, since nothing of it has been shown yet
+                        Some(0),
+                    )),
+                ));
+            }
+        }
+
+        let mut failures = Vec::new();
+        for (prefix, follower) in followers {
+            failures.extend(follower_failure(&prefix, follower.await));
+        }
+        failures
+    });
+
+    combine_failures(failures)
+}
+
+// How a log follower (spawned on the runtime) failed, if it did: what to say about it, and the failure itself (none if
+// the follower panicked)
+fn follower_failure(
+    prefix: &str,
+    joined: Result<Result<(), SlurmtailError>, tokio::task::JoinError>,
+) -> Option<(String, Option<SlurmtailError>)> {
+    match joined {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some((format!("{}: {}", prefix, e), Some(e))),
+        Err(_) => Some(("log follower panicked".to_string(), None)),
+    }
+}
+
+// The failures of several log followers as one
+fn combine_failures(
+    mut failures: Vec<(String, Option<SlurmtailError>)>,
+) -> Result<(), SlurmtailError> {
+    // The first follower that failed in a way with an exit code of its own decides the exit code
+    if let Some(failure) = failures
+        .iter_mut()
+        .find_map(|(_, failure)| failure.take_if(|failure| failure.exit_code() != 1))
+    {
+        return Err(failure);
+    }
+    if !failures.is_empty() {
+        let messages: Vec<String> = failures.into_iter().map(|(message, _)| message).collect();
+        return Err(SlurmtailError::Followers(messages));
+    }
+
+    Ok(())
+}
+
+/// How `run` submits and follows a job
+pub struct RunOptions {
+    pub follow: FollowOptions,
+    pub all_components: bool,
+    pub attach_step: u32,
+    /// Name to record the session under, so it can be resumed (or cancelled) by name
+    pub session_name: Option<String>,
+    /// Options passed to the scheduler along with the script (from the config, e.g. --partition)
+    pub submit_args: Vec<String>,
+    /// Create the directories the job's log files go in, if they don't exist yet (see check_log_dirs)
+    pub create_log_dirs: bool,
+    /// What to do with log files left by an earlier job under the same name (see check_log_collisions)
+    pub existing_logs: ExistingLogs,
+    /// Show how busy the job's GPUs are beneath its output (see GpuUsageLine)
+    pub gpu_usage: bool,
+}
+
+/// What to do with a job's log file that is already there when it is submitted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExistingLogs {
+    /// Leave it, with a warning
+    #[default]
+    Warn,
+    /// Move it aside, to `<name>.1` (or the next free number)
+    BackUp,
+    /// Empty it, so following it shows only the new job's output
+    Truncate,
+}
+
+/// Make sure the directories a job's log files go in (given their patterns) exist before it is submitted: sbatch takes
+/// a job whose log directory is missing, which then dies as soon as it starts, without a word
+/// With `create`, missing directories are created; otherwise they are an error. Directories named with placeholders
+/// (e.g. `logs/%x/`) aren't checked, and neither are those on a --remote host
+pub fn check_log_dirs(
+    script_path: &Path,
+    patterns: &[String],
+    create: bool,
+) -> Result<(), SlurmtailError> {
+    if remote::active() {
+        return Ok(());
+    }
+
+    for pattern in patterns {
+        let Some(dir) = Path::new(pattern).parent().and_then(Path::to_str) else {
+            continue;
+        };
+        if dir.is_empty() || dir.contains(['%', '\\']) {
+            continue;
+        }
+        let dir = logfile_string_to_path(script_path, dir.to_string(), true)?;
+        if dir.is_dir() {
+            continue;
+        }
+
+        if !create {
+            return Err(SlurmtailError::SubmitFailed(format!(
+                "The job's log directory {:?} doesn't exist, so the job would fail without a log; create it, or pass --create-log-dirs",
+                dir
+            )));
+        }
+        std::fs::create_dir_all(&dir)
+            .map_err(SlurmtailError::io("create the log directory", &dir))?;
+        status!("[INFO] Created the log directory {:?}", dir);
+    }
+
+    Ok(())
+}
+
+/// Warn when a job's log files (given their patterns) are already there before it is submitted, as they are when a
+/// pattern names the same file for every job (e.g. without %j): sbatch overwrites them, or with --open-mode=append, adds
+/// to them, and their old output would be followed as if it were the new job's
+/// `existing` can have an existing file moved aside or emptied instead. Patterns using the job ID or node can't collide
+/// or be resolved ahead of time, and files on a --remote host aren't checked
+pub fn check_log_collisions(
+    script_path: &Path,
+    patterns: &[String],
+    directives: &SbatchDirectives,
+    existing: ExistingLogs,
+) -> Result<(), SlurmtailError> {
+    if remote::active() {
+        return Ok(());
+    }
+
+    let array_task = directives.array.as_deref().and_then(first_array_task);
+    let appends = directives
+        .options
+        .iter()
+        .rev()
+        .find(|(name, _)| name == "open-mode")
+        .is_some_and(|(_, mode)| mode == "append");
+    let mut checked = Vec::new();
+    for pattern in patterns {
+        if pattern_uses_job_id(pattern) || pattern_uses_node(pattern) {
+            continue;
+        }
+        let filename = format_log_output_string(
+            pattern.clone(),
+            0,
+            directives.job_name.as_ref(),
+            array_task,
+            None,
+        );
+        let path = logfile_string_to_path(script_path, filename, true)?;
+        if checked.contains(&path) || !path.is_file() {
+            continue;
+        }
+
+        match existing {
+            ExistingLogs::BackUp => {
+                let backup_path = (1..)
+                    .map(|n| PathBuf::from(format!("{}.{}", path.display(), n)))
+                    .find(|candidate| !candidate.exists())
+                    .unwrap_or_default();
+                std::fs::rename(&path, &backup_path)
+                    .map_err(SlurmtailError::io("back up the old log file", &path))?;
+                status!(
+                    "[INFO] Moved the old log file {:?} to {:?}",
+                    path,
+                    backup_path
+                );
+            }
+            ExistingLogs::Truncate => {
+                std::fs::File::create(&path)
+                    .map_err(SlurmtailError::io("empty the old log file", &path))?;
+                status!("[INFO] Emptied the old log file {:?}", path);
+            }
+            ExistingLogs::Warn => warning!(
+                "[WARNING] The job's log file {:?} already exists, so {}; pass --backup-existing to keep it, or --truncate-existing to start it afresh (or give the file a name with %j)",
+                path,
+                if appends {
+                    "the job's output will be added to the old output"
+                } else {
+                    "its old output will be overwritten"
+                }
+            ),
+        }
+        checked.push(path);
+    }
+
+    Ok(())
+}
+
+/// Make sure the scheduler would take the partition, account, and QOS a job asks for (its script's directives, with
+/// the options it is submitted with on top) before it is submitted, so a typo is an error now instead of a cryptic rejection from sbatch
+/// Whatever the scheduler can't look up (e.g. without an accounting database) isn't checked
+pub fn check_placement(directives: &SbatchDirectives) -> Result<(), SlurmtailError> {
+    let problems = scheduler().placement_problems(directives);
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    Err(SlurmtailError::SubmitFailed(format!(
+        "{}, so the job would be rejected",
+        problems.join("; ")
+    )))
+}
+
+/// Submit a batch script, resolve its log file(s), and follow them; returns the submitted job's ID
+pub fn submit_and_follow(script_path: &Path, options: &RunOptions) -> Result<u64, SlurmtailError> {
+    // Options submitted along with the script (from the config, or `rerun`) win over its directives, as with sbatch
+    // The script is only read and parsed once; heterogeneous jobs need the directives of each of its components
+    let content = read_to_string(script_path).map_err(SlurmtailError::io("read", script_path))?;
+    let het_components = SbatchDirectives::parse_components(&content);
+    let directives = SbatchDirectives::parse(&content).with_args(&options.submit_args);
+    let overrides = SbatchDirectives::from_args(&options.submit_args);
+
+    // Extract log output pattern from the script
+    let log_pattern = match overrides.output {
+        Some(pattern) => pattern,
+        None => scheduler().output_pattern(script_path)?,
+    };
+
+    // Extract job name if present
+    let job_name = match overrides.job_name {
+        Some(name) => Some(name),
+        None => scheduler().job_name(script_path)?,
+    };
+
+    // Extract the array range if this is a job array
+    let array_spec = overrides.array.or_else(|| array_spec(&directives));
+    let array_task = array_spec.as_deref().and_then(first_array_task);
+
+    let error_pattern = overrides.error.or_else(|| error_pattern(&directives));
+    let log_patterns: Vec<String> = std::iter::once(log_pattern.clone())
+        .chain(error_pattern.clone())
+        .collect();
+    check_log_dirs(script_path, &log_patterns, options.create_log_dirs)?;
+    check_placement(&directives)?;
+    check_log_collisions(
+        script_path,
+        &log_patterns,
+        &directives,
+        options.existing_logs,
+    )?;
+
+    // Submit the job
+    status!("Submitting job...");
+    let job_id = scheduler()
+        .submit(script_path, &options.submit_args)
+        .map_err(|e| SlurmtailError::SubmitFailed(e.to_string()))?;
+    let submitted_at = jiff::Timestamp::now();
+    status!("Job submitted with ID: {}", job_id);
+    output::emit(
+        "submitted",
+        Some(job_id),
+        serde_json::json!({
+            "script": script_path,
+            "name": options.session_name,
+            "cluster": scheduler().job_cluster(job_id),
+        }),
+    );
+
+    if let Some(spec) = &array_spec {
+        warning!(
+            "[WARNING] Script submits a job array (--array={}), but only the output of task {} will be followed; the states of all the tasks are counted beneath it.",
+            spec,
+            array_task.map_or("?".to_string(), |t| t.to_string())
+        );
+    }
+    let _array_progress = array_spec.is_some().then(|| ArrayProgress::start(job_id));
+    let gpus_requested = directives.requests_gpus();
+    if options.gpu_usage && !gpus_requested {
+        warning!(
+            "[WARNING] The job doesn't ask for GPUs (with --gpus or --gres=gpu), so there's no GPU usage to show"
+        );
+    }
+    let _gpu_usage = (options.gpu_usage && gpus_requested).then(|| GpuUsageLine::start(job_id));
+
+    // %N names the job's first node (and %n its index among them), which isn't known until the job starts
+    let node = if pattern_is_per_node(&log_pattern) {
+        wait_for_batch_host(job_id)
+    } else {
+        None
+    };
+
+    // Heterogeneous jobs write one file per component, with "+<component>" appended to the job ID
+    let mut het_targets: Vec<FollowTarget> = Vec::new();
+    if het_components.len() > 1 {
+        for (index, component) in het_components.iter().enumerate() {
+            let Some(pattern) = component
+                .output
+                .clone()
+                .or_else(|| (index == 0).then(|| log_pattern.clone()))
+            else {
+                continue;
+            };
+            let component_name = component.job_name.as_ref().or(job_name.as_ref());
+            let pattern = pattern.replace("%j", &format!("{}+{}", job_id, index));
+            let filename = format_log_output_string(
+                pattern,
+                job_id,
+                component_name,
+                array_task,
+                node.as_deref(),
+            );
+            let path = logfile_string_to_path(script_path, filename, true)?;
+
+            // Components without their own %j may share a file, which only needs following once
+            if het_targets.iter().all(|target| target.log_path != path) {
+                het_targets.push(FollowTarget {
+                    prefix: format!("+{}", index),
+                    log_path: path,
+                    job_id: Some(job_id),
+                });
+            }
+        }
+    }
+
+    // Each of the job's nodes may write a file of its own under such a pattern (e.g. from `srun --output`)
+    let node_targets = match (&node, het_targets.is_empty()) {
+        (Some(batch_host), true) => node_log_targets(
+            script_path,
+            &log_pattern,
+            job_id,
+            job_name.as_ref(),
+            array_task,
+            batch_host,
+        )?,
+        _ => Vec::new(),
+    };
+
+    // Format the log file path
+    let log_path = match het_targets.first() {
+        Some(first_component) => first_component.log_path.clone(),
+        None => {
+            let log_filename = format_log_output_string(
+                log_pattern,
+                job_id,
+                job_name.as_ref(),
+                array_task,
+                node.as_deref(),
+            );
+            logfile_string_to_path(script_path, log_filename, true)?
+        }
+    };
+    tracing::debug!(path = ?log_path, "Will try to use this log file path");
+
+    // Save resume file
+    let current_dir = current_dir()?;
+    let discards_output = log_path == Path::new("/dev/null");
+    let stderr_path = match error_pattern {
+        Some(pattern) => {
+            let filename = format_log_output_string(
+                pattern,
+                job_id,
+                job_name.as_ref(),
+                array_task,
+                node.as_deref(),
+            );
+            Some(logfile_string_to_path(script_path, filename, true)?)
+        }
+        None => None,
+    };
+    state::save_turd(
+        &current_dir,
+        &ResumeState {
+            job_id: Some(job_id),
+            cluster: scheduler().job_cluster(job_id),
+            script: Some(
+                script_path
+                    .canonicalize()
+                    .map_err(SlurmtailError::io("find", script_path))?,
+            ),
+            stderr: stderr_path.clone(),
+            array: array_spec.clone(),
+            submitted_at: Some(submitted_at.to_string()),
+            name: options.session_name.clone(),
+            project: Some(current_dir.clone()),
+            ..ResumeState::new(&log_path)
+        },
+    )?;
+
+    record_submission(
+        script_path,
+        &options.submit_args,
+        job_id,
+        &log_path,
+        options.session_name.as_deref(),
+        submitted_at,
+    );
+    write_transcript_header(script_path, &options.submit_args, job_id, submitted_at);
+
+    let _follow_claim = state::claim_follow(&current_dir, job_id)?;
+
+    // Start monitoring
+    tui::start();
+    keys::start(Some(job_id));
+    if let Some(stderr_path) = stderr_path.filter(|path| *path != log_path) {
+        follow_stderr_in_tui(stderr_path, job_id);
+    }
+    if options.all_components && het_targets.len() > 1 {
+        status!(
+            "Monitoring {} heterogeneous job component log files",
+            het_targets.len()
+        );
+        mon_logfiles(&het_targets, &options.follow)?;
+    } else if node_targets.len() > 1 {
+        status!(
+            "Monitoring the log files of job {}'s {} nodes",
+            job_id,
+            node_targets.len()
+        );
+        mon_node_logfiles(&node_targets, &options.follow)?;
+    } else {
+        if het_targets.len() > 1 {
+            status!(
+                "[INFO] Script is a heterogeneous job with {} component log files; only the first is followed. Pass --all-components to follow all of them.",
+                het_targets.len()
+            );
+        }
+        if discards_output {
+            warning!(
+                "[WARNING] The job's output goes to /dev/null, so there is no log file to follow."
+            );
+            offer_attach(job_id, options.attach_step)?;
+            return Ok(job_id);
+        }
+
+        status!("Monitoring log file: {:?}", log_path);
+        let result = mon_logfile(&log_path, &options.follow, None, Some(job_id), None);
+        record_read_offset(&current_dir, &log_path, &result);
+        if let Err(e) = result {
+            // Stopped on purpose (Ctrl-C, SIGHUP, SIGTERM), so there is nothing to fall back on
+            if interrupt::interrupted() || interrupt::terminated().is_some() {
+                return Err(e);
+            }
+            // A running job whose log we can't open is probably writing to a node-local filesystem
+            let job_running =
+                scheduler().job_state(job_id).ok().flatten().as_deref() == Some("RUNNING");
+            if job_running && File::open(remote::local_copy(&log_path)).is_err() {
+                warning!(
+                    "[WARNING] Job {} is running, but its log file can't be read from here (node-local filesystem?).",
+                    job_id
+                );
+                offer_attach(job_id, options.attach_step)?;
+                return Ok(job_id);
+            }
+            return Err(e);
+        }
+    }
+
+    Ok(job_id)
+}
+
+/// Turn the state a job ended in into the matching failure, if it didn't complete successfully
+pub fn job_outcome(job_id: u64, final_state: Option<&str>) -> Result<(), SlurmtailError> {
+    match final_state {
+        Some(state) if state.starts_with("CANCELLED") => Err(SlurmtailError::JobCancelled(job_id)),
+        Some(state) if state != "COMPLETED" => Err(SlurmtailError::JobFailed(format!(
+            "Job {} ended in state {}",
+            job_id, state
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Check a script with the scheduler's dry run (`sbatch --test-only`) before submitting it for real; a script it
+/// refuses fails as a submission would
+pub fn preflight(script_path: &Path, submit_args: &[String]) -> Result<(), SlurmtailError> {
+    status!("[INFO] Running sbatch --test-only pre-flight...");
+    let estimate = scheduler()
+        .test_only(script_path, submit_args)
+        .map_err(|e| SlurmtailError::SubmitFailed(e.to_string()))?;
+    status!("[INFO] Pre-flight passed: {}", estimate);
+
+    Ok(())
+}
+
+/// When `run` submits a job again: after it ended in one of `retry_on`'s states (e.g. NODE_FAIL), up to `retries`
+/// times, `retry_delay` later
+#[derive(Debug, Clone, Default)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub retry_on: Vec<String>,
+    pub retry_delay: Duration,
+}
+
+/// Submit and follow a job (see submit_and_follow), submitting it again for as long as the policy says to
+/// Gives the last job's ID and the state it ended in: None if following stopped before it ended (the user detached, or
+/// --until-pattern matched), or if the scheduler can't say
+pub fn run_with_retries(
+    script_path: &Path,
+    options: &RunOptions,
+    policy: &RetryPolicy,
+) -> Result<(u64, Option<String>), SlurmtailError> {
+    let mut attempt = 0;
+    loop {
+        let job_id = submit_and_follow(script_path, options)?;
+        write_transcript_footer(job_id);
+        let final_state = state::finish_session_if_done(&current_dir()?, job_id);
+
+        // The job got as far as it needed to, or the user detached (and it is likely still running)
+        if filter::stopped() || user_detached() {
+            return Ok((job_id, None));
+        }
+        if attempt >= policy.retries {
+            return Ok((job_id, final_state));
+        }
+
+        let Some(state) = job_final_state(job_id) else {
+            warning!(
+                "[WARNING] Could not determine the final state of job {}; not resubmitting.",
+                job_id
+            );
+            return Ok((job_id, None));
+        };
+
+        if !policy
+            .retry_on
+            .iter()
+            .any(|retry_state| retry_state.trim().eq_ignore_ascii_case(&state))
+        {
+            status!("[INFO] Job {} ended in state {}.", job_id, state);
+            return Ok((job_id, Some(state)));
+        }
+
+        attempt += 1;
+        status!(
+            "[INFO] ===== Job {} ended in state {}; resubmitting (attempt {} of {}) =====",
+            job_id,
+            state,
+            attempt + 1,
+            policy.retries + 1
+        );
+        if !policy.retry_delay.is_zero() {
+            status!(
+                "[INFO] Waiting {} before resubmitting...",
+                duration::format_duration(policy.retry_delay)
+            );
+            std::thread::sleep(policy.retry_delay);
+        }
+    }
+}
+
+/// Submit a job from the submission history (the latest one, without a job ID) again, from where it was submitted
+/// the first time (so relative paths mean the same), asking for what it asked for then, and follow it to its end
+pub fn rerun(
+    job_id: Option<u64>,
+    config: &Config,
+    follow: FollowOptions,
+) -> Result<(), SlurmtailError> {
+    let submission = history::find_submission(job_id)?.ok_or_else(|| {
+        SlurmtailError::NotFound(match job_id {
+            Some(job_id) => format!("Job {} isn't in the submission history", job_id),
+            None => "No submissions recorded yet".to_string(),
+        })
+    })?;
+    let script_path = submission
+        .script
+        .clone()
+        .filter(|script| script.exists())
+        .ok_or_else(|| {
+            SlurmtailError::NotFound(format!(
+                "The script job {} was submitted from isn't there any more",
+                submission.job_id
+            ))
+        })?;
+    if let Some(project) = submission.project.as_ref().filter(|dir| dir.is_dir()) {
+        std::env::set_current_dir(project).map_err(SlurmtailError::io("change to", project))?;
+    }
+
+    let mut submit_args = config.submit_args(&script_path)?;
+    submit_args.extend(state::changed_directives(
+        &script_path,
+        &submission.directives,
+    )?);
+    status!(
+        "[INFO] Running job {} ({:?}) again",
+        submission.job_id,
+        script_path
+    );
+    let options = RunOptions {
+        follow: FollowOptions {
+            watch_job: true,
+            ..follow
+        },
+        all_components: false,
+        attach_step: 0,
+        session_name: submission.name.clone(),
+        submit_args,
+        create_log_dirs: false,
+        existing_logs: ExistingLogs::default(),
+        gpu_usage: false,
+    };
+
+    let (job_id, final_state) = run_with_retries(&script_path, &options, &RetryPolicy::default())?;
+    job_outcome(job_id, final_state.as_deref())
+}
+
+/// Follow a session's log again (`slurmtail resume`), from its start, or (with `from_last_offset`, or if its last tail
+/// was cut short by a signal) from where the last tail stopped, if that got anywhere; with `pager_on_exit`, the whole
+/// log is shown in a pager once the job has finished
+pub fn resume_session(
+    session: &ResumeState,
+    project_dir: &Path,
+    options: &FollowOptions,
+    from_last_offset: bool,
+    pager_on_exit: bool,
+) -> Result<(), SlurmtailError> {
+    if !session.stdout.exists() {
+        return Err(SlurmtailError::NotFound(
+            "Log file from resume file no longer exists".to_string(),
+        ));
+    }
+
+    // Don't tail a session another slurmtail is already following
+    let project_dir = session.project.clone().unwrap_or(project_dir.to_path_buf());
+    let _follow_claim = match session.job_id {
+        Some(job_id) => state::claim_follow(&project_dir, job_id)?,
+        None => None,
+    };
+
+    status!("Resuming monitoring of: {:?}", session.stdout);
+    let start_offset = ((from_last_offset || session.stopped_by.is_some()) && session.offset > 0)
+        .then_some(session.offset);
+    if let Some(offset) = start_offset {
+        match &session.stopped_by {
+            Some(signal) => status!(
+                "[INFO] Continuing from byte {} of the log, where the last tail stopped ({})",
+                offset,
+                signal
+            ),
+            None => status!(
+                "[INFO] Continuing from byte {} of the log, where the last tail stopped",
+                offset
+            ),
+        }
+    }
+    tui::start();
+    keys::start(session.job_id);
+    if let (Some(stderr_path), Some(job_id)) = (&session.stderr, session.job_id)
+        && *stderr_path != session.stdout
+    {
+        follow_stderr_in_tui(stderr_path.clone(), job_id);
+    }
+    // A running `slurmtail daemon` has kept the job's output, so it can be replayed without reading the log
+    let from_daemon = session
+        .job_id
+        .and_then(|job_id| mon_daemon_job(job_id, options, start_offset));
+    let result = match from_daemon {
+        Some(result) => result,
+        None => mon_logfile(&session.stdout, options, None, session.job_id, start_offset),
+    };
+    record_read_offset(&project_dir, &session.stdout, &result);
+    if let Some(job_id) = session.job_id {
+        if let Some(cluster) = &session.cluster {
+            scheduler().set_job_cluster(job_id, cluster);
+        }
+        if state::finish_session_if_done(&project_dir, job_id).is_some() && pager_on_exit {
+            pager::show_log(&session.stdout);
+        }
+    }
+
+    result
+}
+
+// The log file each of a job's nodes writes under an output pattern with %N or %n, prefixed with the node's name, the
+// batch host's first (only its own if the scheduler can't say which nodes the job has)
+fn node_log_targets(
+    script_path: &Path,
+    pattern: &str,
+    job_id: u64,
+    job_name: Option<&String>,
+    array_task: Option<u32>,
+    batch_host: &str,
+) -> Result<Vec<FollowTarget>, SlurmtailError> {
+    let nodes = match scheduler().job_nodes(job_id) {
+        Ok(nodes) => nodes,
+        Err(e) => {
+            warning!(
+                "[WARNING] Could not look up the nodes of job {}, so only the log file of its node {} is followed: {}",
+                job_id,
+                batch_host,
+                e
+            );
+            return Ok(Vec::new());
+        }
+    };
+
+    let mut targets: Vec<FollowTarget> = Vec::new();
+    for (index, node) in nodes.iter().enumerate() {
+        let filename = format_log_output_string(
+            with_node_index(pattern, index),
+            job_id,
+            job_name,
+            array_task,
+            Some(node),
+        );
+        let path = logfile_string_to_path(script_path, filename, true)?;
+        let target = FollowTarget {
+            prefix: node.clone(),
+            log_path: path,
+            job_id: Some(job_id),
+        };
+
+        // A node listed twice names the same file, which only needs following once
+        if targets
+            .iter()
+            .any(|other| other.log_path == target.log_path)
+        {
+            continue;
+        }
+        match *node == batch_host {
+            true => targets.insert(0, target),
+            false => targets.push(target),
+        }
+    }
+
+    Ok(targets)
+}
+
+// Wait for a job to start and return the node its batch script runs on (None if the scheduler can't tell us)
+fn wait_for_batch_host(job_id: u64) -> Option<String> {
+    status!(
+        "[INFO] Output pattern uses %N or %n; waiting for job {} to start to find out its nodes...",
+        job_id
+    );
+
+    runtime::block_on(async {
+        let mut checks = time::interval(Duration::from_secs(1));
+        loop {
+            checks.tick().await;
+            let host = runtime::blocking(move || {
+                scheduler()
+                    .batch_host(job_id)
+                    .map(|host| host.ok_or_else(|| job_is_active(job_id)))
+                    .map_err(|e| e.to_string())
+            })
+            .await;
+            match host {
+                Ok(Ok(host)) => return Some(host),
+                // Not started yet
+                Ok(Err(true)) => {}
+                Ok(Err(false)) => return None,
+                Err(e) => {
+                    warning!(
+                        "[WARNING] Could not look up the node of job {}: {}",
+                        job_id,
+                        e
+                    );
+                    return None;
+                }
+            }
+        }
+    })
+}
+
+// Counts of a job array's tasks by state, shown on a status line beneath the output of the task being followed (e.g.
+// "array 12345: 37 done, 4 running, 59 pending, 2 failed") and kept up to date until every task has ended, or this is
+// dropped
+struct ArrayProgress(tokio::task::JoinHandle<()>);
+
+impl ArrayProgress {
+    fn start(job_id: u64) -> ArrayProgress {
+        ArrayProgress(runtime::spawn(async move {
+            let mut checks = time::interval(JOB_STATE_CHECK_INTERVAL);
+            checks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let mut shown = String::new();
+            loop {
+                checks.tick().await;
+                let states = runtime::blocking(move || {
+                    scheduler()
+                        .array_task_states(job_id)
+                        .map_err(|e| e.to_string())
+                })
+                .await;
+                let states = match states {
+                    Ok(states) if !states.is_empty() => states,
+                    Ok(_) => continue,
+                    Err(e) => {
+                        tracing::debug!(error = %e, "Could not count the array's tasks");
+                        return;
+                    }
+                };
+
+                // [done, running, pending, failed]
+                let mut counts = [0; 4];
+                for state in &states {
+                    let slot = match state.as_str() {
+                        "COMPLETED" => 0,
+                        "PENDING" | "REQUEUED" => 2,
+                        state if is_active_state(state) => 1,
+                        _ => 3,
+                    };
+                    counts[slot] += 1;
+                }
+                let summary = ["done", "running", "pending", "failed"]
+                    .iter()
+                    .zip(counts)
+                    .filter(|(_, count)| *count > 0)
+                    .map(|(label, count)| format!("{} {}", count, label))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let line = format!("array {}: {}", job_id, summary);
+                if line != shown {
+                    progress::show_status_line("array", &line);
+                    output::emit(
+                        "array_progress",
+                        Some(job_id),
+                        serde_json::json!({
+                            "done": counts[0],
+                            "running": counts[1],
+                            "pending": counts[2],
+                            "failed": counts[3],
+                        }),
+                    );
+                    shown = line;
+                }
+                if counts[1] == 0 && counts[2] == 0 {
+                    return;
+                }
+            }
+        }))
+    }
+}
+
+impl Drop for ArrayProgress {
+    fn drop(&mut self) {
+        self.0.abort();
+        progress::clear_status_line("array");
+    }
+}
+
+// How busy a running job's GPUs are and how much of their memory is in use, shown on the status line beneath its output
+// (e.g. "gpus 12345: 98% 97% busy, 71.3/160.0 GiB memory") every GPU_USAGE_INTERVAL until this is dropped
+struct GpuUsageLine(tokio::task::JoinHandle<()>);
+
+impl GpuUsageLine {
+    fn start(job_id: u64) -> GpuUsageLine {
+        GpuUsageLine(runtime::spawn(async move {
+            let mut shown = String::new();
+            loop {
+                let gpus = runtime::blocking(move || {
+                    scheduler().gpu_usage(job_id).map_err(|e| e.to_string())
+                })
+                .await;
+                let gpus = match gpus {
+                    Ok(gpus) => gpus,
+                    Err(e) => {
+                        warning!(
+                            "[WARNING] Could not look up the GPU usage of job {}: {}",
+                            job_id,
+                            e
+                        );
+                        return;
+                    }
+                };
+                // Until the job is running
+                if gpus.is_empty() {
+                    time::sleep(JOB_STATE_CHECK_INTERVAL).await;
+                    continue;
+                }
+
+                let busy: Vec<String> = gpus
+                    .iter()
+                    .map(|gpu| format!("{}%", gpu.utilization))
+                    .collect();
+                let memory_used: u64 = gpus.iter().map(|gpu| gpu.memory_used).sum();
+                let memory_total: u64 = gpus.iter().map(|gpu| gpu.memory_total).sum();
+                let line = format!(
+                    "gpus {}: {} busy, {:.1}/{:.1} GiB memory",
+                    job_id,
+                    busy.join(" "),
+                    memory_used as f64 / 1024.0,
+                    memory_total as f64 / 1024.0
+                );
+                if line != shown {
+                    progress::show_status_line("gpus", &line);
+                    output::emit(
+                        "gpu_usage",
+                        Some(job_id),
+                        serde_json::json!({
+                            "gpus": gpus.len(),
+                            "utilization": gpus.iter().map(|gpu| gpu.utilization).sum::<u32>() / gpus.len() as u32,
+                            "memory_used": memory_used,
+                            "memory_total": memory_total,
+                        }),
+                    );
+                    shown = line;
+                }
+                time::sleep(GPU_USAGE_INTERVAL).await;
+            }
+        }))
+    }
+}
+
+impl Drop for GpuUsageLine {
+    fn drop(&mut self) {
+        self.0.abort();
+        progress::clear_status_line("gpus");
+    }
+}
+
+// Offer to follow a job's live output with sattach when there is no log file we can follow
+fn offer_attach(job_id: u64, step: u32) -> Result<(), SlurmtailError> {
+    if !confirm(&format!(
+        "Attach to {} instead?",
+        scheduler().attach_description(job_id, step)
+    ))? {
+        return Ok(());
+    }
+
+    scheduler().attach(job_id, step)
+}
@@ -0,0 +1,178 @@
+use serde_json::{Value, json};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::config::{WebhookConfig, WebhookFormat};
+use crate::error::SlurmtailError;
+use crate::lifecycle::{JobEvent, Milestone};
+use crate::runtime;
+
+// How many of the log's last lines a webhook gets, unless configured otherwise
+pub const DEFAULT_LINES: usize = 20;
+
+// How long to wait for a webhook to answer before giving up on it
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// How much of the log's end a chat message quotes at most (Discord cuts embeds off at 4096 characters)
+const MAX_QUOTED_CHARS: usize = 3000;
+
+// Chat message colors: started, ended, ended by being cancelled, failed
+const COLOR_START: u32 = 0x439fe0;
+const COLOR_END: u32 = 0x2eb886;
+const COLOR_CANCELLED: u32 = 0xdaa038;
+const COLOR_FAIL: u32 = 0xa30200;
+
+// A URL to POST the job's lifecycle events to, which ones, and in what form
+struct Webhook {
+    url: String,
+    format: WebhookFormat,
+    milestones: Vec<Milestone>,
+    lines: usize,
+}
+
+static WEBHOOKS: OnceLock<Vec<Webhook>> = OnceLock::new();
+
+// POST the job's lifecycle events (it started, ended, or failed) as JSON to the webhooks in the config file, and to
+// any URLs given with --webhook (which get all of them)
+pub fn set_webhooks(configured: &[WebhookConfig], urls: &[String]) -> Result<(), SlurmtailError> {
+    let mut webhooks = Vec::new();
+    for webhook in configured {
+        let milestones = webhook
+            .events
+            .iter()
+            .map(|event| {
+                Milestone::from_name(event).ok_or_else(|| {
+                    SlurmtailError::Invalid(format!(
+                        "Invalid event {:?} for webhook {}: expected start, end, or fail",
+                        event, webhook.url
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        webhooks.push(Webhook {
+            url: webhook.url.clone(),
+            format: webhook.format,
+            milestones,
+            lines: webhook.lines,
+        });
+    }
+    webhooks.extend(urls.iter().map(|url| Webhook {
+        url: url.clone(),
+        format: WebhookFormat::Json,
+        milestones: vec![Milestone::Start, Milestone::End, Milestone::Fail],
+        lines: DEFAULT_LINES,
+    }));
+
+    let _ = WEBHOOKS.set(webhooks);
+    Ok(())
+}
+
+// Post an event (see output::emit) to the webhooks that want it, if it is the job starting or finishing, in the
+// background (see runtime::deliver)
+// A webhook that can't be reached is warned about, and doesn't stop slurmtail
+pub fn post_event(kind: &str, job_id: Option<u64>, fields: &Value) {
+    let Some(webhooks) = WEBHOOKS.get() else {
+        return;
+    };
+    let (Some(job_id), Some(state)) = (job_id, fields.get("state").and_then(Value::as_str)) else {
+        return;
+    };
+    let Some(milestone) = Milestone::from_state(state).filter(|_| kind == "state") else {
+        return;
+    };
+    let wanting: Vec<&'static Webhook> = webhooks
+        .iter()
+        .filter(|webhook| milestone.wanted_by(&webhook.milestones))
+        .collect();
+    if wanting.is_empty() {
+        return;
+    }
+
+    let state = state.to_string();
+    runtime::deliver(move || post(&wanting, milestone, job_id, &state));
+}
+
+fn post(webhooks: &[&Webhook], milestone: Milestone, job_id: u64, state: &str) {
+    let event = JobEvent::gather(milestone, job_id, state);
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(TIMEOUT))
+        .build()
+        .into();
+    for webhook in webhooks {
+        tracing::debug!(url = %webhook.url, event = milestone.name(), "Posting to webhook");
+        let payload = match webhook.format {
+            WebhookFormat::Json => event.payload(webhook.lines),
+            WebhookFormat::Slack => slack_payload(&event, webhook.lines),
+            WebhookFormat::Discord => discord_payload(&event, webhook.lines),
+        };
+        if let Err(e) = agent.post(&webhook.url).send_json(payload) {
+            warning!(
+                "[WARNING] Could not post job {}'s {} event to webhook {}: {}",
+                job_id,
+                milestone.name(),
+                webhook.url,
+                e
+            );
+        }
+    }
+}
+
+// A message for a Slack incoming webhook: the summary, with an attachment colored by how the job is doing
+fn slack_payload(event: &JobEvent, lines: usize) -> Value {
+    json!({
+        "text": format!("*{}*", event.headline()),
+        "attachments": [{
+            "color": format!("#{:06x}", color(event)),
+            "text": details(event, lines, "*"),
+            "mrkdwn_in": ["text"],
+        }],
+    })
+}
+
+// A message for a Discord webhook: the summary as an embed, colored by how the job is doing
+fn discord_payload(event: &JobEvent, lines: usize) -> Value {
+    json!({
+        "username": "slurmtail",
+        "embeds": [{
+            "title": event.headline(),
+            "description": details(event, lines, "**"),
+            "color": color(event),
+        }],
+    })
+}
+
+// The summary's body in chat markdown (`bold` being how the chat marks bold text): the exit code, where the log is,
+// and the end of the log in a code block
+fn details(event: &JobEvent, lines: usize, bold: &str) -> String {
+    let mut details = Vec::new();
+    if let Some(exit_code) = &event.exit_code {
+        details.push(format!("{b}Exit code:{b} {}", exit_code, b = bold));
+    }
+    if let Some(log_path) = &event.log_path {
+        details.push(format!("{b}Log:{b} `{}`", log_path.display(), b = bold));
+    }
+
+    let lines = event.last_lines(lines);
+    if !lines.is_empty() {
+        // A line of the log can't be allowed to end the code block early
+        let mut quoted = lines.join("\n").replace("```", "'''");
+        if quoted.len() > MAX_QUOTED_CHARS {
+            let mut cut = quoted.len() - MAX_QUOTED_CHARS;
+            while !quoted.is_char_boundary(cut) {
+                cut += 1;
+            }
+            quoted = format!("...{}", &quoted[cut..]);
+        }
+        details.push(format!("```\n{}\n```", quoted));
+    }
+    details.join("\n")
+}
+
+fn color(event: &JobEvent) -> u32 {
+    match event.milestone {
+        Milestone::Start => COLOR_START,
+        Milestone::Fail => COLOR_FAIL,
+        Milestone::End if event.state.starts_with("CANCELLED") => COLOR_CANCELLED,
+        Milestone::End => COLOR_END,
+    }
+}
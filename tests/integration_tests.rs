@@ -1,3 +1,6 @@
+// The original tests pass borrowed argument arrays and nested ifs; keep them as written.
+#![allow(clippy::needless_borrows_for_generic_args, clippy::collapsible_if)]
+
 use std::fs;
 use std::path::PathBuf;
 use std::process::Command;
@@ -165,6 +168,46 @@ echo "Test job completed at $(date)"
     script_path
 }
 
+// A webhook receiver on a local port that keeps the body of every request it gets; returns its URL and the bodies
+fn start_webhook_receiver() -> (String, std::sync::Arc<std::sync::Mutex<Vec<String>>>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to listen");
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let bodies = Arc::new(Mutex::new(Vec::new()));
+    let received = Arc::clone(&bodies);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                if reader.read_line(&mut header).unwrap_or(0) == 0 || header == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':')
+                    && name.eq_ignore_ascii_case("content-length")
+                {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+            }
+            let mut body = vec![0; content_length];
+            let _ = reader.read_exact(&mut body);
+            received
+                .lock()
+                .unwrap()
+                .push(String::from_utf8_lossy(&body).into_owned());
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        }
+    });
+
+    (url, bodies)
+}
+
 #[test]
 fn test_run_command_basic() -> Result<(), Box<dyn std::error::Error>> {
     // Create temporary directory for this test
@@ -199,7 +242,7 @@ fn test_run_command_basic() -> Result<(), Box<dyn std::error::Error>> {
 
     // Job should have been submitted
     assert!(
-        stdout.contains("Job submitted with ID:"),
+        stderr.contains("Job submitted with ID:"),
         "Should submit job successfully: stdout={}, stderr={}",
         stdout,
         stderr
@@ -252,7 +295,7 @@ fn test_run_command_with_job_name() -> Result<(), Box<dyn std::error::Error>> {
 
     // Job should have been submitted
     assert!(
-        stdout.contains("Job submitted with ID:"),
+        stderr.contains("Job submitted with ID:"),
         "Should submit job successfully: stdout={}, stderr={}",
         stdout,
         stderr
@@ -313,7 +356,7 @@ fn test_run_command_jobname() -> Result<(), Box<dyn std::error::Error>> {
 
     // Job should have been submitted
     assert!(
-        stdout.contains("Job submitted with ID:"),
+        stderr.contains("Job submitted with ID:"),
         "Should submit job successfully: stdout={}, stderr={}",
         stdout,
         stderr
@@ -533,7 +576,7 @@ fn test_no_file_timeout_flag() -> Result<(), Box<dyn std::error::Error>> {
 
     // Job should have been submitted
     assert!(
-        stdout.contains("Job submitted with ID:"),
+        stderr.contains("Job submitted with ID:"),
         "Should submit job successfully: stdout={}, stderr={}",
         stdout,
         stderr
@@ -591,7 +634,7 @@ fn test_no_bytes_timeout_flag() -> Result<(), Box<dyn std::error::Error>> {
 
     // Job should have been submitted
     assert!(
-        stdout.contains("Job submitted with ID:"),
+        stderr.contains("Job submitted with ID:"),
         "Should submit job successfully: stdout={}, stderr={}",
         stdout,
         stderr
@@ -609,3 +652,6199 @@ fn test_no_bytes_timeout_flag() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_pipeline_rejects_dependency_cycle() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    create_test_script(&temp_dir);
+
+    let manifest_path = temp_dir.path().join("pipeline.yaml");
+    fs::write(
+        &manifest_path,
+        r#"jobs:
+  - name: first
+    script: test_job.sh
+    depends_on: [second]
+  - name: second
+    script: test_job.sh
+    depends_on: [first]
+"#,
+    )
+    .expect("Failed to create pipeline manifest");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["pipeline", "run", manifest_path.to_str().unwrap()])
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail pipeline");
+
+    assert!(
+        !output.status.success(),
+        "Should fail with a dependency cycle"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("dependency cycle"),
+        "Should report the dependency cycle: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_pipeline_toml_unknown_dependency() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    create_test_script(&temp_dir);
+
+    let manifest_path = temp_dir.path().join("pipeline.toml");
+    fs::write(
+        &manifest_path,
+        r#"[[jobs]]
+name = "train"
+script = "test_job.sh"
+depends_on = ["preprocess"]
+"#,
+    )
+    .expect("Failed to create pipeline manifest");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["pipeline", "run", manifest_path.to_str().unwrap()])
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail pipeline");
+
+    assert!(
+        !output.status.success(),
+        "Should fail with an unknown dependency"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("unknown job 'preprocess'"),
+        "Should name the unknown dependency: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_resume_with_array_details() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let test_log_path = temp_dir.path().join("array_output.12345_0.log");
+    let resume_dir = temp_dir.path().join("._slurmtail");
+
+    // Create a fake log file for the first array task
+    fs::write(&test_log_path, "Array task 0 started\n").expect("Failed to create test log");
+
+    // Create a session that also records the array range
+    fs::create_dir(&resume_dir).expect("Failed to create resume directory");
+    fs::write(
+        resume_dir.join("12345.json"),
+        serde_json::json!({
+            "version": 1,
+            "job_id": 12345,
+            "cluster": null,
+            "script": null,
+            "stdout": test_log_path,
+            "stderr": null,
+            "array": "0-15%4",
+            "submitted_at": null,
+        })
+        .to_string(),
+    )
+    .expect("Failed to create resume file");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["resume", "--timeout", "1"])
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail resume");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let combined = format!("{}{}", stdout, stderr);
+
+    // The array range must not get in the way of finding the log
+    assert!(
+        combined.contains("Array task 0 started"),
+        "Should resume the first task's log: {}",
+        combined
+    );
+}
+
+#[test]
+fn test_run_confirm_declined() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script(&temp_dir);
+    let resume_file = temp_dir.path().join("._slurmtail");
+
+    let mut child = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap(), "--confirm"])
+        .current_dir(temp_dir.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run slurmtail");
+
+    // Decline the submission
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"n\n")
+        .expect("Failed to answer confirmation prompt");
+    let output = child
+        .wait_with_output()
+        .expect("Failed to wait for slurmtail");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Declining should not be an error");
+    assert!(
+        stderr.contains("Not submitting."),
+        "Should not submit after declining: {}",
+        stderr
+    );
+    assert!(
+        !resume_file.exists(),
+        "No resume file should be created when nothing was submitted"
+    );
+}
+
+#[test]
+fn test_rest_scheduler_requires_token() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script(&temp_dir);
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "rest",
+            "--rest-url",
+            "http://localhost:6820",
+        ])
+        .env_remove("SLURM_JWT")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    assert!(
+        !output.status.success(),
+        "Should fail without a slurmrestd token"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("SLURM_JWT"),
+        "Should explain that SLURM_JWT is missing: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_cancel_without_job_id() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let resume_file = temp_dir.path().join("._slurmtail");
+
+    // Legacy resume files only contain the log path
+    fs::write(&resume_file, "/some/old/log.file").expect("Failed to create resume file");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["cancel"])
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail cancel");
+
+    assert!(
+        !output.status.success(),
+        "Should fail without a recorded job ID"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("does not record a job ID"),
+        "Should indicate the job ID is missing: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_mock_scheduler_run() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    // A job that completes isn't resubmitted, however many retries are allowed
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--retries",
+            "1",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "Mock run should succeed: stdout={}, stderr={}",
+        stdout,
+        stderr
+    );
+    assert!(
+        stderr.contains("Job submitted with ID: 1000"),
+        "Should hand out the first mock job ID: {}",
+        stderr
+    );
+    assert!(
+        stdout.contains("Test job completed"),
+        "Should follow the job's output to the end: {}",
+        stdout
+    );
+    assert!(
+        stderr.contains("Job 1000 ended in state COMPLETED"),
+        "Should not resubmit a completed job: {}",
+        stderr
+    );
+    assert!(temp_dir.path().join("test_output.1000.log").exists());
+}
+
+#[test]
+fn test_mock_scheduler_retry() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    // Every mock job "fails" on a bad node, so the retry policy kicks in
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--retries",
+            "1",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("SLURMTAIL_MOCK_FINAL_STATE", "NODE_FAIL")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Job submitted with ID: 1001"),
+        "Should resubmit after NODE_FAIL: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_mock_scheduler_cancel() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    // Keep the job pending long enough to cancel it; following detaches once --max-duration is up (the file timeout
+    // would wait for the job's estimated start)
+    let _ = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--max-duration",
+            "1",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "60")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["cancel", "--scheduler", "mock"])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail cancel");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Cancelled job 1000"),
+        "Should cancel the pending mock job: {}",
+        stderr
+    );
+    assert_eq!(
+        fs::read_to_string(mock_dir.join("1000").join("state")).unwrap(),
+        "CANCELLED\n"
+    );
+    assert!(
+        !temp_dir.path().join("test_output.1000.log").exists(),
+        "A cancelled pending job should never start"
+    );
+}
+
+#[test]
+fn test_pbs_scheduler_default_output() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+
+    // Stand-ins for the PBS tools: qsub hands out a full PBS job ID, qstat reports the job as finished
+    let bin_dir = temp_dir.path().join("bin");
+    fs::create_dir(&bin_dir).expect("Failed to create bin directory");
+    for (tool, body) in [
+        ("qsub", "echo 4242.pbs-server.example.org"),
+        (
+            "qstat",
+            "echo '    job_state = C'; echo '    exit_status = 0'",
+        ),
+    ] {
+        let tool_path = bin_dir.join(tool);
+        fs::write(&tool_path, format!("#!/bin/sh\n{}\n", body)).expect("Failed to create tool");
+        fs::set_permissions(&tool_path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    // No "#PBS -o", so the output lands in "<job name>.o<job number>"
+    let script_path = temp_dir.path().join("pbs_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#PBS -N pbs_test -l nodes=1:ppn=1\n\necho hello\n",
+    )
+    .expect("Failed to create test script");
+    fs::write(temp_dir.path().join("pbs_test.o4242"), "Hello from PBS\n")
+        .expect("Failed to create log file");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "pbs",
+            "--timeout",
+            "1",
+        ])
+        .env("PATH", path)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Job submitted with ID: 4242"),
+        "Should parse the PBS job ID: {}",
+        stderr
+    );
+    assert!(
+        stdout.contains("Hello from PBS"),
+        "Should follow the default PBS output file: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_lsf_scheduler_auto_detected() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+
+    // Stand-ins for the LSF tools; with only these on PATH, --scheduler auto has to pick LSF
+    let bin_dir = temp_dir.path().join("bin");
+    fs::create_dir(&bin_dir).expect("Failed to create bin directory");
+    for (tool, body) in [
+        (
+            "bsub",
+            "echo 'Job <5151> is submitted to default queue <normal>.'",
+        ),
+        (
+            "bjobs",
+            "echo '5151    user    DONE  normal     login01     node01      lsf_test   Jan  1 10:00'",
+        ),
+    ] {
+        let tool_path = bin_dir.join(tool);
+        fs::write(&tool_path, format!("#!/bin/sh\n{}\n", body)).expect("Failed to create tool");
+        fs::set_permissions(&tool_path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    let script_path = temp_dir.path().join("lsf_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#BSUB -J lsf_test\n#BSUB -o %J.out\n\necho hello\n",
+    )
+    .expect("Failed to create test script");
+    fs::write(temp_dir.path().join("5151.out"), "Hello from LSF\n")
+        .expect("Failed to create log file");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap(), "--timeout", "1"])
+        .env("PATH", &bin_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Job submitted with ID: 5151"),
+        "Should submit through bsub: {}",
+        stderr
+    );
+    assert!(
+        stdout.contains("Hello from LSF"),
+        "Should follow the %J-substituted output file: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_flux_scheduler_f58_job_id() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+
+    // Stand-in for the flux command: `flux batch` records its arguments and prints an F58 job ID
+    let bin_dir = temp_dir.path().join("bin");
+    fs::create_dir(&bin_dir).expect("Failed to create bin directory");
+    let flux_path = bin_dir.join("flux");
+    fs::write(
+        &flux_path,
+        "#!/bin/sh\ncase \"$1\" in\n  batch) echo \"$@\" > batch_args; echo ƒZRwY92z ;;\n  jobs) echo COMPLETED ;;\nesac\n",
+    )
+    .expect("Failed to create flux stand-in");
+    fs::set_permissions(&flux_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let script_path = temp_dir.path().join("flux_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#flux: -N 1\n#flux: --output=job-{{id}}.log\n\necho hello\n",
+    )
+    .expect("Failed to create test script");
+    fs::write(
+        temp_dir.path().join("job-1234567890123.log"),
+        "Hello from Flux\n",
+    )
+    .expect("Failed to create log file");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "flux",
+            "--timeout",
+            "1",
+        ])
+        .env("PATH", &bin_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Job submitted with ID: 1234567890123"),
+        "Should decode the F58 job ID: {}",
+        stderr
+    );
+    assert!(
+        stdout.contains("Hello from Flux"),
+        "Should follow the decimal-ID output file: {}",
+        stdout
+    );
+    let batch_args =
+        fs::read_to_string(temp_dir.path().join("batch_args")).expect("flux batch was not run");
+    assert!(
+        batch_args.contains("--output=job-{{id.dec}}.log"),
+        "Should ask Flux for decimal job IDs in the file name: {}",
+        batch_args
+    );
+}
+
+#[test]
+fn test_sbatch_multi_cluster_output() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let resume_file = temp_dir.path().join("._slurmtail");
+
+    // Stand-ins for sbatch (with a wrapper banner mentioning another number first) and scancel
+    let bin_dir = temp_dir.path().join("bin");
+    fs::create_dir(&bin_dir).expect("Failed to create bin directory");
+    for (tool, body) in [
+        (
+            "sbatch",
+            "echo 'Site wrapper v2: 3 jobs in queue'; echo 'Submitted batch job 777 on cluster east'",
+        ),
+        ("scancel", "echo \"$@\" > scancel_args"),
+    ] {
+        let tool_path = bin_dir.join(tool);
+        fs::write(&tool_path, format!("#!/bin/sh\n{}\n", body)).expect("Failed to create tool");
+        fs::set_permissions(&tool_path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    fs::write(temp_dir.path().join("test_output.777.log"), "Hello\n")
+        .expect("Failed to create log file");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap(), "--timeout", "1"])
+        .env("PATH", &bin_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Job submitted with ID: 777"),
+        "Should parse the job ID from the canonical line: {}",
+        stderr
+    );
+    let resume_content =
+        fs::read_to_string(resume_file.join("777.json")).expect("Failed to read resume file");
+    assert!(
+        resume_content.contains("\"cluster\": \"east\""),
+        "Resume file should record the cluster: {}",
+        resume_content
+    );
+
+    // Cancelling later has to name the cluster
+    let output = Command::new(get_slurmtail_path())
+        .args(["cancel"])
+        .env("PATH", &bin_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail cancel");
+
+    assert!(output.status.success(), "Cancel should succeed");
+    let scancel_args =
+        fs::read_to_string(temp_dir.path().join("scancel_args")).expect("scancel was not run");
+    assert_eq!(scancel_args.trim(), "-M east 777");
+}
+
+#[test]
+fn test_sattach_fallback_for_dev_null_output() {
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::Stdio;
+
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+
+    // Stand-ins for sbatch and sattach (which records the step it was asked to attach to)
+    let bin_dir = temp_dir.path().join("bin");
+    fs::create_dir(&bin_dir).expect("Failed to create bin directory");
+    for (tool, body) in [
+        ("sbatch", "echo 'Submitted batch job 888'"),
+        ("sattach", "echo \"$@\" > sattach_args"),
+    ] {
+        let tool_path = bin_dir.join(tool);
+        fs::write(&tool_path, format!("#!/bin/sh\n{}\n", body)).expect("Failed to create tool");
+        fs::set_permissions(&tool_path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    let script_path = temp_dir.path().join("quiet_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=/dev/null\n\nsrun hostname\n",
+    )
+    .expect("Failed to create test script");
+
+    let mut child = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap(), "--step", "2"])
+        .env("PATH", &bin_dir)
+        .current_dir(temp_dir.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run slurmtail");
+
+    // Accept the offer to attach
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"y\n")
+        .expect("Failed to answer attach prompt");
+    let output = child
+        .wait_with_output()
+        .expect("Failed to wait for slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "Attaching should succeed: {}",
+        stdout
+    );
+    assert!(
+        stderr.contains("goes to /dev/null"),
+        "Should explain why there is nothing to follow: {}",
+        stderr
+    );
+    let sattach_args =
+        fs::read_to_string(temp_dir.path().join("sattach_args")).expect("sattach was not run");
+    assert_eq!(sattach_args.trim(), "888.2");
+}
+
+#[test]
+fn test_sbatch_env_overrides_directives() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+
+    // Stand-in for sbatch
+    let bin_dir = temp_dir.path().join("bin");
+    fs::create_dir(&bin_dir).expect("Failed to create bin directory");
+    let sbatch_path = bin_dir.join("sbatch");
+    fs::write(&sbatch_path, "#!/bin/sh\necho 'Submitted batch job 999'\n")
+        .expect("Failed to create sbatch stand-in");
+    fs::set_permissions(&sbatch_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+    // The environment wins over the script's own --output and --job-name, just like with sbatch
+    let output = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap(), "--timeout", "1"])
+        .env("PATH", &bin_dir)
+        .env("SBATCH_OUTPUT", "env_%x.%j.log")
+        .env("SBATCH_JOB_NAME", "from_env")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("env_from_env.999.log"),
+        "Should resolve the log path from SBATCH_OUTPUT and SBATCH_JOB_NAME: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_chdir_directive_resolves_output() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+    fs::create_dir(temp_dir.path().join("work")).expect("Failed to create work directory");
+
+    // The relative --output is relative to --chdir, not to where the job was submitted from
+    let script_path = temp_dir.path().join("chdir_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --chdir=work\n#SBATCH --output=out.%j.log\n\necho \"Running in $(basename $PWD)\"\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Running in work"),
+        "Should follow the log in the --chdir directory: {}",
+        stdout
+    );
+    assert!(temp_dir.path().join("work").join("out.1000.log").exists());
+}
+
+#[test]
+fn test_default_output_without_directive() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    // No --output at all, so SLURM would write slurm-<jobid>.out in the submission directory
+    let script_path = temp_dir.path().join("plain_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n\necho 'No output directive here'\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "Should not fail without an output directive: {}",
+        stdout
+    );
+    assert!(
+        stderr.contains("slurm-1000.out") && stdout.contains("No output directive here"),
+        "Should follow the default slurm-%j.out file: {} {}",
+        stdout,
+        stderr
+    );
+}
+
+#[test]
+fn test_filename_pattern_placeholders() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    // %N needs the job's node, %6j is zero-padded, %% is a literal '%', and %q is not a placeholder at all
+    let script_path = temp_dir.path().join("pattern_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --job-name=pat\n#SBATCH --output=%x-%N-%6j-%s-%%-%q.log\n\necho 'Patterns resolved'\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("pat-localhost-001000-batch-%-%q.log"),
+        "Should expand every known placeholder: {}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Could not resolve %q"),
+        "Should warn about the unknown placeholder: {}",
+        stderr
+    );
+    assert!(
+        stdout.contains("Patterns resolved"),
+        "Should follow the resolved log file: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_directives_after_first_command_ignored() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    // sbatch stops reading directives at the first command, so the heredoc's --output doesn't count
+    let script_path = temp_dir.path().join("heredoc_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --job-name=heredoc\n\necho 'Writing a sub-script'\ncat > sub.sh <<EOF\n#SBATCH --output=wrong.log\nEOF\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("slurm-1000.out") && !stderr.contains("wrong.log"),
+        "Should ignore #SBATCH lines after the first command: {}",
+        stderr
+    );
+    assert!(
+        stdout.contains("Writing a sub-script"),
+        "Should follow the default log file: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_directive_trailing_comment_and_spacing() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    let script_path = temp_dir.path().join("commented_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH   --job-name = spaced   # short name\n#SBATCH --output=run.%x.%j.log   # main log\n\necho 'Comment stripped'\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        temp_dir.path().join("run.spaced.1000.log").exists(),
+        "Should resolve the output without the trailing comment: {}",
+        stdout
+    );
+    assert!(
+        stdout.contains("Comment stripped"),
+        "Should follow the log file: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_quoted_output_path_with_spaces() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+    fs::create_dir(temp_dir.path().join("results dir")).expect("Failed to create results dir");
+
+    let script_path = temp_dir.path().join("quoted_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --job-name 'my job'\n#SBATCH --output=\"results dir/run %j.log\"  # spaces!\n\necho 'Quoted path works'\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        temp_dir
+            .path()
+            .join("results dir")
+            .join("run 1000.log")
+            .exists(),
+        "Should resolve the quoted path with spaces: {}",
+        stdout
+    );
+    assert!(
+        stdout.contains("Quoted path works"),
+        "Should follow the log file: {}",
+        stdout
+    );
+
+    // The resume file has to carry the path intact as well (the job has finished, so it's resumed by ID)
+    let output = Command::new(get_slurmtail_path())
+        .args(["resume", "1000", "--timeout", "1"])
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail resume");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Quoted path works"),
+        "Should resume the log file: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_short_options_and_repeated_directives() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    // Like sbatch, a later directive overrides an earlier one, and short options may carry their value directly
+    let script_path = temp_dir.path().join("short_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH -N 1 --hold -J first -o first.log\n#SBATCH -ofinal_%x_%j.log -Jsecond\n\necho 'Short options work'\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        temp_dir.path().join("final_second_1000.log").exists(),
+        "Should use the last output and job name directives: {}",
+        stdout
+    );
+    assert!(
+        !temp_dir.path().join("first.log").exists(),
+        "Should not write to the overridden output file"
+    );
+    assert!(
+        stdout.contains("Short options work"),
+        "Should follow the log file: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_inspect_reports_directives() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+
+    let script_path = temp_dir.path().join("inspect_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH -J inspected --time 10:00\n#SBATCH --output=out_%j.log --frobnicate=3\n#SBATCH --time=20:00\n\necho 'Not run'\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["inspect", script_path.to_str().unwrap()])
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail inspect");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "Inspect should succeed: {}",
+        stdout
+    );
+    assert!(
+        stdout.contains("--job-name=inspected"),
+        "Should show short options by their long name: {}",
+        stdout
+    );
+    assert!(
+        stdout.contains("--frobnicate=3  [unknown option]"),
+        "Should flag unknown options: {}",
+        stdout
+    );
+    assert!(
+        stdout.contains("--time=20:00  [given more than once; the last one wins]"),
+        "Should flag duplicate options: {}",
+        stdout
+    );
+    assert!(
+        stdout.contains("Output file: out_%j.log"),
+        "Should show the output pattern: {}",
+        stdout
+    );
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["inspect", "--json", script_path.to_str().unwrap()])
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail inspect --json");
+
+    let report: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("Inspect --json should print JSON");
+    assert_eq!(report["job_name"], "inspected");
+    assert_eq!(
+        report["components"][0]["directives"][3]["option"],
+        "frobnicate"
+    );
+    assert_eq!(report["components"][0]["directives"][3]["known"], false);
+}
+
+#[test]
+fn test_crlf_line_endings() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    // A script saved with Windows line endings
+    let script_path = temp_dir.path().join("crlf_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\r\n#SBATCH --job-name=crlf\r\n#SBATCH --output=\"crlf_%x_%j.log\"\r\n\r\necho 'CRLF works'\r\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Windows (CRLF) line endings"),
+        "Should warn about the line endings: {}",
+        stderr
+    );
+    assert!(
+        temp_dir.path().join("crlf_crlf_1000.log").exists(),
+        "Should resolve the output path without a trailing carriage return: {}",
+        stdout
+    );
+    assert!(
+        stdout.contains("CRLF works"),
+        "Should follow the log file: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_resume_file_records_job_details() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+    let resume_file = temp_dir.path().join("._slurmtail");
+
+    let script_path = temp_dir.path().join("state_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=state_%j.out\n#SBATCH --error=state_%j.err\n\necho 'Recorded'\n",
+    )
+    .expect("Failed to create test script");
+
+    Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let resume_content = fs::read_to_string(resume_file.join("finished").join("1000.json"))
+        .expect("Failed to read resume file");
+    let state: serde_json::Value =
+        serde_json::from_str(&resume_content).expect("Resume file should be JSON");
+    assert_eq!(state["version"], 1);
+    assert_eq!(state["job_id"], 1000);
+    assert!(
+        state["stdout"]
+            .as_str()
+            .is_some_and(|path| path.ends_with("state_1000.out")),
+        "Should record the stdout path: {}",
+        resume_content
+    );
+    assert!(
+        state["stderr"]
+            .as_str()
+            .is_some_and(|path| path.ends_with("state_1000.err")),
+        "Should record the stderr path: {}",
+        resume_content
+    );
+    assert!(
+        state["script"]
+            .as_str()
+            .is_some_and(|path| path.ends_with("state_job.sh")),
+        "Should record the script path: {}",
+        resume_content
+    );
+    assert!(
+        state["submitted_at"].is_string(),
+        "Should record the submit time"
+    );
+    assert_eq!(
+        state["offset"],
+        "Recorded\n".len(),
+        "Should record how far the log was read"
+    );
+    assert_eq!(
+        state["final_state"], "COMPLETED",
+        "Should record the state the job ended in"
+    );
+}
+
+#[test]
+fn test_resume_files_per_job() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+    let resume_dir = temp_dir.path().join("._slurmtail");
+
+    // Two jobs submitted from the same directory: one followed until it finishes, one still running
+    for (name, extra_args) in [
+        (
+            "first",
+            &["--no-file-timeout", "--no-idle-timeout", "--timeout", "10"][..],
+        ),
+        ("second", &["--timeout", "2"][..]),
+    ] {
+        let script_path = temp_dir.path().join(format!("{}.sh", name));
+        fs::write(
+            &script_path,
+            format!(
+                "#!/bin/bash\n#SBATCH --output={}_%j.log\n\necho 'This is the {} job'\nsleep {}\n",
+                name,
+                name,
+                if name == "first" { 0 } else { 8 }
+            ),
+        )
+        .expect("Failed to create test script");
+
+        Command::new(get_slurmtail_path())
+            .args(["run", script_path.to_str().unwrap(), "--scheduler", "mock"])
+            .args(extra_args)
+            .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail");
+    }
+
+    assert!(
+        resume_dir.join("finished").join("1000.json").exists(),
+        "First job's session should be moved aside once it finished"
+    );
+    assert!(
+        resume_dir.join("1001.json").exists(),
+        "Second job should get its own resume file"
+    );
+
+    // Without a job ID, the most recent running job is resumed
+    let output = Command::new(get_slurmtail_path())
+        .args(["resume", "--scheduler", "mock", "--timeout", "1"])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail resume");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("This is the second job"),
+        "Should resume the most recent job: {}",
+        stdout
+    );
+
+    // The finished job is still resumable by its ID
+    let output = Command::new(get_slurmtail_path())
+        .args(["resume", "1000", "--scheduler", "mock", "--timeout", "1"])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail resume");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("This is the first job"),
+        "Should resume the requested job: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_named_sessions() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    // A named job, then an unnamed one
+    for (name, session_name) in [("named", Some("train-v3")), ("unnamed", None)] {
+        let script_path = temp_dir.path().join(format!("{}.sh", name));
+        fs::write(
+            &script_path,
+            format!(
+                "#!/bin/bash\n#SBATCH --output={}_%j.log\n\necho 'This is the {} job'\n",
+                name, name
+            ),
+        )
+        .expect("Failed to create test script");
+
+        let mut args = vec![
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+        ];
+        if let Some(session_name) = session_name {
+            args.extend(["--name", session_name]);
+        }
+        Command::new(get_slurmtail_path())
+            .args(&args)
+            .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail");
+    }
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["resume", "train-v3", "--timeout", "1"])
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail resume");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("This is the named job"),
+        "Should resume the session by name: {}",
+        stdout
+    );
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["resume", "no-such-session"])
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail resume");
+    assert!(!output.status.success(), "Unknown names should fail");
+}
+
+#[test]
+fn test_xdg_state_location() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+    let state_home = temp_dir.path().join("state");
+    let project_dir = temp_dir.path().join("project");
+    let other_dir = temp_dir.path().join("elsewhere");
+    fs::create_dir(&project_dir).expect("Failed to create project dir");
+    fs::create_dir(&other_dir).expect("Failed to create other dir");
+
+    let script_path = project_dir.join("xdg_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=xdg_%j.log\n\necho 'State lives elsewhere'\n",
+    )
+    .expect("Failed to create test script");
+
+    Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--state-location",
+            "xdg",
+            "--name",
+            "xdg-session",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("XDG_STATE_HOME", &state_home)
+        .current_dir(&project_dir)
+        .output()
+        .expect("Failed to run slurmtail");
+
+    assert!(
+        !project_dir.join("._slurmtail").exists(),
+        "Should not write state into the project directory"
+    );
+    assert!(
+        state_home.join("slurmtail").is_dir(),
+        "Should write state under $XDG_STATE_HOME"
+    );
+
+    // The session can be found from any directory
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "resume",
+            "xdg-session",
+            "--state-location",
+            "xdg",
+            "--timeout",
+            "1",
+        ])
+        .env("XDG_STATE_HOME", &state_home)
+        .current_dir(&other_dir)
+        .output()
+        .expect("Failed to run slurmtail resume");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("State lives elsewhere"),
+        "Should resume the session from another directory: {}",
+        stdout
+    );
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["list", "--all", "--state-location", "xdg"])
+        .env("XDG_STATE_HOME", &state_home)
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .current_dir(&other_dir)
+        .output()
+        .expect("Failed to run slurmtail list");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("xdg-session") && stdout.contains("project"),
+        "Should list the session with its project: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_session_followed_only_once() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    let script_path = temp_dir.path().join("busy_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=busy_%j.log\n\necho 'Still going'\nsleep 6\n",
+    )
+    .expect("Failed to create test script");
+
+    // One slurmtail follows the job...
+    let mut child = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .expect("Failed to run slurmtail");
+    std::thread::sleep(std::time::Duration::from_secs(3));
+
+    // ...so a second one resuming the same session backs off
+    let output = Command::new(get_slurmtail_path())
+        .args(["resume", "--timeout", "1"])
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail resume");
+    child.wait().expect("Failed to wait for slurmtail");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "Second follower should fail");
+    assert!(
+        stderr.contains("already being followed by another slurmtail"),
+        "Should explain why: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_clean_stale_sessions() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+    let resume_dir = temp_dir.path().join("._slurmtail");
+
+    // A job that is still running when slurmtail stops following it
+    let script_path = temp_dir.path().join("long_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=long_%j.log\n\necho 'Still running'\nsleep 10\n",
+    )
+    .expect("Failed to create test script");
+    Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--timeout",
+            "1",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    // A session for a job the scheduler has never heard of
+    fs::write(
+        resume_dir.join("4242.json"),
+        format!(
+            "{{\"version\": 1, \"job_id\": 4242, \"stdout\": {:?}}}",
+            temp_dir.path().join("gone.log")
+        ),
+    )
+    .expect("Failed to create resume file");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["clean", "--stale", "--scheduler", "mock"])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail clean");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Removed session for job 4242"),
+        "Should prune the unknown job: {}",
+        stdout
+    );
+    assert!(!resume_dir.join("4242.json").exists());
+    assert!(
+        resume_dir.join("1000.json").exists(),
+        "Should keep the running job's session: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_submission_history() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+    let state_dir = temp_dir.path().join("state");
+
+    let script_path = temp_dir.path().join("history_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=history_%j.log\n#SBATCH -p debug\n\necho 'Logged'\nexit 3\n",
+    )
+    .expect("Failed to create test script");
+
+    Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--name",
+            "logged",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("XDG_STATE_HOME", &state_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    assert!(state_dir.join("slurmtail").join("history.sqlite").exists());
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["history", "--json"])
+        .env("XDG_STATE_HOME", &state_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail history");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let history: serde_json::Value = serde_json::from_str(&stdout).expect("History should be JSON");
+    let submission = &history[0];
+    assert_eq!(submission["job_id"], 1000);
+    assert_eq!(submission["name"], "logged");
+    assert_eq!(
+        submission["directives"],
+        serde_json::json!([["output", "history_%j.log"], ["partition", "debug"]]),
+        "Should snapshot the directives: {}",
+        stdout
+    );
+    assert_eq!(
+        submission["final_state"], "FAILED",
+        "Should record how the job ended: {}",
+        stdout
+    );
+    assert!(
+        submission["accounting"]
+            .as_array()
+            .is_some_and(|fields| fields.contains(&serde_json::json!(["ExitCode", "3:0"]))),
+        "Should record the job's accounting fields: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_resume_by_job_id_from_offset() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    // Two jobs that are still running when slurmtail stops following them
+    let script_path = temp_dir.path().join("offset_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=offset_%j.log\n\necho 'First'\nsleep 8\necho 'Later'\nsleep 10\n",
+    )
+    .expect("Failed to create test script");
+    for _ in 0..2 {
+        Command::new(get_slurmtail_path())
+            .args([
+                "run",
+                script_path.to_str().unwrap(),
+                "--scheduler",
+                "mock",
+                "--timeout",
+                "1",
+            ])
+            .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail");
+    }
+
+    // Wait for the first job to write past where the tail stopped
+    let log_path = temp_dir.path().join("offset_1000.log");
+    for _ in 0..40 {
+        if fs::read_to_string(&log_path).is_ok_and(|log| log.contains("Later")) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(500));
+    }
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "resume",
+            "--job",
+            "1000",
+            "--scheduler",
+            "mock",
+            "--timeout",
+            "1",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail resume");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("offset_1000.log"),
+        "Should resume the requested job rather than the latest: {}",
+        stderr
+    );
+    assert!(
+        stdout.contains("Later") && !stdout.contains("First"),
+        "Should continue from where the last tail stopped: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_clean_all_state() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_dir = temp_dir.path().join("project");
+    let state_dir = temp_dir.path().join("state");
+    let resume_dir = project_dir.join("._slurmtail");
+    let xdg_dir = state_dir.join("slurmtail");
+
+    fs::create_dir_all(&resume_dir).expect("Failed to create resume dir");
+    fs::write(resume_dir.join("1000.json"), "{}").expect("Failed to create resume file");
+    fs::create_dir_all(xdg_dir.join("0123456789abcdef")).expect("Failed to create state dir");
+    fs::write(xdg_dir.join("history.sqlite"), "").expect("Failed to create history");
+
+    // Without confirmation nothing is removed
+    let output = Command::new(get_slurmtail_path())
+        .args(["clean", "--all"])
+        .env("XDG_STATE_HOME", &state_dir)
+        .current_dir(&project_dir)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("Failed to run slurmtail clean");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Nothing removed"),
+        "Should ask before removing anything: {}",
+        stdout
+    );
+    assert!(resume_dir.exists() && xdg_dir.exists());
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["clean", "--all", "--yes"])
+        .env("XDG_STATE_HOME", &state_dir)
+        .current_dir(&project_dir)
+        .output()
+        .expect("Failed to run slurmtail clean");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        !resume_dir.exists(),
+        "Should remove the project's sessions: {}",
+        stdout
+    );
+    assert!(
+        !xdg_dir.exists(),
+        "Should remove the sessions and history under $XDG_STATE_HOME: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_interrupted_state_write_is_ignored() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+    let resume_dir = temp_dir.path().join("._slurmtail");
+
+    // What a write cut short by a crash leaves behind: a truncated temp file next to the real one
+    fs::create_dir_all(&resume_dir).expect("Failed to create resume dir");
+    fs::write(resume_dir.join(".1000.json.tmp-99999"), "{\"vers")
+        .expect("Failed to create temp file");
+
+    let script_path = temp_dir.path().join("atomic_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=atomic_%j.log\n\necho 'Saved'\nsleep 5\n",
+    )
+    .expect("Failed to create test script");
+    Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--timeout",
+            "1",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let session: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(resume_dir.join("1000.json")).expect("Failed to read resume file"),
+    )
+    .expect("Resume file should be complete JSON");
+    assert_eq!(session["job_id"], 1000);
+
+    let leftovers: Vec<_> = fs::read_dir(&resume_dir)
+        .expect("Failed to read resume dir")
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.contains(".tmp-") && name != ".1000.json.tmp-99999")
+        .collect();
+    assert!(
+        leftovers.is_empty(),
+        "Writes should not leave temp files behind: {:?}",
+        leftovers
+    );
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["list", "--scheduler", "mock"])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail list");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success() && stdout.contains("job 1000"),
+        "A stray temp file should not get in the way: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_json_event_output() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "--format",
+            "json",
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Every line should be a JSON event"))
+        .collect();
+    assert!(
+        events
+            .iter()
+            .all(|event| event["type"].is_string() && event["timestamp"].is_string()),
+        "Every event should have a type and timestamp: {}",
+        stdout
+    );
+
+    let of_type = |kind: &str| {
+        events
+            .iter()
+            .filter(|event| event["type"] == kind)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(of_type("submitted")[0]["job_id"], 1000);
+    assert!(
+        of_type("line").iter().any(|event| event["line"]
+            .as_str()
+            .is_some_and(|line| line.starts_with("Test job completed"))
+            && event["job_id"] == 1000),
+        "Should report the job's output line by line: {}",
+        stdout
+    );
+    assert!(
+        of_type("state")
+            .iter()
+            .any(|event| event["state"] == "COMPLETED"),
+        "Should report the job's state changes: {}",
+        stdout
+    );
+    let exit = events.last().expect("Should emit events");
+    assert_eq!(exit["type"], "exit", "Should end with an exit event");
+    assert_eq!(exit["code"], 0);
+}
+
+#[test]
+fn test_porcelain_output() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "--porcelain",
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let records: Vec<Vec<&str>> = stdout
+        .lines()
+        .map(|line| line.split('\t').collect())
+        .collect();
+    assert!(
+        !stdout.contains("[INFO]"),
+        "Should leave out status messages: {}",
+        stdout
+    );
+    assert_eq!(records[0][..2], ["submitted", "1000"], "{}", stdout);
+    assert!(
+        records.iter().any(|record| record[..3]
+            == [
+                "log_file",
+                "1000",
+                temp_dir
+                    .path()
+                    .join("test_output.1000.log")
+                    .to_str()
+                    .unwrap()
+            ]),
+        "Should report the log path: {}",
+        stdout
+    );
+    assert!(
+        records
+            .iter()
+            .any(|record| record[..3] == ["line", "1000", "-"]
+                && record[3].starts_with("Test job completed")),
+        "Should report the job's output: {}",
+        stdout
+    );
+    assert!(
+        records
+            .iter()
+            .any(|record| record[..3] == ["state", "1000", "COMPLETED"]),
+        "Should report state changes: {}",
+        stdout
+    );
+    assert_eq!(records.last().unwrap(), &["exit", "-", "0"]);
+}
+
+#[test]
+fn test_tee_output_to_file() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    for (tee_file, mode) in [("everything.txt", "all"), ("job_only.txt", "output")] {
+        Command::new(get_slurmtail_path())
+            .args([
+                "run",
+                script_path.to_str().unwrap(),
+                "--scheduler",
+                "mock",
+                "--no-file-timeout",
+                "--no-idle-timeout",
+                "--timeout",
+                "10",
+                "--tee",
+                tee_file,
+                "--tee-mode",
+                mode,
+            ])
+            .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail");
+    }
+
+    let everything = fs::read_to_string(temp_dir.path().join("everything.txt"))
+        .expect("Failed to read tee file");
+    assert!(
+        everything.contains("Job submitted with ID: 1000")
+            && everything.contains("Test job completed"),
+        "Should copy everything slurmtail prints: {}",
+        everything
+    );
+
+    let job_only =
+        fs::read_to_string(temp_dir.path().join("job_only.txt")).expect("Failed to read tee file");
+    assert!(
+        job_only.starts_with("Test job started") && job_only.contains("Test job completed"),
+        "Should copy the job's output: {}",
+        job_only
+    );
+    assert!(
+        !job_only.contains("[INFO]") && !job_only.contains("Job submitted"),
+        "Should copy only the job's output: {}",
+        job_only
+    );
+}
+
+#[test]
+fn test_quiet_prints_only_job_output() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+            "--quiet",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "Should succeed: {:?}", output);
+    assert!(
+        stdout.starts_with("Test job started") && stdout.contains("Test job completed"),
+        "Should print the job's output: {}",
+        stdout
+    );
+    assert!(
+        !stdout.contains("[INFO]") && !stdout.contains("[DEBUG]") && !stdout.contains("Submitting"),
+        "Should print nothing but the job's output: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_status_messages_go_to_stderr() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+            "-v",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stdout.starts_with("Test job started") && !stdout.contains("Job submitted"),
+        "Should keep stdout for the job's output: {}",
+        stdout
+    );
+    assert!(
+        stderr.contains("Job submitted with ID: 1000") && stderr.contains("[DEBUG] Reading"),
+        "Should print status and (with -v) debug messages to stderr: {}",
+        stderr
+    );
+    assert!(
+        !stderr.contains("[TRACE]"),
+        "Should only trace with -vv: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_highlight_rules_are_checked() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let config_dir = temp_dir.path().join("config").join("slurmtail");
+    fs::create_dir_all(&config_dir).expect("Failed to create config directory");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["list", "--highlight", "NaN:purple"])
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        !output.status.success() && stderr.contains("Unknown style") && stderr.contains("purple"),
+        "Should reject an unknown style: {}",
+        stderr
+    );
+
+    fs::write(
+        config_dir.join("config.toml"),
+        "[[highlight]]\npattern = \"loss=(\"\nstyle = \"bold green\"\n",
+    )
+    .expect("Failed to write config file");
+    let output = Command::new(get_slurmtail_path())
+        .args(["list", "--highlight", "NaN:bold red"])
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        !output.status.success() && stderr.contains("Invalid highlight pattern"),
+        "Should reject an invalid pattern from the config file: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_grep_filters() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    let script_path = temp_dir.path().join("noisy_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=noisy_%j.log\n\necho 'epoch 1 loss=0.9'\necho 'debug: cache warm'\necho 'epoch 2 loss=0.5'\necho 'epoch 2 debug: checkpoint'\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+            "--grep",
+            "^epoch",
+            "--grep-v",
+            "debug",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        stdout, "epoch 1 loss=0.9\nepoch 2 loss=0.5\n",
+        "Should only show matching lines that aren't excluded"
+    );
+}
+
+#[test]
+fn test_until_pattern_stops_following() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    let script_path = temp_dir.path().join("milestone_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=milestone_%j.log\n\necho 'Training...'\necho 'Final results written'\nsleep 30\necho 'Cleaning up'\n",
+    )
+    .expect("Failed to create test script");
+
+    let started = std::time::Instant::now();
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--timeout",
+            "60",
+            "--until-pattern",
+            "^Final results",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "Should succeed: {:?}", output);
+    assert!(
+        started.elapsed() < std::time::Duration::from_secs(20),
+        "Should stop without waiting for the job to finish"
+    );
+    assert!(
+        stdout.contains("Final results written") && !stdout.contains("Cleaning up"),
+        "Should stop at the matching line: {}",
+        stdout
+    );
+
+    // Don't leave the mock job running
+    Command::new(get_slurmtail_path())
+        .args(["cancel", "--scheduler", "mock"])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+}
+
+#[test]
+fn test_fail_on_pattern_cancels_job() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    let script_path = temp_dir.path().join("oom_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=oom_%j.log\n\necho 'epoch 1 done'\necho 'RuntimeError: CUDA out of memory'\nsleep 30\necho 'epoch 2 done'\n",
+    )
+    .expect("Failed to create test script");
+
+    let started = std::time::Instant::now();
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--timeout",
+            "60",
+            "--succeed-on",
+            "epoch 2 done",
+            "--fail-on",
+            "CUDA out of memory",
+            "--cancel-on-fail",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "Should fail: {:?}", output);
+    assert!(
+        started.elapsed() < std::time::Duration::from_secs(20),
+        "Should stop without waiting for the job to finish"
+    );
+    assert!(
+        stderr.contains("--fail-on") && stderr.contains("Cancelled job 1000"),
+        "Should report the failure and cancel the job: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_color_choice() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    let script_path = temp_dir.path().join("failing_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=failing_%j.log\n\necho 'loss=0.5'\necho 'ERROR: disk full'\n",
+    )
+    .expect("Failed to create test script");
+
+    let run = |args: &[&str], env: &[(&str, &str)]| {
+        let output = Command::new(get_slurmtail_path())
+            .args([
+                "run",
+                script_path.to_str().unwrap(),
+                "--scheduler",
+                "mock",
+                "--no-file-timeout",
+                "--no-idle-timeout",
+                "--timeout",
+                "10",
+                "--tee",
+                "copy.log",
+            ])
+            .args(args)
+            .env_remove("NO_COLOR")
+            .env_remove("CLICOLOR_FORCE")
+            .envs(env.iter().copied())
+            .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail");
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    let stdout = run(&["--color", "always", "--highlight", "loss=:bold"], &[]);
+    assert!(
+        stdout.contains("\x1b[31mERROR: disk full\x1b[0m")
+            && stdout.contains("\x1b[1mloss=\x1b[0m0.5"),
+        "Should color errors and highlights: {:?}",
+        stdout
+    );
+    let copy =
+        fs::read_to_string(temp_dir.path().join("copy.log")).expect("Failed to read tee file");
+    assert!(
+        copy.contains("ERROR: disk full") && !copy.contains('\x1b'),
+        "Should leave the --tee copy uncolored: {:?}",
+        copy
+    );
+
+    let stdout = run(&[], &[]);
+    assert!(
+        !stdout.contains('\x1b'),
+        "Should not color output that isn't going to a terminal: {:?}",
+        stdout
+    );
+
+    let stdout = run(&[], &[("CLICOLOR_FORCE", "1")]);
+    assert!(
+        stdout.contains("\x1b[31m"),
+        "Should color when $CLICOLOR_FORCE is set: {:?}",
+        stdout
+    );
+
+    let stdout = run(&[], &[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")]);
+    assert!(
+        !stdout.contains('\x1b'),
+        "Should not color when $NO_COLOR is set: {:?}",
+        stdout
+    );
+}
+
+#[test]
+fn test_exit_codes() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    let run = |script: &str, timeout: &str, final_state: &str| {
+        let script_path = temp_dir.path().join("exit_job.sh");
+        fs::write(
+            &script_path,
+            format!("#!/bin/bash\n#SBATCH --output=exit_%j.log\n\n{}\n", script),
+        )
+        .expect("Failed to create test script");
+
+        let output = Command::new(get_slurmtail_path())
+            .args([
+                "run",
+                script_path.to_str().unwrap(),
+                "--scheduler",
+                "mock",
+                "--timeout",
+                timeout,
+            ])
+            .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .env("SLURMTAIL_MOCK_FINAL_STATE", final_state)
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail");
+        output.status.code()
+    };
+
+    assert_eq!(run("echo 'all good'", "10", ""), Some(0), "A completed job");
+    assert_eq!(
+        run("echo 'failing'; exit 3", "10", ""),
+        Some(13),
+        "A failed job"
+    );
+    assert_eq!(
+        run("echo 'cancelled'", "10", "CANCELLED"),
+        Some(14),
+        "A cancelled job"
+    );
+    assert_eq!(
+        run("echo 'stuck'; sleep 20", "2", ""),
+        Some(10),
+        "No output for longer than the timeout"
+    );
+}
+
+#[test]
+fn test_transcript_header_and_footer() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+            "--tee",
+            "transcript.txt",
+            "--tee-mode",
+            "output",
+            "--transcript",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("SBATCH_PARTITION", "debug")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let transcript = fs::read_to_string(temp_dir.path().join("transcript.txt"))
+        .expect("Failed to read transcript");
+    let (header, rest) = transcript
+        .split_once("Test job started")
+        .expect("Transcript should contain the job's output");
+    assert!(
+        header.contains("Job ID:    1000")
+            && header.contains("test_job.sh")
+            && header.contains("Submitted: ")
+            && header.contains("Overrides: SBATCH_PARTITION=debug"),
+        "Should start with a header describing the job: {}",
+        transcript
+    );
+    assert!(
+        rest.contains("===== job 1000 finished: COMPLETED =====")
+            && rest.contains("ExitCode:  0:0"),
+        "Should end with the final state and accounting: {}",
+        transcript
+    );
+}
+
+#[test]
+fn test_syslog_events() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    // Stand in for the syslog daemon
+    let socket_path = temp_dir.path().join("log.sock");
+    let socket =
+        std::os::unix::net::UnixDatagram::bind(&socket_path).expect("Failed to bind syslog socket");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--syslog",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("SLURMTAIL_SYSLOG_SOCKET", &socket_path)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    assert!(output.status.success(), "slurmtail should succeed");
+
+    socket
+        .set_nonblocking(true)
+        .expect("Failed to make syslog socket non-blocking");
+    let mut records = Vec::new();
+    let mut buffer = [0; 4096];
+    while let Ok(length) = socket.recv(&mut buffer) {
+        records.push(String::from_utf8_lossy(&buffer[..length]).to_string());
+    }
+
+    assert!(
+        records
+            .iter()
+            .any(|record| record.starts_with("<13>slurmtail[") && record.contains("submitted")),
+        "The submission should be logged at notice level: {:?}",
+        records
+    );
+    assert!(
+        records
+            .iter()
+            .any(|record| record.contains("finished: COMPLETED")),
+        "The job finishing should be logged: {:?}",
+        records
+    );
+    assert!(
+        records
+            .iter()
+            .any(|record| record.contains("Exiting with code 0")),
+        "slurmtail exiting should be logged: {:?}",
+        records
+    );
+    assert!(
+        !records
+            .iter()
+            .any(|record| record.contains("Test job started")),
+        "The job's output should not be logged: {:?}",
+        records
+    );
+}
+
+#[test]
+fn test_rust_log_diagnostics() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap(), "--scheduler", "mock"])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("RUST_LOG", "slurmtail::scheduler=debug")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("[DEBUG] close time.busy=") && stderr.contains("submit{scheduler=\"mock\""),
+        "Should time the submission: {}",
+        stderr
+    );
+    assert!(
+        !stderr.contains("[DEBUG] Reading") && !stderr.contains("[TRACE]"),
+        "Should only show what RUST_LOG selects: {}",
+        stderr
+    );
+    assert!(
+        !stdout.contains("[DEBUG]"),
+        "Should keep diagnostics off stdout: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_tui_needs_terminal() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    // stdout is a pipe here, so the interface can't be shown
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--tui",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        !output.status.success(),
+        "--tui without a terminal should fail"
+    );
+    assert!(
+        stderr.contains("--tui needs a terminal"),
+        "Should explain why: {}",
+        stderr
+    );
+    assert!(
+        !mock_dir.exists() || fs::read_dir(&mock_dir).unwrap().next().is_none(),
+        "Should not submit the job"
+    );
+}
+
+#[test]
+fn test_interrupt_cancels_job() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    // Keep the job pending, so slurmtail is still waiting for its log file when Ctrl-C comes
+    let child = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--on-interrupt",
+            "cancel",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "60")
+        .current_dir(temp_dir.path())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to run slurmtail");
+
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .expect("Failed to send SIGINT");
+    let output = child
+        .wait_with_output()
+        .expect("Failed to wait for slurmtail");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(14),
+        "Should exit as for a cancelled job: {}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Cancelled job 1000"),
+        "Should cancel the job: {}",
+        stderr
+    );
+    assert_eq!(
+        fs::read_to_string(mock_dir.join("1000").join("state")).unwrap(),
+        "CANCELLED\n"
+    );
+}
+
+#[test]
+fn test_sigterm_saves_read_offset() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("slow_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=slow.%j.log\nfor i in $(seq 1 30); do echo \"line $i\"; sleep 0.2; done\n",
+    )
+    .expect("Failed to create test script");
+    let mock_dir = temp_dir.path().join("mock");
+
+    let child = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap(), "--scheduler", "mock"])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to run slurmtail");
+
+    std::thread::sleep(std::time::Duration::from_secs(3));
+    Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .expect("Failed to send SIGTERM");
+    let output = child
+        .wait_with_output()
+        .expect("Failed to wait for slurmtail");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(143),
+        "Should exit with 128 + SIGTERM: {}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Stopped by SIGTERM"),
+        "Should say why it stopped: {}",
+        stderr
+    );
+
+    // The session remembers exactly how much of the log was shown
+    let session: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(temp_dir.path().join("._slurmtail").join("1000.json"))
+            .expect("Session should be kept"),
+    )
+    .unwrap();
+    assert_eq!(session["stopped_by"], "SIGTERM");
+    assert_eq!(session["offset"], output.stdout.len() as u64);
+    assert!(!output.stdout.is_empty(), "Should have shown some output");
+}
+
+#[test]
+fn test_pager_on_exit_needs_terminal() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    // A "pager" that leaves a mark if it is ever run
+    let marker = temp_dir.path().join("paged");
+    let pager = temp_dir.path().join("pager.sh");
+    fs::write(&pager, format!("#!/bin/sh\ntouch {:?}\n", marker)).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&pager, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    // stdout is a pipe here, so the job finishes without a pager
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--pager-on-exit",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("PAGER", &pager)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Job should complete: {}", stderr);
+    assert!(
+        !marker.exists(),
+        "Should not open a pager without a terminal"
+    );
+}
+
+#[test]
+fn test_tail_command() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let log_path = temp_dir.path().join("some.log");
+    fs::write(&log_path, "first line\nsecond line\n").expect("Failed to create log file");
+
+    // No job to watch, so following ends when nothing new shows up
+    let output = Command::new(get_slurmtail_path())
+        .args(["tail", "--timeout", "1", log_path.to_str().unwrap()])
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail tail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, "first line\nsecond line\n");
+    assert_eq!(
+        output.status.code(),
+        Some(10),
+        "Should stop on the idle timeout"
+    );
+}
+
+#[test]
+fn test_tmux_needs_tmux() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--tmux",
+        ])
+        .env_remove("TMUX")
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "--tmux outside tmux should fail");
+    assert!(
+        stderr.contains("--tmux needs slurmtail to run inside a tmux session"),
+        "Should explain why: {}",
+        stderr
+    );
+    assert!(!mock_dir.exists(), "Should not submit the job");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_notify_on_start_and_finish() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    // A notify-send that writes down each notification instead of showing it
+    let bin_dir = temp_dir.path().join("bin");
+    let notifications = temp_dir.path().join("notifications");
+    fs::create_dir(&bin_dir).unwrap();
+    let notify_send = bin_dir.join("notify-send");
+    fs::write(
+        &notify_send,
+        format!("#!/bin/sh\necho \"$@\" >> {:?}\n", notifications),
+    )
+    .unwrap();
+    fs::set_permissions(&notify_send, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--notify",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("PATH", path)
+        .env("DISPLAY", ":0")
+        .env_remove("SSH_CONNECTION")
+        .env_remove("SSH_TTY")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    assert!(
+        output.status.success(),
+        "Job should complete: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Notifications are sent in the background
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    let sent = fs::read_to_string(&notifications).unwrap_or_default();
+    assert!(
+        sent.contains("Job 1000 started"),
+        "Should notify when the job starts: {}",
+        sent
+    );
+    assert!(
+        sent.contains("Job 1000 finished: COMPLETED"),
+        "Should notify when the job finishes: {}",
+        sent
+    );
+}
+
+#[test]
+fn test_webhook_posts_lifecycle_events() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    let (url, bodies) = start_webhook_receiver();
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--webhook",
+            &url,
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Job should complete: {}", stderr);
+
+    let events: Vec<serde_json::Value> = bodies
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|body| serde_json::from_str(body).expect("Payload should be JSON"))
+        .collect();
+    let start = events
+        .iter()
+        .find(|event| event["event"] == "start")
+        .unwrap_or_else(|| panic!("Should post the start: {:?}", events));
+    assert_eq!(start["job_id"], 1000);
+    assert_eq!(start["name"], "slurmtail_test");
+    let end = events
+        .iter()
+        .find(|event| event["event"] == "end")
+        .unwrap_or_else(|| panic!("Should post the end: {:?}", events));
+    assert_eq!(end["state"], "COMPLETED");
+    assert_eq!(end["exit_code"], "0:0");
+    assert!(
+        end["log_path"]
+            .as_str()
+            .is_some_and(|path| path.ends_with("test_output.1000.log")),
+        "Should say where the log is: {}",
+        end
+    );
+    assert!(
+        end["lines"].as_array().is_some_and(|lines| lines
+            .iter()
+            .any(|line| line.as_str().unwrap().starts_with("Test job completed"))),
+        "Should include the end of the log: {}",
+        end
+    );
+}
+
+#[test]
+fn test_mail_to_sends_summary() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    // A sendmail that keeps the email (and how it was called) instead of sending it
+    let bin_dir = temp_dir.path().join("bin");
+    let mail = temp_dir.path().join("mail");
+    fs::create_dir(&bin_dir).unwrap();
+    let sendmail = bin_dir.join("sendmail");
+    fs::write(
+        &sendmail,
+        format!(
+            "#!/bin/sh\necho \"args: $@\" > {:?}\ncat >> {:?}\n",
+            mail, mail
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&sendmail, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--mail-to",
+            "me@example.com",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("PATH", path)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    assert!(
+        output.status.success(),
+        "Job should complete: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let sent = fs::read_to_string(&mail).expect("Should send an email");
+    assert!(sent.starts_with("args: -t -oi\n"), "{}", sent);
+    assert!(sent.contains("To: me@example.com\n"), "{}", sent);
+    assert!(
+        sent.contains("Subject: Job 1000 (slurmtail_test) finished: COMPLETED\n"),
+        "{}",
+        sent
+    );
+    assert!(sent.contains("ExitCode:  0:0\n"), "{}", sent);
+    assert!(sent.contains("Test job completed at"), "{}", sent);
+}
+
+#[test]
+fn test_webhook_slack_format() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    let (url, bodies) = start_webhook_receiver();
+    let config_dir = temp_dir.path().join("config").join("slurmtail");
+    fs::create_dir_all(&config_dir).expect("Failed to create config directory");
+    fs::write(
+        config_dir.join("config.toml"),
+        format!(
+            "[[webhook]]\nurl = \"{}\"\nformat = \"slack\"\nevents = [\"end\"]\n",
+            url
+        ),
+    )
+    .expect("Failed to write config");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap(), "--scheduler", "mock"])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Job should complete: {}", stderr);
+
+    let messages: Vec<serde_json::Value> = bodies
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|body| serde_json::from_str(body).expect("Payload should be JSON"))
+        .collect();
+    assert_eq!(
+        messages.len(),
+        1,
+        "Should only post the end: {:?}",
+        messages
+    );
+    let message = &messages[0];
+    assert!(
+        message["text"]
+            .as_str()
+            .is_some_and(|text| text.contains("Job 1000 (slurmtail_test) finished: COMPLETED")),
+        "Should summarize the job: {}",
+        message
+    );
+    assert_eq!(message["attachments"][0]["color"], "#2eb886");
+    assert!(
+        message["attachments"][0]["text"]
+            .as_str()
+            .is_some_and(|text| text.contains("Test job completed")),
+        "Should quote the end of the log: {}",
+        message
+    );
+}
+
+#[test]
+fn test_ntfy_publishes_job_end() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    // The receiver takes the messages at any path, the server's root included
+    let (url, bodies) = start_webhook_receiver();
+    let config_dir = temp_dir.path().join("config").join("slurmtail");
+    fs::create_dir_all(&config_dir).expect("Failed to create config directory");
+    fs::write(
+        config_dir.join("config.toml"),
+        format!(
+            "[ntfy]\nurl = \"{}\"\ntoken = \"tk_test\"\nevents = [\"end\"]\n",
+            url
+        ),
+    )
+    .expect("Failed to write config");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap(), "--scheduler", "mock"])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Job should complete: {}", stderr);
+
+    let messages: Vec<serde_json::Value> = bodies
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|body| serde_json::from_str(body).expect("Message should be JSON"))
+        .collect();
+    assert_eq!(
+        messages.len(),
+        1,
+        "Should only publish the end: {:?}",
+        messages
+    );
+    let message = &messages[0];
+    assert_eq!(message["topic"], "hook");
+    assert_eq!(
+        message["title"],
+        "Job 1000 (slurmtail_test) finished: COMPLETED"
+    );
+    assert_eq!(message["tags"][0], "white_check_mark");
+    assert!(
+        message["message"]
+            .as_str()
+            .is_some_and(|text| text.contains("Test job completed")),
+        "Should include the end of the log: {}",
+        message
+    );
+}
+
+#[test]
+fn test_hooks_run_on_events() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    let config_dir = temp_dir.path().join("config").join("slurmtail");
+    fs::create_dir_all(&config_dir).expect("Failed to create config directory");
+    fs::write(
+        config_dir.join("config.toml"),
+        r#"[hooks]
+on_submit = "echo \"$SLURMTAIL_EVENT $SLURMTAIL_JOB_ID\" > submit.txt"
+on_end = "cat > end.json"
+on_fail = "touch fail.txt"
+
+[[hooks.on_line_match]]
+pattern = "^Test job completed"
+command = "echo \"$SLURMTAIL_LINE\" > line.txt"
+"#,
+    )
+    .expect("Failed to write config");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap(), "--scheduler", "mock"])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Job should complete: {}", stderr);
+
+    // slurmtail waits for its hooks before exiting
+    let submit =
+        fs::read_to_string(temp_dir.path().join("submit.txt")).expect("on_submit should have run");
+    assert_eq!(submit, "submit 1000\n");
+    let line = fs::read_to_string(temp_dir.path().join("line.txt"))
+        .expect("on_line_match should have run");
+    assert!(line.starts_with("Test job completed at"), "{}", line);
+    let end: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(temp_dir.path().join("end.json")).expect("on_end should have run"),
+    )
+    .expect("on_end should get the event as JSON");
+    assert_eq!(end["event"], "end");
+    assert_eq!(end["job_id"], 1000);
+    assert_eq!(end["state"], "COMPLETED");
+    assert!(
+        !temp_dir.path().join("fail.txt").exists(),
+        "on_fail shouldn't run for a job that completed"
+    );
+}
+
+#[test]
+fn test_project_config_overrides_global_config() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let log_path = temp_dir.path().join("some.log");
+    fs::write(&log_path, "line 1\nline 2\nline 3\nline 4\nline 5\n")
+        .expect("Failed to create log file");
+
+    let config_dir = temp_dir.path().join("config").join("slurmtail");
+    fs::create_dir_all(&config_dir).expect("Failed to create config directory");
+    fs::write(
+        config_dir.join("config.toml"),
+        "timeout = 1\nhistory_lines = 3\n",
+    )
+    .expect("Failed to write global config");
+    fs::write(
+        temp_dir.path().join(".slurmtail.toml"),
+        "history_lines = 2\n",
+    )
+    .expect("Failed to write project config");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["tail", log_path.to_str().unwrap()])
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail tail");
+
+    // The project's history_lines wins, and the global timeout still applies
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, "line 4\nline 5\n");
+    assert_eq!(
+        output.status.code(),
+        Some(10),
+        "Should stop on the configured idle timeout"
+    );
+}
+
+#[test]
+fn test_config_picks_scheduler() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+    fs::write(
+        temp_dir.path().join(".slurmtail.toml"),
+        "scheduler = \"mock\"\n",
+    )
+    .expect("Failed to write project config");
+
+    // No --scheduler, so the project's config decides
+    let output = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap()])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "Job should complete with the mock scheduler: {}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Job submitted with ID: 1000"),
+        "Should submit through the mock scheduler: {}",
+        stderr
+    );
+
+    // An unknown scheduler in the config is an error, not a silent fallback
+    fs::write(
+        temp_dir.path().join(".slurmtail.toml"),
+        "scheduler = \"nope\"\n",
+    )
+    .expect("Failed to write project config");
+    let output = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap()])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("Invalid scheduler \"nope\""), "{}", stderr);
+}
+
+#[test]
+fn test_profile_sets_submit_options() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+    let state_dir = temp_dir.path().join("state");
+
+    let config_dir = temp_dir.path().join("config").join("slurmtail");
+    fs::create_dir_all(&config_dir).expect("Failed to create config directory");
+    fs::write(
+        config_dir.join("config.toml"),
+        "partition = \"debug\"\n\n[profile.gpu]\nscheduler = \"mock\"\npartition = \"gpu\"\nqos = \"high\"\n",
+    )
+    .expect("Failed to write config");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["--profile", "gpu", "run", script_path.to_str().unwrap()])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .env("XDG_STATE_HOME", &state_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Job should complete: {}", stderr);
+
+    // The profile's partition wins over the config's, and its QOS is added
+    let output = Command::new(get_slurmtail_path())
+        .args(["history", "--json"])
+        .env("XDG_STATE_HOME", &state_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail history");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let history: serde_json::Value = serde_json::from_str(&stdout).expect("History should be JSON");
+    let directives = history[0]["directives"]
+        .as_array()
+        .expect("Should snapshot the directives");
+    assert!(
+        directives.contains(&serde_json::json!(["partition", "gpu"])),
+        "{}",
+        stdout
+    );
+    assert!(
+        directives.contains(&serde_json::json!(["qos", "high"])),
+        "{}",
+        stdout
+    );
+    assert!(
+        !directives.contains(&serde_json::json!(["partition", "debug"])),
+        "{}",
+        stdout
+    );
+
+    // A profile that isn't defined is an error
+    let output = Command::new(get_slurmtail_path())
+        .args(["--profile", "cpu", "run", script_path.to_str().unwrap()])
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .env("XDG_STATE_HOME", &state_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("Unknown profile \"cpu\": expected one of gpu"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_env_config_between_config_file_and_flags() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let log_path = temp_dir.path().join("some.log");
+    fs::write(&log_path, "line 1\nline 2\nline 3\nline 4\n").expect("Failed to create log file");
+    fs::write(
+        temp_dir.path().join(".slurmtail.toml"),
+        "history_lines = 3\ntimeout = 30\n",
+    )
+    .expect("Failed to write project config");
+
+    // The environment wins over the config file, and --timeout over the environment
+    let output = Command::new(get_slurmtail_path())
+        .args(["tail", "--timeout", "1", log_path.to_str().unwrap()])
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .env("SLURMTAIL_HISTORY_LINES", "1")
+        .env("SLURMTAIL_TIMEOUT", "60")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail tail");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stdout, "line 4\n");
+    assert!(
+        stderr.contains("no new bytes read for 1s"),
+        "Should use --timeout: {}",
+        stderr
+    );
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["tail", log_path.to_str().unwrap()])
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .env("SLURMTAIL_TIMEOUT", "soon")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail tail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("Invalid SLURMTAIL_TIMEOUT \"soon\""),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_state_dir_env() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+    let state_dir = temp_dir.path().join("site-state");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap()])
+        .env("SLURMTAIL_SCHEDULER", "mock")
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("SLURMTAIL_STATE_DIR", &state_dir)
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Job should complete: {}", stderr);
+    assert!(
+        state_dir.join("history.sqlite").exists(),
+        "The history should be kept in $SLURMTAIL_STATE_DIR"
+    );
+}
+
+#[test]
+fn test_prompt_status() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    // Nothing to say before a job is submitted
+    let output = Command::new(get_slurmtail_path())
+        .args(["prompt-status", "--scheduler", "mock"])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail prompt-status");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+
+    let script_path = temp_dir.path().join("long_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=long_%j.log\n\necho 'Started'\nsleep 30\n",
+    )
+    .expect("Failed to create test script");
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--timeout",
+            "60",
+            "--until-pattern",
+            "^Started",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    assert!(output.status.success(), "Should succeed: {:?}", output);
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["prompt-status", "--scheduler", "mock"])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail prompt-status");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "⏳1000 RUNNING 0:00\n"
+    );
+    assert!(
+        temp_dir
+            .path()
+            .join("._slurmtail")
+            .join("prompt-status")
+            .exists(),
+        "Should cache the job's state"
+    );
+
+    // Don't leave the mock job running
+    Command::new(get_slurmtail_path())
+        .args(["cancel", "--scheduler", "mock"])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+}
+
+#[test]
+fn test_library_resolves_log_path() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("lib_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --job-name=train\n#SBATCH --output=logs/%x.%j.log\n\necho 'Hello'\n",
+    )
+    .expect("Failed to create test script");
+
+    let pattern = slurmtail::script::extract_log_output_pattern(&script_path)
+        .expect("Failed to read the output pattern");
+    let job_name =
+        slurmtail::script::extract_job_name(&script_path).expect("Failed to read the job name");
+    assert_eq!(pattern, "logs/%x.%j.log");
+    assert_eq!(job_name.as_deref(), Some("train"));
+
+    let filename =
+        slurmtail::script::format_log_output_string(pattern, 1234, job_name.as_ref(), None, None);
+    let log_path = slurmtail::script::logfile_string_to_path(&script_path, filename, false)
+        .expect("Failed to resolve the log path");
+    assert_eq!(log_path, temp_dir.path().join("logs/train.1234.log"));
+}
+
+#[test]
+fn test_invalid_config_file_error() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    fs::write(
+        temp_dir.path().join(".slurmtail.toml"),
+        "poll_interval = [\n",
+    )
+    .expect("Failed to create config file");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["history"])
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail history");
+
+    assert_eq!(
+        output.status.code(),
+        Some(1),
+        "Should fail without panicking"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Invalid config file") && stderr.contains(".slurmtail.toml"),
+        "Should name the config file: {}",
+        stderr
+    );
+    assert!(!stderr.contains("panicked"), "Should not panic: {}", stderr);
+}
+
+#[test]
+fn test_library_follow_times_out() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let log_path = temp_dir.path().join("quiet.log");
+    fs::write(&log_path, "first line\n").expect("Failed to create log file");
+
+    let options = slurmtail::watch::FollowOptions {
+        timeout: Some(std::time::Duration::from_secs(1)),
+        poll_interval: Some(std::time::Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let started = std::time::Instant::now();
+    let result = slurmtail::watch::mon_logfile(&log_path, &options, None, None, None);
+
+    assert_eq!(
+        slurmtail::error::exit_code(&result),
+        10,
+        "Should time out without new output: {:?}",
+        result.err()
+    );
+    assert!(
+        started.elapsed() < std::time::Duration::from_secs(5),
+        "Should time out after about a second"
+    );
+}
+
+#[test]
+fn test_daemon_replays_output() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("slow_job.sh");
+    fs::write(
+        &script_path,
+        "#!/usr/bin/env bash\n#SBATCH --output=slow.%j.log\n\nfor i in 1 2 3 4 5 6; do\n  echo \"step $i\"\n  sleep 1\ndone\n",
+    )
+    .expect("Failed to create test script");
+    let mock_dir = temp_dir.path().join("mock");
+    let socket = temp_dir.path().join("daemon.sock");
+    let slurmtail = |args: &[&str]| {
+        let mut command = Command::new(get_slurmtail_path());
+        command
+            .args(args)
+            .env("SLURMTAIL_STATE_DIR", temp_dir.path().join("state"))
+            .env("SLURMTAIL_DAEMON_SOCKET", &socket)
+            .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .current_dir(temp_dir.path());
+        command
+    };
+
+    let output = slurmtail(&["daemon", "--scheduler", "mock"])
+        .output()
+        .expect("Failed to start the daemon");
+    assert!(
+        output.status.success(),
+        "Should start the daemon: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Submit the job, then close the terminal following it while it still runs
+    let mut run = slurmtail(&["run", script_path.to_str().unwrap(), "--scheduler", "mock"])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .expect("Failed to run slurmtail");
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    run.kill().expect("Failed to stop slurmtail run");
+    run.wait().expect("Failed to wait for slurmtail run");
+
+    // The daemon picks the job up on its next look at the submission history
+    let started = std::time::Instant::now();
+    loop {
+        let output = slurmtail(&["daemon", "--status"])
+            .output()
+            .expect("Failed to ask the daemon");
+        if String::from_utf8_lossy(&output.stdout).contains("1000") {
+            break;
+        }
+        assert!(
+            started.elapsed() < std::time::Duration::from_secs(15),
+            "The daemon should follow the job"
+        );
+        std::thread::sleep(std::time::Duration::from_millis(250));
+    }
+
+    let output = slurmtail(&["resume", "--scheduler", "mock", "--timeout", "5"])
+        .output()
+        .expect("Failed to run slurmtail resume");
+    let _ = slurmtail(&["daemon", "--stop"]).output();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Should succeed: {}", stderr);
+    assert!(
+        stderr.contains("Attached to the slurmtail daemon"),
+        "Should attach to the daemon: {}",
+        stderr
+    );
+    assert!(
+        stdout.contains("step 6"),
+        "Should show the job's output up to its end: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_serve_streams_events() {
+    use std::io::{BufRead, Read, Write};
+
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("slow_job.sh");
+    fs::write(
+        &script_path,
+        "#!/usr/bin/env bash\n#SBATCH --output=slow.%j.log\n\nfor i in 1 2 3 4 5 6; do\n  echo \"step $i\"\n  sleep 1\ndone\n",
+    )
+    .expect("Failed to create test script");
+    let mock_dir = temp_dir.path().join("mock");
+    let socket = temp_dir.path().join("daemon.sock");
+    let slurmtail = |args: &[&str]| {
+        let mut command = Command::new(get_slurmtail_path());
+        command
+            .args(args)
+            .env("SLURMTAIL_STATE_DIR", temp_dir.path().join("state"))
+            .env("SLURMTAIL_DAEMON_SOCKET", &socket)
+            .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .current_dir(temp_dir.path());
+        command
+    };
+    let get = |address: &str, path: &str| {
+        let mut stream = std::net::TcpStream::connect(address).expect("Failed to connect");
+        stream
+            .set_read_timeout(Some(std::time::Duration::from_secs(20)))
+            .unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, address).unwrap();
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .expect("Failed to read the response");
+        response
+    };
+
+    // Port 0 picks a free one, which the server names
+    let mut server = slurmtail(&["serve", "--bind", "127.0.0.1:0", "--scheduler", "mock"])
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to run slurmtail serve");
+    let mut server_messages = std::io::BufReader::new(server.stderr.take().unwrap()).lines();
+    let address = server_messages
+        .by_ref()
+        .map_while(Result::ok)
+        .find_map(|line| {
+            line.split_once("http://")
+                .map(|(_, url)| url.trim_end_matches('/').to_string())
+        })
+        .expect("Should name the address it serves at");
+
+    let mut run = slurmtail(&["run", script_path.to_str().unwrap(), "--scheduler", "mock"])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .expect("Failed to run slurmtail");
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    run.kill().expect("Failed to stop slurmtail run");
+    run.wait().expect("Failed to wait for slurmtail run");
+
+    let started = std::time::Instant::now();
+    while !get(&address, "/jobs").contains("\"job_id\":1000") {
+        assert!(
+            started.elapsed() < std::time::Duration::from_secs(15),
+            "The server should follow the job"
+        );
+        std::thread::sleep(std::time::Duration::from_millis(250));
+    }
+    let events = get(&address, "/jobs/1000/events");
+    let metrics = get(&address, "/metrics");
+    let _ = slurmtail(&["daemon", "--stop"]).output();
+    let _ = server.wait();
+
+    assert!(
+        events.starts_with("HTTP/1.1 200 OK") && events.contains("text/event-stream"),
+        "Should answer with an event stream: {}",
+        events
+    );
+    assert!(
+        events.contains("event: line\nid: 7\ndata: step 1\n") && events.contains("data: step 6\n"),
+        "Should send every line of the job's output: {}",
+        events
+    );
+    assert!(
+        events.trim_end().ends_with("event: end\ndata: COMPLETED"),
+        "Should end the stream once the job has ended: {}",
+        events
+    );
+    assert!(
+        metrics.contains("slurmtail_jobs{state=\"COMPLETED\"} 1\n")
+            && metrics.contains("slurmtail_lines_read_total 6\n")
+            && metrics.contains("slurmtail_job_bytes_read_total{job_id=\"1000\"} 42\n"),
+        "Should count the job and what was read of its log: {}",
+        metrics
+    );
+}
+
+#[test]
+fn test_remote_runs_over_ssh() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let laptop_dir = temp_dir.path().join("laptop");
+    let cluster_dir = temp_dir.path().join("cluster");
+    fs::create_dir(&laptop_dir).expect("Failed to create local directory");
+    fs::create_dir(&cluster_dir).expect("Failed to create remote directory");
+
+    // Stand-ins for the SLURM tools, only on the "remote" host's $PATH: sbatch keeps the script it is sent and writes
+    // the job's output, squeue no longer lists the job, and sacct reports it as completed
+    let cluster_bin_dir = temp_dir.path().join("cluster_bin");
+    let bin_dir = temp_dir.path().join("bin");
+    let ssh_log = temp_dir.path().join("ssh.log");
+    fs::create_dir(&cluster_bin_dir).expect("Failed to create bin directory");
+    fs::create_dir(&bin_dir).expect("Failed to create bin directory");
+    // A stand-in for ssh, which runs the command line it is given here, with the tools above on $PATH
+    let ssh = format!(
+        "echo \"$@\" >> {}\n\
+         while [ $# -gt 0 ]; do case \"$1\" in -o|-O) shift 2;; -*) shift;; *) break;; esac; done\n\
+         shift\n\
+         [ \"$1\" = -- ] && shift\n\
+         [ $# -eq 0 ] && exit 0\n\
+         PATH={}:$PATH exec bash -c \"$*\"",
+        ssh_log.display(),
+        cluster_bin_dir.display()
+    );
+    for (tool_path, body) in [
+        (
+            cluster_bin_dir.join("sbatch"),
+            "cat > submitted.sh; echo \"Hello from $(basename \"$PWD\")\" > slurm-4242.out; echo 'Submitted batch job 4242'",
+        ),
+        (cluster_bin_dir.join("squeue"), "exit 0"),
+        (cluster_bin_dir.join("sacct"), "echo COMPLETED"),
+        (bin_dir.join("ssh"), ssh.as_str()),
+    ] {
+        fs::write(&tool_path, format!("#!/bin/bash\n{}\n", body)).expect("Failed to create tool");
+        fs::set_permissions(&tool_path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let script = "#!/bin/bash\n#SBATCH --job-name=remote_test\n\necho hello\n";
+    fs::write(laptop_dir.join("job.sh"), script).expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            "job.sh",
+            "--scheduler",
+            "slurm",
+            "--remote",
+            &format!("login-node:{}", cluster_dir.display()),
+            "--timeout",
+            "20",
+        ])
+        .env("PATH", path)
+        .current_dir(&laptop_dir)
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Should succeed: {}", stderr);
+    assert!(
+        stdout.contains("Hello from cluster"),
+        "Should follow the log written on the remote host: {}",
+        stdout
+    );
+    assert_eq!(
+        fs::read_to_string(cluster_dir.join("submitted.sh")).unwrap(),
+        script,
+        "Should send the script along to sbatch"
+    );
+    let ssh_log = fs::read_to_string(&ssh_log).expect("ssh should have been run");
+    assert!(
+        ssh_log.contains("login-node") && ssh_log.contains("ControlPath="),
+        "Should go over a shared SSH connection: {}",
+        ssh_log
+    );
+}
+
+#[test]
+fn test_otlp_exports_job_trace() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    // The webhook receiver stands in for the collector, keeping what is posted to it
+    let (url, bodies) = start_webhook_receiver();
+    let endpoint = url.trim_end_matches("/hook");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--otlp-endpoint",
+            endpoint,
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "1")
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .env("OTEL_SERVICE_NAME", "training")
+        .env_remove("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .env_remove("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Job should complete: {}", stderr);
+
+    let payloads: Vec<serde_json::Value> = bodies
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|body| serde_json::from_str(body).expect("Payload should be JSON"))
+        .collect();
+    let spans: Vec<&serde_json::Value> = payloads
+        .iter()
+        .flat_map(|payload| payload["resourceSpans"].as_array().unwrap())
+        .inspect(|resource| {
+            assert!(
+                resource["resource"]["attributes"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .any(|attribute| attribute["key"] == "service.name"
+                        && attribute["value"]["stringValue"] == "training"),
+                "Should name the service: {}",
+                resource
+            )
+        })
+        .flat_map(|resource| resource["scopeSpans"][0]["spans"].as_array().unwrap())
+        .collect();
+    let span = |name: &str| {
+        spans
+            .iter()
+            .find(|span| span["name"] == name)
+            .unwrap_or_else(|| panic!("Should send a {:?} span: {:?}", name, spans))
+    };
+    let job = span("slurm job");
+    let queue_wait = span("queue wait");
+    let run = span("run");
+
+    // One trace, with the job's span the parent of the others
+    assert_eq!(job["traceId"].as_str().unwrap().len(), 32);
+    for child in [queue_wait, run] {
+        assert_eq!(child["traceId"], job["traceId"]);
+        assert_eq!(child["parentSpanId"], job["spanId"]);
+    }
+    let time = |span: &serde_json::Value, field: &str| -> u128 {
+        span[field].as_str().unwrap().parse().unwrap()
+    };
+    assert_eq!(
+        time(queue_wait, "endTimeUnixNano"),
+        time(run, "startTimeUnixNano")
+    );
+    assert!(time(job, "startTimeUnixNano") <= time(queue_wait, "startTimeUnixNano"));
+    assert!(time(job, "endTimeUnixNano") >= time(run, "endTimeUnixNano"));
+
+    let attribute = |key: &str| {
+        job["attributes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|attribute| attribute["key"] == key)
+            .map(|attribute| attribute["value"].clone())
+            .unwrap_or_else(|| panic!("Should have the {} attribute: {}", key, job))
+    };
+    assert_eq!(attribute("slurm.job.id")["intValue"], "1000");
+    assert_eq!(attribute("slurm.job.name")["stringValue"], "slurmtail_test");
+    assert_eq!(attribute("slurm.job.state")["stringValue"], "COMPLETED");
+    assert_eq!(attribute("slurm.job.exit_code")["stringValue"], "0:0");
+    assert!(
+        job["events"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|event| event["name"] == "submitted"),
+        "Should note the submission: {}",
+        job
+    );
+}
+
+#[cfg(feature = "lua")]
+#[test]
+fn test_plugin_transforms_lines() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+    let script_path = temp_dir.path().join("plugin_job.sh");
+    fs::write(
+        &script_path,
+        "#!/usr/bin/env bash\n#SBATCH --output=plugin_job.%j.log\n\
+         echo \"DEBUG noise\"\necho \"token=abc123 ready\"\necho \"loss=nan at step 3\"\necho \"done\"\n",
+    )
+    .expect("Failed to create test script");
+    let plugin_path = temp_dir.path().join("plugin.lua");
+    fs::write(
+        &plugin_path,
+        r#"
+function on_line(line, job)
+  if line:match("^DEBUG") then return false end
+  if line:match("loss=nan") then
+    slurmtail.event("nan", "loss went NaN in job " .. job.id)
+    return { style = "bold red" }
+  end
+  return (line:gsub("token=%S+", "token=***"))
+end
+"#,
+    )
+    .expect("Failed to write plugin");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--plugin",
+            plugin_path.to_str().unwrap(),
+            "--porcelain",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Job should complete: {}", stderr);
+
+    let lines: Vec<&str> = stdout
+        .lines()
+        .filter_map(|record| record.strip_prefix("line\t1000\t-\t"))
+        .collect();
+    assert_eq!(
+        lines,
+        ["token=*** ready", "loss=nan at step 3", "done"],
+        "The plugin should drop and rewrite lines: {}",
+        stdout
+    );
+    assert!(
+        stdout.contains("plugin\t1000\tnan\tloss went NaN in job 1000\t-"),
+        "The plugin's event should be a record: {}",
+        stdout
+    );
+}
+
+#[cfg(not(feature = "lua"))]
+#[test]
+fn test_plugin_needs_lua_feature() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--plugin",
+            "plugin.lua",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", temp_dir.path().join("mock"))
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "Should refuse the plugin");
+    assert!(
+        stderr.contains("built without Lua plugins"),
+        "Should say why: {}",
+        stderr
+    );
+    assert!(
+        !temp_dir.path().join("mock").exists(),
+        "Should not submit the job: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_timeouts_take_human_durations() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let log_path = temp_dir.path().join("quiet.log");
+    fs::write(&log_path, "only line\n").expect("Failed to create log file");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "tail",
+            "--timeout",
+            "500ms",
+            "--poll-interval",
+            "100ms",
+            log_path.to_str().unwrap(),
+        ])
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail tail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(10),
+        "Should time out: {}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Timed out after 500ms with no new bytes read"),
+        "Should say how long it waited the way it was given: {}",
+        stderr
+    );
+
+    // The config file takes durations with units too
+    fs::write(
+        temp_dir.path().join(".slurmtail.toml"),
+        "timeout = \"1s\"\npoll_interval = 0.1\n",
+    )
+    .expect("Failed to write project config");
+    let output = Command::new(get_slurmtail_path())
+        .args(["tail", "--format", "json", log_path.to_str().unwrap()])
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail tail");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        output.status.code(),
+        Some(10),
+        "Should time out: {}",
+        stdout
+    );
+    let timeout: serde_json::Value = stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .find(|event| event["type"] == "timeout")
+        .unwrap_or_else(|| panic!("Should emit a timeout event: {}", stdout));
+    assert_eq!(timeout["seconds"], 1);
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["tail", "--timeout", "soon", log_path.to_str().unwrap()])
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail tail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "Should refuse the timeout");
+    assert!(
+        stderr.contains("invalid value 'soon' for '--timeout"),
+        "Should say which value is wrong: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_max_duration_detaches() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("slow_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=slow.%j.log\nfor i in $(seq 1 40); do echo \"line $i\"; sleep 0.2; done\n",
+    )
+    .expect("Failed to create test script");
+    let mock_dir = temp_dir.path().join("mock");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--max-duration",
+            "3s",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "Detaching at --max-duration isn't a failure: {}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Followed for --max-duration (3s); detached"),
+        "Should say why it stopped: {}",
+        stderr
+    );
+    assert!(!output.stdout.is_empty(), "Should have shown some output");
+    assert!(
+        !String::from_utf8_lossy(&output.stdout).contains("line 40"),
+        "Should have detached before the job ended"
+    );
+
+    // The session remembers exactly how much of the log was shown, for resuming
+    let session: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(temp_dir.path().join("._slurmtail").join("1000.json"))
+            .expect("Session should be kept"),
+    )
+    .unwrap();
+    assert_eq!(session["stopped_by"], "--max-duration");
+    assert_eq!(session["offset"], output.stdout.len() as u64);
+    assert!(session["final_state"].is_null(), "The job is still running");
+}
+
+#[test]
+fn test_exit_zero_on_timeout() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let log_path = temp_dir.path().join("quiet.log");
+    fs::write(&log_path, "only line\n").expect("Failed to create log file");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "tail",
+            "--timeout",
+            "500ms",
+            "--exit-zero-on-timeout",
+            log_path.to_str().unwrap(),
+        ])
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail tail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(0),
+        "An idle timeout shouldn't fail: {}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Timed out after 500ms with no new bytes read"),
+        "Should still warn about the timeout: {}",
+        stderr
+    );
+
+    // Likewise for a log file that never appears
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "tail",
+            "--timeout",
+            "500ms",
+            "--exit-zero-on-timeout",
+            temp_dir.path().join("missing.log").to_str().unwrap(),
+        ])
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail tail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(0),
+        "A file timeout shouldn't fail: {}",
+        stderr
+    );
+    assert!(
+        stderr.contains("File took too long to appear"),
+        "Should still warn about the timeout: {}",
+        stderr
+    );
+
+    // Other failures keep their exit codes
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "tail",
+            "--exit-zero-on-timeout",
+            "--fail-on",
+            "only",
+            log_path.to_str().unwrap(),
+        ])
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail tail");
+    assert_eq!(output.status.code(), Some(13));
+}
+
+#[test]
+fn test_separate_file_and_idle_timeouts() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let log_path = temp_dir.path().join("quiet.log");
+    fs::write(&log_path, "only line\n").expect("Failed to create log file");
+    let tail = |args: &[&str]| {
+        Command::new(get_slurmtail_path())
+            .arg("tail")
+            .args(args)
+            .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail tail")
+    };
+
+    // A short idle timeout doesn't take the long file timeout with it
+    let output = tail(&[
+        "--file-timeout",
+        "10m",
+        "--idle-timeout",
+        "500ms",
+        log_path.to_str().unwrap(),
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(10), "Should go idle: {}", stderr);
+    assert!(
+        stderr.contains("Timed out after 500ms"),
+        "Should wait as long as --idle-timeout says: {}",
+        stderr
+    );
+
+    // Nor the other way around, with --timeout covering what isn't given
+    let missing = temp_dir.path().join("missing.log");
+    let output = tail(&[
+        "--timeout",
+        "10m",
+        "--file-timeout",
+        "500ms",
+        missing.to_str().unwrap(),
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(11),
+        "Should give up on the file: {}",
+        stderr
+    );
+    assert!(
+        stderr.contains("longer than timeout of 500ms"),
+        "Should wait as long as --file-timeout says: {}",
+        stderr
+    );
+
+    // The config files can set them apart too, under a timeout from the command line
+    fs::write(
+        temp_dir.path().join(".slurmtail.toml"),
+        "timeout = \"10m\"\nidle_timeout = \"500ms\"\n",
+    )
+    .expect("Failed to write project config");
+    let output = tail(&[log_path.to_str().unwrap()]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Timed out after 500ms"),
+        "Should take idle_timeout from the config: {}",
+        stderr
+    );
+    let output = tail(&["--timeout", "1s", log_path.to_str().unwrap()]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Timed out after 1s"),
+        "--timeout should win over the config: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_no_idle_timeout() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let log_path = temp_dir.path().join("quiet.log");
+    fs::write(&log_path, "only line\n").expect("Failed to create log file");
+
+    // Both stay put through a quiet spell far longer than the idle timeout would have allowed, until --max-duration
+    for args in [
+        vec!["--timeout", "0"],
+        vec!["--idle-timeout", "500ms", "--no-idle-timeout"],
+    ] {
+        let output = Command::new(get_slurmtail_path())
+            .arg("tail")
+            .args(&args)
+            .args(["--max-duration", "2s", log_path.to_str().unwrap()])
+            .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail tail");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            output.status.success(),
+            "{:?} shouldn't time out: {}",
+            args,
+            stderr
+        );
+        assert!(!stderr.contains("Timed out"), "{:?}: {}", args, stderr);
+        assert!(
+            stderr.contains("Followed for --max-duration (2s)"),
+            "{:?} should have kept following: {}",
+            args,
+            stderr
+        );
+    }
+}
+
+#[test]
+fn test_file_timeout_runs_from_estimated_start() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("queued_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=queued.%j.log\necho \"finally running\"\n",
+    )
+    .expect("Failed to create test script");
+
+    // The job waits in the queue for longer than the file timeout, but no longer than the scheduler said it would
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--file-timeout",
+            "2s",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", temp_dir.path().join("mock"))
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "4")
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "Should wait past the file timeout for the estimated start: {}",
+        stderr
+    );
+    assert!(
+        stderr.contains("The scheduler expects job 1000 to start at")
+            && stderr.contains("waiting for its log file until 2s after that"),
+        "Should say how long it will wait: {}",
+        stderr
+    );
+    assert!(stdout.contains("finally running"), "stdout: {}", stdout);
+}
+
+#[test]
+fn test_grace_period_reads_late_output() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("late_job.sh");
+    // The last line lands well after the job has ended (and slurmtail has seen it go), as on a slow shared filesystem
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=late.%j.log\necho \"first line\"\n(sleep 7; echo \"late line\") &\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--grace-period",
+            "10s",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", temp_dir.path().join("mock"))
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "stderr: {}", stderr);
+    assert!(
+        stderr.contains("Job 1000 has left the queue; reading its output for 10s more."),
+        "Should say it is still reading: {}",
+        stderr
+    );
+    assert!(
+        stdout.contains("first line") && stdout.contains("late line"),
+        "Should show the output that came after the job ended: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_missing_log_dir_preflight() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("logged_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=logs/run.%j.log\necho \"into the logs\"\n",
+    )
+    .expect("Failed to create test script");
+    let mock_dir = temp_dir.path().join("mock");
+    let run = |extra_args: &[&str]| {
+        Command::new(get_slurmtail_path())
+            .args(["run", script_path.to_str().unwrap(), "--scheduler", "mock"])
+            .args(extra_args)
+            .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail")
+    };
+
+    // A job whose log directory is missing isn't submitted at all
+    let output = run(&[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(12), "stderr: {}", stderr);
+    assert!(
+        stderr.contains("log directory") && stderr.contains("--create-log-dirs"),
+        "Should say what is wrong and how to fix it: {}",
+        stderr
+    );
+    assert!(
+        !mock_dir.join("1000").exists(),
+        "Should not have submitted the job"
+    );
+
+    let output = run(&["--create-log-dirs"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "stderr: {}", stderr);
+    assert!(
+        stderr.contains("Created the log directory"),
+        "Should say it made the directory: {}",
+        stderr
+    );
+    assert!(temp_dir.path().join("logs").is_dir());
+    assert!(stdout.contains("into the logs"), "stdout: {}", stdout);
+}
+
+#[test]
+fn test_placement_preflight() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("placed_job.sh");
+    let mock_dir = temp_dir.path().join("mock");
+    let run = |directives: &str| {
+        fs::write(
+            &script_path,
+            format!(
+                "#!/bin/bash\n{}#SBATCH --output=placed.%j.log\necho \"placed\"\n",
+                directives
+            ),
+        )
+        .expect("Failed to create test script");
+        Command::new(get_slurmtail_path())
+            .args(["run", script_path.to_str().unwrap(), "--scheduler", "mock"])
+            .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .env("SLURMTAIL_MOCK_PARTITIONS", "debug,gpu")
+            .env("SLURMTAIL_MOCK_QOS", "normal")
+            .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail")
+    };
+
+    // A job asking for a partition and QOS that don't exist isn't submitted at all
+    let output = run("#SBATCH --partition=gpux\n#SBATCH --qos=hihg\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(12), "stderr: {}", stderr);
+    assert!(
+        stderr.contains("Partition \"gpux\"") && stderr.contains("debug, gpu"),
+        "Should name the partition and the ones there are: {}",
+        stderr
+    );
+    assert!(
+        stderr.contains("QOS \"hihg\""),
+        "Should name the QOS too: {}",
+        stderr
+    );
+    assert!(
+        !mock_dir.join("1000").exists(),
+        "Should not have submitted the job"
+    );
+
+    let output = run("#SBATCH --partition=gpu\n");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.contains("placed"), "stdout: {}", stdout);
+}
+
+#[test]
+fn test_log_collision_backup() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("same_log.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=same.log\necho \"new output\"\n",
+    )
+    .expect("Failed to create test script");
+    let log_path = temp_dir.path().join("same.log");
+    fs::write(&log_path, "old output\n").expect("Failed to create old log");
+    let mock_dir = temp_dir.path().join("mock");
+    let run = |extra_args: &[&str]| {
+        Command::new(get_slurmtail_path())
+            .args(["run", script_path.to_str().unwrap(), "--scheduler", "mock"])
+            .args(extra_args)
+            .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail")
+    };
+
+    // Without --backup-existing, the old log is only warned about
+    let output = run(&[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "stderr: {}", stderr);
+    assert!(
+        stderr.contains("already exists") && stderr.contains("--backup-existing"),
+        "Should warn about the old log: {}",
+        stderr
+    );
+
+    // With it, the old log is moved aside, and the job gets a fresh one
+    let output = run(&["--backup-existing"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "stderr: {}", stderr);
+    assert!(
+        stderr.contains("Moved the old log file"),
+        "Should say where the old log went: {}",
+        stderr
+    );
+    let backup = fs::read_to_string(temp_dir.path().join("same.log.1"))
+        .expect("Should have backed up the old log");
+    assert!(backup.starts_with("old output"), "backup: {}", backup);
+    assert!(!stdout.contains("old output"), "stdout: {}", stdout);
+    assert_eq!(
+        fs::read_to_string(&log_path).expect("Should have a new log"),
+        "new output\n"
+    );
+}
+
+#[test]
+fn test_truncate_existing_log() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("fixed_log.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=fixed.log\n#SBATCH --open-mode=append\necho \"this run\"\n",
+    )
+    .expect("Failed to create test script");
+    let log_path = temp_dir.path().join("fixed.log");
+    fs::write(&log_path, "last run\n").expect("Failed to create old log");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--truncate-existing",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", temp_dir.path().join("mock"))
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    // The old run's output is gone, so only this run's is followed
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "stderr: {}", stderr);
+    assert!(
+        stderr.contains("Emptied the old log file"),
+        "Should say it emptied the log: {}",
+        stderr
+    );
+    assert!(stdout.contains("this run"), "stdout: {}", stdout);
+    assert!(!stdout.contains("last run"), "stdout: {}", stdout);
+    assert_eq!(
+        fs::read_to_string(&log_path).expect("Should have the log"),
+        "this run\n"
+    );
+    assert!(!temp_dir.path().join("fixed.log.1").exists());
+}
+
+#[test]
+fn test_log_found_under_another_name() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("renamed_log.sh");
+    // Like a cluster that stages logs under another name: the expected file goes away, and the output lands next to it
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=test_output.%j.log\nrm -f \"test_output.$SLURM_JOB_ID.log\"\necho \"staged output\" > \"test_output.$SLURM_JOB_ID.log.node1\"\n",
+    )
+    .expect("Failed to create test script");
+    let run = |args: &[&str]| {
+        Command::new(get_slurmtail_path())
+            .args(args)
+            .args(["--scheduler", "mock"])
+            .env("SLURMTAIL_MOCK_DIR", temp_dir.path().join("mock"))
+            .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail")
+    };
+
+    // Looking only every 5 seconds, slurmtail can't catch the expected file in the moment before it goes away
+    let output = run(&[
+        "run",
+        script_path.to_str().unwrap(),
+        "--poll-interval",
+        "5s",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "stderr: {}", stderr);
+    assert!(
+        stderr.contains("test_output.1000.log.node1") && stderr.contains("following that instead"),
+        "Should say which file it followed instead: {}",
+        stderr
+    );
+    assert!(stdout.contains("staged output"), "stdout: {}", stdout);
+
+    // Resuming reads the file that was followed
+    let output = run(&["resume", "1000", "--timeout", "1"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Resuming monitoring of") && stderr.contains("test_output.1000.log.node1"),
+        "Should resume the file that was followed: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_attach_newest() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("by_hand.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=by_hand.%j.log\nsleep 4\necho \"submitted by hand\"\n",
+    )
+    .expect("Failed to create test script");
+    let run = |args: &[&str]| {
+        Command::new(get_slurmtail_path())
+            .args(args)
+            .args(["--scheduler", "mock"])
+            .env("SLURMTAIL_MOCK_DIR", temp_dir.path().join("mock"))
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail")
+    };
+
+    // Nothing to attach to yet
+    let output = run(&["attach", "--newest"]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("no jobs"),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Leave a job running, as if it had been submitted without slurmtail
+    let output = run(&["run", script_path.to_str().unwrap(), "--max-duration", "1"]);
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let output = run(&["attach", "--newest"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "stderr: {}", stderr);
+    assert!(
+        stderr.contains("Attaching to job 1000") && stderr.contains("by_hand.1000.log"),
+        "Should say which job it picked: {}",
+        stderr
+    );
+    assert!(stdout.contains("submitted by hand"), "stdout: {}", stdout);
+}
+
+#[test]
+fn test_array_progress() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("array_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=array.%A_%a.log\n#SBATCH --array=1-3\necho \"task starting\"\nsleep 7\necho \"task done\"\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap(), "--scheduler", "mock"])
+        .env("SLURMTAIL_MOCK_DIR", temp_dir.path().join("mock"))
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    // Without a terminal, the task counts come as lines of their own whenever they change
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "stderr: {}", stderr);
+    assert!(
+        stderr.contains("[INFO] array 1000: 3 running"),
+        "Should count the array's tasks: {}",
+        stderr
+    );
+    assert!(stdout.contains("task done"), "stdout: {}", stdout);
+}
+
+#[test]
+fn test_grep_context_lines() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("context_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=context_%j.log\nfor i in 1 2 3 4; do echo \"step $i\"; done\necho 'ERROR: first'\nfor i in 6 7 8; do echo \"step $i\"; done\necho 'ERROR: second'\necho 'step 10'\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--grep",
+            "ERROR",
+            "-C",
+            "1",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", temp_dir.path().join("mock"))
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    // A line before and after each match, with "--" where lines were left out between them, like grep -C
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        stdout,
+        "step 4\nERROR: first\nstep 6\n--\nstep 8\nERROR: second\nstep 10\n",
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_since_skips_old_lines() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let log_path = temp_dir.path().join("timestamped.log");
+    fs::write(
+        &log_path,
+        "starting up\n\
+         2020-01-01 10:00:00 old line\n\
+         Traceback (old)\n\
+         [2020-01-01T10:05:00Z] another old line\n\
+         2030-01-01 09:00:00,250 new line\n\
+         continued\n\
+         2020-06-01 10:00:00 out of order, but after the cut\n",
+    )
+    .expect("Failed to write log");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "tail",
+            "--timeout",
+            "1",
+            "--since",
+            "2025-01-01",
+            log_path.to_str().unwrap(),
+        ])
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail tail");
+
+    // Old lines (and those going with them) are skipped, up to the first line from after --since
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stdout,
+        "starting up\n2030-01-01 09:00:00,250 new line\ncontinued\n2020-06-01 10:00:00 out of order, but after the cut\n",
+        "stderr: {}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Skipped 3 lines from before 2025-01-01 00:00:00"),
+        "Should say how many lines it skipped: {}",
+        stderr
+    );
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "tail",
+            "--since",
+            "yesterday-ish",
+            log_path.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run slurmtail tail");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_convert_timestamps() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let log_path = temp_dir.path().join("timestamped.log");
+    fs::write(
+        &log_path,
+        "[2026-10-16T12:03:27.512Z] bracketed\n\
+         2026-10-16 14:03:27+0200 with an offset\n\
+         no timestamp 2026-10-16 12:00:00\n",
+    )
+    .expect("Failed to write log");
+
+    let tail = |args: &[&str]| {
+        Command::new(get_slurmtail_path())
+            .arg("tail")
+            .args(["--timeout", "1"])
+            .args(args)
+            .arg(&log_path)
+            .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+            .env("TZ", "Asia/Tokyo")
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail tail")
+    };
+
+    // To UTC, as ISO 8601, leaving lines that don't start with a timestamp alone
+    let output = tail(&["--convert-timestamps", "utc"]);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "[2026-10-16T12:03:27.512Z] bracketed\n\
+         2026-10-16T12:03:27Z with an offset\n\
+         no timestamp 2026-10-16 12:00:00\n",
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // To local time, in a format of our own
+    let output = tail(&[
+        "--convert-timestamps",
+        "local",
+        "--timestamp-format",
+        "%H:%M:%S",
+    ]);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "[21:03:27] bracketed\n21:03:27 with an offset\nno timestamp 2026-10-16 12:00:00\n",
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // An unknown zone, or a format that can't be written, is an error
+    assert!(
+        !tail(&["--convert-timestamps", "Mars/Olympus_Mons"])
+            .status
+            .success()
+    );
+    assert!(!tail(&["--timestamp-format", "%"]).status.success());
+}
+
+#[test]
+fn test_highlight_without_style() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let log_path = temp_dir.path().join("training.log");
+    fs::write(&log_path, "epoch 3 val_loss=NaN\ntime: 5s\n").expect("Failed to write log");
+
+    let tail = |highlights: &[&str]| {
+        let mut command = Command::new(get_slurmtail_path());
+        command.args(["tail", "--timeout", "1", "--color", "always"]);
+        for highlight in highlights {
+            command.args(["--highlight", highlight]);
+        }
+        command
+            .arg(&log_path)
+            .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail tail")
+    };
+
+    // A style after the last ':' is the style; without one (or with a ':' that's part of the pattern), matches are
+    // shown in reverse video
+    let output = tail(&["NaN:red", "val_loss", "time: [0-9]+"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("\x1b[7mval_loss\x1b[0m")
+            && stdout.contains("\x1b[31mNaN\x1b[0m")
+            && stdout.contains("\x1b[7mtime: 5\x1b[0m"),
+        "Should highlight each pattern: {:?}",
+        stdout
+    );
+
+    // A word after the ':' that isn't a style is taken for a mistake
+    let output = tail(&["step:done"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        !output.status.success() && stderr.contains("Unknown style \"done\""),
+        "Should reject an unknown style: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_max_lines_stops_following() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    let script_path = temp_dir.path().join("chatty_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=chatty_%j.log\n\nfor i in $(seq 1 10); do echo \"step $i\"; done\nsleep 30\necho 'done'\n",
+    )
+    .expect("Failed to create test script");
+
+    let started = std::time::Instant::now();
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--timeout",
+            "60",
+            "--grep-v",
+            "step 2$",
+            "--max-lines",
+            "3",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    // Lines the filters leave out don't count
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Should succeed: {:?}", output);
+    assert!(
+        started.elapsed() < std::time::Duration::from_secs(20),
+        "Should stop without waiting for the job to finish"
+    );
+    assert_eq!(stdout, "step 1\nstep 3\nstep 4\n", "stderr: {}", stderr);
+    assert!(
+        stderr.contains("--max-lines"),
+        "Should say why it stopped: {}",
+        stderr
+    );
+
+    // Don't leave the mock job running
+    Command::new(get_slurmtail_path())
+        .args(["cancel", "--scheduler", "mock"])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+}
+
+#[test]
+fn test_strigger_end_detection() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    let script_path = temp_dir.path().join("short_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=short_%j.log\n\necho 'working'\nsleep 2\necho 'done'\n",
+    )
+    .expect("Failed to create test script");
+
+    let run = |env: &[(&str, &str)]| {
+        Command::new(get_slurmtail_path())
+            .args([
+                "run",
+                script_path.to_str().unwrap(),
+                "--scheduler",
+                "mock",
+                "--timeout",
+                "30",
+                "--strigger",
+                "-v",
+            ])
+            .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .envs(env.iter().copied())
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail")
+    };
+
+    // The trigger tells slurmtail the job is done, and its sentinel is cleaned up
+    let output = run(&[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Should succeed: {:?}", output);
+    assert!(
+        stdout.contains("done"),
+        "Should show the job's output: {}",
+        stdout
+    );
+    assert!(
+        stderr.contains("The job's end trigger fired"),
+        "Should learn of the job's end from the trigger: {}",
+        stderr
+    );
+    assert!(
+        !temp_dir
+            .path()
+            .join("._slurmtail")
+            .join("1000.ended")
+            .exists(),
+        "Should remove the sentinel file"
+    );
+
+    // Where triggers aren't allowed, the job is asked about as usual
+    let output = run(&[("SLURMTAIL_MOCK_NO_TRIGGERS", "1")]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Should succeed: {:?}", output);
+    assert!(
+        stderr.contains("Could not set a trigger on job 1001's end")
+            && !stderr.contains("The job's end trigger fired"),
+        "Should warn and fall back to polling: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_gpu_usage_line() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("gpu_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=gpu_%j.log\n#SBATCH --gres=gpu:2\necho \"training\"\nsleep 7\necho \"trained\"\n",
+    )
+    .expect("Failed to create test script");
+
+    let run = |extra_args: &[&str]| {
+        Command::new(get_slurmtail_path())
+            .args(["run", script_path.to_str().unwrap(), "--scheduler", "mock"])
+            .arg("--gpu-usage")
+            .args(extra_args)
+            .env("SLURMTAIL_MOCK_DIR", temp_dir.path().join("mock"))
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .env("SLURMTAIL_MOCK_GPUS", "98, 40960, 81920; 2, 1024, 81920")
+            .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail")
+    };
+
+    // Without a terminal, the GPUs' usage comes as a line of its own
+    let output = run(&[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "stderr: {}", stderr);
+    assert!(
+        stderr.contains("[INFO] gpus 1000: 98% 2% busy, 41.0/160.0 GiB memory"),
+        "Should show the GPUs' usage: {}",
+        stderr
+    );
+    assert!(stdout.contains("trained"), "stdout: {}", stdout);
+
+    // And as an event in porcelain mode
+    let output = run(&["--porcelain"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("gpu_usage\t1001\t2\t50\t41984\t163840"),
+        "Should emit a gpu_usage event: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_energy_in_job_summary() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    let state_dir = temp_dir.path().join("state");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--timeout",
+            "10",
+            "--tee",
+            "transcript.txt",
+            "--transcript",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", temp_dir.path().join("mock"))
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("SLURMTAIL_MOCK_ENERGY", "5400000")
+        .env("XDG_STATE_HOME", &state_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    assert!(output.status.success(), "Should succeed: {:?}", output);
+
+    // The summary at the end of the transcript has the energy the job used, in kWh as well
+    let transcript = fs::read_to_string(temp_dir.path().join("transcript.txt"))
+        .expect("Failed to read transcript");
+    assert!(
+        transcript.contains("Energy:    5400000 J (1.50 kWh)"),
+        "Should show the energy used: {}",
+        transcript
+    );
+
+    // And the submission history keeps it
+    let output = Command::new(get_slurmtail_path())
+        .args(["history", "--json"])
+        .env("XDG_STATE_HOME", &state_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail history");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("ConsumedEnergyRaw") && stdout.contains("5400000"),
+        "Should record the energy used: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_per_node_log_files() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("nodes_job.sh");
+    // The mock only runs the script on the first node, so it writes the second node's file itself, as a step there would
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=out_%N_%n.log\n#SBATCH --nodes=2\necho \"from the batch host\"\nsleep 2\necho \"from the other node\" > out_node2_1.log\nsleep 4\necho \"done\"\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap(), "--scheduler", "mock"])
+        .env("SLURMTAIL_MOCK_DIR", temp_dir.path().join("mock"))
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("SLURMTAIL_MOCK_NODES", "node1, node2, node3")
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "stderr: {}", stderr);
+    assert!(
+        stderr.contains("Monitoring the log files of job 1000's 3 nodes"),
+        "Should follow every node's file: {}",
+        stderr
+    );
+    // Each line comes with its node, and node3, which never writes a file, doesn't hold anything up
+    assert!(
+        stdout.contains("node1") && stdout.contains("from the batch host"),
+        "stdout: {}",
+        stdout
+    );
+    assert!(
+        stdout
+            .lines()
+            .any(|line| line.contains("node2") && line.contains("from the other node")),
+        "Should show the other node's output: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_pipeline_job_cancelled_while_pending() {
+    use std::process::Stdio;
+    use std::time::{Duration, Instant};
+
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    create_test_script(&temp_dir);
+    let mock_dir = temp_dir.path().join("mock");
+
+    let manifest_path = temp_dir.path().join("pipeline.yaml");
+    fs::write(
+        &manifest_path,
+        "jobs:\n  - name: only\n    script: test_job.sh\n",
+    )
+    .expect("Failed to create pipeline manifest");
+
+    let pipeline = Command::new(get_slurmtail_path())
+        .args([
+            "pipeline",
+            "run",
+            manifest_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "30")
+        .current_dir(temp_dir.path())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run slurmtail pipeline");
+
+    // Cancelled before it ever starts, the job never writes its log file
+    std::thread::sleep(Duration::from_secs(3));
+    let cancel = Command::new(get_slurmtail_path())
+        .args(["cancel", "1000", "--scheduler", "mock"])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail cancel");
+    assert!(
+        cancel.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&cancel.stderr)
+    );
+
+    // The wait for the log file ends with the job, long before the job would have started
+    let started = Instant::now();
+    let output = pipeline
+        .wait_with_output()
+        .expect("Failed to wait for slurmtail pipeline");
+    assert!(
+        started.elapsed() < Duration::from_secs(20),
+        "Should stop waiting once the job has left the queue"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(14), "stderr: {}", stderr);
+}
+
+#[test]
+fn test_retries_outlast_idle_timeout() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("quiet_job.sh");
+    // Quiet for longer than --timeout, like a job on a node that hung
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=quiet_%j.log\necho \"started\"\nsleep 4\necho \"finished\"\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--timeout",
+            "1",
+            "--retries",
+            "1",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", temp_dir.path().join("mock"))
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("SLURMTAIL_MOCK_FINAL_STATE", "NODE_FAIL")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // Each attempt is followed to its end, and the first one resubmitted
+    assert!(
+        !stderr.contains("Timeout while monitoring"),
+        "Should not time out while retrying: {}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Job 1000 ended in state NODE_FAIL; resubmitting (attempt 2 of 2)"),
+        "Should resubmit the job: {}",
+        stderr
+    );
+    assert_eq!(stdout.matches("finished").count(), 2, "stdout: {}", stdout);
+    assert_eq!(output.status.code(), Some(13), "stderr: {}", stderr);
+}
+
+#[test]
+fn test_lsf_output_path_and_bpeek() {
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::Stdio;
+
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+
+    // Stand-ins for the LSF tools; bjobs reports the job in $LSF_STAT
+    let bin_dir = temp_dir.path().join("bin");
+    fs::create_dir(&bin_dir).expect("Failed to create bin directory");
+    for (tool, body) in [
+        (
+            "bsub",
+            "echo 'Job <5151> is submitted to default queue <normal>.'",
+        ),
+        (
+            "bjobs",
+            "echo \"5151    user    $LSF_STAT  normal     login01     node01      lsf_test   Jan  1 10:00\"",
+        ),
+        (
+            "bpeek",
+            "echo \"$@\" > bpeek_args; echo 'Peeked at the job'",
+        ),
+    ] {
+        let tool_path = bin_dir.join(tool);
+        fs::write(&tool_path, format!("#!/bin/sh\n{}\n", body)).expect("Failed to create tool");
+        fs::set_permissions(&tool_path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    // LSF only fills in %J and %I, so the %x stays in the file's name
+    let script_path = temp_dir.path().join("lsf_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#BSUB -J lsf_test\n#BSUB -o %J.%x.out\n\necho hello\n",
+    )
+    .expect("Failed to create test script");
+    fs::write(temp_dir.path().join("5151.%x.out"), "Hello from LSF\n")
+        .expect("Failed to create log file");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap(), "--scheduler", "lsf"])
+        .args(["--timeout", "1"])
+        .env("PATH", &bin_dir)
+        .env("LSF_STAT", "DONE")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Hello from LSF"),
+        "Should follow the output file with %x left as it is: {}",
+        stdout
+    );
+
+    // A running job whose log can't be read here is followed with bpeek instead
+    fs::remove_file(temp_dir.path().join("5151.%x.out")).expect("Failed to remove log file");
+    let mut child = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap(), "--scheduler", "lsf"])
+        .args(["--timeout", "1"])
+        .env("PATH", &bin_dir)
+        .env("LSF_STAT", "RUN")
+        .current_dir(temp_dir.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run slurmtail");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"y\n")
+        .expect("Failed to answer");
+    let output = child
+        .wait_with_output()
+        .expect("Failed to wait for slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Attach to the output of job 5151 with bpeek instead?"),
+        "Should offer bpeek: {}",
+        stderr
+    );
+    assert!(stdout.contains("Peeked at the job"), "stdout: {}", stdout);
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("bpeek_args")).expect("bpeek was not run"),
+        "-f 5151\n"
+    );
+}
+
+#[test]
+fn test_flux_job_attach() {
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::Stdio;
+
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+
+    // Stand-in for the flux command: the job keeps running, and `flux job attach` records its arguments
+    let bin_dir = temp_dir.path().join("bin");
+    fs::create_dir(&bin_dir).expect("Failed to create bin directory");
+    let flux_path = bin_dir.join("flux");
+    fs::write(
+        &flux_path,
+        "#!/bin/sh\ncase \"$1\" in\n  batch) echo 1234 ;;\n  jobs) echo RUN ;;\n  job) echo \"$@\" > attach_args; echo 'Attached to the job' ;;\nesac\n",
+    )
+    .expect("Failed to create flux stand-in");
+    fs::set_permissions(&flux_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+    // The log never shows up, as if written to a file system slurmtail can't see
+    let script_path = temp_dir.path().join("flux_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#flux: --output=job-{{id}}.log\n\necho hello\n",
+    )
+    .expect("Failed to create test script");
+
+    let mut child = Command::new(get_slurmtail_path())
+        .args(["run", script_path.to_str().unwrap(), "--scheduler", "flux"])
+        .args(["--timeout", "1"])
+        .env("PATH", &bin_dir)
+        .current_dir(temp_dir.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run slurmtail");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"y\n")
+        .expect("Failed to answer");
+    let output = child
+        .wait_with_output()
+        .expect("Failed to wait for slurmtail");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Attach to job 1234 with flux job attach instead?"),
+        "Should offer to attach: {}",
+        stderr
+    );
+    assert!(stdout.contains("Attached to the job"), "stdout: {}", stdout);
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("attach_args"))
+            .expect("flux job attach was not run"),
+        "job attach 1234\n"
+    );
+}
+
+#[test]
+fn test_rerun_and_report_from_history() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = temp_dir.path().join("rerun_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --job-name=first\n#SBATCH --output=%x_%j.log\necho \"first run\"\n",
+    )
+    .expect("Failed to create test script");
+    let run = |args: &[&str]| {
+        Command::new(get_slurmtail_path())
+            .args(args)
+            .args(["--scheduler", "mock"])
+            .env("SLURMTAIL_MOCK_DIR", temp_dir.path().join("mock"))
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+            .env("XDG_STATE_HOME", temp_dir.path().join("state"))
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail")
+    };
+
+    let output = run(&["run", script_path.to_str().unwrap()]);
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // The script has changed since, but running job 1000 again asks for what it was submitted with
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --job-name=second\n#SBATCH --output=%x_%j.log\necho \"rerun\"\n",
+    )
+    .expect("Failed to update test script");
+    let output = run(&["rerun", "1000"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "stderr: {}", stderr);
+    assert!(
+        stderr.contains("Running job 1000") && stderr.contains("Job submitted with ID: 1001"),
+        "Should submit the job again: {}",
+        stderr
+    );
+    assert!(stdout.contains("rerun"), "stdout: {}", stdout);
+    assert!(
+        temp_dir.path().join("first_1001.log").exists(),
+        "Should resubmit with the recorded --job-name"
+    );
+
+    // Both runs count towards the script's report
+    let output = run(&["report", "--json"]);
+    let report: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("Report should be JSON");
+    assert_eq!(report[0]["runs"], 2, "report: {}", report);
+    assert_eq!(report[0]["completed"], 2, "report: {}", report);
+}
+
+#[test]
+fn test_retry_gives_up_on_unknown_final_state() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    // The job removes its own mock state, so the scheduler no longer knows it (as when accounting is off)
+    let script_path = temp_dir.path().join("vanishing_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=vanishing_%j.log\necho \"started\"\nrm -rf \"$SLURMTAIL_MOCK_DIR/$SLURM_JOB_ID\"\n",
+    )
+    .expect("Failed to create test script");
+
+    let started = std::time::Instant::now();
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--retries",
+            "1",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Could not determine the final state of job 1000; not resubmitting"),
+        "Should give up on the retry: {}",
+        stderr
+    );
+    assert!(
+        started.elapsed() < std::time::Duration::from_secs(20),
+        "Should not wait for a final state the scheduler can't report"
+    );
+}
+
+// A stand-in slurmrestd on a local port: submitting gives job 4242, which is COMPLETED; returns its URL and the body
+// of every job it was asked to submit
+fn start_slurmrestd_stand_in() -> (String, std::sync::Arc<std::sync::Mutex<Vec<String>>>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to listen");
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let submissions = Arc::new(Mutex::new(Vec::new()));
+    let received = Arc::clone(&submissions);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            let _ = reader.read_line(&mut request_line);
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                if reader.read_line(&mut header).unwrap_or(0) == 0 || header == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':')
+                    && name.eq_ignore_ascii_case("content-length")
+                {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+            }
+            let mut body = vec![0; content_length];
+            let _ = reader.read_exact(&mut body);
+
+            let reply = if request_line.starts_with("POST") && request_line.contains("/job/submit")
+            {
+                received
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(&body).into_owned());
+                Some(r#"{"job_id": 4242, "errors": []}"#)
+            } else if request_line.starts_with("GET") && request_line.contains("/job/4242") {
+                Some(r#"{"jobs": [{"job_state": ["COMPLETED"]}], "errors": []}"#)
+            } else {
+                None
+            };
+            let response = match reply {
+                Some(reply) => format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    reply.len(),
+                    reply
+                ),
+                None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string(),
+            };
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+
+    (url, submissions)
+}
+
+#[test]
+fn test_rest_scheduler_submits_directives() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let (url, submissions) = start_slurmrestd_stand_in();
+
+    let script_path = temp_dir.path().join("rest_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=rest_%j.log\n#SBATCH --error=rest_%j.err\n#SBATCH -p gpu -A physics --qos high\n#SBATCH --time=1:30:00 --nodes=2-4 --ntasks=8\n#SBATCH --mem=4G\necho \"hello\"\n",
+    )
+    .expect("Failed to create test script");
+    // The stand-in doesn't run anything, so the log file is there already
+    fs::write(temp_dir.path().join("rest_4242.log"), "hello\n").expect("Failed to create log");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "rest",
+            "--rest-url",
+            &url,
+        ])
+        .env("SLURM_JWT", "token")
+        .env("USER", "tester")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Should succeed: {}", stderr);
+
+    let submissions = submissions.lock().unwrap();
+    assert_eq!(
+        submissions.len(),
+        1,
+        "Should submit once: {:?}",
+        submissions
+    );
+    let submission: serde_json::Value =
+        serde_json::from_str(&submissions[0]).expect("Submission should be JSON");
+    let job = &submission["job"];
+    assert_eq!(job["standard_output"], "rest_%j.log", "job: {}", job);
+    assert_eq!(job["standard_error"], "rest_%j.err", "job: {}", job);
+    assert_eq!(job["partition"], "gpu", "job: {}", job);
+    assert_eq!(job["account"], "physics", "job: {}", job);
+    assert_eq!(job["qos"], "high", "job: {}", job);
+    assert_eq!(job["time_limit"]["number"], 90, "job: {}", job);
+    assert_eq!(job["minimum_nodes"], 2, "job: {}", job);
+    assert_eq!(job["maximum_nodes"], 4, "job: {}", job);
+    assert_eq!(job["tasks"], 8, "job: {}", job);
+    assert_eq!(job["memory_per_node"]["number"], 4096, "job: {}", job);
+}
+
+#[test]
+fn test_rest_scheduler_rejects_unsupported_directive() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let (url, submissions) = start_slurmrestd_stand_in();
+
+    let script_path = temp_dir.path().join("rest_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=rest_%j.log\n#SBATCH --gpu-bind=closest\necho \"hello\"\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "rest",
+            "--rest-url",
+            &url,
+        ])
+        .env("SLURM_JWT", "token")
+        .env("USER", "tester")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "Should fail: {}", stderr);
+    assert!(
+        stderr.contains("--gpu-bind is not supported by the rest scheduler"),
+        "Should name the directive: {}",
+        stderr
+    );
+    assert!(
+        submissions.lock().unwrap().is_empty(),
+        "Should not submit the job"
+    );
+}
+
+#[test]
+fn test_failed_test_only_exit_code() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let script_path = create_test_script_fast(&temp_dir);
+    // No sbatch to run the pre-flight with
+    let empty_path = temp_dir.path().join("bin");
+    fs::create_dir(&empty_path).expect("Failed to create directory");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "slurm",
+            "--test-only",
+        ])
+        .env("PATH", &empty_path)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(12),
+        "Should exit as for a failed submission: {}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Submitting the job failed"),
+        "Should report the failed pre-flight: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_profile_defaults_leave_script_directives() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+    let state_dir = temp_dir.path().join("state");
+
+    let script_path = temp_dir.path().join("cpu_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=cpu_%j.log\n#SBATCH --partition=cpu\necho \"done\"\n",
+    )
+    .expect("Failed to create test script");
+
+    let config_dir = temp_dir.path().join("config").join("slurmtail");
+    fs::create_dir_all(&config_dir).expect("Failed to create config directory");
+    fs::write(
+        config_dir.join("config.toml"),
+        "[profile.gpu]\nscheduler = \"mock\"\npartition = \"gpu\"\nqos = \"high\"\n",
+    )
+    .expect("Failed to write config");
+
+    let output = Command::new(get_slurmtail_path())
+        .args(["--profile", "gpu", "run", script_path.to_str().unwrap()])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("config"))
+        .env("XDG_STATE_HOME", &state_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Job should complete: {}", stderr);
+
+    // The script's partition stays; the QOS it doesn't set comes from the profile
+    let output = Command::new(get_slurmtail_path())
+        .args(["history", "--json"])
+        .env("XDG_STATE_HOME", &state_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail history");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let history: serde_json::Value = serde_json::from_str(&stdout).expect("History should be JSON");
+    let directives = history[0]["directives"]
+        .as_array()
+        .expect("Should snapshot the directives");
+    assert!(
+        directives.contains(&serde_json::json!(["partition", "cpu"])),
+        "{}",
+        stdout
+    );
+    assert!(
+        !directives.contains(&serde_json::json!(["partition", "gpu"])),
+        "{}",
+        stdout
+    );
+    assert!(
+        directives.contains(&serde_json::json!(["qos", "high"])),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_het_job_components_followed() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    // The mock runs the script once, writing the first component's file; the script writes the second's itself
+    let script_path = temp_dir.path().join("het_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=het_first.log\n#SBATCH hetjob\n#SBATCH --output=het_second.log\n\necho 'second component' > het_second.log\necho 'first component'\n",
+    )
+    .expect("Failed to create test script");
+
+    let run = |args: &[&str]| {
+        let output = Command::new(get_slurmtail_path())
+            .args([
+                "run",
+                script_path.to_str().unwrap(),
+                "--scheduler",
+                "mock",
+                "--no-file-timeout",
+                "--no-idle-timeout",
+                "--timeout",
+                "10",
+            ])
+            .args(args)
+            .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail");
+        (
+            String::from_utf8_lossy(&output.stdout).to_string(),
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        )
+    };
+
+    let (stdout, stderr) = run(&["--all-components"]);
+    assert!(
+        stdout.contains("[+0] first component") && stdout.contains("[+1] second component"),
+        "Should follow every component with its prefix: {}\n{}",
+        stdout,
+        stderr
+    );
+
+    let (stdout, stderr) = run(&[]);
+    assert!(
+        stdout.contains("first component") && !stdout.contains("second component"),
+        "Should follow only the first component by default: {}",
+        stdout
+    );
+    assert!(
+        stderr.contains("Pass --all-components"),
+        "Should say how to follow the other components: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_absolute_log_path_warned_once() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    let log_path = temp_dir.path().join("absolute_%j.log");
+    let script_path = temp_dir.path().join("absolute_job.sh");
+    fs::write(
+        &script_path,
+        format!(
+            "#!/bin/bash\n#SBATCH --output={}\n\necho 'absolute output'\n",
+            log_path.display()
+        ),
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stdout.contains("absolute output"),
+        "Should follow the absolute log path: {}\n{}",
+        stdout,
+        stderr
+    );
+    assert_eq!(
+        stderr.matches("the logfile is an absolute path").count(),
+        1,
+        "Should warn about the absolute path once: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_resume_most_recent_session_without_terminal() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    // Both jobs are still running when following them gives up, so either could be resumed
+    for name in ["older", "newer"] {
+        let script_path = temp_dir.path().join(format!("{}.sh", name));
+        fs::write(
+            &script_path,
+            format!(
+                "#!/bin/bash\n#SBATCH --output={}_%j.log\n\necho 'This is the {} job'\nsleep 8\n",
+                name, name
+            ),
+        )
+        .expect("Failed to create test script");
+
+        Command::new(get_slurmtail_path())
+            .args([
+                "run",
+                script_path.to_str().unwrap(),
+                "--scheduler",
+                "mock",
+                "--file-timeout",
+                "10",
+                "--idle-timeout",
+                "1",
+            ])
+            .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail");
+    }
+
+    // Without a terminal there's no picker to show, so the most recent session is resumed
+    let output = Command::new(get_slurmtail_path())
+        .args(["resume", "--timeout", "1"])
+        .stdin(std::process::Stdio::null())
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail resume");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stdout.contains("This is the newer job") && !stdout.contains("This is the older job"),
+        "Should resume the most recent session: {}\n{}",
+        stdout,
+        stderr
+    );
+    assert!(
+        stderr.contains("2 jobs were submitted from here") && !stderr.contains("Resume which job?"),
+        "Should say which session it picked instead of asking: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_severity_lines_colored() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    let script_path = temp_dir.path().join("noisy_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=noisy_%j.log\n\necho 'epoch 1 done'\necho 'WARN: learning rate is high'\necho 'Traceback (most recent call last):'\necho 'slurmstepd: error: Detected 1 oom_kill event'\n",
+    )
+    .expect("Failed to create test script");
+
+    let run = |color: &str| {
+        let output = Command::new(get_slurmtail_path())
+            .args([
+                "run",
+                script_path.to_str().unwrap(),
+                "--scheduler",
+                "mock",
+                "--no-file-timeout",
+                "--no-idle-timeout",
+                "--timeout",
+                "10",
+                "--color",
+                color,
+            ])
+            .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+            .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run slurmtail");
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    let stdout = run("always");
+    for line in [
+        "WARN: learning rate is high",
+        "Traceback (most recent call last):",
+        "slurmstepd: error: Detected 1 oom_kill event",
+    ] {
+        assert!(
+            stdout
+                .lines()
+                .any(|colored| colored.contains(line) && colored.contains('\x1b')),
+            "Should color {:?}: {:?}",
+            line,
+            stdout
+        );
+    }
+    assert!(
+        stdout
+            .lines()
+            .any(|plain| plain.contains("epoch 1 done") && !plain.contains('\x1b')),
+        "Should leave routine lines alone: {:?}",
+        stdout
+    );
+
+    let stdout = run("never");
+    assert!(
+        stdout.contains("Traceback") && !stdout.contains('\x1b'),
+        "Should not color anything with --color never: {:?}",
+        stdout
+    );
+}
+
+#[test]
+fn test_no_terminal_title_without_terminal() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    let script_path = temp_dir.path().join("title_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=title_%j.log\n\necho 'title job output'\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "1")
+        .env("TERM", "xterm-256color")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stdout.contains("title job output"),
+        "Job output should be followed: {}",
+        stdout
+    );
+    // Setting (or saving and restoring) the title only makes sense on a terminal
+    assert!(
+        !stderr.contains("\x1b]0;")
+            && !stderr.contains("\x1b[22;0t")
+            && !stderr.contains("\x1b[23;0t"),
+        "Should not set the terminal title when stderr isn't a terminal: {:?}",
+        stderr
+    );
+}
+
+#[test]
+fn test_waiting_lines_without_terminal() {
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    let script_path = temp_dir.path().join("pending_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=pending_%j.log\n\necho 'finally running'\n",
+    )
+    .expect("Failed to create test script");
+
+    let output = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "2")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to run slurmtail");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stdout.contains("finally running"),
+        "Job output should be followed once it starts: {}\n{}",
+        stdout,
+        stderr
+    );
+    // Without a terminal, waiting is a plain line instead of a spinner redrawn in place
+    assert!(
+        stderr.contains("[INFO] Waiting for log file"),
+        "Should say what it is waiting for: {}",
+        stderr
+    );
+    assert!(
+        !stderr.contains('\r') && !stderr.contains("\x1b[2K"),
+        "Should not draw a spinner: {:?}",
+        stderr
+    );
+}
+
+#[test]
+fn test_keys_ignored_without_terminal() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    // Create temporary directory for this test
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let mock_dir = temp_dir.path().join("mock");
+
+    let script_path = temp_dir.path().join("keys_job.sh");
+    fs::write(
+        &script_path,
+        "#!/bin/bash\n#SBATCH --output=keys_%j.log\n\necho 'first line'\nsleep 2\necho 'last line'\n",
+    )
+    .expect("Failed to create test script");
+
+    // Piped in rather than typed, "q" (detach) and "c" (cancel) are left alone
+    let mut child = Command::new(get_slurmtail_path())
+        .args([
+            "run",
+            script_path.to_str().unwrap(),
+            "--scheduler",
+            "mock",
+            "--no-file-timeout",
+            "--no-idle-timeout",
+            "--timeout",
+            "10",
+        ])
+        .env("SLURMTAIL_MOCK_DIR", &mock_dir)
+        .env("SLURMTAIL_MOCK_PENDING_SECONDS", "0")
+        .current_dir(temp_dir.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run slurmtail");
+    {
+        let mut stdin = child.stdin.take().expect("Failed to open stdin");
+        stdin.write_all(b"qcy\n").expect("Failed to write keys");
+    }
+    let output = child
+        .wait_with_output()
+        .expect("Failed to wait for slurmtail");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success() && stdout.contains("last line"),
+        "Should follow the job to the end: {}\n{}",
+        stdout,
+        stderr
+    );
+    assert!(
+        !stderr.contains("Keys:") && !stderr.contains("Cancelled"),
+        "Should not react to keys without a terminal: {}",
+        stderr
+    );
+}